@@ -1,11 +1,12 @@
-use std::{fs, path::{self, Path}, time::{Duration, Instant}};
+use std::{fs, path::{self, Path}, time::Duration};
 
 
 use diff::Diff;
-use macroquad::{color::WHITE, texture::{draw_texture, draw_texture_ex, DrawTextureParams}};
+use macroquad::{color::{Color, WHITE}, file::load_string, math::{vec2, Rect}, texture::{draw_texture, draw_texture_ex, DrawTextureParams}};
+use rapier2d::prelude::{ColliderHandle, RigidBodyHandle};
 use serde::{Deserialize, Serialize};
 
-use crate::{current_unix_millis, texture_loader::TextureLoader};
+use crate::{coordinates::CoordinateSystem, space::Space, texture_atlas::atlas_source, texture_loader::TextureLoader, time::game_unix_millis};
 
 #[derive(Serialize, Deserialize, Diff, PartialEq, Clone)]
 #[diff(attr(
@@ -27,7 +28,7 @@ impl TrackedFrames {
     pub fn next_frame(&mut self) {
         self.current_frame_index += 1;
 
-        if self.current_frame_index >= self.frames.paths.len() {
+        if self.current_frame_index >= self.frames.len() {
             self.current_frame_index = 0;
         }
 
@@ -37,8 +38,14 @@ impl TrackedFrames {
         self.current_frame_index = new_frame_index;
     }
 
+    /// Path of the texture the current frame is drawn from
     pub fn current_frame(&self) -> &String {
-        &self.frames.paths[self.current_frame_index]
+        self.frames.frame(self.current_frame_index).0
+    }
+
+    /// Where the current frame is in its texture, for spritesheets
+    pub fn current_source_rect(&self) -> Option<Rect> {
+        self.frames.frame(self.current_frame_index).1
     }
 }
 
@@ -47,14 +54,75 @@ impl TrackedFrames {
 #[diff(attr(
     #[derive(Serialize, Deserialize)]
 ))]
-pub struct Frames {
-    paths: Vec<String>
+pub enum Frames {
+    /// One texture per frame
+    Files { paths: Vec<String> },
+    /// Every frame is a rect of one spritesheet texture
+    Sheet { texture: String, rects: Vec<Rect> }
 }
 
 impl Frames {
 
     pub fn len(&self) -> usize {
-        self.paths.len()
+        match self {
+            Frames::Files { paths } => paths.len(),
+            Frames::Sheet { rects, .. } => rects.len(),
+        }
+    }
+
+    /// The texture path for a frame, along with where the frame is in it when it is part of a spritesheet
+    pub fn frame(&self, index: usize) -> (&String, Option<Rect>) {
+        match self {
+            Frames::Files { paths } => (&paths[index], None),
+            Frames::Sheet { texture, rects } => (texture, Some(rects[index])),
+        }
+    }
+
+    /// Every texture the frames are drawn from, without repeats
+    pub fn texture_paths(&self) -> Vec<&String> {
+        match self {
+            Frames::Files { paths } => {
+                let mut texture_paths: Vec<&String> = vec![];
+
+                for path in paths {
+                    if !texture_paths.contains(&path) {
+                        texture_paths.push(path);
+                    }
+                }
+
+                texture_paths
+            },
+            Frames::Sheet { texture, .. } => vec![texture],
+        }
+    }
+
+    /// Cut a spritesheet into `count` frames of the same size, left to right and then top to bottom
+    pub fn grid(texture: String, frame_width: f32, frame_height: f32, columns: usize, count: usize) -> Self {
+        let rects = (0..count).map(|index| {
+            Rect::new(
+                (index % columns) as f32 * frame_width, 
+                (index / columns) as f32 * frame_height, 
+                frame_width, 
+                frame_height
+            )
+        }).collect();
+
+        Frames::Sheet { texture, rects }
+    }
+
+    /// Load frame rects from a JSON atlas in the array format TexturePacker and Aseprite export
+    pub fn load_from_atlas(texture: String, atlas_path: &String) -> Self {
+        Self::from_atlas_json(texture, &fs::read_to_string(atlas_path).unwrap()).unwrap()
+    }
+
+    pub fn from_atlas_json(texture: String, atlas_json: &str) -> Result<Self, String> {
+        let atlas: AtlasFile = serde_json::from_str(atlas_json).map_err(|error| format!("invalid atlas: {}", error))?;
+
+        let rects = atlas.frames.iter().map(|atlas_frame| {
+            Rect::new(atlas_frame.frame.x, atlas_frame.frame.y, atlas_frame.frame.w, atlas_frame.frame.h)
+        }).collect();
+
+        Ok(Frames::Sheet { texture, rects })
     }
     
     pub fn load_from_directory(frames_directory: &String) -> Self {
@@ -87,57 +155,397 @@ impl Frames {
                 .and_then(|num_str| num_str.parse::<u32>().ok()) // Parse as a number
         });
 
-        println!("{:?}", paths);
+        log_debug!("{:?}", paths);
 
-        Self {
+        Frames::Files {
             paths
         }
 
     }
 }
+
+#[derive(Deserialize)]
+struct AtlasRect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32
+}
+
+#[derive(Deserialize)]
+struct AtlasFrame {
+    frame: AtlasRect
+}
+
+#[derive(Deserialize)]
+struct AtlasFile {
+    frames: Vec<AtlasFrame>
+}
+
+/// Spritesheet section of animation_meta.json. Frames come from either a grid or a JSON atlas. Paths are relative to the animation directory
+#[derive(Serialize, Deserialize)]
+struct SpriteSheetMeta {
+    texture: String,
+    frame_width: Option<f32>,
+    frame_height: Option<f32>,
+    columns: Option<usize>,
+    count: Option<usize>,
+    atlas: Option<String>
+}
+
+/// What happens when an animation reaches its last frame
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Copy, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub enum PlaybackMode {
+    /// Play once and go back to the first frame
+    Once,
+    /// Play this many times and hold the last frame, or forever with None
+    Loop(Option<u32>),
+    /// Forwards then backwards, forever
+    PingPong,
+    /// Backwards, forever
+    Reverse,
+    /// Play once and stay on the last frame
+    HoldLastFrame
+}
+
+impl Default for PlaybackMode {
+    fn default() -> Self {
+        PlaybackMode::Loop(None)
+    }
+}
+
+impl PlaybackMode {
+
+    /// Which frame to show after `step` frames have played
+    pub fn frame_at(&self, step: u64, frame_count: usize) -> usize {
+        let frame_count = frame_count as u64;
+
+        if frame_count == 0 {
+            return 0;
+        }
+
+        let frame = match self {
+            PlaybackMode::Once => {
+                match step >= frame_count {
+                    true => 0,
+                    false => step,
+                }
+            },
+            PlaybackMode::Loop(None) => step % frame_count,
+            PlaybackMode::Loop(Some(times)) => {
+                match step >= frame_count * *times as u64 {
+                    true => frame_count - 1,
+                    false => step % frame_count,
+                }
+            },
+            PlaybackMode::PingPong => {
+                // the first and last frames aren't repeated when turning around
+                let period = (frame_count * 2 - 2).max(1);
+
+                let position = step % period;
+
+                match position < frame_count {
+                    true => position,
+                    false => period - position,
+                }
+            },
+            PlaybackMode::Reverse => frame_count - 1 - step % frame_count,
+            PlaybackMode::HoldLastFrame => step.min(frame_count - 1),
+        };
+
+        frame as usize
+    }
+
+    /// How many frame steps it takes to finish, None for modes that never do
+    pub fn length(&self, frame_count: usize) -> Option<u64> {
+        match self {
+            PlaybackMode::Once | PlaybackMode::HoldLastFrame => Some(frame_count as u64),
+            PlaybackMode::Loop(Some(times)) => Some(frame_count as u64 * *times as u64),
+            PlaybackMode::Loop(None) | PlaybackMode::PingPong | PlaybackMode::Reverse => None,
+        }
+    }
+}
+
+/// A named event that fires when its frame comes up, like "footstep" or "hitbox_active"
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct FrameEvent {
+    pub frame: usize,
+    pub name: String
+}
+
 #[derive(Serialize, Deserialize)]
 struct AnimationMeta {
-    frame_duration: u64 
+    frame_duration: u64,
+    #[serde(default)]
+    frames: Option<Vec<String>>, // frame files in order, relative to the animation directory. Needed where the directory can't be listed, like wasm
+    #[serde(default)]
+    frame_durations: Option<Vec<u64>>,
+    #[serde(default)]
+    events: Vec<FrameEvent>,
+    #[serde(default)]
+    mode: PlaybackMode,
+    #[serde(default)]
+    spritesheet: Option<SpriteSheetMeta> // without one every png in the directory is a frame
 }
 
+/// The frames and timing of an animation. Loaded once by AnimationLoader and shared by every AnimationInstance playing it
 #[derive(Serialize, Deserialize, Diff, PartialEq, Clone)]
 #[diff(attr(
     #[derive(Serialize, Deserialize)]
 ))]
-pub struct Animation {
-    frames: Frames,
+pub struct AnimationData {
+    pub frames: Frames,
     frame_duration: u64,
-    start_time: Option<u64>,
-    pause_offset: Option<u64>, // the time at which we paused
+    frame_durations: Option<Vec<u64>>, // millis for each frame, frames past the end use frame_duration
+    pub events: Vec<FrameEvent>,
+    pub mode: PlaybackMode // the mode new instances start with
 }
 
-impl Animation {
+impl AnimationData {
+
+    pub fn new(frames: Frames, frame_duration: u64) -> Self {
+        Self {
+            frames,
+            frame_duration,
+            frame_durations: None,
+            events: vec![],
+            mode: PlaybackMode::default(),
+        }
+    }
+
+    pub fn with_frame_durations(mut self, frame_durations: Vec<u64>) -> Self {
+        self.frame_durations = Some(frame_durations);
+
+        self
+    }
+
+    pub fn with_event(mut self, frame: usize, name: &str) -> Self {
+        self.events.push(FrameEvent { frame, name: name.to_string() });
+
+        self
+    }
+
+    pub fn with_mode(mut self, mode: PlaybackMode) -> Self {
+        self.mode = mode;
+
+        self
+    }
+
+    /// How long a frame stays on screen in millis, before speed is applied
+    pub fn frame_duration(&self, frame: usize) -> u64 {
+        let duration = match &self.frame_durations {
+            Some(frame_durations) => frame_durations.get(frame).copied().unwrap_or(self.frame_duration),
+            None => self.frame_duration,
+        };
+
+        duration.max(1)
+    }
+
+    /// Millis from the start of the first frame to the start of `frame`
+    fn frame_offset(&self, frame: usize) -> u64 {
+        (0..frame.min(self.frames.len())).map(|frame| self.frame_duration(frame)).sum()
+    }
+
+    /// How many frames have been shown after `elapsed` millis of playing in `mode`, counting repeats
+    pub fn step_at(&self, mode: &PlaybackMode, elapsed: u64) -> u64 {
+        let frame_count = self.frames.len();
+
+        if frame_count == 0 {
+            return 0;
+        }
+
+        // the order frames are shown in repeats every period steps
+        let period = match mode {
+            PlaybackMode::PingPong => (frame_count * 2 - 2).max(1),
+            _ => frame_count,
+        };
+
+        let durations: Vec<u64> = (0..period as u64)
+            .map(|step| self.frame_duration(mode.frame_at(step, frame_count)))
+            .collect();
+
+        let period_duration: u64 = durations.iter().sum();
+
+        let mut step = elapsed / period_duration * period as u64;
+        let mut remaining = elapsed % period_duration;
+
+        for duration in durations {
+            if remaining < duration {
+                break;
+            }
+
+            remaining -= duration;
+            step += 1;
+        }
+
+        step
+    }
+
+    fn from_meta(animation_meta: AnimationMeta, frames: Frames) -> Self {
+        Self {
+            frames,
+            frame_duration: animation_meta.frame_duration,
+            frame_durations: animation_meta.frame_durations,
+            events: animation_meta.events,
+            mode: animation_meta.mode,
+        }
+    }
+
+    /// Load an animation without touching the filesystem directly, so it works in the browser where assets come over http.
+    /// Frames come from the spritesheet or the frames list in animation_meta.json
+    pub async fn load(frames_directory: &String) -> Result<Self, String> {
+        let meta_path = format!("{}/animation_meta.json", frames_directory);
+
+        let meta_json = load_string(&meta_path).await.map_err(|error| format!("failed to load {}: {}", meta_path, error))?;
+
+        let animation_meta: AnimationMeta = serde_json::from_str(&meta_json).map_err(|error| format!("invalid {}: {}", meta_path, error))?;
+
+        let frames = match (&animation_meta.spritesheet, &animation_meta.frames) {
+            (Some(spritesheet), _) => {
+                let texture = format!("{}/{}", frames_directory, spritesheet.texture);
+
+                match (&spritesheet.atlas, spritesheet.frame_width, spritesheet.frame_height, spritesheet.columns, spritesheet.count) {
+                    (Some(atlas), _, _, _, _) => {
+                        let atlas_path = format!("{}/{}", frames_directory, atlas);
+
+                        let atlas_json = load_string(&atlas_path).await.map_err(|error| format!("failed to load {}: {}", atlas_path, error))?;
+
+                        Frames::from_atlas_json(texture, &atlas_json)?
+                    },
+                    (None, Some(frame_width), Some(frame_height), Some(columns), Some(count)) => {
+                        Frames::grid(texture, frame_width, frame_height, columns, count)
+                    },
+                    _ => return Err(format!("spritesheet in {} needs either an atlas or frame_width, frame_height, columns and count", meta_path))
+                }
+            },
+            (None, Some(frames)) => Frames::Files {
+                paths: frames.iter().map(|frame| format!("{}/{}", frames_directory, frame)).collect()
+            },
+            #[cfg(not(target_arch = "wasm32"))]
+            (None, None) => Frames::load_from_directory(frames_directory),
+            #[cfg(target_arch = "wasm32")]
+            (None, None) => return Err(format!("{} needs a frames list or a spritesheet to load in the browser", meta_path)),
+        };
+
+        Ok(Self::from_meta(animation_meta, frames))
+    }
 
     pub fn new_from_directory(frames_directory: &String) -> Self {
         // need to handle error states!
 
         let animation_meta: AnimationMeta = serde_json::from_str(&fs::read_to_string(format!("{}/animation_meta.json", frames_directory)).unwrap()).unwrap();
 
-        let frames = Frames::load_from_directory(frames_directory);
+        let frames = match (&animation_meta.spritesheet, &animation_meta.frames) {
+            (Some(spritesheet), _) => {
+                let texture = format!("{}/{}", frames_directory, spritesheet.texture);
+
+                match (&spritesheet.atlas, spritesheet.frame_width, spritesheet.frame_height, spritesheet.columns, spritesheet.count) {
+                    (Some(atlas), _, _, _, _) => Frames::load_from_atlas(texture, &format!("{}/{}", frames_directory, atlas)),
+                    (None, Some(frame_width), Some(frame_height), Some(columns), Some(count)) => {
+                        Frames::grid(texture, frame_width, frame_height, columns, count)
+                    },
+                    _ => panic!("spritesheet in {} needs either an atlas or frame_width, frame_height, columns and count", frames_directory)
+                }
+            },
+            (None, Some(frames)) => Frames::Files {
+                paths: frames.iter().map(|frame| format!("{}/{}", frames_directory, frame)).collect()
+            },
+            (None, None) => Frames::load_from_directory(frames_directory),
+        };
+
+        Self::from_meta(animation_meta, frames)
+    }
+}
+
+/// One entity playing an animation. Only holds playback state, the frames are looked up in the AnimationData at animation_path
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct AnimationInstance {
+    pub animation_path: String,
+    start_time: Option<u64>,
+    pause_offset: Option<u64>, // the time at which we paused
+    pub mode: PlaybackMode,
+    speed: f32,
+    last_event_step: Option<u64> // the last step drain_events looked at
+}
+
+impl AnimationInstance {
 
+    pub fn new(animation_path: &String, animation_data: &AnimationData) -> Self {
         Self {
-            frames,
-            frame_duration: animation_meta.frame_duration,
+            animation_path: animation_path.clone(),
             start_time: None,
             pause_offset: None,
+            mode: animation_data.mode,
+            speed: 1.,
+            last_event_step: None,
         }
     }
 
+    pub fn with_mode(mut self, mode: PlaybackMode) -> Self {
+        self.mode = mode;
+
+        self
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Change how fast the animation plays without jumping to a different frame
+    pub fn set_speed(&mut self, speed: f32) {
+        let speed = speed.max(0.);
+
+        let scale = |raw_elapsed: u64| (raw_elapsed as f64 * self.speed as f64 / speed.max(f32::EPSILON) as f64) as u64;
+
+        // the raw elapsed time has to be rescaled so that raw elapsed * speed stays the same
+        match (self.start_time, self.pause_offset) {
+            (Some(_), Some(pause_offset)) => self.pause_offset = Some(scale(pause_offset)),
+            (Some(start_time), None) => {
+                let now = game_unix_millis();
+
+                self.start_time = Some(now - scale(now - start_time).min(now));
+            },
+            (None, _) => {},
+        }
+
+        self.speed = speed;
+    }
+
     /// Set the animation start point to now
     pub fn start(&mut self) {
         
-        self.start_time = Some(current_unix_millis());
+        self.start_time = Some(game_unix_millis());
+        self.last_event_step = None;
+    }
+
+    /// Start playing from the beginning of a frame instead of the first one
+    pub fn start_at_frame(&mut self, frame: usize, animation_data: &AnimationData) {
+        let offset = animation_data.frame_offset(frame);
+
+        let now = game_unix_millis();
+
+        // elapsed gets multiplied by the speed, so the offset has to be divided by it
+        let raw_offset = (offset as f64 / self.speed.max(f32::EPSILON) as f64) as u64;
+
+        self.start_time = Some(now - raw_offset.min(now));
+        self.pause_offset = None;
+        self.last_event_step = None;
     }
 
     /// Delete the start time and pause offsets and stop the animation
     pub fn stop(&mut self) {
         self.start_time = None;
         self.pause_offset = None;
+        self.last_event_step = None;
 
     }
 
@@ -157,7 +565,7 @@ impl Animation {
         };
 
         // to make sure we start back at the tight frame we calculate where we were when we paused and use that as the new starting time
-        *start_time = current_unix_millis() - *pause_offset;
+        *start_time = game_unix_millis() - *pause_offset;
 
         self.pause_offset = None;   
 
@@ -174,20 +582,15 @@ impl Animation {
             None => {return Result::Err(())},
         };
 
-        self.pause_offset = Some(current_unix_millis() - start_time);
+        self.pause_offset = Some(game_unix_millis() - start_time);
 
         return Result::Ok(());
     }
 
-    pub fn current_frame(&self) -> usize {
+    /// Playing time since start, scaled by the speed. None if the animation hasn't been started
+    fn elapsed(&self) -> Option<u64> {
 
-        let start_time = match self.start_time {
-            Some(start_time) => start_time,
-            None => {
-                // if we havent started the animation yet we just return the first frame
-                return 0
-            },
-        };
+        let start_time = self.start_time?;
 
         let elapsed = match self.pause_offset {
             Some(pause_offset) => {
@@ -196,23 +599,127 @@ impl Animation {
             },
             None => {
                 // if we are currently playing, we return the actual elapsed time since we started the animation
-                current_unix_millis() - start_time
+                game_unix_millis() - start_time
             },
         };
 
-        let current_frame = (elapsed / self.frame_duration) as usize % self.frames.paths.len();
+        Some((elapsed as f64 * self.speed as f64) as u64)
+    }
+
+    pub fn current_frame(&self, animation_data: &AnimationData) -> usize {
+
+        // if we havent started the animation yet we show where the mode starts
+        let elapsed = self.elapsed().unwrap_or(0);
 
-        return current_frame
+        self.mode.frame_at(animation_data.step_at(&self.mode, elapsed), animation_data.frames.len())
     } 
 
-    pub async fn draw(&mut self, x: f32, y: f32, textures: &mut TextureLoader, params: DrawTextureParams) {
+    /// Whether a mode that ends has played all the way through. Looping modes never finish
+    pub fn finished(&self, animation_data: &AnimationData) -> bool {
+        let (elapsed, length) = match (self.elapsed(), self.mode.length(animation_data.frames.len())) {
+            (Some(elapsed), Some(length)) => (elapsed, length),
+            _ => return false,
+        };
+
+        animation_data.step_at(&self.mode, elapsed) >= length
+    }
 
-        let current_frame = self.current_frame();
+    /// Names of every event whose frame came up since the last call, in order
+    pub fn drain_events(&mut self, animation_data: &AnimationData) -> Vec<String> {
+        let elapsed = match self.elapsed() {
+            Some(elapsed) => elapsed,
+            None => return vec![],
+        };
+
+        let mut current_step = animation_data.step_at(&self.mode, elapsed);
 
-        let current_frame_texture = textures.get(
-            &self.frames.paths[current_frame]
+        // modes that end don't fire anything once they are done
+        if let Some(length) = self.mode.length(animation_data.frames.len()) {
+            if length == 0 {
+                return vec![];
+            }
+
+            current_step = current_step.min(length - 1);
+        }
+
+        let first_step = match self.last_event_step {
+            Some(last_event_step) => last_event_step + 1,
+            None => 0,
+        };
+
+        self.last_event_step = Some(current_step);
+
+        let mut events = vec![];
+
+        for step in first_step..=current_step {
+            let frame = self.mode.frame_at(step, animation_data.frames.len());
+
+            events.extend(
+                animation_data.events.iter()
+                    .filter(|event| event.frame == frame)
+                    .map(|event| event.name.clone())
+            );
+        }
+
+        events
+    }
+
+    /// Spritesheet frames replace params.source with the frame's rect
+    pub async fn draw(&self, animation_data: &AnimationData, x: f32, y: f32, textures: &mut TextureLoader, params: DrawTextureParams) {
+        self.draw_tinted(animation_data, x, y, textures, params, WHITE).await;
+    }
+
+    /// Draw the current frame over a physics body, rotated with the body and sized to its cuboid collider like HasPhysics::draw_texture
+    pub async fn draw_on_body(
+        &self, 
+        animation_data: &AnimationData,
+        space: &Space, 
+        rigid_body_handle: RigidBodyHandle, 
+        collider_handle: ColliderHandle, 
+        textures: &mut TextureLoader, 
+        flip_x: bool, 
+        flip_y: bool, 
+        coordinates: &CoordinateSystem
+    ) {
+        let rigid_body = space.rigid_body_set.get(rigid_body_handle).unwrap();
+        let collider = space.collider_set.get(collider_handle).unwrap();
+
+        let shape = collider.shape().as_cuboid().unwrap();
+
+        let position = rigid_body.position().translation;
+        let rotation = rigid_body.rotation().angle();
+
+        let draw_pos = coordinates.rapier_to_macroquad(&vec2(position.x, position.y));
+
+        self.draw(
+            animation_data,
+            draw_pos.x - shape.half_extents.x, 
+            draw_pos.y - shape.half_extents.y, 
+            textures, 
+            DrawTextureParams {
+                dest_size: Some(vec2(shape.half_extents.x * 2., shape.half_extents.y * 2.)),
+                source: None,
+                rotation: rotation * -1.,
+                flip_x,
+                flip_y,
+                pivot: None,
+            }
         ).await;
+    }
+
+    /// Like draw, but multiplied by a color. Used to fade animations in and out
+    pub async fn draw_tinted(&self, animation_data: &AnimationData, x: f32, y: f32, textures: &mut TextureLoader, mut params: DrawTextureParams, color: Color) {
+
+        let current_frame = self.current_frame(animation_data);
 
-        draw_texture_ex(current_frame_texture, x, y, WHITE, params);
+        let (texture_path, source) = animation_data.frames.frame(current_frame);
+
+        let (current_frame_texture, region) = textures.get_region(texture_path).await;
+
+        if source.is_some() || region.is_some() {
+            params.source = atlas_source(region, source.or(params.source));
+        }
+
+        draw_texture_ex(current_frame_texture, x, y, color, params);
     }
-}
\ No newline at end of file
+}
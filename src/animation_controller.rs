@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use diff::Diff;
+use macroquad::{color::Color, texture::DrawTextureParams};
+use serde::{Deserialize, Serialize};
+
+use crate::{animation::AnimationInstance, animation_loader::AnimationLoader, texture_loader::TextureLoader, time::game_unix_millis};
+
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub enum TransitionCondition {
+    /// The current animation finished playing. Never true for looping animations
+    Finished,
+    /// A parameter set with set_parameter has this value
+    Parameter { name: String, value: bool }
+}
+
+/// A rule for switching between two states on its own
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct Transition {
+    pub from: Option<String>, // None matches any state
+    pub to: String,
+    pub condition: TransitionCondition,
+    pub crossfade: u64, // millis spent fading from the old animation to the new one, 0 cuts straight over
+    pub carry_frame: bool // start the new animation on the frame the old one was on
+}
+
+impl Transition {
+    pub fn new(from: Option<&str>, to: &str, condition: TransitionCondition) -> Self {
+        Self {
+            from: from.map(|from| from.to_string()),
+            to: to.to_string(),
+            condition,
+            crossfade: 0,
+            carry_frame: false,
+        }
+    }
+
+    pub fn with_crossfade(mut self, crossfade: u64) -> Self {
+        self.crossfade = crossfade;
+
+        self
+    }
+
+    pub fn with_carry_frame(mut self) -> Self {
+        self.carry_frame = true;
+
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+struct Crossfade {
+    from: String,
+    start_time: u64, // game_unix_millis
+    duration: u64
+}
+
+/// A state machine over named animations like "idle", "run" and "jump". Diffable, so the current state syncs like any other field.
+/// Only holds instances, the frames are looked up in the AnimationLoader passed to update and draw
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct AnimationController {
+    animations: HashMap<String, AnimationInstance>,
+    transitions: Vec<Transition>,
+    parameters: HashMap<String, bool>,
+    current: Option<String>,
+    crossfade: Option<Crossfade>
+}
+
+impl Default for AnimationController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnimationController {
+
+    pub fn new() -> Self {
+        Self {
+            animations: HashMap::new(),
+            transitions: vec![],
+            parameters: HashMap::new(),
+            current: None,
+            crossfade: None,
+        }
+    }
+
+    /// The first state added is the one the controller starts in
+    pub fn add_state(&mut self, name: &str, mut animation: AnimationInstance) {
+
+        if self.current.is_none() {
+            animation.start();
+
+            self.current = Some(name.to_string());
+        }
+
+        self.animations.insert(name.to_string(), animation);
+    }
+
+    /// Transitions are checked in the order they were added and the first one that matches wins
+    pub fn add_transition(&mut self, transition: Transition) {
+        self.transitions.push(transition);
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: bool) {
+        self.parameters.insert(name.to_string(), value);
+    }
+
+    pub fn parameter(&self, name: &str) -> bool {
+        self.parameters.get(name).copied().unwrap_or(false)
+    }
+
+    pub fn current_state(&self) -> Option<&String> {
+        self.current.as_ref()
+    }
+
+    pub fn current_animation(&self) -> Option<&AnimationInstance> {
+        self.animations.get(self.current.as_ref()?)
+    }
+
+    pub fn current_animation_mut(&mut self) -> Option<&mut AnimationInstance> {
+        self.animations.get_mut(self.current.as_ref()?)
+    }
+
+    /// Switch states right away. Uses the crossfade and frame carryover of a transition between the two states if there is one.
+    /// Returns an error if there is no state called `name`
+    pub fn set_state(&mut self, name: &str, animations: &mut AnimationLoader) -> Result<(), String> {
+
+        if self.current.as_deref() == Some(name) {
+            return Ok(());
+        }
+
+        let transition = self.transitions.iter()
+            .find(|transition| {
+                transition.to == name && (transition.from.is_none() || transition.from == self.current)
+            })
+            .cloned()
+            .unwrap_or(Transition::new(None, name, TransitionCondition::Finished));
+
+        self.transition(&transition, animations)
+    }
+
+    fn transition(&mut self, transition: &Transition, animations: &mut AnimationLoader) -> Result<(), String> {
+
+        if !self.animations.contains_key(&transition.to) {
+            return Err(format!("no animation state named {}", transition.to));
+        }
+
+        let carried_frame = match (transition.carry_frame, self.current_animation()) {
+            (true, Some(animation)) => Some(animation.current_frame(animations.get(&animation.animation_path))),
+            _ => None,
+        };
+
+        self.crossfade = match (&self.current, transition.crossfade) {
+            (Some(current), crossfade) if crossfade > 0 => Some(
+                Crossfade { from: current.clone(), start_time: game_unix_millis(), duration: crossfade }
+            ),
+            _ => None,
+        };
+
+        let animation = self.animations.get_mut(&transition.to).unwrap();
+
+        match carried_frame {
+            Some(frame) => {
+                let animation_path = animation.animation_path.clone();
+
+                animation.start_at_frame(frame, animations.get(&animation_path))
+            },
+            None => animation.start(),
+        }
+
+        self.current = Some(transition.to.clone());
+
+        Ok(())
+    }
+
+    fn condition_met(&self, condition: &TransitionCondition, animations: &mut AnimationLoader) -> bool {
+        match condition {
+            TransitionCondition::Finished => self.current_animation().is_some_and(|animation| {
+                animation.finished(animations.get(&animation.animation_path))
+            }),
+            TransitionCondition::Parameter { name, value } => self.parameter(name) == *value,
+        }
+    }
+
+    /// Take any transition whose condition is met. Call once per frame
+    pub fn update(&mut self, animations: &mut AnimationLoader) {
+
+        let transition = self.transitions.iter()
+            .find(|transition| {
+                Some(&transition.to) != self.current.as_ref()
+                    && self.animations.contains_key(&transition.to) // transitions to states that were never added are ignored
+                    && (transition.from.is_none() || transition.from == self.current)
+                    && self.condition_met(&transition.condition, animations)
+            })
+            .cloned();
+
+        if let Some(transition) = transition {
+            let _ = self.transition(&transition, animations);
+        }
+
+        if let Some(crossfade) = &self.crossfade {
+            if game_unix_millis().saturating_sub(crossfade.start_time) >= crossfade.duration {
+                self.crossfade = None;
+            }
+        }
+    }
+
+    /// Draw the current animation, faded over the previous one while a crossfade is running
+    pub async fn draw(&self, x: f32, y: f32, animations: &mut AnimationLoader, textures: &mut TextureLoader, params: DrawTextureParams) {
+
+        let current = match self.current.clone() {
+            Some(current) => current,
+            None => return,
+        };
+
+        let fade = match &self.crossfade {
+            Some(crossfade) => {
+                let progress = game_unix_millis().saturating_sub(crossfade.start_time) as f32 / crossfade.duration.max(1) as f32;
+
+                Some((crossfade.from.clone(), progress.clamp(0., 1.)))
+            },
+            None => None,
+        };
+
+        if let Some((from, progress)) = &fade {
+            if let Some(animation) = self.animations.get(from) {
+                let animation_data = animations.get(&animation.animation_path);
+
+                animation.draw_tinted(animation_data, x, y, textures, params.clone(), Color::new(1., 1., 1., 1. - progress)).await;
+            }
+        }
+
+        let alpha = match fade {
+            Some((_, progress)) => progress,
+            None => 1.,
+        };
+
+        let animation = self.animations.get(&current).unwrap();
+
+        animation.draw_tinted(animations.get(&animation.animation_path), x, y, textures, params, Color::new(1., 1., 1., alpha)).await;
+    }
+}
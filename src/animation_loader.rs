@@ -1,27 +1,82 @@
 use fxhash::FxHashMap;
 
-use crate::animation::Animation;
+use crate::animation::{AnimationData, AnimationInstance};
 
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+use crate::hot_reload::FileWatcher;
+
+/// Caches the frames and timing of each animation. Entities each get their own AnimationInstance so they don't share playback
 pub struct AnimationLoader {
-    pub cache: FxHashMap<String, Animation>
+    pub cache: FxHashMap<String, AnimationData>,
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    pub watcher: FileWatcher // watches animation_meta.json, the frames themselves are reloaded by TextureLoader
 }
 
 impl AnimationLoader {
     pub fn new() -> Self {
         AnimationLoader {
             cache: FxHashMap::default(),
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            watcher: FileWatcher::new(),
         }
     }
 
-    pub fn get(&mut self, animation_path: &String) -> &mut Animation {
+    pub fn get(&mut self, animation_path: &String) -> &AnimationData {
         
         if !self.cache.contains_key(animation_path) {
-            let animation = Animation::new_from_directory(animation_path);
+            let animation = AnimationData::new_from_directory(animation_path);
 
             self.cache.insert(animation_path.clone(), animation);
+
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            self.watcher.watch(&format!("{}/animation_meta.json", animation_path));
         };
 
+        self.cache.get(animation_path).unwrap()
+
+    }
+
+    /// For changing a cached animation in place, like adding events. Every instance of it sees the change
+    pub fn get_mut(&mut self, animation_path: &String) -> &mut AnimationData {
+        self.get(animation_path);
+
         self.cache.get_mut(animation_path).unwrap()
+    }
+
+    /// Like get, but loads through macroquad's file loading so it also works in the browser
+    pub async fn load(&mut self, animation_path: &String) -> Result<&AnimationData, String> {
+
+        if !self.cache.contains_key(animation_path) {
+            let animation = AnimationData::load(animation_path).await?;
+
+            self.cache.insert(animation_path.clone(), animation);
+
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            self.watcher.watch(&format!("{}/animation_meta.json", animation_path));
+        };
 
+        Ok(self.cache.get(animation_path).unwrap())
     }
-}
\ No newline at end of file
+
+    /// A new stopped instance of the animation, with its own playback state
+    pub fn instance(&mut self, animation_path: &String) -> AnimationInstance {
+        AnimationInstance::new(animation_path, self.get(animation_path))
+    }
+
+    /// Reload every cached animation whose animation_meta.json changed on disk. Instances pick up the new frames on their next draw
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    pub async fn hot_reload(&mut self) {
+        for meta_path in self.watcher.changed() {
+            let animation_path = meta_path.trim_end_matches("/animation_meta.json").to_string();
+
+            match AnimationData::load(&animation_path).await {
+                Ok(animation) => {
+                    log_info!("reloaded {}", animation_path);
+
+                    self.cache.insert(animation_path, animation);
+                },
+                Err(error) => log_warn!("failed to reload {}: {}", animation_path, error),
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use macroquad::{file::load_string, window::next_frame};
+use serde::{Deserialize, Serialize};
+
+use crate::{animation_loader::AnimationLoader, font_loader::FontLoader, texture_loader::TextureLoader};
+
+/// Everything a game wants loaded before it starts. Paths are the same ones passed to the loaders
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct AssetManifest {
+    #[serde(default)]
+    pub textures: Vec<String>,
+    #[serde(default)]
+    pub fonts: Vec<String>,
+    #[serde(default)]
+    pub animations: Vec<String> // animation directories. Their frame textures are loaded along with them
+}
+
+impl AssetManifest {
+    pub fn total(&self) -> usize {
+        self.textures.len() + self.fonts.len() + self.animations.len()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PreloadProgress {
+    pub loaded: usize,
+    pub total: usize,
+    pub current: Option<String> // the asset being loaded right now
+}
+
+impl PreloadProgress {
+
+    /// From 0 to 1, for a loading bar
+    pub fn fraction(&self) -> f32 {
+        match self.total {
+            0 => 1.,
+            total => self.loaded as f32 / total as f32
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.loaded >= self.total
+    }
+}
+
+/// The texture, font and animation loaders in one place, so everything can be loaded up front instead of the first time it's drawn
+pub struct Assets {
+    pub textures: TextureLoader,
+    pub fonts: FontLoader,
+    pub animations: AnimationLoader,
+    progress: PreloadProgress
+}
+
+impl Default for Assets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Assets {
+
+    pub fn new() -> Self {
+        Self {
+            textures: TextureLoader::new(),
+            fonts: FontLoader::new(),
+            animations: AnimationLoader::new(),
+            progress: PreloadProgress::default(),
+        }
+    }
+
+    pub fn progress(&self) -> &PreloadProgress {
+        &self.progress
+    }
+
+    /// Load every asset listed in a JSON AssetManifest. `on_progress` is called before each asset and once more at the end,
+    /// with a frame in between each one so it can draw a loading screen
+    pub async fn preload(&mut self, manifest_path: &str, on_progress: impl FnMut(&PreloadProgress)) -> Result<(), String> {
+        let manifest_json = load_string(manifest_path).await.map_err(|error| format!("failed to load {}: {}", manifest_path, error))?;
+
+        let manifest: AssetManifest = serde_json::from_str(&manifest_json).map_err(|error| format!("invalid {}: {}", manifest_path, error))?;
+
+        self.preload_manifest(&manifest, on_progress).await
+    }
+
+    pub async fn preload_manifest(&mut self, manifest: &AssetManifest, mut on_progress: impl FnMut(&PreloadProgress)) -> Result<(), String> {
+        self.progress = PreloadProgress {
+            loaded: 0,
+            total: manifest.total(),
+            current: None,
+        };
+
+        for texture_path in &manifest.textures {
+            self.start_item(texture_path, &mut on_progress).await;
+
+            self.textures.get(texture_path).await;
+
+            self.progress.loaded += 1;
+        }
+
+        for font_path in &manifest.fonts {
+            self.start_item(font_path, &mut on_progress).await;
+
+            self.fonts.get(font_path).await;
+
+            self.progress.loaded += 1;
+        }
+
+        for animation_path in &manifest.animations {
+            self.start_item(animation_path, &mut on_progress).await;
+
+            let texture_paths: Vec<String> = self.animations.load(animation_path).await?
+                .frames
+                .texture_paths()
+                .into_iter()
+                .cloned()
+                .collect();
+
+            for texture_path in &texture_paths {
+                self.textures.get(texture_path).await;
+            }
+
+            self.progress.loaded += 1;
+        }
+
+        self.progress.current = None;
+
+        on_progress(&self.progress);
+
+        Ok(())
+    }
+
+    async fn start_item(&mut self, path: &String, on_progress: &mut impl FnMut(&PreloadProgress)) {
+        self.progress.current = Some(path.clone());
+
+        on_progress(&self.progress);
+
+        // give the game a frame to draw the loading screen
+        next_frame().await;
+    }
+}
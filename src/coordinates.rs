@@ -0,0 +1,52 @@
+use macroquad::{math::{Rect, Vec2}, texture::RenderTarget, window::{screen_height, screen_width}};
+
+use crate::mouse_world_pos;
+
+/// Converts between macroquad's y down coordinates and rapier's y up coordinates for a viewport of a fixed size.
+/// Use the size of whatever is being drawn to, which is only the window when drawing straight to the screen
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CoordinateSystem {
+    pub width: f32,
+    pub height: f32
+}
+
+impl CoordinateSystem {
+
+    pub fn new(width: f32, height: f32) -> Self {
+        Self {
+            width,
+            height,
+        }
+    }
+
+    /// The window as it is right now
+    pub fn screen() -> Self {
+        Self::new(screen_width(), screen_height())
+    }
+
+    pub fn render_target(render_target: &RenderTarget) -> Self {
+        let size = render_target.texture.size();
+
+        Self::new(size.x, size.y)
+    }
+
+    pub fn macroquad_to_rapier(&self, macroquad_coords: &Vec2) -> Vec2 {
+        Vec2 {
+            x: macroquad_coords.x,
+            y: (macroquad_coords.y * -1.) + self.height
+        }
+    }
+
+    pub fn rapier_to_macroquad(&self, rapier_coords: &Vec2) -> Vec2 {
+        Vec2 {
+            x: rapier_coords.x,
+            y: (rapier_coords.y * -1.) + self.height
+        }
+    }
+
+    pub fn rapier_mouse_world_pos(&self, camera_rect: &Rect) -> Vec2 {
+        self.macroquad_to_rapier(
+            &mouse_world_pos(camera_rect)
+        )
+    }
+}
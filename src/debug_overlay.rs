@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+
+use macroquad::{color::{Color, GREEN, RED, WHITE, YELLOW}, input::{is_key_pressed, KeyCode}, shapes::draw_rectangle, text::{draw_text_ex, TextParams}, time::{get_fps, get_frame_time}};
+
+use crate::{font_loader::FontLoader, space::stats::SpaceStats, sync::metrics::SyncMetrics};
+
+/// How many frames the frame time graph shows
+const FRAME_HISTORY: usize = 120;
+
+/// FPS, a frame time graph, physics stats and network stats drawn over the game. Toggled with toggle_key
+pub struct DebugOverlay {
+    pub visible: bool,
+    pub toggle_key: Option<KeyCode>,
+    pub font: Option<String>, // None uses macroquad's default font
+    pub font_size: u16,
+    pub x: f32,
+    pub y: f32,
+    pub background: Color,
+    frame_times: VecDeque<f32> // seconds
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugOverlay {
+
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            toggle_key: Some(KeyCode::F3),
+            font: None,
+            font_size: 16,
+            x: 10.,
+            y: 10.,
+            background: Color::new(0., 0., 0., 0.6),
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+        }
+    }
+
+    pub fn with_font(mut self, font: &str) -> Self {
+        self.font = Some(font.to_string());
+
+        self
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Record this frame's time and check the toggle key. Call once per frame, even while hidden, so the graph is full when it's opened
+    pub fn update(&mut self) {
+        if self.toggle_key.is_some_and(|toggle_key| is_key_pressed(toggle_key)) {
+            self.toggle();
+        }
+
+        if self.frame_times.len() == FRAME_HISTORY {
+            self.frame_times.pop_front();
+        }
+
+        self.frame_times.push_back(get_frame_time());
+    }
+
+    fn lines(&self, space_stats: Option<SpaceStats>, sync_metrics: Option<SyncMetrics>) -> Vec<String> {
+        let average_frame_time = self.frame_times.iter().sum::<f32>() / self.frame_times.len().max(1) as f32;
+
+        let worst_frame_time = self.frame_times.iter().copied().fold(0., f32::max);
+
+        let mut lines = vec![
+            format!("fps: {}", get_fps()),
+            format!("frame: {:.2}ms avg, {:.2}ms worst", average_frame_time * 1000., worst_frame_time * 1000.),
+        ];
+
+        if let Some(space_stats) = space_stats {
+            lines.push(format!(
+                "bodies: {} ({} active), colliders: {}, islands: {}", 
+                space_stats.body_count, space_stats.active_body_count, space_stats.collider_count, space_stats.island_count
+            ));
+            lines.push(format!(
+                "contact pairs: {}, step: {:.2}ms", 
+                space_stats.contact_pair_count, space_stats.last_step_duration.as_secs_f32() * 1000.
+            ));
+        }
+
+        if let Some(sync_metrics) = sync_metrics {
+            lines.push(format!(
+                "up: {:.1} KB/s, down: {:.1} KB/s", 
+                sync_metrics.bytes_sent_per_second / 1024., sync_metrics.bytes_received_per_second / 1024.
+            ));
+            lines.push(format!(
+                "last diff: {} B raw, {} B compressed", 
+                sync_metrics.last_diff_raw_size, sync_metrics.last_diff_compressed_size
+            ));
+
+            match sync_metrics.round_trip_time {
+                Some(round_trip_time) => lines.push(format!("rtt: {}ms", round_trip_time.as_millis())),
+                None => lines.push("rtt: -".to_string()),
+            }
+        }
+
+        lines
+    }
+
+    /// Stats that aren't available, like network stats in single player, are left out
+    pub fn draw(&self, fonts: &mut FontLoader, space_stats: Option<SpaceStats>, sync_metrics: Option<SyncMetrics>) {
+        if !self.visible {
+            return;
+        }
+
+        let lines = self.lines(space_stats, sync_metrics);
+
+        let font = match &self.font {
+            Some(font_path) => fonts.get_blocking(font_path),
+            None => None,
+        };
+
+        let line_height = self.font_size as f32 * 1.25;
+        let graph_height = 40.;
+        let width = 360.;
+
+        let height = line_height * lines.len() as f32 + graph_height + 16.;
+
+        draw_rectangle(self.x, self.y, width, height, self.background);
+
+        for (index, line) in lines.iter().enumerate() {
+            draw_text_ex(
+                line, 
+                self.x + 6., 
+                self.y + line_height * (index + 1) as f32, 
+                TextParams { font, font_size: self.font_size, color: WHITE, ..Default::default() }
+            );
+        }
+
+        // frame time graph, full height is 33ms (30 fps)
+        let graph_top = self.y + line_height * lines.len() as f32 + 8.;
+        let bar_width = (width - 12.) / FRAME_HISTORY as f32;
+
+        for (index, frame_time) in self.frame_times.iter().enumerate() {
+            let bar_height = (frame_time / (1. / 30.)).min(1.) * graph_height;
+
+            let color = match *frame_time {
+                frame_time if frame_time > 1. / 30. => RED,
+                frame_time if frame_time > 1. / 60. + 0.001 => YELLOW,
+                _ => GREEN,
+            };
+
+            draw_rectangle(
+                self.x + 6. + index as f32 * bar_width, 
+                graph_top + graph_height - bar_height, 
+                bar_width.max(1.), 
+                bar_height, 
+                color
+            );
+        }
+    }
+}
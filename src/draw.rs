@@ -0,0 +1,204 @@
+use std::time::Duration;
+
+use macroquad::{color::Color, math::{vec2, Vec2}, shapes::{draw_circle, draw_circle_lines, draw_line, draw_rectangle_ex, draw_triangle, DrawRectangleParams}, text::{draw_text_ex, measure_text, Font, TextDimensions, TextParams}};
+
+use crate::{coordinates::CoordinateSystem, font_loader::FontLoader, time::{self, Instant}};
+
+pub fn draw_line_rapier(start: Vec2, end: Vec2, thickness: f32, color: Color, coordinates: &CoordinateSystem) {
+    let start = coordinates.rapier_to_macroquad(&start);
+    let end = coordinates.rapier_to_macroquad(&end);
+
+    draw_line(start.x, start.y, end.x, end.y, thickness, color);
+}
+
+pub fn draw_circle_rapier(center: Vec2, radius: f32, color: Color, coordinates: &CoordinateSystem) {
+    let center = coordinates.rapier_to_macroquad(&center);
+
+    draw_circle(center.x, center.y, radius, color);
+}
+
+pub fn draw_circle_lines_rapier(center: Vec2, radius: f32, thickness: f32, color: Color, coordinates: &CoordinateSystem) {
+    let center = coordinates.rapier_to_macroquad(&center);
+
+    draw_circle_lines(center.x, center.y, radius, thickness, color);
+}
+
+/// Draw a rectangle centered on `center`, the same way cuboid colliders are placed. `rotation` is in radians, counter clockwise like rapier
+pub fn draw_rectangle_rapier(center: Vec2, size: Vec2, rotation: f32, color: Color, coordinates: &CoordinateSystem) {
+    let center = coordinates.rapier_to_macroquad(&center);
+
+    draw_rectangle_ex(
+        center.x, 
+        center.y, 
+        size.x, 
+        size.y, 
+        DrawRectangleParams { offset: vec2(0.5, 0.5), rotation: rotation * -1., color }
+    );
+}
+
+pub fn draw_rectangle_lines_rapier(center: Vec2, size: Vec2, rotation: f32, thickness: f32, color: Color, coordinates: &CoordinateSystem) {
+    let half_size = size / 2.;
+
+    let corners: Vec<Vec2> = [vec2(-1., -1.), vec2(1., -1.), vec2(1., 1.), vec2(-1., 1.)].iter()
+        .map(|corner| center + Vec2::from_angle(rotation).rotate(*corner * half_size))
+        .collect();
+
+    draw_polygon_lines_rapier(&corners, thickness, color, coordinates);
+}
+
+/// Fill a convex polygon. Concave ones have to be split up first
+pub fn draw_polygon_rapier(points: &[Vec2], color: Color, coordinates: &CoordinateSystem) {
+    if points.len() < 3 {
+        return;
+    }
+
+    let points: Vec<Vec2> = points.iter().map(|point| coordinates.rapier_to_macroquad(point)).collect();
+
+    for index in 1..points.len() - 1 {
+        draw_triangle(points[0], points[index], points[index + 1], color);
+    }
+}
+
+/// Outline a polygon, closing it back to the first point
+pub fn draw_polygon_lines_rapier(points: &[Vec2], thickness: f32, color: Color, coordinates: &CoordinateSystem) {
+    for (index, start) in points.iter().enumerate() {
+        let end = points[(index + 1) % points.len()];
+
+        draw_line_rapier(*start, end, thickness, color, coordinates);
+    }
+}
+
+/// Draw text with the left end of its baseline at `position` in rapier coords. Draw with the world camera set, the same as any other world drawing
+pub fn draw_text_rapier(text: &str, position: Vec2, font: Option<&Font>, font_size: u16, color: Color, coordinates: &CoordinateSystem) -> TextDimensions {
+    let position = coordinates.rapier_to_macroquad(&position);
+
+    draw_text_ex(text, position.x, position.y, TextParams { font, font_size, color, ..Default::default() })
+}
+
+/// Draw text centered on `position` in rapier coords, for labels over entities
+pub fn draw_text_rapier_centered(text: &str, position: Vec2, font: Option<&Font>, font_size: u16, color: Color, coordinates: &CoordinateSystem) -> TextDimensions {
+    let dimensions = measure_text(text, font, font_size, 1.);
+
+    // offset_y is how far the baseline is below the top of the text. rapier y points up, so below is minus
+    let baseline = vec2(
+        position.x - dimensions.width / 2., 
+        position.y + dimensions.height / 2. - dimensions.offset_y
+    );
+
+    draw_text_rapier(text, baseline, font, font_size, color, coordinates)
+}
+
+/// Text that drifts upwards and fades out, like damage numbers
+#[derive(Clone, Debug)]
+pub struct FloatingText {
+    pub text: String,
+    pub position: Vec2, // rapier coords of the center
+    pub velocity: Vec2, // rapier units per second
+    pub color: Color,
+    pub font_size: u16,
+    pub spawned_at: Instant,
+    pub duration: Duration
+}
+
+impl FloatingText {
+
+    pub fn new(text: &str, position: Vec2, color: Color) -> Self {
+        Self {
+            text: text.to_string(),
+            position,
+            velocity: vec2(0., 40.),
+            color,
+            font_size: 24,
+            spawned_at: time::now(),
+            duration: Duration::from_millis(800),
+        }
+    }
+
+    pub fn with_velocity(mut self, velocity: Vec2) -> Self {
+        self.velocity = velocity;
+
+        self
+    }
+
+    pub fn with_font_size(mut self, font_size: u16) -> Self {
+        self.font_size = font_size;
+
+        self
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+
+        self
+    }
+
+    /// From 0 when spawned to 1 when it's gone
+    pub fn progress(&self) -> f32 {
+        (self.spawned_at.elapsed().as_secs_f32() / self.duration.as_secs_f32()).min(1.)
+    }
+
+    pub fn finished(&self) -> bool {
+        self.spawned_at.elapsed() >= self.duration
+    }
+
+    pub fn draw(&self, font: Option<&Font>, coordinates: &CoordinateSystem) {
+        let elapsed = self.spawned_at.elapsed().as_secs_f32();
+
+        // fully visible for the first half, then fades
+        let alpha = (2. - self.progress() * 2.).min(1.);
+
+        draw_text_rapier_centered(
+            &self.text, 
+            self.position + self.velocity * elapsed, 
+            font, 
+            self.font_size, 
+            Color::new(self.color.r, self.color.g, self.color.b, self.color.a * alpha), 
+            coordinates
+        );
+    }
+}
+
+/// Every live FloatingText, dropped once they have faded
+#[derive(Default)]
+pub struct FloatingTexts {
+    texts: Vec<FloatingText>,
+    pub font: Option<String> // font path for the FontLoader. None uses macroquad's default font
+}
+
+impl FloatingTexts {
+
+    pub fn new() -> Self {
+        Self {
+            texts: vec![],
+            font: None,
+        }
+    }
+
+    pub fn with_font(mut self, font_path: &str) -> Self {
+        self.font = Some(font_path.to_string());
+
+        self
+    }
+
+    pub fn spawn(&mut self, text: FloatingText) {
+        self.texts.push(text);
+    }
+
+    /// Shorthand for a damage number at `position`
+    pub fn spawn_number(&mut self, value: f32, position: Vec2, color: Color) {
+        self.spawn(FloatingText::new(&format!("{}", value.round() as i64), position, color));
+    }
+
+    pub fn draw(&mut self, fonts: &mut FontLoader, coordinates: &CoordinateSystem) {
+        self.texts.retain(|text| !text.finished());
+
+        let font = match &self.font {
+            Some(font_path) => fonts.get_blocking(font_path),
+            None => None,
+        };
+
+        for text in &self.texts {
+            text.draw(font, coordinates);
+        }
+    }
+}
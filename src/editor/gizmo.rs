@@ -0,0 +1,249 @@
+use std::f32::consts::PI;
+
+use macroquad::{color::{Color, ORANGE, WHITE}, input::{is_key_down, is_mouse_button_down, is_mouse_button_pressed, KeyCode, MouseButton}, math::{vec2, Rect, Vec2}, shapes::{draw_circle_lines, draw_rectangle_ex, DrawRectangleParams}};
+use nalgebra::{point, vector};
+use rapier2d::{math::{Isometry, Rotation}, prelude::RigidBodyHandle};
+
+use crate::{coordinates::CoordinateSystem, space::Space, traits::HasPhysics};
+
+use super::Snapping;
+
+/// A part of the gizmo that can be grabbed with the mouse
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GizmoHandle {
+    Resize { x: i8, y: i8 }, // which side of the cuboid, -1, 0 or 1 on each axis. Corners have both set, edges only one
+    Rotate
+}
+
+impl GizmoHandle {
+    pub const RESIZE: [GizmoHandle; 8] = [
+        GizmoHandle::Resize { x: -1, y: -1 },
+        GizmoHandle::Resize { x: 0, y: -1 },
+        GizmoHandle::Resize { x: 1, y: -1 },
+        GizmoHandle::Resize { x: 1, y: 0 },
+        GizmoHandle::Resize { x: 1, y: 1 },
+        GizmoHandle::Resize { x: 0, y: 1 },
+        GizmoHandle::Resize { x: -1, y: 1 },
+        GizmoHandle::Resize { x: -1, y: 0 },
+    ];
+}
+
+struct Grab {
+    handle: GizmoHandle,
+    rigid_body_handle: RigidBodyHandle,
+    start: Isometry<f32>, // body position when the handle was grabbed. Everything is worked out from here so nothing drifts
+    start_half_extents: Vec2,
+    start_angle: f32 // angle from the body to the mouse when the rotate ring was grabbed
+}
+
+/// Mouse driven resize handles and rotation ring for selected HasPhysics entities with cuboid colliders.
+/// One gizmo can serve every entity since only one handle is held at a time
+pub struct Gizmo {
+    grab: Option<Grab>,
+    pub handle_size: f32,
+    pub ring_offset: f32, // how far the rotation ring sits outside the corners
+    pub ring_thickness: f32,
+    pub rotation_snap: f32 // radians, used while shift is held
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gizmo {
+
+    pub fn new() -> Self {
+        Self {
+            grab: None,
+            handle_size: 8.,
+            ring_offset: 20.,
+            ring_thickness: 6.,
+            rotation_snap: PI / 12.,
+        }
+    }
+
+    /// A handle is currently held, so dragging and selecting should be skipped this frame
+    pub fn active(&self) -> bool {
+        self.grab.is_some()
+    }
+
+    fn cuboid(space: &Space, entity: &dyn HasPhysics) -> Option<(Isometry<f32>, Vec2)> {
+        let rigid_body = space.rigid_body_set.get(*entity.rigid_body_handle())?;
+        let collider = space.collider_set.get(*entity.collider_handle())?;
+
+        let cuboid = collider.shape().as_cuboid()?;
+
+        Some((*rigid_body.position(), vec2(cuboid.half_extents.x, cuboid.half_extents.y)))
+    }
+
+    fn ring_radius(&self, half_extents: Vec2) -> f32 {
+        half_extents.length() + self.ring_offset
+    }
+
+    /// Where a resize handle sits in rapier coords
+    pub fn handle_position(position: &Isometry<f32>, half_extents: Vec2, x: i8, y: i8) -> Vec2 {
+        let world = position * point![x as f32 * half_extents.x, y as f32 * half_extents.y];
+
+        vec2(world.x, world.y)
+    }
+
+    /// The handle under `point`. Resize handles win over the ring
+    pub fn handle_at(&self, space: &Space, entity: &dyn HasPhysics, point: Vec2) -> Option<GizmoHandle> {
+        let (position, half_extents) = Self::cuboid(space, entity)?;
+
+        for handle in GizmoHandle::RESIZE {
+            if let GizmoHandle::Resize { x, y } = handle {
+                let handle_position = Self::handle_position(&position, half_extents, x, y);
+
+                if handle_position.distance(point) <= self.handle_size {
+                    return Some(handle);
+                }
+            }
+        }
+
+        let center = vec2(position.translation.x, position.translation.y);
+
+        if (center.distance(point) - self.ring_radius(half_extents)).abs() <= self.ring_thickness {
+            return Some(GizmoHandle::Rotate);
+        }
+
+        None
+    }
+
+    /// Grab, move and release handles on a selected entity. Call before update_selected and update_drag, and skip those while active() is true
+    pub fn update(&mut self, space: &mut Space, entity: &mut dyn HasPhysics, camera_rect: &Rect, coordinates: &CoordinateSystem, snapping: &Snapping) {
+        
+        if !is_mouse_button_down(MouseButton::Left) {
+            self.grab = None;
+
+            return;
+        }
+
+        let mouse_pos = coordinates.rapier_mouse_world_pos(camera_rect);
+
+        if is_mouse_button_pressed(MouseButton::Left) && self.grab.is_none() && *entity.selected() {
+            let handle = match self.handle_at(space, entity, mouse_pos) {
+                Some(handle) => handle,
+                None => return,
+            };
+
+            let (start, start_half_extents) = match Self::cuboid(space, entity) {
+                Some(cuboid) => cuboid,
+                None => return,
+            };
+
+            self.grab = Some(Grab {
+                handle,
+                rigid_body_handle: *entity.rigid_body_handle(),
+                start,
+                start_half_extents,
+                start_angle: (mouse_pos.y - start.translation.y).atan2(mouse_pos.x - start.translation.x),
+            });
+
+            *entity.dragging() = false;
+        }
+
+        let grab = match &self.grab {
+            Some(grab) if grab.rigid_body_handle == *entity.rigid_body_handle() => grab,
+            _ => return,
+        };
+
+        match grab.handle {
+            GizmoHandle::Resize { x, y } => {
+                // work in the body's local space as it was when grabbed, with the opposite side pinned in place
+                let local = grab.start.inverse_transform_point(&point![mouse_pos.x, mouse_pos.y]);
+
+                let min_extent = snapping.grid.unwrap_or(1.) / 2.;
+
+                let mut half_extents = grab.start_half_extents;
+                let mut center = Vec2::ZERO;
+
+                if x != 0 {
+                    let anchor = -(x as f32) * grab.start_half_extents.x;
+
+                    half_extents.x = snapping.snap((local.x - anchor).abs() / 2.).max(min_extent);
+                    center.x = anchor + x as f32 * half_extents.x;
+                }
+
+                if y != 0 {
+                    let anchor = -(y as f32) * grab.start_half_extents.y;
+
+                    half_extents.y = snapping.snap((local.y - anchor).abs() / 2.).max(min_extent);
+                    center.y = anchor + y as f32 * half_extents.y;
+                }
+
+                let center = grab.start * point![center.x, center.y];
+
+                if let Some(collider) = space.collider_set.get_mut(*entity.collider_handle()) {
+                    if let Some(cuboid) = collider.shape_mut().as_cuboid_mut() {
+                        cuboid.half_extents = vector![half_extents.x, half_extents.y];
+                    }
+                }
+
+                if let Some(rigid_body) = space.rigid_body_set.get_mut(grab.rigid_body_handle) {
+                    rigid_body.set_position(Isometry::new(vector![center.x, center.y], grab.start.rotation.angle()), true);
+                }
+            },
+
+            GizmoHandle::Rotate => {
+                let angle = (mouse_pos.y - grab.start.translation.y).atan2(mouse_pos.x - grab.start.translation.x);
+
+                let mut rotation = grab.start.rotation.angle() + angle - grab.start_angle;
+
+                if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {
+                    rotation = (rotation / self.rotation_snap).round() * self.rotation_snap;
+                }
+
+                if let Some(rigid_body) = space.rigid_body_set.get_mut(grab.rigid_body_handle) {
+                    rigid_body.set_rotation(Rotation::new(rotation), true);
+                }
+            },
+        }
+    }
+
+    /// Draw the handles and ring over a selected entity. Call after draw_collider
+    pub fn draw(&self, space: &Space, entity: &dyn HasPhysics, coordinates: &CoordinateSystem) {
+        if !*entity.selected() {
+            return;
+        }
+
+        let (position, half_extents) = match Self::cuboid(space, entity) {
+            Some(cuboid) => cuboid,
+            None => return,
+        };
+
+        let held = self.grab.as_ref()
+            .filter(|grab| grab.rigid_body_handle == *entity.rigid_body_handle())
+            .map(|grab| grab.handle);
+
+        let center = coordinates.rapier_to_macroquad(&vec2(position.translation.x, position.translation.y));
+
+        let ring_color = match held {
+            Some(GizmoHandle::Rotate) => ORANGE,
+            _ => Color::new(1., 0.63, 0., 0.5),
+        };
+
+        draw_circle_lines(center.x, center.y, self.ring_radius(half_extents), 2., ring_color);
+
+        for handle in GizmoHandle::RESIZE {
+            if let GizmoHandle::Resize { x, y } = handle {
+                let handle_position = coordinates.rapier_to_macroquad(&Self::handle_position(&position, half_extents, x, y));
+
+                let color = match held == Some(handle) {
+                    true => WHITE,
+                    false => ORANGE,
+                };
+
+                draw_rectangle_ex(
+                    handle_position.x, 
+                    handle_position.y, 
+                    self.handle_size, 
+                    self.handle_size, 
+                    DrawRectangleParams { offset: vec2(0.5, 0.5), rotation: position.rotation.angle() * -1., color }
+                );
+            }
+        }
+    }
+}
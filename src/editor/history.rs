@@ -0,0 +1,314 @@
+use std::collections::VecDeque;
+
+use fxhash::FxHashMap;
+use macroquad::input::{is_key_down, is_key_pressed, is_mouse_button_down, KeyCode, MouseButton};
+use rapier2d::{dynamics::RigidBody, geometry::{Collider, ColliderHandle}, prelude::RigidBodyHandle};
+
+use crate::{space::Space, traits::HasPhysics};
+
+use super::BodyTransform;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransformKind {
+    Move,
+    Resize,
+    Rotate
+}
+
+/// A body and its colliders as they were right before being removed, so they can be put back
+#[derive(Clone)]
+pub struct BodySnapshot {
+    rigid_body: RigidBody,
+    colliders: Vec<(ColliderHandle, Collider)>
+}
+
+impl BodySnapshot {
+
+    fn take(space: &Space, rigid_body_handle: RigidBodyHandle) -> Option<Self> {
+        let rigid_body = space.rigid_body_set.get(rigid_body_handle)?;
+
+        let colliders = rigid_body.colliders().iter()
+            .filter_map(|collider_handle| Some((*collider_handle, space.collider_set.get(*collider_handle)?.clone())))
+            .collect();
+
+        Some(Self {
+            rigid_body: rigid_body.clone(),
+            colliders,
+        })
+    }
+
+    /// Insert the body again. rapier hands out new handles, so the old ones are returned mapped to the new ones
+    fn restore(&self, space: &mut Space, old_rigid_body_handle: RigidBodyHandle) -> HandleRemap {
+        let new_rigid_body_handle = space.rigid_body_set.insert(self.rigid_body.clone());
+
+        let colliders = self.colliders.iter()
+            .map(|(old_collider_handle, collider)| {
+                let new_collider_handle = space.collider_set.insert_with_parent(collider.clone(), new_rigid_body_handle, &mut space.rigid_body_set);
+
+                (*old_collider_handle, new_collider_handle)
+            })
+            .collect();
+
+        HandleRemap {
+            old_rigid_body_handle,
+            new_rigid_body_handle,
+            colliders,
+        }
+    }
+}
+
+fn remove_body(space: &mut Space, rigid_body_handle: RigidBodyHandle) {
+    space.rigid_body_set.remove(
+        rigid_body_handle, 
+        &mut space.island_manager, 
+        &mut space.collider_set, 
+        &mut space.impulse_joint_set, 
+        &mut space.multibody_joint_set, 
+        true
+    );
+}
+
+/// Undoing a delete or redoing a create brings a body back under new handles. Entities holding the old handles need to swap them out
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandleRemap {
+    pub old_rigid_body_handle: RigidBodyHandle,
+    pub new_rigid_body_handle: RigidBodyHandle,
+    pub colliders: Vec<(ColliderHandle, ColliderHandle)> // old, new
+}
+
+#[derive(Clone)]
+pub enum EditorOperation {
+    Transform { 
+        kind: TransformKind, 
+        rigid_body_handle: RigidBodyHandle, 
+        collider_handle: ColliderHandle, 
+        before: BodyTransform, 
+        after: BodyTransform 
+    },
+    Delete { rigid_body_handle: RigidBodyHandle, snapshot: BodySnapshot },
+    Create { rigid_body_handle: RigidBodyHandle, snapshot: Option<BodySnapshot> } // the snapshot is taken when the create is undone
+}
+
+impl EditorOperation {
+
+    fn remap(&mut self, remap: &HandleRemap) {
+        let (rigid_body_handle, collider_handle) = match self {
+            EditorOperation::Transform { rigid_body_handle, collider_handle, .. } => (rigid_body_handle, Some(collider_handle)),
+            EditorOperation::Delete { rigid_body_handle, .. } => (rigid_body_handle, None),
+            EditorOperation::Create { rigid_body_handle, .. } => (rigid_body_handle, None),
+        };
+
+        if *rigid_body_handle == remap.old_rigid_body_handle {
+            *rigid_body_handle = remap.new_rigid_body_handle;
+        }
+
+        if let Some(collider_handle) = collider_handle {
+            if let Some((_, new_collider_handle)) = remap.colliders.iter().find(|(old, _)| old == collider_handle) {
+                *collider_handle = *new_collider_handle;
+            }
+        }
+    }
+}
+
+/// Undo and redo for the HasPhysics editor tools.
+/// Moves, resizes and rotates are picked up by track. Deletes and creates go through delete and record_create
+pub struct EditorHistory {
+    undo_stack: VecDeque<EditorOperation>,
+    redo_stack: Vec<EditorOperation>,
+    pub limit: usize, // oldest operations are forgotten past this many
+    rest_transforms: FxHashMap<RigidBodyHandle, BodyTransform>, // where each tracked body was when its last edit ended
+    editing: FxHashMap<RigidBodyHandle, bool> // whether an edit was in progress last frame
+}
+
+impl Default for EditorHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EditorHistory {
+
+    pub fn new() -> Self {
+        Self {
+            undo_stack: VecDeque::new(),
+            redo_stack: vec![],
+            limit: 100,
+            rest_transforms: FxHashMap::default(),
+            editing: FxHashMap::default(),
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn push(&mut self, operation: EditorOperation) {
+        self.undo_stack.push_back(operation);
+
+        while self.undo_stack.len() > self.limit {
+            self.undo_stack.pop_front();
+        }
+
+        // a new edit branches off, what was undone before can't come back
+        self.redo_stack.clear();
+    }
+
+    /// Watch an entity for moves, resizes and rotates made with the HasPhysics editor tools. Call every frame after them.
+    /// An edit is recorded as one operation when the mouse and editor keys are let go, so a whole drag undoes at once
+    pub fn track(&mut self, entity: &impl HasPhysics, space: &Space) {
+        let rigid_body_handle = *entity.rigid_body_handle();
+        let collider_handle = *entity.collider_handle();
+
+        let current = match BodyTransform::capture(space, rigid_body_handle, collider_handle) {
+            Some(current) => current,
+            None => return,
+        };
+
+        // dragging, resizing and rotating are all done with the left mouse button
+        let editing = *entity.selected() && is_mouse_button_down(MouseButton::Left);
+
+        let was_editing = self.editing.insert(rigid_body_handle, editing).unwrap_or(false);
+
+        let rest = *self.rest_transforms.entry(rigid_body_handle).or_insert(current);
+
+        // bodies moved by the simulation rather than the editor just move the rest position along
+        if editing {
+            return;
+        }
+
+        if was_editing && rest != current {
+            let kind = match (rest.half_extents != current.half_extents, rest.rotation != current.rotation) {
+                (true, _) => TransformKind::Resize,
+                (false, true) => TransformKind::Rotate,
+                (false, false) => TransformKind::Move,
+            };
+
+            self.push(EditorOperation::Transform { kind, rigid_body_handle, collider_handle, before: rest, after: current });
+        }
+
+        self.rest_transforms.insert(rigid_body_handle, current);
+    }
+
+    /// Remove a body and its colliders so the removal can be undone
+    pub fn delete(&mut self, space: &mut Space, rigid_body_handle: RigidBodyHandle) {
+        let snapshot = match BodySnapshot::take(space, rigid_body_handle) {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+
+        remove_body(space, rigid_body_handle);
+
+        self.rest_transforms.remove(&rigid_body_handle);
+        self.editing.remove(&rigid_body_handle);
+
+        self.push(EditorOperation::Delete { rigid_body_handle, snapshot });
+    }
+
+    /// Record a body that was just added so adding it can be undone
+    pub fn record_create(&mut self, rigid_body_handle: RigidBodyHandle) {
+        self.push(EditorOperation::Create { rigid_body_handle, snapshot: None });
+    }
+
+    fn remap(&mut self, remap: &HandleRemap) {
+        for operation in self.undo_stack.iter_mut().chain(self.redo_stack.iter_mut()) {
+            operation.remap(remap);
+        }
+
+        if let Some(rest) = self.rest_transforms.remove(&remap.old_rigid_body_handle) {
+            self.rest_transforms.insert(remap.new_rigid_body_handle, rest);
+        }
+    }
+
+    /// Revert the latest operation. Returns the handles that changed if a body was brought back
+    pub fn undo(&mut self, space: &mut Space) -> Vec<HandleRemap> {
+        let mut operation = match self.undo_stack.pop_back() {
+            Some(operation) => operation,
+            None => return vec![],
+        };
+
+        let mut remaps = vec![];
+
+        match &mut operation {
+            EditorOperation::Transform { rigid_body_handle, collider_handle, before, .. } => {
+                before.apply(space, *rigid_body_handle, *collider_handle);
+
+                self.rest_transforms.insert(*rigid_body_handle, *before);
+            },
+            EditorOperation::Delete { rigid_body_handle, snapshot } => {
+                remaps.push(snapshot.restore(space, *rigid_body_handle));
+            },
+            EditorOperation::Create { rigid_body_handle, snapshot } => {
+                *snapshot = BodySnapshot::take(space, *rigid_body_handle);
+
+                remove_body(space, *rigid_body_handle);
+            },
+        }
+
+        self.redo_stack.push(operation);
+
+        for remap in &remaps {
+            self.remap(remap);
+        }
+
+        remaps
+    }
+
+    /// Apply the latest undone operation again. Returns the handles that changed if a body was brought back
+    pub fn redo(&mut self, space: &mut Space) -> Vec<HandleRemap> {
+        let operation = match self.redo_stack.pop() {
+            Some(operation) => operation,
+            None => return vec![],
+        };
+
+        let mut remaps = vec![];
+
+        match &operation {
+            EditorOperation::Transform { rigid_body_handle, collider_handle, after, .. } => {
+                after.apply(space, *rigid_body_handle, *collider_handle);
+
+                self.rest_transforms.insert(*rigid_body_handle, *after);
+            },
+            EditorOperation::Delete { rigid_body_handle, .. } => {
+                remove_body(space, *rigid_body_handle);
+            },
+            EditorOperation::Create { rigid_body_handle, snapshot } => {
+                if let Some(snapshot) = snapshot {
+                    remaps.push(snapshot.restore(space, *rigid_body_handle));
+                }
+            },
+        }
+
+        // push directly, push would clear the rest of the redo stack
+        self.undo_stack.push_back(operation);
+
+        for remap in &remaps {
+            self.remap(remap);
+        }
+
+        remaps
+    }
+
+    /// Ctrl+Z to undo, Ctrl+Y or Ctrl+Shift+Z to redo. Call once per frame
+    pub fn update_bindings(&mut self, space: &mut Space) -> Vec<HandleRemap> {
+        let control = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+
+        if !control {
+            return vec![];
+        }
+
+        if is_key_pressed(KeyCode::Y) || (shift && is_key_pressed(KeyCode::Z)) {
+            return self.redo(space);
+        }
+
+        if is_key_pressed(KeyCode::Z) {
+            return self.undo(space);
+        }
+
+        vec![]
+    }
+}
@@ -0,0 +1,87 @@
+use macroquad::math::{vec2, Vec2};
+use nalgebra::vector;
+use rapier2d::{geometry::ColliderHandle, math::Isometry, prelude::RigidBodyHandle};
+
+use crate::space::Space;
+
+pub mod gizmo;
+pub mod history;
+pub mod selection;
+
+/// Grid snapping and axis locking for the HasPhysics drag and resize tools
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Snapping {
+    pub grid: Option<f32>, // positions and half extents land on multiples of this
+    pub axis_lock: bool // hold X or Y while dragging to only move along that axis
+}
+
+impl Default for Snapping {
+    fn default() -> Self {
+        Self {
+            grid: None,
+            axis_lock: true,
+        }
+    }
+}
+
+impl Snapping {
+
+    pub fn grid(grid: f32) -> Self {
+        Self {
+            grid: Some(grid),
+            ..Default::default()
+        }
+    }
+
+    pub fn snap(&self, value: f32) -> f32 {
+        match self.grid {
+            Some(grid) if grid > 0. => (value / grid).round() * grid,
+            _ => value,
+        }
+    }
+
+    pub fn snap_vec(&self, value: Vec2) -> Vec2 {
+        vec2(self.snap(value.x), self.snap(value.y))
+    }
+}
+
+/// Everything the editor tools can change about a body with a cuboid collider
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BodyTransform {
+    pub position: Vec2,
+    pub rotation: f32,
+    pub half_extents: Option<Vec2> // None if the collider isn't a cuboid
+}
+
+impl BodyTransform {
+
+    pub fn capture(space: &Space, rigid_body_handle: RigidBodyHandle, collider_handle: ColliderHandle) -> Option<Self> {
+        let rigid_body = space.rigid_body_set.get(rigid_body_handle)?;
+        let collider = space.collider_set.get(collider_handle)?;
+
+        let translation = rigid_body.position().translation;
+
+        Some(Self {
+            position: vec2(translation.x, translation.y),
+            rotation: rigid_body.rotation().angle(),
+            half_extents: collider.shape().as_cuboid().map(|cuboid| vec2(cuboid.half_extents.x, cuboid.half_extents.y)),
+        })
+    }
+
+    pub fn apply(&self, space: &mut Space, rigid_body_handle: RigidBodyHandle, collider_handle: ColliderHandle) {
+        if let Some(rigid_body) = space.rigid_body_set.get_mut(rigid_body_handle) {
+            rigid_body.set_position(Isometry::new(vector![self.position.x, self.position.y], self.rotation), true);
+
+            rigid_body.set_linvel(vector![0., 0.], true);
+            rigid_body.set_angvel(0., true);
+        }
+
+        if let (Some(collider), Some(half_extents)) = (space.collider_set.get_mut(collider_handle), self.half_extents) {
+            if let Some(cuboid) = collider.shape_mut().as_cuboid_mut() {
+                cuboid.half_extents = vector![half_extents.x, half_extents.y];
+            }
+        }
+
+        space.rigid_body_set.propagate_modified_body_positions_to_colliders(&mut space.collider_set);
+    }
+}
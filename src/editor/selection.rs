@@ -0,0 +1,231 @@
+use macroquad::{color::Color, input::{is_key_down, is_mouse_button_down, is_mouse_button_pressed, KeyCode, MouseButton}, math::{vec2, Rect, Vec2}, shapes::{draw_rectangle, draw_rectangle_lines}};
+use nalgebra::{point, vector};
+use rapier2d::{geometry::Aabb, math::{Isometry, Rotation}, pipeline::QueryFilter, prelude::RigidBodyHandle};
+
+use crate::{coordinates::CoordinateSystem, space::Space, traits::HasPhysics};
+
+use super::history::EditorHistory;
+
+/// Selecting and editing several HasPhysics entities at once. Entities are passed in as `&mut dyn HasPhysics` so different types can be mixed
+pub struct Selection {
+    box_start: Option<Vec2>, // rapier coords
+    box_end: Vec2,
+    dragged: Option<(RigidBodyHandle, Vec2)> // the body the mouse is dragging and where it was last frame
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Selection {
+
+    pub fn new() -> Self {
+        Self {
+            box_start: None,
+            box_end: Vec2::ZERO,
+            dragged: None,
+        }
+    }
+
+    /// The rubber band box in rapier coords while it is being dragged out
+    pub fn box_rect(&self) -> Option<Rect> {
+        let box_start = self.box_start?;
+
+        let min = box_start.min(self.box_end);
+        let max = box_start.max(self.box_end);
+
+        Some(Rect::new(min.x, min.y, max.x - min.x, max.y - min.y))
+    }
+
+    /// Drag from empty space to select every entity the box touches. Hold shift to add to the selection instead of replacing it.
+    /// Call after update_selected so clicking empty space has already cleared the selection
+    pub fn update_box(&mut self, space: &mut Space, entities: &mut [&mut dyn HasPhysics], camera_rect: &Rect, coordinates: &CoordinateSystem) {
+        let mouse_pos = coordinates.rapier_mouse_world_pos(camera_rect);
+
+        if is_mouse_button_pressed(MouseButton::Left) {
+            space.query_pipeline.update(&space.collider_set);
+
+            let mut over_collider = false;
+
+            space.query_pipeline.intersections_with_point(
+                &space.rigid_body_set, &space.collider_set, &point![mouse_pos.x, mouse_pos.y], QueryFilter::default(), |_| {
+                    over_collider = true;
+
+                    false
+                }
+            );
+
+            if !over_collider {
+                self.box_start = Some(mouse_pos);
+            }
+        }
+
+        self.box_end = mouse_pos;
+
+        if is_mouse_button_down(MouseButton::Left) {
+            return;
+        }
+
+        // released, select whatever is in the box
+        let box_rect = match self.box_rect() {
+            Some(box_rect) => box_rect,
+            None => return,
+        };
+
+        self.box_start = None;
+
+        let aabb = Aabb::new(point![box_rect.x, box_rect.y], point![box_rect.right(), box_rect.bottom()]);
+
+        let mut in_box = vec![];
+
+        space.query_pipeline.colliders_with_aabb_intersecting_aabb(&aabb, |collider_handle| {
+            in_box.push(*collider_handle);
+
+            true
+        });
+
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+
+        for entity in entities.iter_mut() {
+            let inside = in_box.contains(entity.collider_handle());
+
+            match shift {
+                true => *entity.selected_mut() = *entity.selected() || inside,
+                false => *entity.selected_mut() = inside,
+            }
+        }
+    }
+
+    pub fn draw_box(&self, coordinates: &CoordinateSystem) {
+        let box_rect = match self.box_rect() {
+            Some(box_rect) => box_rect,
+            None => return,
+        };
+
+        // the top left corner in macroquad coords is the top left corner in rapier coords, which has the larger y
+        let top_left = coordinates.rapier_to_macroquad(&vec2(box_rect.x, box_rect.bottom()));
+
+        draw_rectangle(top_left.x, top_left.y, box_rect.w, box_rect.h, Color::new(0.4, 0.6, 1., 0.2));
+        draw_rectangle_lines(top_left.x, top_left.y, box_rect.w, box_rect.h, 1., Color::new(0.4, 0.6, 1., 0.8));
+    }
+
+    /// While one selected entity is dragged, move every other selected entity along with it. Call after update_drag
+    pub fn update_group_drag(&mut self, space: &mut Space, entities: &mut [&mut dyn HasPhysics]) {
+        let mut dragged = None;
+
+        for entity in entities.iter_mut() {
+            if *entity.selected() && *entity.dragging() {
+                dragged = Some(*entity.rigid_body_handle());
+
+                break;
+            }
+        }
+
+        let dragged = match dragged {
+            Some(dragged) => dragged,
+            None => {
+                self.dragged = None;
+
+                return;
+            },
+        };
+
+        let position = match space.rigid_body_set.get(dragged) {
+            Some(rigid_body) => vec2(rigid_body.position().translation.x, rigid_body.position().translation.y),
+            None => return,
+        };
+
+        let delta = match self.dragged {
+            Some((last_dragged, last_position)) if last_dragged == dragged => position - last_position,
+            _ => Vec2::ZERO,
+        };
+
+        self.dragged = Some((dragged, position));
+
+        if delta == Vec2::ZERO {
+            return;
+        }
+
+        for entity in entities.iter() {
+            if !*entity.selected() || *entity.rigid_body_handle() == dragged {
+                continue;
+            }
+
+            if let Some(rigid_body) = space.rigid_body_set.get_mut(*entity.rigid_body_handle()) {
+                let translation = rigid_body.position().translation;
+
+                rigid_body.set_translation(vector![translation.x + delta.x, translation.y + delta.y], true);
+            }
+        }
+    }
+
+    /// Move every selected entity by `delta`
+    pub fn group_move(space: &mut Space, entities: &[&mut dyn HasPhysics], delta: Vec2) {
+        for entity in entities.iter().filter(|entity| *entity.selected()) {
+            if let Some(rigid_body) = space.rigid_body_set.get_mut(*entity.rigid_body_handle()) {
+                let translation = rigid_body.position().translation;
+
+                rigid_body.set_translation(vector![translation.x + delta.x, translation.y + delta.y], true);
+            }
+        }
+    }
+
+    /// Rotate every selected entity by `angle` radians around the middle of the selection, keeping their layout
+    pub fn group_rotate(space: &mut Space, entities: &[&mut dyn HasPhysics], angle: f32) {
+        let positions: Vec<Vec2> = entities.iter()
+            .filter(|entity| *entity.selected())
+            .filter_map(|entity| space.rigid_body_set.get(*entity.rigid_body_handle()))
+            .map(|rigid_body| vec2(rigid_body.position().translation.x, rigid_body.position().translation.y))
+            .collect();
+
+        if positions.is_empty() {
+            return;
+        }
+
+        let center = positions.iter().copied().sum::<Vec2>() / positions.len() as f32;
+
+        let rotation = Rotation::new(angle);
+
+        for entity in entities.iter().filter(|entity| *entity.selected()) {
+            if let Some(rigid_body) = space.rigid_body_set.get_mut(*entity.rigid_body_handle()) {
+                let translation = rigid_body.position().translation;
+
+                let offset = rotation * vector![translation.x - center.x, translation.y - center.y];
+
+                rigid_body.set_position(
+                    Isometry::new(vector![center.x + offset.x, center.y + offset.y], rigid_body.rotation().angle() + angle), 
+                    true
+                );
+            }
+        }
+    }
+
+    /// Remove every selected entity's body. Goes through the history when there is one so it can be undone.
+    /// Returns the removed bodies so the game can drop the entities that owned them
+    pub fn group_delete(space: &mut Space, entities: &[&mut dyn HasPhysics], mut history: Option<&mut EditorHistory>) -> Vec<RigidBodyHandle> {
+        let selected: Vec<RigidBodyHandle> = entities.iter()
+            .filter(|entity| *entity.selected())
+            .map(|entity| *entity.rigid_body_handle())
+            .collect();
+
+        for rigid_body_handle in &selected {
+            match &mut history {
+                Some(history) => history.delete(space, *rigid_body_handle),
+                None => {
+                    space.rigid_body_set.remove(
+                        *rigid_body_handle, 
+                        &mut space.island_manager, 
+                        &mut space.collider_set, 
+                        &mut space.impulse_joint_set, 
+                        &mut space.multibody_joint_set, 
+                        true
+                    );
+                },
+            }
+        }
+
+        selected
+    }
+}
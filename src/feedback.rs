@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use macroquad::{camera::Camera2D, math::{vec2, Rect, Vec2}};
+
+use crate::{id, time::GameClock};
+
+/// Trauma based screen shake. Hits add trauma, which decays over time, and the shake grows with trauma squared
+/// so small hits are subtle and big ones are violent. Runs on real time but freezes while the clock is paused
+pub struct ScreenShake {
+    pub trauma: f32, // 0 to 1
+    pub decay: f32, // trauma lost per second
+    pub max_offset: f32, // in world units, at full trauma
+    pub max_angle: f32, // radians, at full trauma
+    pub frequency: f32, // how fast the shake moves
+    direction: Option<Vec2>, // shake along one axis, like recoil, instead of every way
+    phases: [f32; 3], // random noise offsets so two shakes don't move the same way
+    time: f32
+}
+
+impl Default for ScreenShake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScreenShake {
+
+    pub fn new() -> Self {
+        let seed = id::random_u64();
+
+        Self {
+            trauma: 0.,
+            decay: 1.5,
+            max_offset: 16.,
+            max_angle: 0.05,
+            frequency: 25.,
+            direction: None,
+            phases: [(seed & 0xFFFF) as f32, ((seed >> 16) & 0xFFFF) as f32, ((seed >> 32) & 0xFFFF) as f32],
+            time: 0.,
+        }
+    }
+
+    pub fn with_max_offset(mut self, max_offset: f32) -> Self {
+        self.max_offset = max_offset;
+
+        self
+    }
+
+    pub fn with_decay(mut self, decay: f32) -> Self {
+        self.decay = decay;
+
+        self
+    }
+
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.);
+
+        self.direction = None;
+    }
+
+    /// Shake back and forth along `direction` only, for recoil and knockback
+    pub fn add_directional_trauma(&mut self, amount: f32, direction: Vec2) {
+        self.trauma = (self.trauma + amount).min(1.);
+
+        self.direction = Some(direction.normalize_or_zero());
+    }
+
+    pub fn update(&mut self, clock: &GameClock) {
+        if clock.paused {
+            return;
+        }
+
+        let delta = clock.real_delta().as_secs_f32();
+
+        self.time += delta;
+
+        self.trauma = (self.trauma - self.decay * delta).max(0.);
+
+        if self.trauma == 0. {
+            self.direction = None;
+        }
+    }
+
+    /// Smooth noise from -1 to 1, different for each phase
+    fn noise(&self, phase: f32) -> f32 {
+        let t = self.time * self.frequency + phase;
+
+        ((t.sin() + (t * 2.3).sin() * 0.5 + (t * 4.7).sin() * 0.25) / 1.75).clamp(-1., 1.)
+    }
+
+    /// How far the camera is pushed this frame, in world units
+    pub fn offset(&self) -> Vec2 {
+        let shake = self.trauma * self.trauma;
+
+        match self.direction {
+            Some(direction) => direction * self.noise(self.phases[0]) * self.max_offset * shake,
+            None => vec2(self.noise(self.phases[0]), self.noise(self.phases[1])) * self.max_offset * shake,
+        }
+    }
+
+    /// Camera rotation this frame in radians. Directional shakes don't rotate
+    pub fn angle(&self) -> f32 {
+        match self.direction {
+            Some(_) => 0.,
+            None => self.noise(self.phases[2]) * self.max_angle * self.trauma * self.trauma,
+        }
+    }
+
+    /// The camera rect moved by the shake. Use the unshaken rect for mouse_world_pos so aiming doesn't shake
+    pub fn apply(&self, camera_rect: Rect) -> Rect {
+        let offset = self.offset();
+
+        Rect::new(camera_rect.x + offset.x, camera_rect.y + offset.y, camera_rect.w, camera_rect.h)
+    }
+
+    /// A window camera for `camera_rect` with the shake and rotation applied
+    pub fn camera(&self, camera_rect: Rect) -> Camera2D {
+        let mut camera = Camera2D::from_display_rect(self.apply(camera_rect));
+        camera.zoom.y = -camera.zoom.y;
+
+        camera.rotation = self.angle().to_degrees();
+
+        camera
+    }
+}
+
+/// Freezes or slows the game for a moment on impact by dropping the clock's time_scale, then puts it back.
+/// Counts down in real time, so it ends on time even though game time is barely moving
+#[derive(Default)]
+pub struct Hitstop {
+    remaining: Duration,
+    restore_scale: Option<f32> // the time_scale from before the hitstop
+}
+
+impl Hitstop {
+
+    pub fn new() -> Self {
+        Self {
+            remaining: Duration::ZERO,
+            restore_scale: None,
+        }
+    }
+
+    /// Slow the clock to `scale` for `duration`. Hits during a hitstop extend it instead of stacking
+    pub fn trigger(&mut self, clock: &mut GameClock, duration: Duration, scale: f32) {
+        if self.restore_scale.is_none() {
+            self.restore_scale = Some(clock.time_scale);
+        }
+
+        clock.time_scale = scale;
+
+        self.remaining = self.remaining.max(duration);
+    }
+
+    pub fn active(&self) -> bool {
+        self.restore_scale.is_some()
+    }
+
+    /// Call once a frame after clock.update. Waits while the clock is paused
+    pub fn update(&mut self, clock: &mut GameClock) {
+        let restore_scale = match self.restore_scale {
+            Some(restore_scale) => restore_scale,
+            None => return,
+        };
+
+        if clock.paused {
+            return;
+        }
+
+        self.remaining = self.remaining.saturating_sub(clock.real_delta());
+
+        if self.remaining.is_zero() {
+            clock.time_scale = restore_scale;
+
+            self.restore_scale = None;
+        }
+    }
+}
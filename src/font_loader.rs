@@ -0,0 +1,173 @@
+use fxhash::{FxHashMap, FxHashSet};
+use macroquad::text::{load_ttf_font, measure_text, Font, TextDimensions};
+
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+use crate::hot_reload::FileWatcher;
+
+/// Every printable ascii character, for prewarm
+pub const ASCII: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// Measurements are dropped all at once past this many, so text that changes every frame can't grow the cache forever
+const MEASURE_CACHE_LIMIT: usize = 4096;
+
+pub struct FontLoader {
+    pub cache: FxHashMap<String, Font>,
+    pub strict: bool, // get_blocking panics on anything that wasn't loaded beforehand
+    failed_paths: FxHashSet<String>,
+    prewarmed: FxHashMap<String, Vec<(u16, String)>>, // sizes and characters to rasterize again after a hot reload
+    measurements: FxHashMap<(String, Option<String>, u16), TextDimensions>, // text, font path, size
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    pub watcher: FileWatcher
+}
+
+impl Default for FontLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FontLoader {
+
+    pub fn new() -> Self {
+        FontLoader { 
+            cache: FxHashMap::default(),
+            strict: false,
+            failed_paths: FxHashSet::default(),
+            prewarmed: FxHashMap::default(),
+            measurements: FxHashMap::default(),
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            watcher: FileWatcher::new(),
+        }
+    }
+
+    pub async fn get(&mut self, font_path: &String) -> &Font {
+        if !self.cache.contains_key(font_path) {
+
+            let font = load_ttf_font(font_path).await.unwrap();
+
+            self.cache.insert(font_path.clone(), font);
+
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            self.watcher.watch(font_path);
+
+        }
+
+        self.cache.get(font_path).unwrap()
+    }
+
+    /// Rasterize `characters` at every size up front, so text showing up mid game doesn't stall on building its glyphs.
+    /// Sizes are the font size after any scaling, the same number that ends up in TextParams
+    pub async fn prewarm(&mut self, font_path: &String, sizes: &[u16], characters: &str) {
+        let glyphs: Vec<char> = characters.chars().collect();
+
+        let font = self.get(font_path).await;
+
+        for size in sizes {
+            font.populate_font_cache(&glyphs, *size);
+        }
+
+        let prewarmed = self.prewarmed.entry(font_path.clone()).or_default();
+
+        for size in sizes {
+            prewarmed.push((*size, characters.to_string()));
+        }
+    }
+
+    /// measure_text, but remembered. Text that is drawn every frame only gets laid out once
+    pub fn measure(&mut self, text: &str, font_path: Option<&String>, font_size: u16) -> TextDimensions {
+        let key = (text.to_string(), font_path.cloned(), font_size);
+
+        if let Some(dimensions) = self.measurements.get(&key) {
+            return *dimensions;
+        }
+
+        let font = match font_path {
+            Some(font_path) => self.get_blocking(font_path),
+            None => None,
+        };
+
+        let dimensions = measure_text(text, font, font_size, 1.);
+
+        if self.measurements.len() >= MEASURE_CACHE_LIMIT {
+            self.measurements.clear();
+        }
+
+        self.measurements.insert(key, dimensions);
+
+        dimensions
+    }
+
+    /// Make every get_blocking of a font that hasn't been loaded yet panic instead of reading it from disk
+    pub fn with_strict(mut self) -> Self {
+        self.strict = true;
+
+        self
+    }
+
+    /// Like get, but usable outside async code. On native the font is read from disk right away.
+    /// On wasm it has to be loaded already. None means it couldn't be loaded and the default font should be used
+    pub fn get_blocking(&mut self, font_path: &String) -> Option<&Font> {
+
+        if !self.cache.contains_key(font_path) {
+
+            if self.strict {
+                panic!("font {} was not preloaded", font_path)
+            }
+
+            match read_font_from_disk(font_path) {
+                Ok(font) => {
+                    self.failed_paths.remove(font_path);
+
+                    self.cache.insert(font_path.clone(), font);
+
+                    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+                    self.watcher.watch(font_path);
+                },
+                Err(error) => {
+                    // only complain once, this gets called every frame
+                    if self.failed_paths.insert(font_path.clone()) {
+                        log_warn!("failed to load font {}: {}", font_path, error);
+                    }
+
+                    return None;
+                },
+            }
+        }
+
+        self.cache.get(font_path)
+    }
+
+    /// Reload every cached font that changed on disk. Call once per frame
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    pub async fn hot_reload(&mut self) {
+        for font_path in self.watcher.changed() {
+            match load_ttf_font(&font_path).await {
+                Ok(font) => {
+                    log_info!("reloaded {}", font_path);
+
+                    // the new font starts with an empty glyph cache
+                    for (size, characters) in self.prewarmed.get(&font_path).into_iter().flatten() {
+                        font.populate_font_cache(&characters.chars().collect::<Vec<char>>(), *size);
+                    }
+
+                    self.measurements.retain(|(_, measured_font_path, _), _| measured_font_path.as_ref() != Some(&font_path));
+
+                    self.cache.insert(font_path, font);
+                },
+                Err(error) => log_warn!("failed to reload {}: {}", font_path, error),
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_font_from_disk(font_path: &String) -> Result<Font, String> {
+    let bytes = std::fs::read(font_path).map_err(|error| error.to_string())?;
+
+    macroquad::text::load_ttf_font_from_bytes(&bytes).map_err(|error| error.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_font_from_disk(_font_path: &String) -> Result<Font, String> {
+    Err("fonts can't be loaded without awaiting in the browser, preload it first".to_string())
+}
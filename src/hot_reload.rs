@@ -0,0 +1,70 @@
+use std::{fs, time::{Duration, SystemTime}};
+
+use fxhash::FxHashMap;
+
+use crate::time::{self, Instant};
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Polls the modified time of asset files so the loaders can reload them while the game is running.
+/// Polling instead of OS notifications keeps it dependency free, and there are rarely more than a few hundred assets
+pub struct FileWatcher {
+    modified: FxHashMap<String, Option<SystemTime>>, // None if the file couldn't be read last time we looked
+    last_poll: Option<Instant>,
+    pub poll_interval: Duration
+}
+
+impl Default for FileWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileWatcher {
+
+    pub fn new() -> Self {
+        Self {
+            modified: FxHashMap::default(),
+            last_poll: None,
+            poll_interval: Duration::from_millis(500),
+        }
+    }
+
+    /// Start watching a file from its current modified time. Does nothing if it is already watched
+    pub fn watch(&mut self, path: &String) {
+        if !self.modified.contains_key(path) {
+            self.modified.insert(path.clone(), modified_time(path));
+        }
+    }
+
+    pub fn unwatch(&mut self, path: &String) {
+        self.modified.remove(path);
+    }
+
+    /// Every watched file whose modified time changed since the last call. Only looks at the disk once every poll_interval
+    pub fn changed(&mut self) -> Vec<String> {
+
+        if self.last_poll.is_some_and(|last_poll| last_poll.elapsed() < self.poll_interval) {
+            return vec![];
+        }
+
+        self.last_poll = Some(time::now());
+
+        let mut changed = vec![];
+
+        for (path, modified) in self.modified.iter_mut() {
+            let current = modified_time(path);
+
+            // editors often delete and rewrite the file, so wait until it exists again
+            if current.is_some() && current != *modified {
+                changed.push(path.clone());
+            }
+
+            *modified = current;
+        }
+
+        changed
+    }
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+/// Bits of a generated id that hold the counter. The rest holds the node
+const COUNTER_BITS: u32 = 48;
+
+/// Hands out 64 bit ids prefixed with a node id, usually the client id the server assigned us.
+/// As long as every client uses a different node, ids never collide, no matter how many are made
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct IdGenerator {
+    node: u16,
+    counter: u64
+}
+
+impl IdGenerator {
+
+    /// Only the low 16 bits of the node are used
+    pub fn new(node: u64) -> Self {
+        Self {
+            node: node as u16,
+            counter: 0,
+        }
+    }
+
+    /// A generator with a random node, for when there is no client id to use. Collisions become possible
+    /// once there are a few hundred generators, but not within one
+    pub fn random() -> Self {
+        Self::new(random_u64())
+    }
+
+    pub fn node(&self) -> u16 {
+        self.node
+    }
+
+    pub fn next_id(&mut self) -> u64 {
+        self.counter += 1;
+
+        if self.counter >= 1 << COUNTER_BITS {
+            panic!("id generator for node {} ran out of ids", self.node)
+        }
+
+        ((self.node as u64) << COUNTER_BITS) | self.counter
+    }
+}
+
+/// Random 64 bit id. Collisions get likely around 4 billion ids
+pub fn random_u64() -> u64 {
+    let mut bytes = [0u8; 8];
+
+    getrandom::getrandom(&mut bytes).unwrap();
+
+    u64::from_le_bytes(bytes)
+}
+
+/// Random 128 bit id, for when ids come from many places with no node to tell them apart
+pub fn random_u128() -> u128 {
+    let mut bytes = [0u8; 16];
+
+    getrandom::getrandom(&mut bytes).unwrap();
+
+    u128::from_le_bytes(bytes)
+}
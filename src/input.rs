@@ -0,0 +1,290 @@
+use fxhash::{FxHashMap, FxHashSet};
+use macroquad::input::{get_last_key_pressed, is_key_down, is_key_pressed, is_key_released, is_mouse_button_down, is_mouse_button_pressed, is_mouse_button_released, KeyCode, MouseButton};
+use serde::{Deserialize, Serialize};
+
+/// How far an analog axis has to be pushed before an action bound to it counts as down
+pub const AXIS_THRESHOLD: f32 = 0.5;
+
+/// macroquad doesn't read gamepads, so the game feeds these in with ActionMap::set_gamepad_button
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    LeftShoulder,
+    RightShoulder,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY
+}
+
+/// One input that can trigger an action
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Binding {
+    Key(#[serde(with = "key_code")] KeyCode),
+    Mouse(#[serde(with = "mouse_button")] MouseButton),
+    Gamepad(GamepadButton),
+    /// One direction of an analog axis. `positive` picks which half
+    GamepadAxis { axis: GamepadAxis, positive: bool }
+}
+
+/// Binds named actions like "jump" to any number of inputs, so games check actions instead of hard coding keys.
+/// Only the bindings are serialized, so the map can be saved as the player's controls and loaded back
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ActionMap {
+    bindings: FxHashMap<String, Vec<Binding>>,
+    #[serde(skip)]
+    gamepad_buttons: FxHashSet<GamepadButton>,
+    #[serde(skip)]
+    previous_gamepad_buttons: FxHashSet<GamepadButton>,
+    #[serde(skip)]
+    gamepad_axes: FxHashMap<GamepadAxis, f32>,
+    #[serde(skip)]
+    previous_gamepad_axes: FxHashMap<GamepadAxis, f32>
+}
+
+impl ActionMap {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a binding to an action, creating the action if needed
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let bindings = self.bindings.entry(action.to_string()).or_default();
+
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn with_binding(mut self, action: &str, binding: Binding) -> Self {
+        self.bind(action, binding);
+
+        self
+    }
+
+    pub fn unbind(&mut self, action: &str, binding: Binding) {
+        if let Some(bindings) = self.bindings.get_mut(action) {
+            bindings.retain(|bound| *bound != binding);
+        }
+    }
+
+    /// Replace every binding of an action, for rebinding controls at runtime
+    pub fn rebind(&mut self, action: &str, bindings: Vec<Binding>) {
+        self.bindings.insert(action.to_string(), bindings);
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        match self.bindings.get(action) {
+            Some(bindings) => bindings,
+            None => &[],
+        }
+    }
+
+    /// Call once at the start of every frame, before feeding in gamepad state
+    pub fn update(&mut self) {
+        self.previous_gamepad_buttons = self.gamepad_buttons.clone();
+        self.previous_gamepad_axes = self.gamepad_axes.clone();
+    }
+
+    pub fn set_gamepad_button(&mut self, button: GamepadButton, down: bool) {
+        match down {
+            true => self.gamepad_buttons.insert(button),
+            false => self.gamepad_buttons.remove(&button),
+        };
+    }
+
+    /// `value` goes from -1 to 1
+    pub fn set_gamepad_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.gamepad_axes.insert(axis, value.clamp(-1., 1.));
+    }
+
+    fn axis_value(axes: &FxHashMap<GamepadAxis, f32>, axis: GamepadAxis, positive: bool) -> f32 {
+        let value = axes.get(&axis).copied().unwrap_or(0.);
+
+        match positive {
+            true => value.max(0.),
+            false => (-value).max(0.),
+        }
+    }
+
+    /// How strongly a binding is held, from 0 to 1. Digital inputs are either 0 or 1
+    pub fn binding_value(&self, binding: &Binding) -> f32 {
+        let down = match binding {
+            Binding::Key(key) => is_key_down(*key),
+            Binding::Mouse(button) => is_mouse_button_down(*button),
+            Binding::Gamepad(button) => self.gamepad_buttons.contains(button),
+            Binding::GamepadAxis { axis, positive } => return Self::axis_value(&self.gamepad_axes, *axis, *positive),
+        };
+
+        match down {
+            true => 1.,
+            false => 0.,
+        }
+    }
+
+    fn binding_just_pressed(&self, binding: &Binding) -> bool {
+        match binding {
+            Binding::Key(key) => is_key_pressed(*key),
+            Binding::Mouse(button) => is_mouse_button_pressed(*button),
+            Binding::Gamepad(button) => self.gamepad_buttons.contains(button) && !self.previous_gamepad_buttons.contains(button),
+            Binding::GamepadAxis { axis, positive } => {
+                Self::axis_value(&self.gamepad_axes, *axis, *positive) >= AXIS_THRESHOLD
+                    && Self::axis_value(&self.previous_gamepad_axes, *axis, *positive) < AXIS_THRESHOLD
+            },
+        }
+    }
+
+    fn binding_just_released(&self, binding: &Binding) -> bool {
+        match binding {
+            Binding::Key(key) => is_key_released(*key),
+            Binding::Mouse(button) => is_mouse_button_released(*button),
+            Binding::Gamepad(button) => !self.gamepad_buttons.contains(button) && self.previous_gamepad_buttons.contains(button),
+            Binding::GamepadAxis { axis, positive } => {
+                Self::axis_value(&self.gamepad_axes, *axis, *positive) < AXIS_THRESHOLD
+                    && Self::axis_value(&self.previous_gamepad_axes, *axis, *positive) >= AXIS_THRESHOLD
+            },
+        }
+    }
+
+    /// The strongest of the action's bindings, from 0 to 1
+    pub fn action_value(&self, action: &str) -> f32 {
+        self.bindings(action).iter()
+            .map(|binding| self.binding_value(binding))
+            .fold(0., f32::max)
+    }
+
+    /// Any binding of the action is held down
+    pub fn is_action_pressed(&self, action: &str) -> bool {
+        self.action_value(action) >= AXIS_THRESHOLD
+    }
+
+    /// A binding of the action went down this frame
+    pub fn is_action_just_pressed(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|binding| self.binding_just_pressed(binding))
+    }
+
+    /// A binding of the action came up this frame
+    pub fn is_action_just_released(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|binding| self.binding_just_released(binding))
+    }
+
+    /// -1 to 1 from a pair of actions, like "move_left" and "move_right"
+    pub fn axis(&self, negative_action: &str, positive_action: &str) -> f32 {
+        self.action_value(positive_action) - self.action_value(negative_action)
+    }
+
+    /// Whatever was pressed this frame, for "press a key" rebinding screens
+    pub fn capture_binding(&self) -> Option<Binding> {
+        if let Some(key) = get_last_key_pressed() {
+            return Some(Binding::Key(key));
+        }
+
+        for button in [MouseButton::Left, MouseButton::Right, MouseButton::Middle] {
+            if is_mouse_button_pressed(button) {
+                return Some(Binding::Mouse(button));
+            }
+        }
+
+        if let Some(button) = self.gamepad_buttons.difference(&self.previous_gamepad_buttons).next() {
+            return Some(Binding::Gamepad(*button));
+        }
+
+        None
+    }
+}
+
+/// KeyCode has no serde support, so keys are stored by name
+mod key_code {
+    use macroquad::input::KeyCode;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    macro_rules! key_names {
+        ($($key:ident),* $(,)?) => {
+            fn name(key: KeyCode) -> &'static str {
+                match key {
+                    $(KeyCode::$key => stringify!($key),)*
+                    _ => "Unknown",
+                }
+            }
+
+            fn from_name(name: &str) -> Option<KeyCode> {
+                match name {
+                    $(stringify!($key) => Some(KeyCode::$key),)*
+                    "Unknown" => Some(KeyCode::Unknown),
+                    _ => None,
+                }
+            }
+        };
+    }
+
+    key_names!(
+        Space, Apostrophe, Comma, Minus, Period, Slash,
+        Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+        Semicolon, Equal,
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        LeftBracket, Backslash, RightBracket, GraveAccent, World1, World2,
+        Escape, Enter, Tab, Backspace, Insert, Delete, Right, Left, Down, Up,
+        PageUp, PageDown, Home, End, CapsLock, ScrollLock, NumLock, PrintScreen, Pause,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+        Kp0, Kp1, Kp2, Kp3, Kp4, Kp5, Kp6, Kp7, Kp8, Kp9,
+        KpDecimal, KpDivide, KpMultiply, KpSubtract, KpAdd, KpEnter, KpEqual,
+        LeftShift, LeftControl, LeftAlt, LeftSuper, RightShift, RightControl, RightAlt, RightSuper, Menu,
+    );
+
+    pub fn serialize<S: Serializer>(key: &KeyCode, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(name(*key))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<KeyCode, D::Error> {
+        let key_name = String::deserialize(deserializer)?;
+
+        // a typo in a bindings file shouldn't quietly unbind the action
+        from_name(&key_name).ok_or_else(|| D::Error::custom(format!("unknown key {}", key_name)))
+    }
+}
+
+mod mouse_button {
+    use macroquad::input::MouseButton;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(button: &MouseButton, serializer: S) -> Result<S::Ok, S::Error> {
+        let name = match button {
+            MouseButton::Left => "Left",
+            MouseButton::Right => "Right",
+            MouseButton::Middle => "Middle",
+            MouseButton::Unknown => "Unknown",
+        };
+
+        serializer.serialize_str(name)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<MouseButton, D::Error> {
+        let name = String::deserialize(deserializer)?;
+
+        match name.as_str() {
+            "Left" => Ok(MouseButton::Left),
+            "Right" => Ok(MouseButton::Right),
+            "Middle" => Ok(MouseButton::Middle),
+            "Unknown" => Ok(MouseButton::Unknown),
+            _ => Err(D::Error::custom(format!("unknown mouse button {}", name))),
+        }
+    }
+}
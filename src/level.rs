@@ -0,0 +1,139 @@
+use macroquad::file::load_string;
+use rapier2d::{geometry::ColliderHandle, prelude::RigidBodyHandle};
+use serde::{Deserialize, Serialize};
+
+use crate::{space::Space, traits::HasPhysics};
+
+/// Bumped whenever the level format changes in a way older builds can't read
+pub const LEVEL_VERSION: u32 = 1;
+
+/// What the game needs to rebuild one entity on top of the bodies in the level's Space
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LevelEntity {
+    pub name: String,
+    pub rigid_body_handle: RigidBodyHandle,
+    pub collider_handle: ColliderHandle,
+    #[serde(default)]
+    pub texture_path: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>
+}
+
+impl LevelEntity {
+
+    pub fn new(name: &str, rigid_body_handle: RigidBodyHandle, collider_handle: ColliderHandle) -> Self {
+        Self {
+            name: name.to_string(),
+            rigid_body_handle,
+            collider_handle,
+            texture_path: None,
+            tags: vec![],
+        }
+    }
+
+    pub fn from_entity(name: &str, entity: &dyn HasPhysics) -> Self {
+        Self::new(name, *entity.rigid_body_handle(), *entity.collider_handle())
+    }
+
+    pub fn with_texture(mut self, texture_path: &str) -> Self {
+        self.texture_path = Some(texture_path.to_string());
+
+        self
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+
+        self
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|entity_tag| entity_tag == tag)
+    }
+}
+
+/// A Space and the entities built on it, saved as JSON.
+/// The body and collider sets are saved with their handles, so every handle an entity refers to points at the same body after loading
+#[derive(Serialize, Deserialize)]
+pub struct Level {
+    pub version: u32,
+    pub space: Space,
+    pub entities: Vec<LevelEntity>
+}
+
+impl Level {
+
+    pub fn new(space: Space) -> Self {
+        Self {
+            version: LEVEL_VERSION,
+            space,
+            entities: vec![],
+        }
+    }
+
+    pub fn with_entity(mut self, entity: LevelEntity) -> Self {
+        self.entities.push(entity);
+
+        self
+    }
+
+    pub fn add_entity(&mut self, entity: LevelEntity) {
+        self.entities.push(entity);
+    }
+
+    pub fn entity(&self, name: &str) -> Option<&LevelEntity> {
+        self.entities.iter().find(|entity| entity.name == name)
+    }
+
+    pub fn tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a LevelEntity> {
+        self.entities.iter().filter(move |entity| entity.has_tag(tag))
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|error| error.to_string())
+    }
+
+    pub fn from_json(level_json: &str) -> Result<Self, String> {
+
+        // check the version first so a newer level gives a useful error instead of whatever field failed to parse
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32
+        }
+
+        let version: Version = serde_json::from_str(level_json).map_err(|error| format!("invalid level: {}", error))?;
+
+        if version.version > LEVEL_VERSION {
+            return Err(format!("level version {} is newer than the supported version {}", version.version, LEVEL_VERSION));
+        }
+
+        let mut level: Level = serde_json::from_str(level_json).map_err(|error| format!("invalid level: {}", error))?;
+
+        // make sure queries work before the first step
+        level.space.query_pipeline.update(&level.space.collider_set);
+
+        Ok(level)
+    }
+
+    pub async fn load(level_path: &str) -> Result<Self, String> {
+        let level_json = load_string(level_path).await.map_err(|error| format!("failed to load {}: {}", level_path, error))?;
+
+        Self::from_json(&level_json).map_err(|error| format!("{}: {}", level_path, error))
+    }
+
+    pub fn save(&self, level_path: &str) -> Result<(), String> {
+        let level_json = self.to_json()?;
+
+        write_level_to_disk(level_path, &level_json)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_level_to_disk(level_path: &str, level_json: &str) -> Result<(), String> {
+    std::fs::write(level_path, level_json).map_err(|error| format!("failed to save {}: {}", level_path, error))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_level_to_disk(_level_path: &str, _level_json: &str) -> Result<(), String> {
+    Err("levels can't be saved to disk in the browser, use to_json instead".to_string())
+}
@@ -1,16 +1,49 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-
-use macroquad::{camera::Camera2D, input::mouse_position, math::{Rect, Vec2}, window::screen_height};
+use coordinates::CoordinateSystem;
+use macroquad::{camera::Camera2D, input::mouse_position, math::{Rect, Vec2}};
 
+#[macro_use]
+pub mod logging;
 pub mod timeline;
+pub mod rng;
+pub mod id;
 pub mod time;
 pub mod space;
 pub mod traits;
 pub mod menu;
 pub mod texture_loader;
+pub mod texture_atlas;
+pub mod font_loader;
 pub mod sync;
 pub mod animation;
 pub mod animation_loader;
+pub mod animation_controller;
+pub mod replay;
+pub mod input;
+pub mod coordinates;
+pub mod virtual_screen;
+pub mod debug_overlay;
+pub mod editor;
+pub mod assets;
+pub mod level;
+pub mod tags;
+pub mod tilemap;
+pub mod trail;
+pub mod lighting;
+pub mod draw;
+pub mod sprite_batch;
+pub mod shaders;
+pub mod post_process;
+pub mod feedback;
+pub mod navigation;
+pub mod steering;
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+pub mod hot_reload;
+
+// the entity macros in traits go through these, so games don't need the same forks and versions as direct dependencies
+pub use diff;
+pub use macroquad;
+pub use rapier2d;
+pub use serde;
 
 pub fn current_unix_millis() -> u64 {
     web_time::SystemTime::now()
@@ -29,42 +62,27 @@ pub fn mouse_world_pos(camera_rect: &Rect) -> Vec2 {
 
 }
 
+/// Assumes the world is drawn straight to the window. See CoordinateSystem for render targets
 pub fn rapier_mouse_world_pos(camera_rect: &Rect) -> Vec2 {
-    macroquad_to_rapier(
-        &mouse_world_pos(camera_rect)
-    )
+    CoordinateSystem::screen().rapier_mouse_world_pos(camera_rect)
 }
 
-#[cfg(target_arch = "x86_64")]
+/// Logs at info level. See the logging module for levels and sinks
 pub fn log(message: &str) {
-    println!("{message}");
+    log_info!("{}", message);
 }
 
-#[cfg(target_arch = "wasm32")]
-pub fn log(message: &str) {
-    web_sys::console::log_1(&message.into());
-}
+/// Random 128 bit id as a string. See the id module for numeric ids
 pub fn uuid() -> String {
-
-    // WTF
-    let mut buf = [0u8; 4];
-    getrandom::getrandom(&mut buf).unwrap();
-    u32::from_be_bytes(buf).to_string()
-
+    id::random_u128().to_string()
 }
 
+/// Uses the current window height. See CoordinateSystem for render targets
 pub fn macroquad_to_rapier(macroquad_coords: &Vec2) -> Vec2 {
-
-    // translate macroquad coords to rapier coords
-    Vec2 { 
-        x: macroquad_coords.x, 
-        y: (macroquad_coords.y * -1.) + screen_height()
-    }
+    CoordinateSystem::screen().macroquad_to_rapier(macroquad_coords)
 }
 
+/// Uses the current window height. See CoordinateSystem for render targets
 pub fn rapier_to_macroquad(rapier_coords: &Vec2) -> Vec2 {
-    Vec2 {
-        x: rapier_coords.x,
-        y: (rapier_coords.y * -1.) + screen_height()
-    }
+    CoordinateSystem::screen().rapier_to_macroquad(rapier_coords)
 }
\ No newline at end of file
@@ -0,0 +1,211 @@
+use std::f32::consts::TAU;
+
+use macroquad::{camera::{pop_camera_state, push_camera_state, set_camera, Camera2D}, color::{Color, WHITE}, material::{gl_use_default_material, gl_use_material, Material}, math::{vec2, Rect, Vec2}, miniquad::{BlendFactor, BlendState, BlendValue, Equation}, models::{draw_mesh, Mesh, Vertex}, texture::{draw_texture_ex, render_target, DrawTextureParams, RenderTarget}, window::clear_background};
+use nalgebra::{point, vector};
+use rapier2d::{geometry::Ray, pipeline::QueryFilter, prelude::RigidBodyHandle};
+
+use crate::{coordinates::CoordinateSystem, shaders::{fragment_material, DEFAULT_FRAGMENT_SHADER}, space::Space};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub position: Vec2, // rapier coords
+    pub radius: f32,
+    pub color: Color,
+    pub intensity: f32, // multiplies the color at the center. Brightness falls off linearly to nothing at the radius
+    pub cone: Option<(f32, f32)>, // direction and half angle in radians. None shines all the way around
+    pub shadows: bool,
+    pub ignore: Option<RigidBodyHandle>, // a body that doesn't block this light, like the player holding the flashlight
+    pub rays: usize // for a full circle. More rays give smoother shadow edges
+}
+
+impl Light {
+
+    pub fn point(position: Vec2, radius: f32, color: Color) -> Self {
+        Self {
+            position,
+            radius,
+            color,
+            intensity: 1.,
+            cone: None,
+            shadows: true,
+            ignore: None,
+            rays: 180,
+        }
+    }
+
+    /// A light that only shines within `half_angle` of `direction`, like a flashlight
+    pub fn cone(position: Vec2, radius: f32, color: Color, direction: f32, half_angle: f32) -> Self {
+        Self {
+            cone: Some((direction, half_angle)),
+            ..Self::point(position, radius, color)
+        }
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+
+        self
+    }
+
+    pub fn with_shadows(mut self, shadows: bool) -> Self {
+        self.shadows = shadows;
+
+        self
+    }
+
+    pub fn ignoring(mut self, rigid_body_handle: RigidBodyHandle) -> Self {
+        self.ignore = Some(rigid_body_handle);
+
+        self
+    }
+
+    /// How far the light reaches along each ray, stopping at the first collider when it casts shadows
+    fn reach(&self, space: &Space) -> Vec<(Vec2, f32)> {
+        let (start_angle, arc) = match self.cone {
+            Some((direction, half_angle)) => (direction - half_angle, half_angle * 2.),
+            None => (0., TAU),
+        };
+
+        let ray_count = ((self.rays as f32 * arc / TAU).ceil() as usize).max(2);
+
+        let mut filter = QueryFilter::default().exclude_sensors();
+
+        if let Some(ignore) = self.ignore {
+            filter = filter.exclude_rigid_body(ignore);
+        }
+
+        (0..=ray_count).map(|index| {
+            let angle = start_angle + arc * index as f32 / ray_count as f32;
+
+            let direction = vec2(angle.cos(), angle.sin());
+
+            if !self.shadows {
+                return (direction, self.radius);
+            }
+
+            let ray = Ray::new(point![self.position.x, self.position.y], vector![direction.x, direction.y]);
+
+            let distance = space.query_pipeline.cast_ray(&space.rigid_body_set, &space.collider_set, &ray, self.radius, true, filter)
+                .map(|(_, distance)| distance)
+                .unwrap_or(self.radius);
+
+            (direction, distance)
+        }).collect()
+    }
+
+    /// A triangle fan from the light out to where each ray stopped. Vertex colors fade with distance, which gives the falloff for free
+    fn mesh(&self, space: &Space, coordinates: &CoordinateSystem) -> Mesh {
+        let center = coordinates.rapier_to_macroquad(&self.position);
+
+        let center_color = Color::new(self.color.r * self.intensity, self.color.g * self.intensity, self.color.b * self.intensity, self.color.a);
+
+        let mut vertices = vec![Vertex::new(center.x, center.y, 0., 0., 0., center_color)];
+        let mut indices = vec![];
+
+        for (direction, distance) in self.reach(space) {
+            let edge = coordinates.rapier_to_macroquad(&(self.position + direction * distance));
+
+            let falloff = 1. - distance / self.radius;
+
+            let color = Color::new(center_color.r * falloff, center_color.g * falloff, center_color.b * falloff, center_color.a);
+
+            vertices.push(Vertex::new(edge.x, edge.y, 0., 0., 0., color));
+        }
+
+        for index in 1..vertices.len() as u16 - 1 {
+            indices.extend_from_slice(&[0, index, index + 1]);
+        }
+
+        Mesh { vertices, indices, texture: None }
+    }
+}
+
+/// Darkens the world to `ambient` everywhere except where lights reach. Lights are added together on an offscreen light map,
+/// which is then multiplied over whatever has already been drawn
+pub struct Lighting {
+    pub ambient: Color, // the light level with no lights around. Black is pitch dark, white turns lighting off
+    pub flip_y: bool, // set when compositing onto a render target with a camera that isn't flipped, like VirtualScreen::camera
+    light_map: Option<RenderTarget>,
+    additive: Material,
+    multiply: Material
+}
+
+impl Lighting {
+
+    pub fn new(ambient: Color) -> Self {
+        Self {
+            ambient,
+            flip_y: false,
+            light_map: None,
+            additive: fragment_material(DEFAULT_FRAGMENT_SHADER, vec![], vec![], Some(BlendState::new(Equation::Add, BlendFactor::Value(BlendValue::SourceAlpha), BlendFactor::One))),
+            multiply: fragment_material(DEFAULT_FRAGMENT_SHADER, vec![], vec![], Some(BlendState::new(Equation::Add, BlendFactor::Value(BlendValue::DestinationColor), BlendFactor::Zero))),
+        }
+    }
+
+    fn light_map(&mut self, coordinates: &CoordinateSystem) -> RenderTarget {
+        let (width, height) = (coordinates.width as u32, coordinates.height as u32);
+
+        let resized = self.light_map.as_ref()
+            .map(|light_map| light_map.texture.size() != vec2(width as f32, height as f32))
+            .unwrap_or(true);
+
+        if resized {
+            self.light_map = Some(render_target(width.max(1), height.max(1)));
+        }
+
+        self.light_map.clone().unwrap()
+    }
+
+    /// Light the world. Call after drawing the world and before the UI, with the world camera still set.
+    /// `camera_rect` is the same rect the world camera was made from
+    pub fn draw(&mut self, space: &mut Space, lights: &[Light], camera_rect: &Rect, coordinates: &CoordinateSystem) {
+        let light_map = self.light_map(coordinates);
+
+        space.query_pipeline.update(&space.collider_set);
+
+        push_camera_state();
+
+        let mut camera = Camera2D::from_display_rect(*camera_rect);
+
+        camera.render_target = Some(light_map.clone());
+
+        set_camera(&camera);
+
+        clear_background(self.ambient);
+
+        gl_use_material(&self.additive);
+
+        for light in lights {
+            // skip lights that can't reach the screen
+            let center = coordinates.rapier_to_macroquad(&light.position);
+
+            let bounds = Rect::new(center.x - light.radius, center.y - light.radius, light.radius * 2., light.radius * 2.);
+
+            if !bounds.overlaps(camera_rect) {
+                continue;
+            }
+
+            draw_mesh(&light.mesh(space, coordinates));
+        }
+
+        gl_use_default_material();
+
+        pop_camera_state();
+
+        gl_use_material(&self.multiply);
+
+        draw_texture_ex(
+            &light_map.texture, 
+            camera_rect.x, 
+            camera_rect.y, 
+            WHITE, 
+            DrawTextureParams { 
+                dest_size: Some(camera_rect.size()), 
+                flip_y: self.flip_y,
+                ..Default::default() 
+            }
+        );
+
+        gl_use_default_material();
+    }
+}
@@ -0,0 +1,298 @@
+use std::{cell::Cell, collections::VecDeque, fmt, sync::{Arc, Mutex}};
+
+use macroquad::{color::{Color, WHITE}, shapes::draw_rectangle, text::draw_text};
+
+use crate::current_unix_millis;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogLevel::Trace => write!(f, "TRACE"),
+            LogLevel::Debug => write!(f, "DEBUG"),
+            LogLevel::Info => write!(f, "INFO"),
+            LogLevel::Warn => write!(f, "WARN"),
+            LogLevel::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    pub target: String, // module path of where it was logged
+    pub message: String,
+    pub time: u64 // unix millis
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{} {}] {}", self.level, self.target, self.message)
+    }
+}
+
+/// Somewhere log records end up
+pub trait LogSink: Send {
+    fn write(&mut self, record: &LogRecord);
+}
+
+/// stdout on native and the browser console on wasm
+pub struct ConsoleSink;
+
+impl LogSink for ConsoleSink {
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn write(&mut self, record: &LogRecord) {
+        match record.level {
+            LogLevel::Warn | LogLevel::Error => eprintln!("{}", record),
+            _ => println!("{}", record),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn write(&mut self, record: &LogRecord) {
+        let message = record.to_string().into();
+
+        match record.level {
+            LogLevel::Warn => web_sys::console::warn_1(&message),
+            LogLevel::Error => web_sys::console::error_1(&message),
+            _ => web_sys::console::log_1(&message),
+        }
+    }
+}
+
+/// Appends every record to a file, one per line
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileSink {
+    writer: std::io::BufWriter<std::fs::File>
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileSink {
+    pub fn create(path: &str) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self { writer: std::io::BufWriter::new(file) })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl LogSink for FileSink {
+    fn write(&mut self, record: &LogRecord) {
+        use std::io::Write;
+
+        // a log that fails to write has nowhere to report it
+        let _ = writeln!(self.writer, "{} {}", record.time, record);
+
+        // flush right away so nothing is lost if the game crashes
+        let _ = self.writer.flush();
+    }
+}
+
+/// Sink half of a LogOverlay
+pub struct OverlaySink {
+    lines: Arc<Mutex<VecDeque<LogRecord>>>,
+    max_lines: usize
+}
+
+impl LogSink for OverlaySink {
+    fn write(&mut self, record: &LogRecord) {
+        let mut lines = self.lines.lock().unwrap();
+
+        lines.push_back(record.clone());
+
+        while lines.len() > self.max_lines {
+            lines.pop_front();
+        }
+    }
+}
+
+/// The latest log records drawn over the game, for live debugging. Add its sink to the Logger
+pub struct LogOverlay {
+    lines: Arc<Mutex<VecDeque<LogRecord>>>,
+    max_lines: usize,
+    pub visible: bool,
+    pub font_size: f32,
+    pub background: Color
+}
+
+impl LogOverlay {
+    pub fn new(max_lines: usize) -> Self {
+        Self {
+            lines: Arc::new(Mutex::new(VecDeque::new())),
+            max_lines,
+            visible: false,
+            font_size: 16.,
+            background: Color::new(0., 0., 0., 0.6),
+        }
+    }
+
+    pub fn sink(&self) -> OverlaySink {
+        OverlaySink {
+            lines: self.lines.clone(),
+            max_lines: self.max_lines,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.lock().unwrap().clear();
+    }
+
+    pub fn draw(&self, x: f32, y: f32, width: f32) {
+        if !self.visible {
+            return;
+        }
+
+        let lines = self.lines.lock().unwrap();
+
+        let line_height = self.font_size * 1.2;
+
+        draw_rectangle(x, y, width, line_height * self.max_lines as f32 + line_height * 0.5, self.background);
+
+        for (index, record) in lines.iter().enumerate() {
+            let color = match record.level {
+                LogLevel::Warn => Color::new(1., 0.85, 0.3, 1.),
+                LogLevel::Error => Color::new(1., 0.4, 0.4, 1.),
+                LogLevel::Trace | LogLevel::Debug => Color::new(0.7, 0.7, 0.7, 1.),
+                LogLevel::Info => WHITE,
+            };
+
+            draw_text(&record.to_string(), x + 4., y + line_height * (index + 1) as f32, self.font_size, color);
+        }
+    }
+}
+
+/// Decides which records are kept and where they go. Install it with init
+pub struct Logger {
+    pub level: LogLevel, // for targets without a filter
+    filters: Vec<(String, LogLevel)>, // target prefix, level
+    sinks: Vec<Arc<Mutex<dyn LogSink>>> // shared so records can be written without holding the logger
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self::new(LogLevel::Info).with_sink(ConsoleSink)
+    }
+}
+
+impl Logger {
+    pub fn new(level: LogLevel) -> Self {
+        Self {
+            level,
+            filters: vec![],
+            sinks: vec![],
+        }
+    }
+
+    /// Use `level` for every target starting with `target`, like "gamelibrary::sync". The longest matching prefix wins
+    pub fn with_filter(mut self, target: &str, level: LogLevel) -> Self {
+        self.filters.retain(|(existing, _)| existing != target);
+
+        self.filters.push((target.to_string(), level));
+
+        self
+    }
+
+    pub fn with_sink(mut self, sink: impl LogSink + 'static) -> Self {
+        self.sinks.push(Arc::new(Mutex::new(sink)));
+
+        self
+    }
+
+    pub fn enabled(&self, level: LogLevel, target: &str) -> bool {
+        let minimum = self.filters.iter()
+            .filter(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.level);
+
+        level >= minimum
+    }
+}
+
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+
+thread_local! {
+    static WRITING: Cell<bool> = const { Cell::new(false) }; // set while this thread is inside a sink
+}
+
+/// Replace the global logger. Until this is called, info and above go to the console
+pub fn init(logger: Logger) {
+    *LOGGER.lock().unwrap() = Some(logger);
+}
+
+/// Used by the logging macros
+pub fn log_record(level: LogLevel, target: &str, args: fmt::Arguments) {
+
+    // a sink that logs would end up writing to itself while it is already writing
+    if WRITING.with(|writing| writing.get()) {
+        return;
+    }
+
+    let sinks = {
+        let mut logger = LOGGER.lock().unwrap();
+
+        let logger = logger.get_or_insert_with(Logger::default);
+
+        if !logger.enabled(level, target) {
+            return;
+        }
+
+        logger.sinks.clone()
+    };
+
+    let record = LogRecord {
+        level,
+        target: target.to_string(),
+        message: args.to_string(),
+        time: current_unix_millis(),
+    };
+
+    WRITING.with(|writing| writing.set(true));
+
+    for sink in sinks {
+        // a sink that panicked before is skipped rather than taking the game down with it
+        if let Ok(mut sink) = sink.lock() {
+            sink.write(&record);
+        }
+    }
+
+    WRITING.with(|writing| writing.set(false));
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    ($($arg:tt)*) => { $crate::logging::log_record($crate::logging::LogLevel::Trace, module_path!(), format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => { $crate::logging::log_record($crate::logging::LogLevel::Debug, module_path!(), format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => { $crate::logging::log_record($crate::logging::LogLevel::Info, module_path!(), format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { $crate::logging::log_record($crate::logging::LogLevel::Warn, module_path!(), format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => { $crate::logging::log_record($crate::logging::LogLevel::Error, module_path!(), format_args!($($arg)*)) };
+}
@@ -0,0 +1,238 @@
+use diff::Diff;
+use macroquad::math::{Rect, Vec2};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Copy, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub enum LayoutDirection {
+    Vertical,
+    Horizontal
+}
+
+/// Where items sit across the stacking direction when they are different sizes
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Copy, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub enum Alignment {
+    Start,
+    Center,
+    End
+}
+
+impl Alignment {
+    fn fraction(&self) -> f32 {
+        match self {
+            Alignment::Start => 0.,
+            Alignment::Center => 0.5,
+            Alignment::End => 1.,
+        }
+    }
+}
+
+/// The point of the screen a menu is pinned to
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Copy, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub enum Anchor {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    CenterLeft,
+    Center,
+    CenterRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight
+}
+
+impl Anchor {
+    fn fraction(&self) -> Vec2 {
+        match self {
+            Anchor::TopLeft => Vec2::new(0., 0.),
+            Anchor::TopCenter => Vec2::new(0.5, 0.),
+            Anchor::TopRight => Vec2::new(1., 0.),
+            Anchor::CenterLeft => Vec2::new(0., 0.5),
+            Anchor::Center => Vec2::new(0.5, 0.5),
+            Anchor::CenterRight => Vec2::new(1., 0.5),
+            Anchor::BottomLeft => Vec2::new(0., 1.),
+            Anchor::BottomCenter => Vec2::new(0.5, 1.),
+            Anchor::BottomRight => Vec2::new(1., 1.),
+        }
+    }
+}
+
+/// How a Menu places its items. The default stacks 150x30 items vertically with no gaps, from the menu's position
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct MenuLayout {
+    pub direction: LayoutDirection,
+    pub spacing: f32, // between items
+    pub padding: f32, // between the items and the edge of the menu
+    pub alignment: Alignment,
+    pub anchor: Option<Anchor>, // when set the menu's position is an offset from this point of the screen or camera
+    pub item_size: Vec2, // size of items added with add_button and friends
+    pub min_width: f32, // limits for Menu::fit_text
+    pub max_width: f32
+}
+
+impl Default for MenuLayout {
+    fn default() -> Self {
+        Self {
+            direction: LayoutDirection::Vertical,
+            spacing: 0.,
+            padding: 0.,
+            alignment: Alignment::Start,
+            anchor: None,
+            item_size: Vec2::new(150., 30.),
+            min_width: 50.,
+            max_width: 400.,
+        }
+    }
+}
+
+impl MenuLayout {
+
+    pub fn vertical() -> Self {
+        Self::default()
+    }
+
+    pub fn horizontal() -> Self {
+        Self {
+            direction: LayoutDirection::Horizontal,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_spacing(mut self, spacing: f32) -> Self {
+        self.spacing = spacing;
+
+        self
+    }
+
+    pub fn with_padding(mut self, padding: f32) -> Self {
+        self.padding = padding;
+
+        self
+    }
+
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+
+        self
+    }
+
+    pub fn with_width_limits(mut self, min_width: f32, max_width: f32) -> Self {
+        self.min_width = min_width;
+        self.max_width = max_width;
+
+        self
+    }
+
+    pub fn with_anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = Some(anchor);
+
+        self
+    }
+
+    /// Place items of the given sizes. `bounds` is the area anchors are relative to.
+    /// Returns the rect of every item followed by the rect of the whole menu
+    pub fn arrange(&self, position: Vec2, sizes: &[Vec2], bounds: Rect) -> (Vec<Rect>, Rect) {
+
+        // swap into main axis (x) and cross axis (y) so both directions share the same math
+        let to_main_cross = |size: Vec2| match self.direction {
+            LayoutDirection::Vertical => Vec2::new(size.y, size.x),
+            LayoutDirection::Horizontal => size,
+        };
+
+        let main_length: f32 = sizes.iter().map(|size| to_main_cross(*size).x).sum::<f32>()
+            + self.spacing * sizes.len().saturating_sub(1) as f32;
+
+        let cross_length = sizes.iter().map(|size| to_main_cross(*size).y).fold(0., f32::max);
+
+        let menu_size = to_main_cross(Vec2::new(main_length, cross_length)) + Vec2::splat(self.padding * 2.);
+
+        let origin = match self.anchor {
+            Some(anchor) => bounds.point() + (bounds.size() - menu_size) * anchor.fraction() + position,
+            None => position,
+        };
+
+        let mut rects = Vec::with_capacity(sizes.len());
+
+        let mut cursor = self.padding;
+
+        for size in sizes {
+            let item = to_main_cross(*size);
+
+            let cross_offset = self.padding + (cross_length - item.y) * self.alignment.fraction();
+
+            let offset = to_main_cross(Vec2::new(cursor, cross_offset));
+
+            rects.push(Rect::new(origin.x + offset.x, origin.y + offset.y, size.x, size.y));
+
+            cursor += item.x + self.spacing;
+        }
+
+        (rects, Rect::new(origin.x, origin.y, menu_size.x, menu_size.y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::{vec2, Rect};
+
+    use super::{Alignment, Anchor, MenuLayout};
+
+    const SCREEN: Rect = Rect { x: 0., y: 0., w: 800., h: 600. };
+
+    #[test]
+    fn vertical_stacks_items_with_spacing_and_padding() {
+        let layout = MenuLayout::vertical().with_spacing(5.).with_padding(10.);
+
+        let (rects, menu) = layout.arrange(vec2(100., 50.), &[vec2(150., 30.), vec2(150., 20.)], SCREEN);
+
+        assert_eq!(rects, vec![Rect::new(110., 60., 150., 30.), Rect::new(110., 95., 150., 20.)]);
+        assert_eq!(menu, Rect::new(100., 50., 170., 75.));
+    }
+
+    #[test]
+    fn horizontal_aligns_across_the_row() {
+        let layout = MenuLayout::horizontal().with_spacing(10.).with_alignment(Alignment::Center);
+
+        let (rects, menu) = layout.arrange(vec2(0., 0.), &[vec2(40., 40.), vec2(60., 20.)], SCREEN);
+
+        assert_eq!(rects, vec![Rect::new(0., 0., 40., 40.), Rect::new(50., 10., 60., 20.)]);
+        assert_eq!(menu, Rect::new(0., 0., 110., 40.));
+
+        let (rects, _) = layout.with_alignment(Alignment::End).arrange(vec2(0., 0.), &[vec2(40., 40.), vec2(60., 20.)], SCREEN);
+
+        assert_eq!(rects[1], Rect::new(50., 20., 60., 20.));
+    }
+
+    #[test]
+    fn anchors_position_the_menu_inside_the_bounds() {
+        let sizes = [vec2(200., 100.)];
+
+        let (_, menu) = MenuLayout::vertical().with_anchor(Anchor::Center).arrange(vec2(0., 0.), &sizes, SCREEN);
+
+        assert_eq!(menu, Rect::new(300., 250., 200., 100.));
+
+        // the position becomes an offset from the anchor
+        let (_, menu) = MenuLayout::vertical().with_anchor(Anchor::BottomRight).arrange(vec2(-10., -10.), &sizes, SCREEN);
+
+        assert_eq!(menu, Rect::new(590., 490., 200., 100.));
+    }
+
+    #[test]
+    fn empty_menu_is_just_padding() {
+        let (rects, menu) = MenuLayout::vertical().with_padding(8.).arrange(vec2(5., 5.), &[], SCREEN);
+
+        assert!(rects.is_empty());
+        assert_eq!(menu, Rect::new(5., 5., 16., 16.));
+    }
+}
@@ -0,0 +1,856 @@
+use diff::Diff;
+use layout::MenuLayout;
+use macroquad::{camera::{pop_camera_state, push_camera_state, set_camera, set_default_camera}, color::Color, input::{self, mouse_position, mouse_wheel}, math::{Rect, Vec2}, shapes::draw_rectangle_lines, window::{screen_height, screen_width}, text::Font};
+use navigation::NavigationInput;
+use progress_bar::ProgressBar;
+use scroll_list::ScrollList;
+use serde::{Deserialize, Serialize};
+use theme::{ItemState, MenuTheme};
+
+use crate::{current_unix_millis, font_loader::FontLoader, virtual_screen::Viewport};
+
+pub mod layout;
+pub mod navigation;
+pub mod progress_bar;
+pub mod scroll_list;
+pub mod theme;
+
+/// Where a menu's positions live
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Copy, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub enum MenuSpace {
+    /// Pixels on the window, unaffected by the camera. Anchors are relative to the window
+    Screen,
+    /// World coordinates, drawn with the game's camera. Anchors are relative to the camera rect
+    World
+}
+
+
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct Menu {
+    items: Vec<MenuItem>,
+    position: Vec2,
+    pub theme: MenuTheme,
+    pub containing_rect: Rect,
+    pub layout: MenuLayout,
+    pub space: MenuSpace,
+    pub focused: Option<usize>, // item picked with the keyboard or a gamepad
+    pub keyboard_navigation: bool,
+    pub viewport: Option<Viewport>, // set when the game draws to a VirtualScreen so the mouse lines up
+    camera_rect: Option<Rect> // the last camera rect given to update, for world space anchoring
+}
+
+impl Menu {
+
+    /// A menu with the default theme in the given item color
+    pub fn new(position: Vec2, color: Color) -> Self {
+        Self {
+            items: vec![],
+            position: position,
+            theme: MenuTheme::with_background(color),
+            containing_rect: Rect::new(position.x, position.y, 0., 0.),
+            layout: MenuLayout::default(),
+            space: MenuSpace::Screen,
+            focused: None,
+            keyboard_navigation: true,
+            viewport: None,
+            camera_rect: None
+        }
+    }
+
+    pub fn with_theme(mut self, theme: MenuTheme) -> Self {
+        self.theme = theme;
+
+        self
+    }
+
+    pub fn set_theme(&mut self, theme: MenuTheme) {
+        self.theme = theme;
+    }
+
+    /// Keep this up to date with VirtualScreen::viewport, it changes when the window is resized
+    pub fn set_viewport(&mut self, viewport: Option<Viewport>) {
+        self.viewport = viewport;
+    }
+
+    /// Size of what the menu is drawn on in screen space, the virtual screen or the window
+    fn surface_size(&self) -> Vec2 {
+        match self.viewport {
+            Some(viewport) => viewport.size,
+            None => Vec2::new(screen_width(), screen_height()),
+        }
+    }
+
+    /// The mouse in the same space as the items
+    fn cursor_position(&self) -> Vec2 {
+        let surface_position = match self.viewport {
+            Some(viewport) => viewport.mouse_position(),
+            None => Vec2::from_array(mouse_position().into()),
+        };
+
+        // same mapping as mouse_world_pos
+        match (self.space, self.camera_rect) {
+            (MenuSpace::World, Some(camera_rect)) => camera_rect.point() + surface_position / self.surface_size() * camera_rect.size(),
+            _ => surface_position,
+        }
+    }
+
+    pub fn with_space(mut self, space: MenuSpace) -> Self {
+        self.space = space;
+
+        self
+    }
+
+    pub fn with_layout(mut self, layout: MenuLayout) -> Self {
+        self.layout = layout;
+
+        self.arrange();
+
+        self
+    }
+
+    pub fn set_position(&mut self, position: Vec2) {
+        self.position = position;
+
+        self.arrange();
+    }
+
+    /// Recompute every item's rect from the layout. Runs every update so anchored menus follow window resizes
+    pub fn arrange(&mut self) {
+        let sizes: Vec<Vec2> = self.items.iter().map(|menu_item| menu_item.base_rect().size()).collect();
+
+        let bounds = match (self.space, self.camera_rect) {
+            (MenuSpace::World, Some(camera_rect)) => camera_rect,
+            _ => Rect::new(0., 0., self.surface_size().x, self.surface_size().y),
+        };
+
+        let (rects, menu_rect) = self.layout.arrange(self.position, &sizes, bounds);
+
+        for (menu_item, rect) in self.items.iter_mut().zip(rects) {
+            menu_item.set_base_rect(rect);
+        }
+
+        self.containing_rect = menu_rect;
+    }
+
+    /// Resize every item to fit its text with the theme's font, within the layout's min_width and max_width.
+    /// Call it again after changing labels or the theme. Text that still doesn't fit is ellipsized when drawn
+    pub async fn fit_text(&mut self, fonts: &mut FontLoader) {
+
+        let font = match &self.theme.font {
+            Some(font_path) => Some(fonts.get(font_path).await),
+            None => None,
+        };
+
+        for menu_item in &mut self.items {
+
+            let text_width = match menu_item {
+                MenuItem::Button(button) => self.theme.label_width(&button.text, font),
+                MenuItem::Dropdown(dropdown) => {
+                    dropdown.options.iter()
+                        .map(|option| self.theme.label_width(option, font))
+                        .fold(0., f32::max) + DROPDOWN_ARROW_WIDTH
+                },
+                MenuItem::ScrollList(scroll_list) => {
+                    scroll_list.rows.iter()
+                        .map(|row| self.theme.label_width(row, font))
+                        .fold(0., f32::max)
+                },
+                MenuItem::ProgressBar(progress_bar) => {
+                    match progress_bar.label() {
+                        Some(label) => self.theme.label_width(&label, font),
+                        None => progress_bar.rect.w,
+                    }
+                },
+            };
+
+            let mut rect = menu_item.base_rect();
+
+            rect.w = text_width.clamp(self.layout.min_width, self.layout.max_width);
+
+            menu_item.set_base_rect(rect);
+        }
+
+        self.arrange();
+    }
+
+    /// Pass the camera rect the game draws with. Screen space menus ignore it
+    pub fn update(&mut self, camera_rect: Option<&Rect>) {
+
+        self.camera_rect = match self.space {
+            MenuSpace::World => camera_rect.copied(),
+            MenuSpace::Screen => None,
+        };
+
+        let mouse_position = self.cursor_position();
+
+        // also resets the containing rect because the menu items can change
+        self.arrange();
+
+        // an open dropdown list covers the items below it, so they shouldn't react to the mouse
+        let covered_rect = self.items.iter().find_map(|menu_item| {
+            match menu_item {
+                MenuItem::Dropdown(dropdown) if dropdown.expanded => Some(dropdown.options_rect()),
+                _ => None,
+            }
+        });
+
+        let mouse_covered = match covered_rect {
+            Some(covered_rect) => covered_rect.contains(mouse_position),
+            None => false,
+        };
+
+        for menu_item in &mut self.items {
+
+            match menu_item {
+                MenuItem::Button(button) => {
+                    button.update(mouse_position);
+
+                    if mouse_covered {
+                        button.block();
+                    }
+                },
+                MenuItem::Dropdown(dropdown) => dropdown.update(mouse_position),
+                MenuItem::ScrollList(scroll_list) => {
+                    scroll_list.update(mouse_position);
+
+                    if mouse_covered {
+                        scroll_list.block();
+                    }
+                },
+                MenuItem::ProgressBar(_) => {},
+            }
+
+            self.containing_rect = self.containing_rect.combine_with(menu_item.rect());
+        }
+
+        if self.keyboard_navigation {
+            self.navigate(NavigationInput::from_keyboard());
+        }
+
+    }
+
+    /// Move focus or activate the focused item. Update already does this for the keyboard.
+    /// For other inputs like a gamepad call it after update, otherwise update clears the clicks it makes
+    pub fn navigate(&mut self, navigation: NavigationInput) {
+
+        if navigation.is_empty() || self.items.is_empty() {
+            return;
+        }
+
+        // an open dropdown takes every key until it closes
+        for menu_item in &mut self.items {
+            if let MenuItem::Dropdown(dropdown) = menu_item {
+                if dropdown.expanded {
+                    dropdown.navigate(navigation);
+
+                    return;
+                }
+            }
+        }
+
+        if navigation.back {
+            self.focused = None;
+
+            return;
+        }
+
+        let item_count = self.items.len();
+
+        // the first press only shows where focus is
+        let focused = match self.focused {
+            Some(focused) if focused < item_count => focused,
+            _ => {
+                self.focused = Some(0);
+
+                return;
+            }
+        };
+
+        if navigation.previous {
+            self.focused = Some((focused + item_count - 1) % item_count);
+        }
+
+        if navigation.next {
+            self.focused = Some((focused + 1) % item_count);
+        }
+
+        if navigation.activate {
+            match &mut self.items[focused] {
+                MenuItem::Button(button) => button.clicked = true,
+                MenuItem::Dropdown(dropdown) => dropdown.navigate(navigation),
+                MenuItem::ScrollList(_) | MenuItem::ProgressBar(_) => {},
+            }
+        }
+    }
+
+    pub fn get_menu_items(&self) -> &Vec<MenuItem> {
+        &self.items
+    }
+
+    pub fn buttons(&self) -> impl Iterator<Item = &Button> {
+        self.items.iter().filter_map(|menu_item| {
+            match menu_item {
+                MenuItem::Button(button) => Some(button),
+                _ => None,
+            }
+        })
+    }
+
+    /// Get a dropdown by the index add_dropdown returned
+    pub fn get_dropdown(&self, index: usize) -> Option<&Dropdown> {
+        match self.items.get(index) {
+            Some(MenuItem::Dropdown(dropdown)) => Some(dropdown),
+            _ => None,
+        }
+    }
+
+    fn next_item_rect(&self) -> Rect {
+        // the real position gets filled in by arrange
+        Rect::new(self.position.x, self.position.y, self.layout.item_size.x, self.layout.item_size.y)
+    }
+
+    /// Get a button by the index add_button returned
+    pub fn get_button_mut(&mut self, index: usize) -> Option<&mut Button> {
+        match self.items.get_mut(index) {
+            Some(MenuItem::Button(button)) => Some(button),
+            _ => None,
+        }
+    }
+
+    /// Returns the button's index for get_button_mut
+    pub fn add_button(&mut self, text: String) -> usize {
+
+        self.items.push(
+            MenuItem::Button(
+                Button::new(text, self.next_item_rect())
+            )
+        );
+
+        self.arrange();
+
+        self.items.len() - 1
+    }
+
+    /// Add a dropdown with the first option selected. Returns its index for get_dropdown
+    pub fn add_dropdown(&mut self, options: Vec<String>) -> usize {
+
+        self.items.push(
+            MenuItem::Dropdown(
+                Dropdown::new(options, self.next_item_rect())
+            )
+        );
+
+        self.arrange();
+
+        self.items.len() - 1
+    }
+
+    /// Add a scroll list `height` tall. Returns its index for get_scroll_list
+    pub fn add_scroll_list(&mut self, rows: Vec<String>, height: f32) -> usize {
+
+        let mut rect = self.next_item_rect();
+
+        rect.h = height;
+
+        self.items.push(
+            MenuItem::ScrollList(
+                ScrollList::new(rows, rect, self.layout.item_size.y)
+            )
+        );
+
+        self.arrange();
+
+        self.items.len() - 1
+    }
+
+    pub fn get_scroll_list(&self, index: usize) -> Option<&ScrollList> {
+        match self.items.get(index) {
+            Some(MenuItem::ScrollList(scroll_list)) => Some(scroll_list),
+            _ => None,
+        }
+    }
+
+    pub fn get_scroll_list_mut(&mut self, index: usize) -> Option<&mut ScrollList> {
+        match self.items.get_mut(index) {
+            Some(MenuItem::ScrollList(scroll_list)) => Some(scroll_list),
+            _ => None,
+        }
+    }
+
+    /// Add an empty progress bar. Returns its index for get_progress_bar_mut
+    pub fn add_progress_bar(&mut self, fill: Color) -> usize {
+
+        self.items.push(
+            MenuItem::ProgressBar(
+                ProgressBar::new(self.next_item_rect(), fill)
+            )
+        );
+
+        self.arrange();
+
+        self.items.len() - 1
+    }
+
+    pub fn get_progress_bar_mut(&mut self, index: usize) -> Option<&mut ProgressBar> {
+        match self.items.get_mut(index) {
+            Some(MenuItem::ProgressBar(progress_bar)) => Some(progress_bar),
+            _ => None,
+        }
+    }
+
+    /// Where a rect in the menu's space ends up in the pixels of whatever the menu is being drawn to
+    fn to_screen_rect(&self, rect: Rect) -> Rect {
+        match (self.space, self.camera_rect, self.viewport) {
+            (MenuSpace::World, Some(camera_rect), _) => {
+                let scale = self.surface_size() / camera_rect.size();
+
+                let top_left = (rect.point() - camera_rect.point()) * scale;
+                let size = rect.size() * scale;
+
+                Rect::new(top_left.x, top_left.y, size.x, size.y)
+            },
+            // screen space menus are drawn straight to the window even with a virtual screen
+            (MenuSpace::Screen, _, Some(viewport)) => {
+                let top_left = viewport.to_window(rect.point());
+                let size = rect.size() * viewport.scale();
+
+                Rect::new(top_left.x, top_left.y, size.x, size.y)
+            },
+            _ => rect,
+        }
+    }
+
+    /// World space menus draw with whatever camera is set. Screen space menus switch to the default camera while drawing,
+    /// or to the viewport's window camera when drawing over a virtual screen, so call it after VirtualScreen::end
+    pub async fn draw(&self, fonts: &mut FontLoader) {
+
+        let font = match &self.theme.font {
+            Some(font_path) => Some(fonts.get(font_path).await),
+            None => None,
+        };
+
+        if self.space == MenuSpace::Screen {
+            push_camera_state();
+
+            match self.viewport {
+                Some(viewport) => set_camera(&viewport.window_camera()),
+                None => set_default_camera(),
+            }
+        }
+
+        for item in &self.items {
+            match item {
+                MenuItem::Button(button) => button.draw(&self.theme, font).await,
+                MenuItem::Dropdown(dropdown) => dropdown.draw(&self.theme, font).await,
+                MenuItem::ScrollList(scroll_list) => scroll_list.draw(&self.theme, font, self.to_screen_rect(scroll_list.rect)).await,
+                MenuItem::ProgressBar(progress_bar) => progress_bar.draw(&self.theme, font).await,
+            }
+        }
+
+        if let Some(focused) = self.focused.and_then(|focused| self.items.get(focused)) {
+            let rect = focused.base_rect();
+
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, self.theme.border_thickness, self.theme.focus);
+        }
+
+        // open lists go on top of everything else
+        for item in &self.items {
+            if let MenuItem::Dropdown(dropdown) = item {
+                dropdown.draw_options(&self.theme, font).await;
+            }
+        }
+
+        if let Some(outline) = self.theme.outline {
+            draw_rectangle_lines(self.containing_rect.x, self.containing_rect.y, self.containing_rect.w, self.containing_rect.h, self.theme.border_thickness, outline);
+        }
+
+        if self.space == MenuSpace::Screen {
+            pop_camera_state();
+        }
+
+    }
+}
+
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub enum MenuItem {
+    Button(Button),
+    Dropdown(Dropdown),
+    ScrollList(ScrollList),
+    ProgressBar(ProgressBar)
+}
+
+impl MenuItem {
+    /// The area the item currently covers, including an open dropdown's list
+    pub fn rect(&self) -> Rect {
+        match self {
+            MenuItem::Button(button) => button.rect,
+            MenuItem::Dropdown(dropdown) => dropdown.bounding_rect(),
+            MenuItem::ScrollList(scroll_list) => scroll_list.rect,
+            MenuItem::ProgressBar(progress_bar) => progress_bar.rect,
+        }
+    }
+
+    /// The rect the layout places, not counting an open dropdown's list
+    pub fn base_rect(&self) -> Rect {
+        match self {
+            MenuItem::Button(button) => button.rect,
+            MenuItem::Dropdown(dropdown) => dropdown.rect,
+            MenuItem::ScrollList(scroll_list) => scroll_list.rect,
+            MenuItem::ProgressBar(progress_bar) => progress_bar.rect,
+        }
+    }
+
+    pub fn set_base_rect(&mut self, rect: Rect) {
+        match self {
+            MenuItem::Button(button) => button.rect = rect,
+            MenuItem::Dropdown(dropdown) => dropdown.rect = rect,
+            MenuItem::ScrollList(scroll_list) => scroll_list.rect = rect,
+            MenuItem::ProgressBar(progress_bar) => progress_bar.rect = rect,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct Button {
+    pub rect: Rect,
+    pub text: String,
+    pub hovered: bool,
+    pub clicked: bool, // released over the button after pressing it, or a repeat fired
+    pub pressed: bool, // a press started on the button this frame
+    pub held: bool, // a press that started on the button is still down
+    pub released: bool, // the press ended this frame, over the button or not
+    pub repeat: Option<ButtonRepeat>,
+    held_since: u64, // unix millis
+    last_repeat: u64 // unix millis
+}
+
+/// Makes a held button click on press and then keep clicking, like a held key
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Copy, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct ButtonRepeat {
+    pub delay: u64, // millis before the first repeat
+    pub interval: u64 // millis between repeats after that
+}
+
+impl Button {
+
+    pub fn new(text: String, rect: Rect) -> Self {
+        Self {
+            rect,
+            text,
+            hovered: false,
+            clicked: false,
+            pressed: false,
+            held: false,
+            released: false,
+            repeat: None,
+            held_since: 0,
+            last_repeat: 0,
+        }
+    }
+
+    pub fn with_repeat(mut self, delay: u64, interval: u64) -> Self {
+        self.repeat = Some(ButtonRepeat { delay, interval });
+
+        self
+    }
+
+    pub fn state(&self) -> ItemState {
+        match (self.hovered, self.held) {
+            (true, true) => ItemState::Pressed,
+            (true, false) => ItemState::Hovered,
+            (false, _) => ItemState::Idle,
+        }
+    }
+
+    /// Something is on top of the button, so drop the hover and any press in progress
+    pub fn block(&mut self) {
+        self.hovered = false;
+        self.clicked = false;
+        self.pressed = false;
+        self.held = false;
+    }
+
+    pub async fn draw(&self, theme: &MenuTheme, font: Option<&Font>) {
+
+        let state = self.state();
+
+        theme.draw_panel(self.rect, state);
+        theme.draw_label(&self.text, self.rect, state, font);
+    }
+
+    /// `mouse_position` is in the same space as the button's rect
+    pub fn update(&mut self, mouse_position: Vec2) {
+
+        self.hovered = self.rect.contains(mouse_position);
+        self.clicked = false;
+        self.pressed = false;
+        self.released = false;
+
+        let now = current_unix_millis();
+
+        // only presses that start on the button count, so dragging across it does nothing
+        if self.hovered && input::is_mouse_button_pressed(input::MouseButton::Left) {
+            self.pressed = true;
+            self.held = true;
+            self.held_since = now;
+            self.last_repeat = now;
+
+            if self.repeat.is_some() {
+                self.clicked = true;
+            }
+
+            return;
+        }
+
+        if !self.held {
+            return;
+        }
+
+        if input::is_mouse_button_released(input::MouseButton::Left) || !input::is_mouse_button_down(input::MouseButton::Left) {
+            self.held = false;
+            self.released = true;
+
+            // repeating buttons already clicked on press
+            if self.hovered && self.repeat.is_none() {
+                self.clicked = true;
+            }
+
+            return;
+        }
+
+        if let Some(repeat) = self.repeat {
+
+            // stop repeating while the mouse is off the button, but keep the press so coming back resumes it
+            if self.hovered
+                && now.saturating_sub(self.held_since) >= repeat.delay
+                && now.saturating_sub(self.last_repeat) >= repeat.interval {
+
+                self.clicked = true;
+                self.last_repeat = now;
+            }
+        }
+    }
+}
+
+
+
+/// Space at the right of a dropdown for the arrow
+const DROPDOWN_ARROW_WIDTH: f32 = 18.;
+
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct Dropdown {
+    pub rect: Rect, // the collapsed part that shows the selection
+    pub options: Vec<String>,
+    pub selected: usize,
+    pub expanded: bool,
+    pub hovered: bool,
+    pub highlighted: Option<usize>, // option under the mouse or picked with the keyboard while expanded
+    pub changed: bool // the selection changed this frame
+}
+
+impl Dropdown {
+
+    pub fn new(options: Vec<String>, rect: Rect) -> Self {
+        Self {
+            rect,
+            options,
+            selected: 0,
+            expanded: false,
+            hovered: false,
+            highlighted: None,
+            changed: false,
+        }
+    }
+
+    pub fn selected_option(&self) -> Option<&String> {
+        self.options.get(self.selected)
+    }
+
+    fn option_rect(&self, index: usize) -> Rect {
+        Rect::new(self.rect.x, self.rect.y + self.rect.h * (index + 1) as f32, self.rect.w, self.rect.h)
+    }
+
+    /// The area the option list covers when expanded
+    pub fn options_rect(&self) -> Rect {
+        Rect::new(self.rect.x, self.rect.y + self.rect.h, self.rect.w, self.rect.h * self.options.len() as f32)
+    }
+
+    pub fn bounding_rect(&self) -> Rect {
+        match self.expanded {
+            true => self.rect.combine_with(self.options_rect()),
+            false => self.rect,
+        }
+    }
+
+    fn select(&mut self, index: usize) {
+        if index != self.selected {
+            self.selected = index;
+            self.changed = true;
+        }
+
+        self.expanded = false;
+        self.highlighted = None;
+    }
+
+    /// `mouse_position` is in the same space as the dropdown's rect
+    pub fn update(&mut self, mouse_position: Vec2) {
+
+        self.changed = false;
+        self.hovered = self.rect.contains(mouse_position);
+
+        if self.options.is_empty() {
+            self.expanded = false;
+            return;
+        }
+
+        let clicked = input::is_mouse_button_pressed(input::MouseButton::Left);
+
+        let scroll = mouse_wheel().1;
+
+        if !self.expanded {
+
+            if self.hovered && clicked {
+                self.expanded = true;
+                self.highlighted = Some(self.selected);
+                return;
+            }
+
+            // scrolling over a closed dropdown flips through the options
+            if self.hovered && scroll != 0. {
+                let next = match scroll > 0. {
+                    true => self.selected.saturating_sub(1),
+                    false => (self.selected + 1).min(self.options.len() - 1),
+                };
+
+                self.select(next);
+            }
+
+            return;
+        }
+
+        if let Some(index) = (0..self.options.len()).find(|index| self.option_rect(*index).contains(mouse_position)) {
+            self.highlighted = Some(index);
+
+            if clicked {
+                self.select(index);
+                return;
+            }
+        }
+
+        let highlighted = self.highlighted.unwrap_or(self.selected);
+
+        if scroll > 0. {
+            self.highlighted = Some(highlighted.saturating_sub(1));
+        }
+
+        if scroll < 0. {
+            self.highlighted = Some((highlighted + 1).min(self.options.len() - 1));
+        }
+
+        // clicking anywhere else closes the list without changing the selection
+        if (clicked && !self.bounding_rect().contains(mouse_position)) || (clicked && self.hovered) {
+            self.expanded = false;
+            self.highlighted = None;
+        }
+    }
+
+    /// Activate opens the list. While open, previous and next move the highlight, activate picks it and back closes the list
+    pub fn navigate(&mut self, navigation: NavigationInput) {
+
+        if self.options.is_empty() {
+            return;
+        }
+
+        if !self.expanded {
+            if navigation.activate {
+                self.expanded = true;
+                self.highlighted = Some(self.selected);
+            }
+
+            return;
+        }
+
+        let highlighted = self.highlighted.unwrap_or(self.selected);
+
+        if navigation.previous {
+            self.highlighted = Some(highlighted.saturating_sub(1));
+        }
+
+        if navigation.next {
+            self.highlighted = Some((highlighted + 1).min(self.options.len() - 1));
+        }
+
+        if navigation.activate {
+            self.select(self.highlighted.unwrap_or(self.selected));
+
+            return;
+        }
+
+        if navigation.back {
+            self.expanded = false;
+            self.highlighted = None;
+        }
+    }
+
+    pub async fn draw(&self, theme: &MenuTheme, font: Option<&Font>) {
+
+        let state = match self.hovered {
+            true => ItemState::Hovered,
+            false => ItemState::Idle
+        };
+
+        let text = match self.selected_option() {
+            Some(option) => option.as_str(),
+            None => "",
+        };
+
+        theme.draw_panel(self.rect, state);
+        theme.draw_label(text, Rect::new(self.rect.x, self.rect.y, self.rect.w - DROPDOWN_ARROW_WIDTH, self.rect.h), state, font);
+
+        // arrow showing which way the list opens
+        let arrow = match self.expanded {
+            true => "^",
+            false => "v",
+        };
+
+        theme.draw_label(arrow, Rect::new(self.rect.x + self.rect.w - DROPDOWN_ARROW_WIDTH, self.rect.y, DROPDOWN_ARROW_WIDTH, self.rect.h), state, font);
+    }
+
+    pub async fn draw_options(&self, theme: &MenuTheme, font: Option<&Font>) {
+
+        if !self.expanded {
+            return;
+        }
+
+        for (index, option) in self.options.iter().enumerate() {
+            let option_rect = self.option_rect(index);
+
+            let state = match (self.highlighted == Some(index), index == self.selected) {
+                (true, _) => ItemState::Hovered,
+                (false, true) => ItemState::Selected,
+                (false, false) => ItemState::Idle,
+            };
+
+            theme.draw_panel(option_rect, state);
+            theme.draw_label(option, option_rect, state, font);
+        }
+    }
+}
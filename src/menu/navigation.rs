@@ -0,0 +1,38 @@
+use macroquad::input::{is_key_pressed, KeyCode};
+
+/// One frame of menu navigation. Read from the keyboard automatically, or built by the game from a gamepad
+/// (D-pad for previous/next, South for activate, East for back) and passed to Menu::navigate
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct NavigationInput {
+    pub previous: bool,
+    pub next: bool,
+    pub activate: bool,
+    pub back: bool
+}
+
+impl NavigationInput {
+
+    /// Arrow keys move, enter or space activates, escape backs out
+    pub fn from_keyboard() -> Self {
+        Self {
+            previous: is_key_pressed(KeyCode::Up) || is_key_pressed(KeyCode::Left),
+            next: is_key_pressed(KeyCode::Down) || is_key_pressed(KeyCode::Right),
+            activate: is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::Space),
+            back: is_key_pressed(KeyCode::Escape),
+        }
+    }
+
+    /// Pressed in either
+    pub fn combine(self, other: Self) -> Self {
+        Self {
+            previous: self.previous || other.previous,
+            next: self.next || other.next,
+            activate: self.activate || other.activate,
+            back: self.back || other.back,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
@@ -0,0 +1,81 @@
+use diff::Diff;
+use macroquad::{color::Color, math::{vec2, Rect}, shapes::draw_rectangle, text::Font};
+use rapier2d::dynamics::RigidBodyHandle;
+use serde::{Deserialize, Serialize};
+
+use crate::{coordinates::CoordinateSystem, space::Space};
+
+use super::theme::{ItemState, MenuTheme};
+
+/// A bar that fills from left to right, for loading screens and health bars.
+/// Works as a menu item or on its own, in which case it is drawn wherever the current camera puts it
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct ProgressBar {
+    pub rect: Rect,
+    pub value: f32, // 0 is empty and 1 is full
+    pub fill: Color,
+    pub text: Option<String> // drawn over the bar. {percent} is replaced with the value as a percentage
+}
+
+impl ProgressBar {
+
+    pub fn new(rect: Rect, fill: Color) -> Self {
+        Self {
+            rect,
+            value: 0.,
+            fill,
+            text: None,
+        }
+    }
+
+    pub fn with_text(mut self, text: String) -> Self {
+        self.text = Some(text);
+
+        self
+    }
+
+    pub fn set_value(&mut self, value: f32) {
+        self.value = value.clamp(0., 1.);
+    }
+
+    /// The overlay text with {percent} filled in
+    pub fn label(&self) -> Option<String> {
+        self.text.as_ref().map(|text| {
+            text.replace("{percent}", &format!("{:.0}%", self.value.clamp(0., 1.) * 100.))
+        })
+    }
+
+    /// Center the bar horizontally over a rigid body with its bottom `offset` above the body's origin, for health bars.
+    /// The bar ends up in world space, so draw it with the game's camera
+    pub fn place_above_body(&mut self, space: &Space, rigid_body_handle: RigidBodyHandle, offset: f32, coordinates: &CoordinateSystem) {
+        let rigid_body = space.rigid_body_set.get(rigid_body_handle).unwrap();
+
+        let position = rigid_body.position().translation;
+
+        let draw_pos = coordinates.rapier_to_macroquad(&vec2(position.x, position.y));
+
+        self.rect.x = draw_pos.x - self.rect.w / 2.;
+        self.rect.y = draw_pos.y - offset - self.rect.h;
+    }
+
+    pub async fn draw(&self, theme: &MenuTheme, font: Option<&Font>) {
+
+        theme.draw_panel(self.rect, ItemState::Idle);
+
+        // inset by the border so the fill doesn't cover it
+        let inset = theme.border_thickness / 2.;
+
+        let fill_width = (self.rect.w - inset * 2.).max(0.) * self.value.clamp(0., 1.);
+
+        draw_rectangle(self.rect.x + inset, self.rect.y + inset, fill_width, self.rect.h - inset * 2., self.fill);
+
+        theme.draw_panel_border(self.rect);
+
+        if let Some(label) = self.label() {
+            theme.draw_label(&label, self.rect, ItemState::Idle, font);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use diff::Diff;
+use macroquad::{input::{self, mouse_wheel}, math::{Rect, Vec2}, shapes::draw_rectangle, text::Font, window::{get_internal_gl, screen_height}};
+use serde::{Deserialize, Serialize};
+
+use super::theme::{ItemState, MenuTheme};
+
+/// How far the mouse has to move while pressed before a press becomes a drag instead of a click
+const DRAG_THRESHOLD: f32 = 5.;
+
+/// A fixed size panel of rows that scrolls with the mouse wheel or by dragging, for lists too long to fit on screen
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct ScrollList {
+    pub rect: Rect,
+    pub rows: Vec<String>,
+    pub row_height: f32,
+    pub scroll: f32, // how far the rows are scrolled up, in the menu's units
+    pub hovered: Option<usize>, // row under the mouse
+    pub clicked: Option<usize>, // row clicked this frame
+    drag_start: Option<(f32, f32)>, // mouse y and scroll when the press started
+    dragging: bool
+}
+
+impl ScrollList {
+
+    pub fn new(rows: Vec<String>, rect: Rect, row_height: f32) -> Self {
+        Self {
+            rect,
+            rows,
+            row_height,
+            scroll: 0.,
+            hovered: None,
+            clicked: None,
+            drag_start: None,
+            dragging: false,
+        }
+    }
+
+    pub fn max_scroll(&self) -> f32 {
+        (self.rows.len() as f32 * self.row_height - self.rect.h).max(0.)
+    }
+
+    pub fn row_rect(&self, index: usize) -> Rect {
+        Rect::new(self.rect.x, self.rect.y + index as f32 * self.row_height - self.scroll, self.rect.w, self.row_height)
+    }
+
+    /// Scroll just far enough that the row is fully visible
+    pub fn scroll_to(&mut self, index: usize) {
+        let top = index as f32 * self.row_height;
+
+        if top < self.scroll {
+            self.scroll = top;
+        }
+
+        else if top + self.row_height > self.scroll + self.rect.h {
+            self.scroll = top + self.row_height - self.rect.h;
+        }
+
+        self.scroll = self.scroll.clamp(0., self.max_scroll());
+    }
+
+    /// Something is on top of the list, so drop the hover and any press in progress
+    pub fn block(&mut self) {
+        self.hovered = None;
+        self.clicked = None;
+        self.drag_start = None;
+        self.dragging = false;
+    }
+
+    /// `mouse_position` is in the same space as the list's rect
+    pub fn update(&mut self, mouse_position: Vec2) {
+
+        self.clicked = None;
+
+        let inside = self.rect.contains(mouse_position);
+
+        // rows scrolled out of the panel can't be hovered
+        self.hovered = match inside {
+            true => {
+                let index = ((mouse_position.y - self.rect.y + self.scroll) / self.row_height).floor() as usize;
+
+                (index < self.rows.len()).then_some(index)
+            },
+            false => None,
+        };
+
+        let scroll = mouse_wheel().1;
+
+        if inside && scroll != 0. {
+            self.scroll -= scroll.signum() * self.row_height;
+        }
+
+        if inside && input::is_mouse_button_pressed(input::MouseButton::Left) {
+            self.drag_start = Some((mouse_position.y, self.scroll));
+            self.dragging = false;
+        }
+
+        if let Some((start_y, start_scroll)) = self.drag_start {
+
+            if (mouse_position.y - start_y).abs() > DRAG_THRESHOLD {
+                self.dragging = true;
+            }
+
+            if self.dragging {
+                self.scroll = start_scroll - (mouse_position.y - start_y);
+            }
+
+            if !input::is_mouse_button_down(input::MouseButton::Left) {
+
+                // a press that turned into a drag isn't a click
+                if !self.dragging {
+                    self.clicked = self.hovered;
+                }
+
+                self.drag_start = None;
+                self.dragging = false;
+            }
+        }
+
+        self.scroll = self.scroll.clamp(0., self.max_scroll());
+    }
+
+    /// `clip` is the list's rect in screen pixels, rows outside of it are cut off
+    pub async fn draw(&self, theme: &MenuTheme, font: Option<&Font>, clip: Rect) {
+
+        let (background, _) = theme.colors(ItemState::Idle);
+
+        draw_rectangle(self.rect.x, self.rect.y, self.rect.w, self.rect.h, background);
+
+        let gl = unsafe { get_internal_gl() };
+
+        // everything queued before this would be clipped too
+        gl.flush();
+
+        // gl scissor rects start from the bottom of the window
+        gl.quad_gl.scissor(Some((clip.x as i32, (screen_height() - clip.y - clip.h) as i32, clip.w as i32, clip.h as i32)));
+
+        let first_visible = (self.scroll / self.row_height).floor() as usize;
+        let visible_count = (self.rect.h / self.row_height).ceil() as usize + 1;
+
+        for (index, row) in self.rows.iter().enumerate().skip(first_visible).take(visible_count) {
+
+            let state = match (self.hovered == Some(index), self.drag_start.is_some() && !self.dragging) {
+                (true, true) => ItemState::Pressed,
+                (true, false) => ItemState::Hovered,
+                (false, _) => ItemState::Idle,
+            };
+
+            let row_rect = self.row_rect(index);
+
+            theme.draw_panel(row_rect, state);
+            theme.draw_label(row, row_rect, state, font);
+        }
+
+        gl.flush();
+
+        gl.quad_gl.scissor(None);
+
+        // scroll bar, only when there is something to scroll
+        let max_scroll = self.max_scroll();
+
+        if max_scroll > 0. {
+            let thumb_height = (self.rect.h * self.rect.h / (self.rect.h + max_scroll)).max(10.);
+            let thumb_y = self.rect.y + (self.rect.h - thumb_height) * (self.scroll / max_scroll);
+
+            draw_rectangle(self.rect.x + self.rect.w - 4., thumb_y, 4., thumb_height, theme.border);
+        }
+
+        theme.draw_panel_border(self.rect);
+    }
+}
@@ -0,0 +1,200 @@
+use diff::Diff;
+use macroquad::{color::{Color, BLACK, DARKGRAY, GRAY, LIGHTGRAY, WHITE, YELLOW}, math::Rect, shapes::{draw_arc, draw_circle, draw_line, draw_rectangle, draw_rectangle_lines}, text::{draw_text_ex, measure_text, Font, TextDimensions, TextParams}};
+use serde::{Deserialize, Serialize};
+
+/// Space between a label and the left and right edges of its item
+pub const LABEL_PADDING: f32 = 3.;
+
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Copy, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub enum CornerStyle {
+    Square,
+    Rounded(f32) // radius
+}
+
+/// What an item is doing right now, which decides the colors it is drawn with
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ItemState {
+    Idle,
+    Hovered,
+    Pressed,
+    Selected // the current choice in an open dropdown
+}
+
+/// How every item in a Menu looks. Swap it at runtime with Menu::set_theme
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct MenuTheme {
+    pub background: Color,
+    pub text: Color,
+    pub hovered_background: Color,
+    pub hovered_text: Color,
+    pub pressed_background: Color,
+    pub pressed_text: Color,
+    pub selected_background: Color,
+    pub border: Color,
+    pub border_thickness: f32,
+    pub outline: Option<Color>, // drawn around the whole menu
+    pub font: Option<String>, // path to a ttf loaded through the FontLoader given to Menu::draw. None uses macroquad's default font
+    pub focus: Color, // drawn around the item focused with the keyboard or a gamepad
+    pub font_size: f32,
+    pub corner_style: CornerStyle
+}
+
+impl Default for MenuTheme {
+    fn default() -> Self {
+        Self {
+            background: GRAY,
+            text: WHITE,
+            hovered_background: WHITE,
+            hovered_text: BLACK,
+            pressed_background: LIGHTGRAY,
+            pressed_text: BLACK,
+            selected_background: DARKGRAY,
+            border: BLACK,
+            border_thickness: 3.,
+            outline: Some(WHITE),
+            focus: YELLOW,
+            font: None,
+            font_size: 20.,
+            corner_style: CornerStyle::Square,
+        }
+    }
+}
+
+impl MenuTheme {
+
+    /// The default theme with a different item color
+    pub fn with_background(background: Color) -> Self {
+        Self {
+            background,
+            ..Default::default()
+        }
+    }
+
+    /// Background and text color for an item in this state
+    pub fn colors(&self, state: ItemState) -> (Color, Color) {
+        match state {
+            ItemState::Idle => (self.background, self.text),
+            ItemState::Hovered => (self.hovered_background, self.hovered_text),
+            ItemState::Pressed => (self.pressed_background, self.pressed_text),
+            ItemState::Selected => (self.selected_background, self.text),
+        }
+    }
+
+    fn corners(rect: Rect, radius: f32) -> [(f32, f32, f32); 4] {
+        // center of each corner circle and the angle its arc starts at
+        [
+            (rect.x + radius, rect.y + radius, 180.),
+            (rect.x + rect.w - radius, rect.y + radius, 270.),
+            (rect.x + rect.w - radius, rect.y + rect.h - radius, 0.),
+            (rect.x + radius, rect.y + rect.h - radius, 90.),
+        ]
+    }
+
+    /// Draw an item's background and border
+    pub fn draw_panel(&self, rect: Rect, state: ItemState) {
+        let (background, _) = self.colors(state);
+
+        match self.corner_style {
+            CornerStyle::Square => {
+                draw_rectangle(rect.x, rect.y, rect.w, rect.h, background);
+            },
+            CornerStyle::Rounded(radius) => {
+                let radius = radius.min(rect.w / 2.).min(rect.h / 2.);
+
+                // a cross of two rects with a circle filling each corner
+                draw_rectangle(rect.x + radius, rect.y, rect.w - radius * 2., rect.h, background);
+                draw_rectangle(rect.x, rect.y + radius, rect.w, rect.h - radius * 2., background);
+
+                for (x, y, _) in Self::corners(rect, radius) {
+                    draw_circle(x, y, radius, background);
+                }
+            },
+        }
+
+        self.draw_panel_border(rect);
+    }
+
+    pub fn draw_panel_border(&self, rect: Rect) {
+
+        if self.border_thickness <= 0. {
+            return;
+        }
+
+        let thickness = self.border_thickness;
+
+        match self.corner_style {
+            CornerStyle::Square => {
+                draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, thickness, self.border);
+            },
+            CornerStyle::Rounded(radius) => {
+                let radius = radius.min(rect.w / 2.).min(rect.h / 2.);
+
+                draw_line(rect.x + radius, rect.y, rect.x + rect.w - radius, rect.y, thickness, self.border);
+                draw_line(rect.x + radius, rect.y + rect.h, rect.x + rect.w - radius, rect.y + rect.h, thickness, self.border);
+                draw_line(rect.x, rect.y + radius, rect.x, rect.y + rect.h - radius, thickness, self.border);
+                draw_line(rect.x + rect.w, rect.y + radius, rect.x + rect.w, rect.y + rect.h - radius, thickness, self.border);
+
+                for (x, y, rotation) in Self::corners(rect, radius) {
+                    draw_arc(x, y, 16, radius - thickness / 2., rotation, thickness, 90., self.border);
+                }
+            },
+        }
+    }
+
+    pub fn measure(&self, text: &str, font: Option<&Font>) -> TextDimensions {
+        measure_text(text, font, self.font_size as u16, 1.)
+    }
+
+    /// The width an item needs to fit the label without cutting it off
+    pub fn label_width(&self, text: &str, font: Option<&Font>) -> f32 {
+        self.measure(text, font).width + LABEL_PADDING * 2.
+    }
+
+    /// Cut the text down and end it with "..." so it is at most `max_width` wide
+    pub fn ellipsize(&self, text: &str, max_width: f32, font: Option<&Font>) -> String {
+
+        if self.measure(text, font).width <= max_width {
+            return text.to_string();
+        }
+
+        let mut characters: Vec<char> = text.chars().collect();
+
+        while !characters.is_empty() {
+            characters.pop();
+
+            let shortened = format!("{}...", characters.iter().collect::<String>());
+
+            if self.measure(&shortened, font).width <= max_width {
+                return shortened;
+            }
+        }
+
+        String::new()
+    }
+
+    /// Draw a label inside an item, left aligned and vertically centered like the default buttons.
+    /// Labels too wide for the rect are ellipsized
+    pub fn draw_label(&self, text: &str, rect: Rect, state: ItemState, font: Option<&Font>) {
+        let (_, text_color) = self.colors(state);
+
+        let text = self.ellipsize(text, rect.w - LABEL_PADDING * 2., font);
+
+        draw_text_ex(
+            &text, 
+            rect.x + LABEL_PADDING, 
+            rect.y + rect.h / 2., 
+            TextParams {
+                font,
+                font_size: self.font_size as u16,
+                color: text_color,
+                ..Default::default()
+            }
+        );
+    }
+}
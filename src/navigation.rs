@@ -0,0 +1,438 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use fxhash::FxHashMap;
+use macroquad::math::{vec2, Rect, Vec2};
+use nalgebra::Isometry2;
+use rapier2d::{geometry::{Aabb, ColliderHandle, Cuboid}, pipeline::QueryFilter};
+
+use crate::space::Space;
+
+const DIAGONAL_COST: f32 = std::f32::consts::SQRT_2;
+
+/// A path found on a NavGrid, in rapier coords
+#[derive(Clone, Debug, PartialEq)]
+pub struct NavPath {
+    pub waypoints: Vec<Vec2>,
+    revision: u64 // the grid revision it was planned on
+}
+
+impl NavPath {
+
+    /// Whether the grid changed under the path in a way that blocks it. Cheap when nothing changed since it was planned
+    pub fn needs_replan(&self, grid: &NavGrid) -> bool {
+        if self.revision == grid.revision {
+            return false;
+        }
+
+        self.waypoints.windows(2).any(|segment| !grid.line_clear(segment[0], segment[1]))
+    }
+}
+
+#[derive(PartialEq)]
+struct OpenCell {
+    cost: f32, // cost so far plus the heuristic
+    cell: usize
+}
+
+impl Eq for OpenCell {}
+
+impl Ord for OpenCell {
+    // BinaryHeap is a max heap, so the order is reversed to pop the cheapest cell first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl PartialOrd for OpenCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A walkability grid over part of a Space, built from its fixed colliders, with A* path queries.
+/// Call refresh every so often to pick up colliders that were added, removed or moved
+pub struct NavGrid {
+    pub bounds: Rect, // rapier coords, x and y are the bottom left corner
+    pub cell_size: f32,
+    pub agent_radius: f32, // cells closer than this to a collider are blocked, so agents don't clip corners
+    width: usize,
+    height: usize,
+    blocked: Vec<bool>,
+    obstacles: FxHashMap<ColliderHandle, Aabb>, // what the grid was last built from
+    revision: u64 // bumped whenever a cell changes
+}
+
+impl NavGrid {
+
+    pub fn new(space: &mut Space, bounds: Rect, cell_size: f32, agent_radius: f32) -> Self {
+        let width = (bounds.w / cell_size).ceil().max(1.) as usize;
+        let height = (bounds.h / cell_size).ceil().max(1.) as usize;
+
+        let mut grid = Self {
+            bounds,
+            cell_size,
+            agent_radius,
+            width,
+            height,
+            blocked: vec![false; width * height],
+            obstacles: FxHashMap::default(),
+            revision: 0,
+        };
+
+        grid.obstacles = Self::fixed_obstacles(space);
+
+        grid.rebuild_region(space, bounds);
+
+        grid
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Every collider that doesn't move on its own: ones without a body or on a fixed body. Sensors don't block
+    fn fixed_obstacles(space: &Space) -> FxHashMap<ColliderHandle, Aabb> {
+        space.collider_set.iter()
+            .filter(|(_, collider)| !collider.is_sensor())
+            .filter(|(_, collider)| match collider.parent() {
+                Some(rigid_body_handle) => space.rigid_body_set.get(rigid_body_handle).is_some_and(|rigid_body| rigid_body.is_fixed()),
+                None => true,
+            })
+            .map(|(collider_handle, collider)| (collider_handle, collider.compute_aabb()))
+            .collect()
+    }
+
+    /// Rebuild the cells around any fixed collider that changed since the last refresh. Returns whether anything changed
+    pub fn refresh(&mut self, space: &mut Space) -> bool {
+        let obstacles = Self::fixed_obstacles(space);
+
+        let mut changed: Vec<Aabb> = vec![];
+
+        for (collider_handle, aabb) in &obstacles {
+            match self.obstacles.get(collider_handle) {
+                Some(old_aabb) if old_aabb == aabb => {},
+                Some(old_aabb) => {
+                    changed.push(*old_aabb);
+                    changed.push(*aabb);
+                },
+                None => changed.push(*aabb),
+            }
+        }
+
+        for (collider_handle, old_aabb) in &self.obstacles {
+            if !obstacles.contains_key(collider_handle) {
+                changed.push(*old_aabb);
+            }
+        }
+
+        self.obstacles = obstacles;
+
+        let revision = self.revision;
+
+        for aabb in changed {
+            let region = Rect::new(aabb.mins.x, aabb.mins.y, aabb.maxs.x - aabb.mins.x, aabb.maxs.y - aabb.mins.y);
+
+            self.rebuild_region(space, region);
+        }
+
+        self.revision != revision
+    }
+
+    /// Recheck every cell overlapping `region` (rapier coords) against the space
+    pub fn rebuild_region(&mut self, space: &mut Space, region: Rect) {
+        space.query_pipeline.update(&space.collider_set);
+
+        let padding = self.agent_radius + self.cell_size;
+
+        let min = self.cell_at(vec2(region.x - padding, region.y - padding));
+        let max = self.cell_at(vec2(region.right() + padding, region.bottom() + padding));
+
+        let half_extents = self.cell_size / 2. + self.agent_radius;
+
+        let cell_shape = Cuboid::new(nalgebra::vector![half_extents, half_extents]);
+
+        let filter = QueryFilter::default().exclude_sensors().exclude_dynamic().exclude_kinematic();
+
+        let mut changed = false;
+
+        for y in min.1..=max.1 {
+            for x in min.0..=max.0 {
+                let center = self.cell_center(x, y);
+
+                let mut blocked = false;
+
+                space.query_pipeline.intersections_with_shape(
+                    &space.rigid_body_set, 
+                    &space.collider_set, 
+                    &Isometry2::translation(center.x, center.y), 
+                    &cell_shape, 
+                    filter, 
+                    |_| {
+                        blocked = true;
+
+                        false
+                    }
+                );
+
+                let index = y * self.width + x;
+
+                if self.blocked[index] != blocked {
+                    self.blocked[index] = blocked;
+
+                    changed = true;
+                }
+            }
+        }
+
+        if changed {
+            self.revision += 1;
+        }
+    }
+
+    /// The cell containing `position`, clamped to the grid
+    pub fn cell_at(&self, position: Vec2) -> (usize, usize) {
+        let x = ((position.x - self.bounds.x) / self.cell_size).floor().clamp(0., (self.width - 1) as f32);
+        let y = ((position.y - self.bounds.y) / self.cell_size).floor().clamp(0., (self.height - 1) as f32);
+
+        (x as usize, y as usize)
+    }
+
+    pub fn cell_center(&self, x: usize, y: usize) -> Vec2 {
+        vec2(
+            self.bounds.x + (x as f32 + 0.5) * self.cell_size, 
+            self.bounds.y + (y as f32 + 0.5) * self.cell_size
+        )
+    }
+
+    pub fn is_blocked(&self, x: usize, y: usize) -> bool {
+        self.blocked[y * self.width + x]
+    }
+
+    pub fn is_walkable(&self, position: Vec2) -> bool {
+        let (x, y) = self.cell_at(position);
+
+        self.bounds.contains(position) && !self.is_blocked(x, y)
+    }
+
+    /// Whether a straight line between two points only crosses walkable cells
+    pub fn line_clear(&self, start: Vec2, end: Vec2) -> bool {
+        let steps = ((end - start).length() / (self.cell_size / 2.)).ceil().max(1.) as usize;
+
+        (0..=steps).all(|step| {
+            let (x, y) = self.cell_at(start.lerp(end, step as f32 / steps as f32));
+
+            !self.is_blocked(x, y)
+        })
+    }
+
+    fn neighbours(&self, cell: usize) -> impl Iterator<Item = (usize, f32)> + '_ {
+        let (x, y) = ((cell % self.width) as i64, (cell / self.width) as i64);
+
+        [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)].into_iter().filter_map(move |(dx, dy)| {
+            let (nx, ny) = (x + dx, y + dy);
+
+            if nx < 0 || ny < 0 || nx >= self.width as i64 || ny >= self.height as i64 {
+                return None;
+            }
+
+            if self.is_blocked(nx as usize, ny as usize) {
+                return None;
+            }
+
+            // no cutting corners past a blocked cell
+            if dx != 0 && dy != 0 && (self.is_blocked(nx as usize, y as usize) || self.is_blocked(x as usize, ny as usize)) {
+                return None;
+            }
+
+            let cost = match dx != 0 && dy != 0 {
+                true => DIAGONAL_COST,
+                false => 1.,
+            };
+
+            Some((ny as usize * self.width + nx as usize, cost))
+        })
+    }
+
+    /// Octile distance, exact for 8 way movement with no obstacles
+    fn heuristic(&self, from: usize, to: usize) -> f32 {
+        let dx = (from % self.width).abs_diff(to % self.width) as f32;
+        let dy = (from / self.width).abs_diff(to / self.width) as f32;
+
+        dx.max(dy) + (DIAGONAL_COST - 1.) * dx.min(dy)
+    }
+
+    /// A* from `start` to `goal`. The waypoints start at `start`, end at `goal` and skip every corner that can be seen past.
+    /// None if either end is blocked or there is no way through
+    pub fn find_path(&self, start: Vec2, goal: Vec2) -> Option<NavPath> {
+        let start_cell = self.cell_at(start);
+        let goal_cell = self.cell_at(goal);
+
+        if self.is_blocked(start_cell.0, start_cell.1) || self.is_blocked(goal_cell.0, goal_cell.1) {
+            return None;
+        }
+
+        let start_index = start_cell.1 * self.width + start_cell.0;
+        let goal_index = goal_cell.1 * self.width + goal_cell.0;
+
+        let mut costs: FxHashMap<usize, f32> = FxHashMap::default();
+        let mut came_from: FxHashMap<usize, usize> = FxHashMap::default();
+        let mut open = BinaryHeap::new();
+
+        costs.insert(start_index, 0.);
+        open.push(OpenCell { cost: self.heuristic(start_index, goal_index), cell: start_index });
+
+        while let Some(OpenCell { cell, .. }) = open.pop() {
+            if cell == goal_index {
+                break;
+            }
+
+            let cost = costs[&cell];
+
+            for (neighbour, step_cost) in self.neighbours(cell) {
+                let new_cost = cost + step_cost;
+
+                if costs.get(&neighbour).is_some_and(|old_cost| *old_cost <= new_cost) {
+                    continue;
+                }
+
+                costs.insert(neighbour, new_cost);
+                came_from.insert(neighbour, cell);
+
+                open.push(OpenCell { cost: new_cost + self.heuristic(neighbour, goal_index), cell: neighbour });
+            }
+        }
+
+        if start_index != goal_index && !came_from.contains_key(&goal_index) {
+            return None;
+        }
+
+        let mut cells = vec![goal_index];
+
+        while let Some(previous) = came_from.get(cells.last().unwrap()) {
+            cells.push(*previous);
+        }
+
+        cells.reverse();
+
+        // the real end points instead of the centers of their cells
+        let mut points: Vec<Vec2> = cells.iter().map(|cell| self.cell_center(cell % self.width, cell / self.width)).collect();
+
+        *points.first_mut().unwrap() = start;
+        *points.last_mut().unwrap() = goal;
+
+        Some(NavPath {
+            waypoints: self.smooth(points),
+            revision: self.revision,
+        })
+    }
+
+    /// Drop every waypoint that the one before it can see past
+    fn smooth(&self, points: Vec<Vec2>) -> Vec<Vec2> {
+        let mut smoothed = vec![points[0]];
+
+        let mut index = 0;
+
+        while index < points.len() - 1 {
+            let mut furthest = index + 1;
+
+            for next in (index + 2..points.len()).rev() {
+                if self.line_clear(points[index], points[next]) {
+                    furthest = next;
+
+                    break;
+                }
+            }
+
+            smoothed.push(points[furthest]);
+
+            index = furthest;
+        }
+
+        smoothed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::{vec2, Rect};
+    use nalgebra::vector;
+    use rapier2d::geometry::ColliderBuilder;
+
+    use crate::space::Space;
+
+    use super::NavGrid;
+
+    fn insert_wall(space: &mut Space, center: (f32, f32), half_extents: (f32, f32)) {
+        let collider = ColliderBuilder::cuboid(half_extents.0, half_extents.1)
+            .translation(vector![center.0, center.1])
+            .build();
+
+        space.collider_set.insert(collider);
+    }
+
+    #[test]
+    fn open_grid_path_is_a_straight_line() {
+        let mut space = Space::new();
+
+        let grid = NavGrid::new(&mut space, Rect::new(0., 0., 100., 100.), 10., 0.);
+
+        let path = grid.find_path(vec2(5., 5.), vec2(95., 95.)).unwrap();
+
+        assert_eq!(path.waypoints, vec![vec2(5., 5.), vec2(95., 95.)]);
+    }
+
+    #[test]
+    fn path_goes_around_walls() {
+        let mut space = Space::new();
+
+        // a wall across the middle with a gap at the top
+        insert_wall(&mut space, (50., 35.), (5., 35.));
+
+        let grid = NavGrid::new(&mut space, Rect::new(0., 0., 100., 100.), 10., 0.);
+
+        assert!(!grid.line_clear(vec2(15., 15.), vec2(85., 15.)));
+
+        let path = grid.find_path(vec2(15., 15.), vec2(85., 15.)).unwrap();
+
+        assert!(path.waypoints.len() > 2);
+        assert!(path.waypoints.windows(2).all(|segment| grid.line_clear(segment[0], segment[1])));
+        assert!(!path.needs_replan(&grid));
+    }
+
+    #[test]
+    fn no_path_to_an_enclosed_cell() {
+        let mut space = Space::new();
+
+        insert_wall(&mut space, (50., 50.), (50., 5.));
+
+        let grid = NavGrid::new(&mut space, Rect::new(0., 0., 100., 100.), 10., 0.);
+
+        assert!(grid.find_path(vec2(5., 5.), vec2(95., 95.)).is_none());
+        assert!(grid.find_path(vec2(50., 50.), vec2(95., 95.)).is_none()); // starts inside the wall
+    }
+
+    #[test]
+    fn refresh_picks_up_new_walls() {
+        let mut space = Space::new();
+
+        let mut grid = NavGrid::new(&mut space, Rect::new(0., 0., 100., 100.), 10., 0.);
+
+        let path = grid.find_path(vec2(15., 15.), vec2(85., 15.)).unwrap();
+
+        assert!(!grid.refresh(&mut space));
+
+        insert_wall(&mut space, (50., 35.), (5., 35.));
+
+        assert!(grid.refresh(&mut space));
+        assert!(path.needs_replan(&grid));
+        assert!(!grid.is_walkable(vec2(50., 15.)));
+    }
+}
@@ -0,0 +1,281 @@
+use macroquad::{camera::{set_camera, set_default_camera, Camera2D}, color::WHITE, material::{gl_use_default_material, gl_use_material, Material}, math::{vec2, Rect, Vec2}, miniquad::{UniformDesc, UniformType}, texture::{draw_texture_ex, render_target, DrawTextureParams, FilterMode, RenderTarget, Texture2D}, window::{screen_height, screen_width}};
+
+use crate::{coordinates::CoordinateSystem, shaders::fragment_material};
+
+const VIGNETTE_SHADER: &str = "#version 100
+precision mediump float;
+
+varying vec4 color;
+varying vec2 uv;
+
+uniform sampler2D Texture;
+uniform float strength;
+
+void main() {
+    vec4 base = texture2D(Texture, uv);
+
+    base.rgb *= 1.0 - smoothstep(0.3, 0.75, distance(uv, vec2(0.5))) * strength;
+
+    gl_FragColor = base;
+}
+";
+
+const CHROMATIC_ABERRATION_SHADER: &str = "#version 100
+precision mediump float;
+
+varying vec4 color;
+varying vec2 uv;
+
+uniform sampler2D Texture;
+uniform float amount;
+
+void main() {
+    vec2 offset = (uv - vec2(0.5)) * amount;
+
+    gl_FragColor = vec4(
+        texture2D(Texture, uv + offset).r,
+        texture2D(Texture, uv).g,
+        texture2D(Texture, uv - offset).b,
+        texture2D(Texture, uv).a
+    );
+}
+";
+
+const BLOOM_SHADER: &str = "#version 100
+precision mediump float;
+
+varying vec4 color;
+varying vec2 uv;
+
+uniform sampler2D Texture;
+uniform vec2 texel;
+uniform float threshold;
+uniform float intensity;
+
+void main() {
+    vec4 base = texture2D(Texture, uv);
+
+    vec3 bloom = vec3(0.0);
+
+    for (int x = -2; x <= 2; x++) {
+        for (int y = -2; y <= 2; y++) {
+            vec3 pixel = texture2D(Texture, uv + vec2(float(x), float(y)) * texel * 2.0).rgb;
+
+            bloom += max(pixel - vec3(threshold), vec3(0.0));
+        }
+    }
+
+    gl_FragColor = vec4(base.rgb + bloom / 25.0 * intensity, base.a);
+}
+";
+
+const PALETTE_SWAP_SHADER: &str = "#version 100
+precision mediump float;
+
+varying vec4 color;
+varying vec2 uv;
+
+uniform sampler2D Texture;
+uniform sampler2D Palette;
+uniform float palette_size;
+
+void main() {
+    vec4 base = texture2D(Texture, uv);
+
+    float luminance = dot(base.rgb, vec3(0.299, 0.587, 0.114));
+
+    float index = floor(luminance * (palette_size - 1.0) + 0.5);
+
+    gl_FragColor = vec4(texture2D(Palette, vec2((index + 0.5) / palette_size, 0.5)).rgb, base.a);
+}
+";
+
+/// One full screen shader applied to the output of the pass before it
+pub struct PostPass {
+    pub name: String,
+    pub enabled: bool,
+    pub material: Material,
+    uses_texel: bool // gets a `texel` uniform with the size of one pixel in uv units
+}
+
+impl PostPass {
+
+    /// A pass with your own fragment shader. It gets the previous pass as `Texture` and `uv`, like macroquad's default shader.
+    /// Declare a `vec2 texel` uniform to be given the size of a pixel
+    pub fn custom(name: &str, fragment_shader: &str, uniforms: &[(&str, UniformType)], textures: &[&str]) -> Self {
+        let uses_texel = uniforms.iter().any(|(uniform, _)| *uniform == "texel");
+
+        Self {
+            name: name.to_string(),
+            enabled: true,
+            material: fragment_material(
+                fragment_shader, 
+                uniforms.iter().map(|(uniform, uniform_type)| UniformDesc::new(uniform, *uniform_type)).collect(), 
+                textures.iter().map(|texture| texture.to_string()).collect(), 
+                None
+            ),
+            uses_texel,
+        }
+    }
+
+    /// Darkens the edges of the screen. 0 is off, 1 is black corners
+    pub fn vignette(strength: f32) -> Self {
+        let pass = Self::custom("vignette", VIGNETTE_SHADER, &[("strength", UniformType::Float1)], &[]);
+
+        pass.material.set_uniform("strength", strength);
+
+        pass
+    }
+
+    /// Splits red and blue apart towards the edges of the screen. Around 0.01 is subtle
+    pub fn chromatic_aberration(amount: f32) -> Self {
+        let pass = Self::custom("chromatic_aberration", CHROMATIC_ABERRATION_SHADER, &[("amount", UniformType::Float1)], &[]);
+
+        pass.material.set_uniform("amount", amount);
+
+        pass
+    }
+
+    /// Glow around everything brighter than `threshold`
+    pub fn bloom(threshold: f32, intensity: f32) -> Self {
+        let pass = Self::custom(
+            "bloom", 
+            BLOOM_SHADER, 
+            &[("texel", UniformType::Float2), ("threshold", UniformType::Float1), ("intensity", UniformType::Float1)], 
+            &[]
+        );
+
+        pass.material.set_uniform("threshold", threshold);
+        pass.material.set_uniform("intensity", intensity);
+
+        pass
+    }
+
+    /// Recolors the screen by brightness using a palette texture, one color per pixel from left to right, darkest first
+    pub fn palette_swap(palette: &Texture2D) -> Self {
+        let pass = Self::custom("palette_swap", PALETTE_SWAP_SHADER, &[("palette_size", UniformType::Float1)], &["Palette"]);
+
+        pass.material.set_uniform("palette_size", palette.width());
+        pass.material.set_texture("Palette", palette.clone());
+
+        pass
+    }
+
+    pub fn set_uniform<T>(&self, name: &str, value: T) {
+        self.material.set_uniform(name, value);
+    }
+}
+
+/// Draws the world into a render target and runs it through a chain of PostPasses on the way to the window.
+/// Draw the world between begin and end
+pub struct PostProcess {
+    pub passes: Vec<PostPass>,
+    targets: [RenderTarget; 2], // the world is drawn into the first, then passes bounce between the two
+    size: Vec2
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PostProcess {
+
+    pub fn new() -> Self {
+        let size = vec2(screen_width(), screen_height());
+
+        Self {
+            passes: vec![],
+            targets: Self::create_targets(size),
+            size,
+        }
+    }
+
+    fn create_targets(size: Vec2) -> [RenderTarget; 2] {
+        let targets = [render_target(size.x as u32, size.y as u32), render_target(size.x as u32, size.y as u32)];
+
+        for target in &targets {
+            target.texture.set_filter(FilterMode::Linear);
+        }
+
+        targets
+    }
+
+    pub fn with_pass(mut self, pass: PostPass) -> Self {
+        self.passes.push(pass);
+
+        self
+    }
+
+    pub fn add_pass(&mut self, pass: PostPass) {
+        self.passes.push(pass);
+    }
+
+    pub fn pass(&self, name: &str) -> Option<&PostPass> {
+        self.passes.iter().find(|pass| pass.name == name)
+    }
+
+    pub fn pass_mut(&mut self, name: &str) -> Option<&mut PostPass> {
+        self.passes.iter_mut().find(|pass| pass.name == name)
+    }
+
+    /// For converting between rapier and macroquad coordinates while drawing the world
+    pub fn coordinates(&self) -> CoordinateSystem {
+        CoordinateSystem::render_target(&self.targets[0])
+    }
+
+    /// A camera that shows `camera_rect` of the world. Render targets are stored upside down, so the y zoom isn't flipped
+    pub fn camera(&self, camera_rect: Rect) -> Camera2D {
+        let mut camera = Camera2D::from_display_rect(camera_rect);
+
+        camera.render_target = Some(self.targets[0].clone());
+
+        camera
+    }
+
+    /// Start drawing the world. Follows the window size
+    pub fn begin(&mut self, camera_rect: Rect) {
+        let size = vec2(screen_width(), screen_height());
+
+        if size != self.size {
+            self.targets = Self::create_targets(size);
+            self.size = size;
+        }
+
+        set_camera(&self.camera(camera_rect));
+    }
+
+    /// Run the passes and put the result on the window
+    pub fn end(&mut self) {
+        let mut source = 0;
+
+        let screen_rect = Rect::new(0., 0., self.size.x, self.size.y);
+
+        for pass in self.passes.iter().filter(|pass| pass.enabled) {
+            let destination = 1 - source;
+
+            let mut camera = Camera2D::from_display_rect(screen_rect);
+
+            camera.render_target = Some(self.targets[destination].clone());
+
+            set_camera(&camera);
+
+            if pass.uses_texel {
+                pass.material.set_uniform("texel", vec2(1. / self.size.x, 1. / self.size.y));
+            }
+
+            gl_use_material(&pass.material);
+
+            draw_texture_ex(&self.targets[source].texture, 0., 0., WHITE, DrawTextureParams { dest_size: Some(self.size), ..Default::default() });
+
+            gl_use_default_material();
+
+            source = destination;
+        }
+
+        set_default_camera();
+
+        draw_texture_ex(&self.targets[source].texture, 0., 0., WHITE, DrawTextureParams { dest_size: Some(self.size), ..Default::default() });
+    }
+}
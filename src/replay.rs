@@ -0,0 +1,243 @@
+use std::{collections::VecDeque, fs::File, io::{self, BufReader, BufWriter, Read, Write}, path::Path};
+
+use diff::Diff;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{current_unix_millis, sync::{client::SyncClient, codec::Codec}, time};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffDirection {
+    Outgoing, // made locally and sent to the server
+    Incoming
+}
+
+/// One record in a replay file. Times are unix millis
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ReplayEntry<S, D> {
+    /// The whole state, at the start of the recording and whenever the server resyncs us
+    State { time: u64, state: S },
+    Diff { time: u64, direction: DiffDirection, diff: D }
+}
+
+impl<S, D> ReplayEntry<S, D> {
+    pub fn time(&self) -> u64 {
+        match self {
+            ReplayEntry::State { time, .. } => *time,
+            ReplayEntry::Diff { time, .. } => *time,
+        }
+    }
+}
+
+fn invalid_data(error: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+/// Writes every diff that goes in or out of a state to a file so the session can be played back later with ReplayPlayer.
+/// Each entry is stored as a little endian u32 length followed by the bitcode serialized entry
+pub struct ReplayRecorder<T: Serialize + Diff> {
+    writer: BufWriter<File>,
+    state_type: std::marker::PhantomData<T>
+}
+
+impl<T> ReplayRecorder<T>
+where
+    T: Serialize + Diff,
+    <T as Diff>::Repr: Serialize {
+
+    /// Start a new recording, beginning from `state`
+    pub fn create(path: impl AsRef<Path>, state: &T) -> io::Result<Self> {
+        let mut recorder = Self {
+            writer: BufWriter::new(File::create(path)?),
+            state_type: std::marker::PhantomData,
+        };
+
+        recorder.record_state(state)?;
+
+        Ok(recorder)
+    }
+
+    fn write_entry(&mut self, entry: &ReplayEntry<&T, &<T as Diff>::Repr>) -> io::Result<()> {
+        let bytes = bitcode::serialize(entry).map_err(invalid_data)?;
+
+        self.writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&bytes)?;
+
+        Ok(())
+    }
+
+    pub fn record_state(&mut self, state: &T) -> io::Result<()> {
+        self.write_entry(&ReplayEntry::State { time: current_unix_millis(), state })
+    }
+
+    pub fn record_diff(&mut self, time: u64, direction: DiffDirection, diff: &<T as Diff>::Repr) -> io::Result<()> {
+        self.write_entry(&ReplayEntry::Diff { time, direction, diff })
+    }
+
+    /// Record everything the client sent and received since the last call, including states the server resynced it with.
+    /// The client needs set_record_diffs turned on
+    pub fn record_client<C: Codec>(&mut self, client: &mut SyncClient<T, C>) -> io::Result<()>
+    where
+        T: DeserializeOwned + Clone + PartialEq,
+        <T as Diff>::Repr: DeserializeOwned {
+
+        let mut entries: Vec<ReplayEntry<T, <T as Diff>::Repr>> = client.drain_sent_diffs().into_iter()
+            .map(|(time, diff)| ReplayEntry::Diff { time, direction: DiffDirection::Outgoing, diff })
+            .chain(
+                client.drain_received_diffs().into_iter().map(|(time, diff)| ReplayEntry::Diff { time, direction: DiffDirection::Incoming, diff })
+            )
+            // a resync replaces everything before it, so it goes after diffs from the same millisecond
+            .chain(
+                client.drain_received_states().into_iter().map(|(time, state)| ReplayEntry::State { time, state })
+            )
+            .collect();
+
+        // stable, so entries from the same millisecond keep the order they happened in
+        entries.sort_by_key(|entry| entry.time());
+
+        for entry in &entries {
+            match entry {
+                ReplayEntry::State { time, state } => self.write_entry(&ReplayEntry::State { time: *time, state })?,
+                ReplayEntry::Diff { time, direction, diff } => self.record_diff(*time, *direction, diff)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Plays a file written by ReplayRecorder back into a state at the pace it was recorded at
+pub struct ReplayPlayer<T: Diff> {
+    entries: VecDeque<ReplayEntry<T, <T as Diff>::Repr>>,
+    recording_start: u64, // unix millis
+    playback_start: time::Instant,
+    pub speed: f32
+}
+
+impl<T> ReplayPlayer<T>
+where
+    T: DeserializeOwned + Diff,
+    <T as Diff>::Repr: DeserializeOwned {
+
+    /// Load a recording. Returns the player along with the state the recording starts from
+    pub fn open(path: impl AsRef<Path>) -> io::Result<(Self, T)> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut entries = VecDeque::new();
+
+        loop {
+            let mut length_bytes = [0; 4];
+
+            match reader.read_exact(&mut length_bytes) {
+                Ok(_) => {},
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break, // end of the recording
+                Err(error) => return Err(error),
+            }
+
+            let mut entry_bytes = vec![0; u32::from_le_bytes(length_bytes) as usize];
+
+            reader.read_exact(&mut entry_bytes)?;
+
+            entries.push_back(bitcode::deserialize(&entry_bytes).map_err(invalid_data)?);
+        }
+
+        let (recording_start, state) = match entries.pop_front() {
+            Some(ReplayEntry::State { time, state }) => (time, state),
+            _ => return Err(invalid_data("replay does not start with a full state")),
+        };
+
+        Ok((
+            Self {
+                entries,
+                recording_start,
+                playback_start: time::now(),
+                speed: 1.,
+            },
+            state
+        ))
+    }
+
+    /// Apply every entry whose time has come
+    pub fn update(&mut self, state: &mut T) {
+        let elapsed = (self.playback_start.elapsed().as_secs_f32() * self.speed * 1000.) as u64;
+
+        while let Some(entry) = self.entries.front() {
+            if entry.time().saturating_sub(self.recording_start) > elapsed {
+                break;
+            }
+
+            match self.entries.pop_front().unwrap() {
+                ReplayEntry::State { state: new_state, .. } => *state = new_state,
+                ReplayEntry::Diff { diff, .. } => state.apply(&diff),
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diff::Diff;
+    use nalgebra::vector;
+    use rapier2d::dynamics::RigidBodyBuilder;
+
+    use crate::{current_unix_millis, space::Space};
+
+    use super::{DiffDirection, ReplayPlayer, ReplayRecorder};
+
+    #[test]
+    fn recordings_play_back_to_the_same_state() {
+        let path = std::env::temp_dir().join(format!("gamelibrary_replay_test_{}.replay", std::process::id()));
+
+        let mut space = Space::new();
+
+        let rigid_body_handle = space.rigid_body_set.insert(RigidBodyBuilder::dynamic());
+
+        let mut recorder = ReplayRecorder::create(&path, &space).unwrap();
+
+        for x in [1., 2., 3.] {
+            let mut moved = space.clone();
+
+            moved.rigid_body_set.get_mut(rigid_body_handle).unwrap().set_translation(vector![x, 0.], false);
+
+            recorder.record_diff(current_unix_millis(), DiffDirection::Outgoing, &space.diff(&moved)).unwrap();
+
+            space = moved;
+        }
+
+        recorder.flush().unwrap();
+
+        let (mut player, mut played) = ReplayPlayer::<Space>::open(&path).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+
+        // everything was recorded within a few millis, this plays it all at once
+        player.speed = 1e9;
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+
+        player.update(&mut played);
+
+        assert!(player.is_finished());
+        assert!(played == space);
+    }
+
+    #[test]
+    fn files_that_dont_start_with_a_state_are_refused() {
+        let path = std::env::temp_dir().join(format!("gamelibrary_replay_test_empty_{}.replay", std::process::id()));
+
+        std::fs::write(&path, []).unwrap();
+
+        let result = ReplayPlayer::<Space>::open(&path);
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use std::ops::Range;
+
+use diff::Diff;
+use serde::{Deserialize, Serialize};
+
+/// Seeded xoshiro256** generator meant to live in synced state. Every client that applies the same diffs
+/// draws the same numbers, so crit rolls and bullet spread come out identical everywhere
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct SyncRng {
+    s0: u64,
+    s1: u64,
+    s2: u64,
+    s3: u64
+}
+
+/// Spreads a single seed over the four words of state, as recommended by the xoshiro authors
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+
+    let mut z = *state;
+
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+
+    z ^ (z >> 31)
+}
+
+impl SyncRng {
+
+    pub fn new(seed: u64) -> Self {
+        let mut splitmix_state = seed;
+
+        Self {
+            s0: splitmix64(&mut splitmix_state),
+            s1: splitmix64(&mut splitmix_state),
+            s2: splitmix64(&mut splitmix_state),
+            s3: splitmix64(&mut splitmix_state),
+        }
+    }
+
+    /// Seeded from the OS. Only the owner of the state should do this, everyone else gets the state through sync
+    pub fn from_entropy() -> Self {
+        let mut seed = [0u8; 8];
+
+        getrandom::getrandom(&mut seed).unwrap();
+
+        Self::new(u64::from_le_bytes(seed))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.s1.wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+
+        let t = self.s1 << 17;
+
+        self.s2 ^= self.s0;
+        self.s3 ^= self.s1;
+        self.s1 ^= self.s2;
+        self.s0 ^= self.s3;
+
+        self.s2 ^= t;
+
+        self.s3 = self.s3.rotate_left(45);
+
+        result
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        // the high bits are the best ones
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// From 0 up to but not including 1
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range_f32(&mut self, range: Range<f32>) -> f32 {
+        range.start + self.next_f32() * (range.end - range.start)
+    }
+
+    /// Panics if the range is empty
+    pub fn range_u64(&mut self, range: Range<u64>) -> u64 {
+        if range.is_empty() {
+            panic!("range_u64 called with an empty range {:?}", range)
+        }
+
+        let span = range.end - range.start;
+
+        // reject the top of the u64 range that doesn't divide evenly, otherwise low numbers come up more often
+        let zone = u64::MAX - (u64::MAX - span + 1) % span;
+
+        loop {
+            let value = self.next_u64();
+
+            if value <= zone {
+                return range.start + value % span;
+            }
+        }
+    }
+
+    pub fn range_usize(&mut self, range: Range<usize>) -> usize {
+        self.range_u64(range.start as u64..range.end as u64) as usize
+    }
+
+    /// True with the given probability, 0 never and 1 always
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+
+        Some(&items[self.range_usize(0..items.len())])
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for index in (1..items.len()).rev() {
+            let other = self.range_usize(0..index + 1);
+
+            items.swap(index, other);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diff::Diff;
+
+    use super::SyncRng;
+
+    #[test]
+    fn matches_reference_xoshiro256starstar() {
+        let mut rng = SyncRng::new(0);
+
+        assert_eq!(rng.next_u64(), 0x99ec5f36cb75f2b4);
+        assert_eq!(rng.next_u64(), 0xbf6e1f784956452a);
+        assert_eq!(rng.next_u64(), 0x1a5f849d4933e6e0);
+    }
+
+    #[test]
+    fn synced_copies_draw_the_same_numbers() {
+        let mut owner = SyncRng::new(42);
+        let mut remote = SyncRng::new(7);
+
+        let before = remote.clone();
+
+        owner.next_u64();
+
+        remote.apply(&before.diff(&owner));
+
+        for _ in 0..100 {
+            assert_eq!(owner.next_u64(), remote.next_u64());
+        }
+    }
+
+    #[test]
+    fn ranges_stay_in_bounds() {
+        let mut rng = SyncRng::new(1);
+
+        for _ in 0..1000 {
+            assert!((10..13).contains(&rng.range_u64(10..13)));
+
+            let value = rng.range_f32(-1.0..1.0);
+
+            assert!((-1.0..1.0).contains(&value));
+
+            assert!((0.0..1.0).contains(&rng.next_f32()));
+        }
+
+        assert_eq!(rng.range_u64(5..6), 5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_range_panics() {
+        SyncRng::new(1).range_u64(3..3);
+    }
+
+    #[test]
+    fn chance_extremes() {
+        let mut rng = SyncRng::new(2);
+
+        assert!((0..100).all(|_| !rng.chance(0.)));
+        assert!((0..100).all(|_| rng.chance(1.)));
+    }
+
+    #[test]
+    fn shuffle_keeps_every_item() {
+        let mut rng = SyncRng::new(3);
+
+        let mut items: Vec<u32> = (0..50).collect();
+
+        rng.shuffle(&mut items);
+
+        assert_ne!(items, (0..50).collect::<Vec<u32>>());
+
+        items.sort();
+
+        assert_eq!(items, (0..50).collect::<Vec<u32>>());
+
+        assert_eq!(rng.choose::<u32>(&[]), None);
+        assert!(rng.choose(&items).is_some());
+    }
+}
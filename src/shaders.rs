@@ -0,0 +1,46 @@
+use macroquad::{material::{load_material, Material, MaterialParams}, miniquad::{BlendState, PipelineParams, ShaderSource, UniformDesc}};
+
+/// macroquad's default vertex shader, for materials that only change the fragment shader or the blending
+pub const DEFAULT_VERTEX_SHADER: &str = "#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+    uv = texcoord;
+}
+";
+
+pub const DEFAULT_FRAGMENT_SHADER: &str = "#version 100
+varying lowp vec4 color;
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+
+void main() {
+    gl_FragColor = color * texture2D(Texture, uv);
+}
+";
+
+/// Load a material with the default vertex shader. Panics if the shader doesn't compile, since that's a bug in the game rather than something to recover from
+pub fn fragment_material(fragment_shader: &str, uniforms: Vec<UniformDesc>, textures: Vec<String>, blend: Option<BlendState>) -> Material {
+    load_material(
+        ShaderSource::Glsl { vertex: DEFAULT_VERTEX_SHADER, fragment: fragment_shader },
+        MaterialParams {
+            pipeline_params: PipelineParams {
+                color_blend: blend,
+                ..Default::default()
+            },
+            uniforms,
+            textures,
+        }
+    ).unwrap_or_else(|error| panic!("failed to compile shader: {:?}", error))
+}
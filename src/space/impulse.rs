@@ -0,0 +1,91 @@
+use macroquad::math::Vec2;
+use nalgebra::{vector, Isometry2};
+use rapier2d::{dynamics::RigidBodyHandle, geometry::{Ball, ColliderHandle}, pipeline::QueryFilter};
+use serde::{Deserialize, Serialize};
+
+use super::Space;
+
+/// How the strength of a radial impulse drops off with distance from its center
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Falloff {
+    Constant,
+    Linear,
+    Quadratic
+}
+
+impl Falloff {
+
+    /// Strength multiplier for a point `distance` away from the center of an impulse with the given radius
+    pub fn factor(&self, distance: f32, radius: f32) -> f32 {
+        let remaining = (1. - (distance / radius)).clamp(0., 1.);
+
+        match self {
+            Falloff::Constant => 1.,
+            Falloff::Linear => remaining,
+            Falloff::Quadratic => remaining * remaining,
+        }
+    }
+}
+
+impl Space {
+
+    /// Push every dynamic body with a collider inside the radius away from `center` (in rapier coordinates).
+    /// Returns the bodies that were pushed
+    pub fn apply_radial_impulse(&mut self, center: Vec2, radius: f32, strength: f32, falloff: Falloff) -> Vec<RigidBodyHandle> {
+
+        self.query_pipeline.update(&self.collider_set);
+
+        let mut collider_handles: Vec<ColliderHandle> = vec![];
+
+        self.query_pipeline.intersections_with_shape(
+            &self.rigid_body_set, 
+            &self.collider_set, 
+            &Isometry2::translation(center.x, center.y), 
+            &Ball::new(radius), 
+            QueryFilter::default().exclude_fixed(), 
+            |collider_handle| {
+                collider_handles.push(collider_handle);
+
+                true
+            }
+        );
+
+        let mut affected_rigid_bodies: Vec<RigidBodyHandle> = vec![];
+
+        for collider_handle in collider_handles {
+
+            let rigid_body_handle = match self.collider_set.get(collider_handle).and_then(|collider| collider.parent()) {
+                Some(rigid_body_handle) => rigid_body_handle,
+                None => continue,
+            };
+
+            // a body with multiple colliders only gets pushed once
+            if affected_rigid_bodies.contains(&rigid_body_handle) {
+                continue;
+            }
+
+            let rigid_body = self.rigid_body_set.get_mut(rigid_body_handle).unwrap();
+
+            if !rigid_body.is_dynamic() {
+                continue;
+            }
+
+            let offset = rigid_body.center_of_mass().coords - vector![center.x, center.y];
+
+            let distance = offset.norm();
+
+            // cant tell which way to push something sitting right on the center
+            if distance <= f32::EPSILON {
+                continue;
+            }
+
+            let impulse = (offset / distance) * strength * falloff.factor(distance, radius);
+
+            rigid_body.apply_impulse(impulse, true);
+
+            affected_rigid_bodies.push(rigid_body_handle);
+        }
+
+        affected_rigid_bodies
+    }
+}
@@ -0,0 +1,150 @@
+use fxhash::FxHashMap;
+use rapier2d::dynamics::{GenericJoint, ImpulseJointHandle, ImpulseJointSet, RigidBodyHandle};
+use serde::{Deserialize, Serialize};
+
+use super::Space;
+
+/// Every joint from body1 to body2 after a change. Empty when they were all removed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JointChange {
+    pub body1: RigidBodyHandle,
+    pub body2: RigidBodyHandle,
+    pub joints: Vec<GenericJoint>
+}
+
+/// Joint handles are handed out separately on every peer, so joints are told apart by the bodies they connect
+pub(crate) fn joints_by_bodies(impulse_joint_set: &ImpulseJointSet) -> FxHashMap<(RigidBodyHandle, RigidBodyHandle), Vec<GenericJoint>> {
+    let mut joints: FxHashMap<(RigidBodyHandle, RigidBodyHandle), Vec<GenericJoint>> = FxHashMap::default();
+
+    for (_joint_handle, joint) in impulse_joint_set.iter() {
+        joints.entry((joint.body1, joint.body2)).or_default().push(joint.data);
+    }
+
+    joints
+}
+
+impl Space {
+
+    /// The pairs of bodies whose joints were added, changed or removed between the two spaces
+    pub(crate) fn diff_joints(&self, other: &Self) -> Option<Vec<JointChange>> {
+        let old_joints = joints_by_bodies(&self.impulse_joint_set);
+        let new_joints = joints_by_bodies(&other.impulse_joint_set);
+
+        let mut changes = vec![];
+
+        for (&(body1, body2), joints) in &new_joints {
+            if old_joints.get(&(body1, body2)) != Some(joints) {
+                changes.push(JointChange { body1, body2, joints: joints.clone() });
+            }
+        }
+
+        for &(body1, body2) in old_joints.keys() {
+            if !new_joints.contains_key(&(body1, body2)) {
+                changes.push(JointChange { body1, body2, joints: vec![] });
+            }
+        }
+
+        match changes.is_empty() {
+            true => None,
+            false => Some(changes),
+        }
+    }
+
+    /// Replace the joints between each pair of bodies with the changed ones
+    pub(crate) fn apply_joint_changes(&mut self, changes: &[JointChange]) {
+        for change in changes {
+            let old_joint_handles: Vec<ImpulseJointHandle> = self.impulse_joint_set.iter()
+                .filter(|(_, joint)| joint.body1 == change.body1 && joint.body2 == change.body2)
+                .map(|(joint_handle, _)| joint_handle)
+                .collect();
+
+            for joint_handle in old_joint_handles {
+                self.impulse_joint_set.remove(joint_handle, true);
+            }
+
+            // one of the bodies was removed by the same diff
+            if !self.rigid_body_set.contains(change.body1) || !self.rigid_body_set.contains(change.body2) {
+                continue;
+            }
+
+            for joint in &change.joints {
+                self.impulse_joint_set.insert(change.body1, change.body2, *joint, true);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diff::Diff;
+    use macroquad::math::Vec2;
+    use rapier2d::dynamics::{RigidBodyBuilder, RigidBodyHandle};
+
+    use crate::space::Space;
+
+    fn space_with_bodies(count: usize) -> (Space, Vec<RigidBodyHandle>) {
+        let mut space = Space::new();
+
+        let bodies = (0..count)
+            .map(|_| space.rigid_body_set.insert(RigidBodyBuilder::dynamic()))
+            .collect();
+
+        (space, bodies)
+    }
+
+    #[test]
+    fn joints_added_by_two_peers_both_sync() {
+        let (mut server, bodies) = space_with_bodies(3);
+
+        let mut peer1 = server.clone();
+        let mut peer2 = server.clone();
+
+        peer1.add_revolute_joint(bodies[0], bodies[1], Vec2::ZERO, Vec2::ZERO);
+        peer2.add_rope_joint(bodies[1], bodies[2], Vec2::ZERO, Vec2::ZERO, 5.);
+
+        let peer1_diff = server.diff(&peer1);
+        let peer2_diff = server.diff(&peer2);
+
+        server.apply(&peer1_diff);
+        server.apply(&peer2_diff);
+
+        assert_eq!(server.impulse_joint_set.len(), 2);
+
+        // each peer catches up on the other's joint
+        peer1.apply(&peer2_diff);
+        peer2.apply(&peer1_diff);
+
+        assert!(peer1 == server);
+        assert!(peer2 == server);
+    }
+
+    #[test]
+    fn joints_inserted_into_the_set_directly_sync() {
+        let (mut remote, bodies) = space_with_bodies(2);
+
+        let mut local = remote.clone();
+
+        let joint_handle = local.impulse_joint_set.insert(bodies[0], bodies[1], rapier2d::dynamics::FixedJointBuilder::new(), true);
+
+        remote.apply(&remote.diff(&local));
+
+        assert_eq!(remote.impulse_joint_set.len(), 1);
+
+        let before = local.clone();
+
+        local.remove_joint(joint_handle);
+
+        remote.apply(&before.diff(&local));
+
+        assert_eq!(remote.impulse_joint_set.len(), 0);
+    }
+
+    #[test]
+    fn unchanged_joints_are_left_out() {
+        let (mut space, bodies) = space_with_bodies(2);
+
+        space.add_fixed_joint(bodies[0], bodies[1], Vec2::ZERO, Vec2::ZERO);
+
+        assert!(space.diff_joints(&space.clone()).is_none());
+    }
+}
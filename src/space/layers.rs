@@ -0,0 +1,116 @@
+use rapier2d::geometry::{Group, InteractionGroups};
+use serde::{Deserialize, Serialize};
+
+/// Registry of named collision layers, each one mapped to a bit of rapier's InteractionGroups
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct CollisionLayers {
+    // the index of the name is the bit it occupies
+    names: Vec<String>
+}
+
+impl CollisionLayers {
+
+    pub fn new() -> Self {
+        Self {
+            names: vec![],
+        }
+    }
+
+    /// Create a registry with the given layers registered in order. Returns an Err if there are more than 32
+    pub fn from_names(names: &[&str]) -> Result<Self, String> {
+        let mut layers = Self::new();
+
+        for name in names {
+            layers.register(name)?;
+        }
+
+        Ok(layers)
+    }
+
+    /// Register a new layer (or return the existing one with the same name). Returns an Err once all 32 layers are in use
+    pub fn register(&mut self, name: &str) -> Result<Group, String> {
+
+        if let Some(group) = self.get(name) {
+            return Ok(group);
+        }
+
+        if self.names.len() >= 32 {
+            return Err(format!("cannot register collision layer '{}': all 32 layers are in use", name));
+        }
+
+        self.names.push(name.to_string());
+
+        Ok(Group::from_bits_truncate(1 << (self.names.len() - 1)))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Group> {
+        let index = self.names.iter().position(|layer_name| layer_name == name)?;
+
+        Some(Group::from_bits_truncate(1 << index))
+    }
+
+    /// Get the group for a layer, or an Err naming the layer if it was never registered
+    pub fn group(&self, name: &str) -> Result<Group, String> {
+        self.get(name).ok_or_else(|| format!("unknown collision layer '{}'", name))
+    }
+
+    /// Combine several layers into one group
+    pub fn groups(&self, names: &[&str]) -> Result<Group, String> {
+        let mut group = Group::empty();
+
+        for name in names {
+            group |= self.group(name)?;
+        }
+
+        Ok(group)
+    }
+
+    /// Build InteractionGroups that are members of `memberships` and collide with `filter`
+    pub fn interaction_groups(&self, memberships: &[&str], filter: &[&str]) -> Result<InteractionGroups, String> {
+        Ok(InteractionGroups::new(self.groups(memberships)?, self.groups(filter)?))
+    }
+
+    pub fn names(&self) -> &Vec<String> {
+        &self.names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rapier2d::geometry::Group;
+
+    use super::CollisionLayers;
+
+    #[test]
+    fn layers_get_bits_in_registration_order() {
+        let mut layers = CollisionLayers::from_names(&["player", "enemy"]).unwrap();
+
+        assert_eq!(layers.group("player"), Ok(Group::GROUP_1));
+        assert_eq!(layers.register("enemy"), Ok(Group::GROUP_2));
+        assert_eq!(layers.groups(&["player", "enemy"]), Ok(Group::GROUP_1 | Group::GROUP_2));
+    }
+
+    #[test]
+    fn unknown_layers_are_an_error() {
+        let layers = CollisionLayers::from_names(&["player"]).unwrap();
+
+        assert!(layers.group("wall").is_err());
+        assert!(layers.interaction_groups(&["player"], &["wall"]).is_err());
+    }
+
+    #[test]
+    fn only_32_layers_fit() {
+        let names: Vec<String> = (0..32).map(|index| format!("layer {}", index)).collect();
+
+        let mut layers = CollisionLayers::new();
+
+        for name in &names {
+            assert!(layers.register(name).is_ok());
+        }
+
+        assert!(layers.register("one too many").is_err());
+
+        // registering an existing layer still works when full
+        assert!(layers.register("layer 3").is_ok());
+    }
+}
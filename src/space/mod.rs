@@ -0,0 +1,560 @@
+use std::{cell::Cell, time::Duration};
+
+use diff::Diff;
+use macroquad::math::Vec2;
+use nalgebra::{point, vector, Unit};
+use rapier2d::{crossbeam::{self, channel::Receiver}, dynamics::{CCDSolver, FixedJointBuilder, GenericJoint, ImpulseJointHandle, ImpulseJointSet, IntegrationParameters, IslandManager, MultibodyJointSet, PrismaticJointBuilder, RevoluteJointBuilder, RigidBody, RigidBodyHandle, RigidBodySet, RopeJointBuilder}, geometry::{Collider, ColliderHandle, ColliderSet, DefaultBroadPhase, NarrowPhase}, pipeline::{PhysicsPipeline, QueryPipeline}, prelude::{ChannelEventCollector, CollisionEvent}};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use joints::JointChange;
+use layers::CollisionLayers;
+use platforms::MovingPlatform;
+use fxhash::FxHashSet;
+use transforms::{resting_rigid_bodies, DiffQuantization, DiffThresholds, QuantizedTransform, QuantizedTransforms};
+
+use crate::time::{self, GameClock};
+
+pub mod impulse;
+pub mod joints;
+pub mod layers;
+pub mod platforms;
+pub mod stats;
+pub mod terrain;
+pub mod transforms;
+
+#[derive(Serialize)]
+pub struct Space {
+    
+    pub rigid_body_set: RigidBodySet,
+    #[serde(skip)]
+    pub collision_recv: Receiver<CollisionEvent>,
+    pub collider_set: ColliderSet,
+    pub gravity: nalgebra::Matrix<f32, nalgebra::Const<2>, nalgebra::Const<1>, nalgebra::ArrayStorage<f32, 2, 1>>,
+    pub integration_parameters: IntegrationParameters,
+    #[serde(skip)]
+    pub physics_pipeline: PhysicsPipeline,
+    pub island_manager: IslandManager,
+    pub broad_phase: DefaultBroadPhase,
+    pub narrow_phase: NarrowPhase,
+    pub impulse_joint_set: ImpulseJointSet,
+    pub multibody_joint_set: MultibodyJointSet,
+    pub ccd_solver: CCDSolver,
+    pub query_pipeline: QueryPipeline,
+    pub physics_hooks: (),
+    pub moving_platforms: Vec<MovingPlatform>,
+    platform_time: f64, // seconds of simulation the platforms have been stepped through, sent along with platform changes
+    #[serde(skip)]
+    pub diff_quantization: Option<DiffQuantization>, // send transform changes as fixed point values
+    #[serde(skip)]
+    pub diff_thresholds: Option<DiffThresholds>, // skip transform changes smaller than these
+    #[serde(skip)]
+    resting_rigid_bodies: FxHashSet<RigidBodyHandle>, // bodies the last step didn't move
+    #[serde(skip)]
+    last_step_duration: Duration,
+    #[serde(skip)]
+    pub measure_diff_size: bool, // serialize every diff an extra time to fill in SpaceStats::last_diff_size
+    #[serde(skip)]
+    last_diff_size: Cell<usize>,
+    #[serde(skip)]
+    pub event_handler: ChannelEventCollector,
+}
+
+impl<'de> Deserialize<'de> for Space {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct SpaceHelper {
+            rigid_body_set: RigidBodySet,
+            collider_set: ColliderSet,
+            gravity: nalgebra::Matrix<f32, nalgebra::Const<2>, nalgebra::Const<1>, nalgebra::ArrayStorage<f32, 2, 1>>,
+            integration_parameters: IntegrationParameters,
+            island_manager: IslandManager,
+            broad_phase: DefaultBroadPhase,
+            narrow_phase: NarrowPhase,
+            impulse_joint_set: ImpulseJointSet,
+            multibody_joint_set: MultibodyJointSet,
+            ccd_solver: CCDSolver,
+            query_pipeline: QueryPipeline,
+            #[serde(default)]
+            moving_platforms: Vec<MovingPlatform>,
+            #[serde(default)]
+            platform_time: f64,
+        }
+
+        let helper = SpaceHelper::deserialize(deserializer)?;
+
+        let (collision_send, collision_recv) = crossbeam::channel::unbounded();
+        let (contact_force_send, _contact_force_recv) = crossbeam::channel::unbounded();
+        let event_handler = ChannelEventCollector::new(collision_send, contact_force_send);
+
+        Ok(Space {
+            rigid_body_set: helper.rigid_body_set,
+            collision_recv,
+            collider_set: helper.collider_set,
+            gravity: helper.gravity,
+            integration_parameters: helper.integration_parameters,
+            physics_pipeline: PhysicsPipeline::new(),
+            island_manager: helper.island_manager,
+            broad_phase: helper.broad_phase,
+            narrow_phase: helper.narrow_phase,
+            impulse_joint_set: helper.impulse_joint_set,
+            multibody_joint_set: helper.multibody_joint_set,
+            ccd_solver: helper.ccd_solver,
+            query_pipeline: helper.query_pipeline,
+            event_handler,
+            physics_hooks: (),
+            moving_platforms: helper.moving_platforms,
+            platform_time: helper.platform_time,
+            diff_quantization: None,
+            diff_thresholds: None,
+            resting_rigid_bodies: FxHashSet::default(),
+            last_step_duration: Duration::ZERO,
+            measure_diff_size: false,
+            last_diff_size: Cell::new(0)
+        })
+    }
+}
+
+impl Clone for Space {
+    fn clone(&self) -> Self {
+
+        let (collision_send, collision_recv) = crossbeam::channel::unbounded();
+        let (contact_force_send, _contact_force_recv) = crossbeam::channel::unbounded();
+        let event_handler = ChannelEventCollector::new(collision_send, contact_force_send);
+
+        Self {
+            rigid_body_set: self.rigid_body_set.clone(),
+            collider_set: self.collider_set.clone(),
+            gravity: self.gravity.clone(),
+            integration_parameters: self.integration_parameters.clone(),
+            physics_pipeline: self.physics_pipeline.clone(),
+            island_manager: self.island_manager.clone(),
+            broad_phase: self.broad_phase.clone(),
+            narrow_phase: self.narrow_phase.clone(),
+            impulse_joint_set: self.impulse_joint_set.clone(),
+            multibody_joint_set: self.multibody_joint_set.clone(),
+            ccd_solver: self.ccd_solver.clone(),
+            query_pipeline: self.query_pipeline.clone(),
+            physics_hooks: self.physics_hooks.clone(),
+            moving_platforms: self.moving_platforms.clone(),
+            platform_time: self.platform_time,
+            diff_quantization: self.diff_quantization,
+            diff_thresholds: self.diff_thresholds,
+            resting_rigid_bodies: self.resting_rigid_bodies.clone(),
+            last_step_duration: self.last_step_duration,
+            measure_diff_size: self.measure_diff_size,
+            last_diff_size: self.last_diff_size.clone(),
+            event_handler,
+            collision_recv
+        }
+    }
+}
+
+impl PartialEq for Space {
+    fn eq(&self, other: &Self) -> bool {
+        other.rigid_body_set == self.rigid_body_set 
+            && other.collider_set == self.collider_set 
+            && joints::joints_by_bodies(&other.impulse_joint_set) == joints::joints_by_bodies(&self.impulse_joint_set)
+            && other.moving_platforms == self.moving_platforms
+    }
+
+    fn ne(&self, other: &Self) -> bool {
+        !self.eq(other)
+    }
+}
+
+impl Space {
+
+    pub fn new() -> Self {
+        let rigid_body_set = RigidBodySet::new();
+        let collider_set = ColliderSet::new();
+
+
+        let (collision_send, collision_recv) = crossbeam::channel::unbounded();
+        let (contact_force_send, _contact_force_recv) = crossbeam::channel::unbounded();
+        let event_handler = ChannelEventCollector::new(collision_send, contact_force_send);
+    
+        /* Create other structures necessary for the simulation. */
+        let gravity = vector![0.0, 0.];
+        let integration_parameters = IntegrationParameters::default();
+        let physics_pipeline = PhysicsPipeline::new();
+        let island_manager = IslandManager::new();
+        let broad_phase = DefaultBroadPhase::new();
+        let narrow_phase = NarrowPhase::new();
+        let impulse_joint_set = ImpulseJointSet::new();
+        let multibody_joint_set = MultibodyJointSet::new();
+        let ccd_solver = CCDSolver::new();
+        let query_pipeline = QueryPipeline::new();
+        let physics_hooks = ();
+
+        Self { 
+            rigid_body_set, 
+            collider_set, 
+            gravity, 
+            integration_parameters, 
+            physics_pipeline, 
+            island_manager, 
+            broad_phase, 
+            narrow_phase, 
+            impulse_joint_set, 
+            multibody_joint_set, 
+            ccd_solver, 
+            query_pipeline, 
+            physics_hooks, 
+            moving_platforms: vec![],
+            platform_time: 0.,
+            diff_quantization: None,
+            diff_thresholds: None,
+            resting_rigid_bodies: FxHashSet::default(),
+            last_step_duration: Duration::ZERO,
+            measure_diff_size: false,
+            last_diff_size: Cell::new(0),
+            event_handler,
+            collision_recv
+        }
+    }
+
+    fn insert_joint(&mut self, body1: RigidBodyHandle, body2: RigidBodyHandle, joint: impl Into<GenericJoint>) -> ImpulseJointHandle {
+        self.impulse_joint_set.insert(body1, body2, joint, true)
+    }
+
+    /// Pin two bodies together at the given local anchors, letting them rotate freely around it
+    pub fn add_revolute_joint(&mut self, body1: RigidBodyHandle, body2: RigidBodyHandle, anchor1: Vec2, anchor2: Vec2) -> ImpulseJointHandle {
+        let joint = RevoluteJointBuilder::new()
+            .local_anchor1(point![anchor1.x, anchor1.y])
+            .local_anchor2(point![anchor2.x, anchor2.y]);
+
+        self.insert_joint(body1, body2, joint)
+    }
+
+    /// Constrain two bodies to slide along `axis` (relative to body1), optionally within `limits`
+    pub fn add_prismatic_joint(&mut self, body1: RigidBodyHandle, body2: RigidBodyHandle, anchor1: Vec2, anchor2: Vec2, axis: Vec2, limits: Option<[f32; 2]>) -> ImpulseJointHandle {
+        let mut joint = PrismaticJointBuilder::new(Unit::new_normalize(vector![axis.x, axis.y]))
+            .local_anchor1(point![anchor1.x, anchor1.y])
+            .local_anchor2(point![anchor2.x, anchor2.y]);
+
+        if let Some(limits) = limits {
+            joint = joint.limits(limits);
+        }
+
+        self.insert_joint(body1, body2, joint)
+    }
+
+    /// Weld two bodies together so they move as one
+    pub fn add_fixed_joint(&mut self, body1: RigidBodyHandle, body2: RigidBodyHandle, anchor1: Vec2, anchor2: Vec2) -> ImpulseJointHandle {
+        let joint = FixedJointBuilder::new()
+            .local_anchor1(point![anchor1.x, anchor1.y])
+            .local_anchor2(point![anchor2.x, anchor2.y]);
+
+        self.insert_joint(body1, body2, joint)
+    }
+
+    /// Keep the anchors of two bodies within `max_distance` of each other
+    pub fn add_rope_joint(&mut self, body1: RigidBodyHandle, body2: RigidBodyHandle, anchor1: Vec2, anchor2: Vec2, max_distance: f32) -> ImpulseJointHandle {
+        let joint = RopeJointBuilder::new(max_distance)
+            .local_anchor1(point![anchor1.x, anchor1.y])
+            .local_anchor2(point![anchor2.x, anchor2.y]);
+
+        self.insert_joint(body1, body2, joint)
+    }
+
+    /// Insert a collider (optionally attached to a parent body) with its collision groups set from named layers.
+    /// Returns an Err without inserting anything if one of the layers was never registered
+    pub fn insert_collider_with_layers(&mut self, mut collider: Collider, parent: Option<RigidBodyHandle>, layers: &CollisionLayers, memberships: &[&str], filter: &[&str]) -> Result<ColliderHandle, String> {
+        collider.set_collision_groups(layers.interaction_groups(memberships, filter)?);
+
+        let collider_handle = match parent {
+            Some(parent) => self.collider_set.insert_with_parent(collider, parent, &mut self.rigid_body_set),
+            None => self.collider_set.insert(collider),
+        };
+
+        Ok(collider_handle)
+    }
+
+    /// Which rigid bodies would be added, removed or modified by applying `diff`. Useful for rejecting diffs that touch bodies a client doesn't own
+    pub fn rigid_bodies_changed_by(&self, diff: &SpaceDiff) -> Vec<RigidBodyHandle> {
+        let mut changed: Vec<RigidBodyHandle> = vec![];
+
+        if let Some(transforms) = &diff.transforms {
+            changed.extend(transforms.transforms.iter().map(|transform| transform.handle));
+        }
+
+        let rigid_body_set_diff = match &diff.rigid_body_set {
+            Some(rigid_body_set_diff) => rigid_body_set_diff,
+            None => return changed,
+        };
+
+        // the rigid body set diff doesn't say which bodies it touches, so it is tried on a copy of just the bodies
+        let mut applied = self.rigid_body_set.clone();
+
+        applied.apply(rigid_body_set_diff);
+
+        for (rigid_body_handle, rigid_body) in applied.iter() {
+            match self.rigid_body_set.get(rigid_body_handle) {
+                Some(old_rigid_body) if old_rigid_body == rigid_body => {},
+                _ => changed.push(rigid_body_handle),
+            }
+        }
+
+        for (rigid_body_handle, _) in self.rigid_body_set.iter() {
+            if !applied.contains(rigid_body_handle) {
+                changed.push(rigid_body_handle);
+            }
+        }
+
+        changed.sort_unstable_by_key(|rigid_body_handle| rigid_body_handle.into_raw_parts());
+        changed.dedup();
+
+        changed
+    }
+
+    /// A copy of this space where the bodies `visible` returns false for, and their colliders, are left how they were in `sent`.
+    /// Diffing `sent` against it leaves those bodies out. Bodies that are new or had colliders attached or removed always come through
+    pub fn masked(&self, sent: &Space, mut visible: impl FnMut(&RigidBody) -> bool) -> Space {
+        let mut masked = self.clone();
+
+        for (rigid_body_handle, rigid_body) in self.rigid_body_set.iter() {
+            if visible(rigid_body) {
+                continue;
+            }
+
+            let sent_rigid_body = match sent.rigid_body_set.get(rigid_body_handle) {
+                Some(sent_rigid_body) => sent_rigid_body,
+                None => continue,
+            };
+
+            if sent_rigid_body.colliders() != rigid_body.colliders() {
+                continue;
+            }
+
+            *masked.rigid_body_set.get_mut(rigid_body_handle).unwrap() = sent_rigid_body.clone();
+
+            for collider_handle in sent_rigid_body.colliders() {
+                if let (Some(collider), Some(sent_collider)) = (masked.collider_set.get_mut(*collider_handle), sent.collider_set.get(*collider_handle)) {
+                    *collider = sent_collider.clone();
+                }
+            }
+        }
+
+        masked
+    }
+
+    pub fn remove_joint(&mut self, joint_handle: ImpulseJointHandle) {
+        self.impulse_joint_set.remove(joint_handle, true);
+    }
+
+    /// Step once for every fixed tick the clock has this frame, so nothing moves while it is paused
+    pub fn step_clock(&mut self, clock: &GameClock, owned_rigid_bodies: &Vec<RigidBodyHandle>, owned_colliders: &Vec<ColliderHandle>) {
+        for _ in 0..clock.ticks_this_frame() {
+            self.step(clock.fixed_delta, owned_rigid_bodies, owned_colliders);
+        }
+    }
+
+    pub fn step(&mut self, dt: Duration, owned_rigid_bodies: &Vec<RigidBodyHandle>, owned_colliders: &Vec<ColliderHandle>) {
+        
+        let step_start = time::now();
+
+        // any colliders/bodies we do not own we will return to their original state here
+        let rigid_body_set_before = self.rigid_body_set.clone();
+        let collider_set_before = self.collider_set.clone();
+
+        self.integration_parameters.dt = dt.as_secs_f32();
+        
+
+        for (rigid_body_handle, rigid_body) in self.rigid_body_set.iter_mut() {
+
+            // this is a temporary workaround but i think we are failing to sync sleep states
+            rigid_body.wake_up(true);   
+            if owned_rigid_bodies.contains(&rigid_body_handle) {
+                continue;
+            }
+
+            //rigid_body.set_body_type(rapier2d::prelude::RigidBodyType::KinematicPositionBased, false);
+        }
+
+        self.update_moving_platforms(dt);
+        
+        self.physics_pipeline.step(
+            &self.gravity,
+            &self.integration_parameters,
+            &mut self.island_manager,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.rigid_body_set,
+            &mut self.collider_set,
+            &mut self.impulse_joint_set,
+            &mut self.multibody_joint_set,
+            &mut self.ccd_solver,
+            Some(&mut self.query_pipeline),
+            &self.physics_hooks,
+            &self.event_handler
+        );
+        //println!("time: {:?}", self.);
+        
+        for (rigid_body_handle, rigid_body) in self.rigid_body_set.iter_mut() {
+            if owned_rigid_bodies.contains(&rigid_body_handle) {
+                continue;
+            }
+
+            // every client drives the platforms itself
+            if self.moving_platforms.iter().any(|platform| platform.rigid_body_handle == rigid_body_handle) {
+                continue;
+            }
+
+            let rigid_body_before = rigid_body_set_before.get(rigid_body_handle).expect("Unable to find old version of rigid body before it was updated");
+
+            // we should probably remove this instead of cloning?
+            *rigid_body = rigid_body_before.clone();
+         
+        }
+
+        for (collider_handle, _collider) in self.collider_set.iter_mut() {
+            if owned_colliders.contains(&collider_handle) {
+                continue;
+            }
+
+            let _collider_before = collider_set_before.get(collider_handle).expect("Unable to find old version of collider before it was updated");
+
+            // we should probably remove this instead of cloning?
+            //*collider = collider_before.clone();
+        }
+
+        self.resting_rigid_bodies = resting_rigid_bodies(&rigid_body_set_before, &self.rigid_body_set);
+
+        self.last_step_duration = step_start.elapsed();
+
+    }
+    
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SpaceDiff {
+    // for some reason i cant use RigidBodySetDiff directly
+    rigid_body_set: Option<<RigidBodySet as Diff>::Repr>,
+    transforms: Option<QuantizedTransforms>,
+    collider_set: Option<<ColliderSet as Diff>::Repr>,
+    gravity: Option<nalgebra::Matrix<f32, nalgebra::Const<2>, nalgebra::Const<1>, nalgebra::ArrayStorage<f32, 2, 1>>>,
+    // joints are matched up by the bodies they connect since each peer has its own joint handles
+    impulse_joints: Option<Vec<JointChange>>,
+    // only the paths are sent, platform positions are computed locally from the platform time
+    moving_platforms: Option<(f64, Vec<MovingPlatform>)>,
+    //broad_phase: Option<BroadPhaseMultiSap>
+    // might wanna add the rest of the fields
+}
+
+impl SpaceDiff {
+
+    /// Keep only the quantized transform updates for which `keep` returns true
+    pub fn retain_transforms(&mut self, keep: impl FnMut(&QuantizedTransform) -> bool) {
+        if let Some(transforms) = &mut self.transforms {
+            transforms.transforms.retain(keep);
+        }
+    }
+}
+
+impl Diff for Space {
+    type Repr = SpaceDiff; 
+
+    fn diff(&self, other: &Self) -> Self::Repr {
+        let mut diff = SpaceDiff {
+            rigid_body_set: None,
+            transforms: None,
+            collider_set: None,
+            gravity: None,
+            impulse_joints: None,
+            moving_platforms: None,
+            //broad_phase: None
+        };
+
+        let (masked_rigid_body_set, transforms) = self.split_transforms(other);
+
+        diff.transforms = transforms;
+
+        let new_rigid_body_set = match &masked_rigid_body_set {
+            Some(masked_rigid_body_set) => masked_rigid_body_set,
+            None => &other.rigid_body_set,
+        };
+
+        if *new_rigid_body_set != self.rigid_body_set {
+            diff.rigid_body_set = Some(self.rigid_body_set.diff(new_rigid_body_set))
+        }
+
+        if other.collider_set != self.collider_set {
+            diff.collider_set = Some(self.collider_set.diff(&other.collider_set))
+        }
+
+        if other.gravity != self.gravity {
+            diff.gravity = Some(other.gravity)
+        }
+
+        diff.impulse_joints = self.diff_joints(other);
+
+        if other.moving_platforms != self.moving_platforms {
+            diff.moving_platforms = Some((other.platform_time, other.moving_platforms.clone()))
+        }
+
+        // roughly what the diff costs on the wire before compression. SyncMetrics has the real frame sizes
+        if other.measure_diff_size {
+            other.last_diff_size.set(
+                bitcode::serialize(&diff).map(|bytes| bytes.len()).unwrap_or(0)
+            );
+        }
+
+        // if other.broad_phase != self.broad_phase {
+        //     diff.broad_phase = Some(other.broad_phase.clone())
+        // }
+
+
+        // if other.island_manager != self.island_manager {
+        //     diff.island_manager = Some(other.island_manager.clone());
+        // }
+
+        // if self.rigid_body_set.len() != other.rigid_body_set.len() {
+        //     fs::write("diff.yaml", bitcode::serialize(&diff).unwrap()).unwrap();
+        // }
+
+        diff
+
+    }
+
+    fn apply(&mut self, diff: &Self::Repr) {
+        if let Some(rigid_body_set_diff) = &diff.rigid_body_set {
+            self.rigid_body_set.apply(rigid_body_set_diff);
+        }
+
+        if let Some(transforms) = &diff.transforms {
+            transforms.apply(&mut self.rigid_body_set);
+        }
+
+        if let Some(collider_set_diff) = &diff.collider_set {
+            self.collider_set.apply(collider_set_diff);
+        }
+
+        if let Some(gravity) = &diff.gravity {
+            self.gravity = *gravity;
+        }
+
+        if let Some(joint_changes) = &diff.impulse_joints {
+            self.apply_joint_changes(joint_changes);
+        }
+
+        if let Some((platform_time, moving_platforms)) = &diff.moving_platforms {
+            self.platform_time = *platform_time;
+            self.moving_platforms = moving_platforms.clone();
+        }
+
+        // if let Some(broad_phase) = &diff.broad_phase {
+        //     self.broad_phase = broad_phase.clone()
+        // }
+
+        // if let Some(island_manager) = &diff.island_manager {
+        //     self.island_manager = island_manager.clone();
+        // }
+    }
+
+    fn identity() -> Self {
+        Space::new()
+    }
+}
\ No newline at end of file
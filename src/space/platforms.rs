@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use macroquad::math::Vec2;
+use nalgebra::vector;
+use rapier2d::dynamics::{RigidBodyHandle, RigidBodyType};
+use serde::{Deserialize, Serialize};
+
+use super::Space;
+
+/// A kinematic body that travels around a closed loop of waypoints at a constant speed.
+/// The position on the path is derived from the Space's platform time, which only advances when the space is stepped and is synced along with the platforms,
+/// so every client moves the platform identically without syncing its position
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MovingPlatform {
+    pub rigid_body_handle: RigidBodyHandle,
+    pub waypoints: Vec<Vec2>,
+    pub speed: f32,
+    pub start_time: f64 // platform time in seconds
+}
+
+impl MovingPlatform {
+
+    fn path_length(&self) -> f32 {
+        let mut length = 0.;
+
+        for index in 0..self.waypoints.len() {
+            let next_index = (index + 1) % self.waypoints.len();
+
+            length += self.waypoints[index].distance(self.waypoints[next_index]);
+        }
+
+        length
+    }
+
+    /// Where the platform should be `elapsed` seconds after it started
+    pub fn position_at(&self, elapsed: f32) -> Vec2 {
+        let path_length = self.path_length();
+
+        if path_length <= 0. {
+            return self.waypoints[0]
+        }
+
+        let mut distance = (elapsed * self.speed) % path_length;
+
+        for index in 0..self.waypoints.len() {
+            let start = self.waypoints[index];
+            let end = self.waypoints[(index + 1) % self.waypoints.len()];
+
+            let segment_length = start.distance(end);
+
+            if distance <= segment_length {
+                return start.lerp(end, distance / segment_length)
+            }
+
+            distance -= segment_length;
+        }
+
+        self.waypoints[0]
+    }
+
+    /// Seconds since the platform started at the given platform time
+    pub fn elapsed(&self, platform_time: f64) -> f32 {
+        (platform_time - self.start_time).max(0.) as f32
+    }
+}
+
+impl Space {
+
+    /// Turn a rigid body into a velocity based kinematic platform that follows `waypoints` (in rapier coordinates)
+    pub fn add_moving_platform(&mut self, rigid_body_handle: RigidBodyHandle, waypoints: Vec<Vec2>, speed: f32) -> Result<(), String> {
+
+        if waypoints.is_empty() {
+            return Err("moving platform needs at least one waypoint".to_string());
+        }
+
+        let rigid_body = self.rigid_body_set.get_mut(rigid_body_handle).ok_or("invalid rigid body handle")?;
+
+        rigid_body.set_body_type(RigidBodyType::KinematicVelocityBased, true);
+
+        // replace any existing platform for this body
+        self.remove_moving_platform(rigid_body_handle);
+
+        self.moving_platforms.push(
+            MovingPlatform { 
+                rigid_body_handle, 
+                waypoints, 
+                speed, 
+                start_time: self.platform_time
+            }
+        );
+
+        Ok(())
+    }
+
+    pub fn remove_moving_platform(&mut self, rigid_body_handle: RigidBodyHandle) {
+        self.moving_platforms.retain(|platform| platform.rigid_body_handle != rigid_body_handle);
+    }
+
+    /// Seconds of simulation the moving platforms have been stepped through
+    pub fn platform_time(&self) -> f64 {
+        self.platform_time
+    }
+
+    pub fn is_moving_platform(&self, rigid_body_handle: RigidBodyHandle) -> bool {
+        self.moving_platforms.iter().any(|platform| platform.rigid_body_handle == rigid_body_handle)
+    }
+
+    /// Set each platform's velocity so it reaches where it should be by the end of this step, then advance the platform time
+    pub(crate) fn update_moving_platforms(&mut self, dt: Duration) {
+
+        let dt = dt.as_secs_f32();
+
+        if dt <= 0. {
+            return;
+        }
+
+        for platform in &self.moving_platforms {
+            let rigid_body = match self.rigid_body_set.get_mut(platform.rigid_body_handle) {
+                Some(rigid_body) => rigid_body,
+                None => continue,
+            };
+
+            let target = platform.position_at(platform.elapsed(self.platform_time) + dt);
+
+            let current = rigid_body.translation();
+
+            rigid_body.set_linvel(vector![(target.x - current.x) / dt, (target.y - current.y) / dt], true);
+        }
+
+        self.platform_time += dt as f64;
+    }
+}
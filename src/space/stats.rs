@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use fxhash::FxHashMap;
+use rapier2d::dynamics::RigidBodyHandle;
+
+use super::Space;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SpaceStats {
+    pub body_count: usize,
+    pub active_body_count: usize,
+    pub collider_count: usize,
+    pub island_count: usize,
+    pub contact_pair_count: usize, // pairs the broad phase found overlapping
+    pub last_step_duration: Duration,
+    pub last_diff_size: usize // bitcode size in bytes of the last SpaceDiff generated against this space, 0 unless measure_diff_size is on
+}
+
+impl Space {
+
+    pub fn stats(&self) -> SpaceStats {
+        SpaceStats {
+            body_count: self.rigid_body_set.len(),
+            active_body_count: self.island_manager.active_dynamic_bodies().len() + self.island_manager.active_kinematic_bodies().len(),
+            collider_count: self.collider_set.len(),
+            island_count: self.island_count(),
+            contact_pair_count: self.narrow_phase.contact_pairs().count(),
+            last_step_duration: self.last_step_duration,
+            last_diff_size: self.last_diff_size.get(),
+        }
+    }
+
+    /// Count groups of dynamic bodies connected by active contacts or joints.
+    /// rapier doesn't expose its own islands so we rebuild them here
+    fn island_count(&self) -> usize {
+
+        fn find(parents: &mut FxHashMap<RigidBodyHandle, RigidBodyHandle>, handle: RigidBodyHandle) -> RigidBodyHandle {
+            let parent = parents[&handle];
+
+            if parent == handle {
+                return handle
+            }
+
+            let root = find(parents, parent);
+
+            parents.insert(handle, root);
+
+            root
+        }
+
+        let mut parents: FxHashMap<RigidBodyHandle, RigidBodyHandle> = FxHashMap::default();
+
+        for (rigid_body_handle, rigid_body) in self.rigid_body_set.iter() {
+            if rigid_body.is_dynamic() {
+                parents.insert(rigid_body_handle, rigid_body_handle);
+            }
+        }
+
+        let mut connections: Vec<(RigidBodyHandle, RigidBodyHandle)> = vec![];
+
+        for contact_pair in self.narrow_phase.contact_pairs() {
+            if !contact_pair.has_any_active_contact {
+                continue;
+            }
+
+            let body1 = self.collider_set.get(contact_pair.collider1).and_then(|collider| collider.parent());
+            let body2 = self.collider_set.get(contact_pair.collider2).and_then(|collider| collider.parent());
+
+            if let (Some(body1), Some(body2)) = (body1, body2) {
+                connections.push((body1, body2));
+            }
+        }
+
+        for (_joint_handle, joint) in self.impulse_joint_set.iter() {
+            connections.push((joint.body1, joint.body2));
+        }
+
+        for (body1, body2) in connections {
+            // static and kinematic bodies don't join islands together
+            if !parents.contains_key(&body1) || !parents.contains_key(&body2) {
+                continue;
+            }
+
+            let root1 = find(&mut parents, body1);
+            let root2 = find(&mut parents, body2);
+
+            if root1 != root2 {
+                parents.insert(root1, root2);
+            }
+        }
+
+        let handles: Vec<RigidBodyHandle> = parents.keys().copied().collect();
+
+        let mut island_count = 0;
+
+        for handle in handles {
+            if find(&mut parents, handle) == handle {
+                island_count += 1;
+            }
+        }
+
+        island_count
+    }
+}
@@ -0,0 +1,59 @@
+use macroquad::{color::Color, math::{vec2, Vec2}, shapes::draw_line};
+use nalgebra::{point, vector, DVector};
+use rapier2d::geometry::{ColliderBuilder, ColliderHandle, Segment, TypedShape};
+
+use crate::coordinates::CoordinateSystem;
+
+use super::Space;
+
+impl Space {
+
+    /// Insert a static polyline collider running through `points` (in rapier coordinates). Needs at least 2 points
+    pub fn insert_terrain(&mut self, points: &[Vec2]) -> Result<ColliderHandle, String> {
+
+        if points.len() < 2 {
+            return Err(format!("terrain needs at least 2 points, got {}", points.len()));
+        }
+
+        let vertices = points.iter().map(|point| point![point.x, point.y]).collect();
+
+        let collider = ColliderBuilder::polyline(vertices, None).build();
+
+        Ok(self.collider_set.insert(collider))
+    }
+
+    /// Insert a static heightfield collider. `heights` are evenly spaced across `width`, with the heightfield centered on `position`. Needs at least 2 heights
+    pub fn insert_heightfield_terrain(&mut self, heights: &[f32], width: f32, height_scale: f32, position: Vec2) -> Result<ColliderHandle, String> {
+
+        if heights.len() < 2 {
+            return Err(format!("heightfield needs at least 2 heights, got {}", heights.len()));
+        }
+
+        let collider = ColliderBuilder::heightfield(DVector::from_column_slice(heights), vector![width, height_scale])
+            .translation(vector![position.x, position.y])
+            .build();
+
+        Ok(self.collider_set.insert(collider))
+    }
+
+    /// Draw the segments of a polyline or heightfield collider
+    pub fn draw_terrain(&self, collider_handle: ColliderHandle, thickness: f32, color: Color, coordinates: &CoordinateSystem) {
+        let collider = self.collider_set.get(collider_handle).expect("Invalid collider handle");
+
+        let segments: Vec<Segment> = match collider.shape().as_typed_shape() {
+            TypedShape::Polyline(polyline) => polyline.segments().collect(),
+            TypedShape::HeightField(heightfield) => heightfield.segments().collect(),
+            _ => panic!("cannot draw non terrain shape")
+        };
+
+        for segment in segments {
+            let a = collider.position() * segment.a;
+            let b = collider.position() * segment.b;
+
+            let draw_a = coordinates.rapier_to_macroquad(&vec2(a.x, a.y));
+            let draw_b = coordinates.rapier_to_macroquad(&vec2(b.x, b.y));
+
+            draw_line(draw_a.x, draw_a.y, draw_b.x, draw_b.y, thickness, color);
+        }
+    }
+}
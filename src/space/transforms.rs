@@ -0,0 +1,416 @@
+use std::f32::consts::PI;
+
+use fxhash::FxHashSet;
+use nalgebra::{vector, Isometry2, Vector2};
+use rapier2d::dynamics::{RigidBody, RigidBodyHandle, RigidBodySet};
+use serde::{Deserialize, Serialize};
+
+use super::Space;
+
+/// Encode rigid body transforms in SpaceDiff as fixed point integers instead of full floats
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DiffQuantization {
+    pub position_precision: f32, // smallest position step that can be represented, in world units
+    pub velocity_precision: f32
+}
+
+impl Default for DiffQuantization {
+    fn default() -> Self {
+        Self {
+            position_precision: 0.01,
+            velocity_precision: 0.01,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct QuantizedTransform {
+    pub handle: RigidBodyHandle,
+    angle: i16,
+    encoding: TransformEncoding
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+enum TransformEncoding {
+    Delta { x: i16, y: i16, linvel_x: i16, linvel_y: i16, angvel: i16 }, // steps from what the receiver has
+    Absolute { x: i32, y: i32, linvel_x: i32, linvel_y: i32, angvel: i32 } // for changes too big to fit a delta
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QuantizedTransforms {
+    quantization: DiffQuantization,
+    pub transforms: Vec<QuantizedTransform>
+}
+
+fn quantize(value: f32, precision: f32) -> Option<i32> {
+    let quantized = (value / precision).round();
+
+    // values that dont fit get sent at full precision instead
+    if !quantized.is_finite() || quantized > i32::MAX as f32 || quantized < i32::MIN as f32 {
+        return None
+    }
+
+    Some(quantized as i32)
+}
+
+fn quantize_delta(old: f32, new: f32, precision: f32) -> Option<i16> {
+    let quantized = quantize(new - old, precision)?;
+
+    i16::try_from(quantized).ok()
+}
+
+fn quantize_angle(angle: f32) -> i16 {
+    ((angle / PI).clamp(-1., 1.) * i16::MAX as f32).round() as i16
+}
+
+impl QuantizedTransforms {
+
+    pub fn apply(&self, rigid_body_set: &mut RigidBodySet) {
+        let position_precision = self.quantization.position_precision;
+        let velocity_precision = self.quantization.velocity_precision;
+
+        for transform in &self.transforms {
+            let rigid_body = match rigid_body_set.get_mut(transform.handle) {
+                Some(rigid_body) => rigid_body,
+                None => continue, // the body was removed by the same diff
+            };
+
+            let (translation, linvel, angvel) = match transform.encoding {
+                TransformEncoding::Delta { x, y, linvel_x, linvel_y, angvel } => (
+                    rigid_body.translation() + vector![x as f32 * position_precision, y as f32 * position_precision],
+                    rigid_body.linvel() + vector![linvel_x as f32 * velocity_precision, linvel_y as f32 * velocity_precision],
+                    rigid_body.angvel() + angvel as f32 * velocity_precision
+                ),
+                TransformEncoding::Absolute { x, y, linvel_x, linvel_y, angvel } => (
+                    vector![x as f32 * position_precision, y as f32 * position_precision],
+                    vector![linvel_x as f32 * velocity_precision, linvel_y as f32 * velocity_precision],
+                    angvel as f32 * velocity_precision
+                ),
+            };
+
+            rigid_body.set_position(Isometry2::new(translation, (transform.angle as f32 / i16::MAX as f32) * PI), true);
+
+            rigid_body.set_linvel(linvel, true);
+            rigid_body.set_angvel(angvel, true);
+        }
+    }
+}
+
+/// Changes smaller than these are left out of SpaceDiff so resting bodies don't get sent every frame
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DiffThresholds {
+    pub position: f32,
+    pub velocity: f32,
+    pub angle: f32
+}
+
+impl Default for DiffThresholds {
+    fn default() -> Self {
+        Self {
+            position: 0.01,
+            velocity: 0.01,
+            angle: 0.001,
+        }
+    }
+}
+
+/// The parts of a rigid body that SpaceDiff can send on their own
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BodyTransform {
+    position: Isometry2<f32>,
+    linvel: Vector2<f32>,
+    angvel: f32
+}
+
+impl BodyTransform {
+    fn of(rigid_body: &RigidBody) -> Self {
+        Self {
+            position: *rigid_body.position(),
+            linvel: *rigid_body.linvel(),
+            angvel: rigid_body.angvel(),
+        }
+    }
+}
+
+fn reset_transform(rigid_body: &mut RigidBody, old_rigid_body: &RigidBody) {
+    rigid_body.set_position(*old_rigid_body.position(), false);
+    rigid_body.set_linvel(*old_rigid_body.linvel(), false);
+    rigid_body.set_angvel(old_rigid_body.angvel(), false);
+}
+
+/// The bodies that are in the same place and moving the same as before a step
+pub(super) fn resting_rigid_bodies(before: &RigidBodySet, after: &RigidBodySet) -> FxHashSet<RigidBodyHandle> {
+    after.iter()
+        .filter(|(rigid_body_handle, rigid_body)| {
+            before.get(*rigid_body_handle).is_some_and(|rigid_body_before| BodyTransform::of(rigid_body_before) == BodyTransform::of(rigid_body))
+        })
+        .map(|(rigid_body_handle, _)| rigid_body_handle)
+        .collect()
+}
+
+impl Space {
+
+    /// Split the transform changes between two spaces out of the rigid body set.
+    /// `self` has to be what the receiver already has, so changes held back by the thresholds add up until they are big enough to send.
+    /// Returns the new rigid body set with transforms that are under the change thresholds, could be quantized or belong to moving platforms reset to their old values, and the quantized transforms themselves
+    pub(crate) fn split_transforms(&self, other: &Self) -> (Option<RigidBodySet>, Option<QuantizedTransforms>) {
+
+        if other.diff_quantization.is_none() && other.diff_thresholds.is_none() && other.moving_platforms.is_empty() {
+            return (None, None)
+        }
+
+        let mut masked_rigid_body_set = other.rigid_body_set.clone();
+        let mut masked_any = false;
+        let mut transforms: Vec<QuantizedTransform> = vec![];
+
+        for (rigid_body_handle, rigid_body) in other.rigid_body_set.iter() {
+
+            // new bodies are always sent in full
+            let old_rigid_body = match self.rigid_body_set.get(rigid_body_handle) {
+                Some(old_rigid_body) => old_rigid_body,
+                None => continue,
+            };
+
+            let old_transform = BodyTransform::of(old_rigid_body);
+            let new_transform = BodyTransform::of(rigid_body);
+
+            if old_transform == new_transform {
+                continue;
+            }
+
+            // every peer moves the platforms itself from the synced platform time
+            if other.is_moving_platform(rigid_body_handle) {
+                reset_transform(masked_rigid_body_set.get_mut(rigid_body_handle).unwrap(), old_rigid_body);
+
+                masked_any = true;
+
+                continue;
+            }
+
+            // a body that stopped short of the thresholds would never be sent where it stopped, so it goes in the lossless rigid body diff
+            if other.resting_rigid_bodies.contains(&rigid_body_handle) {
+                continue;
+            }
+
+            if let Some(thresholds) = other.diff_thresholds {
+                if !thresholds.exceeded(&old_transform, &new_transform) {
+                    reset_transform(masked_rigid_body_set.get_mut(rigid_body_handle).unwrap(), old_rigid_body);
+
+                    masked_any = true;
+
+                    continue;
+                }
+            }
+
+            let quantization = match other.diff_quantization {
+                Some(quantization) => quantization,
+                None => continue,
+            };
+
+            let encoding = match quantization.encode(&old_transform, &new_transform) {
+                Some(encoding) => encoding,
+                None => continue, // lossless fallback
+            };
+
+            transforms.push(
+                QuantizedTransform { 
+                    handle: rigid_body_handle, 
+                    angle: quantize_angle(rigid_body.rotation().angle()), 
+                    encoding
+                }
+            );
+
+            // make the transform look unchanged so the regular rigid body diff skips it
+            reset_transform(masked_rigid_body_set.get_mut(rigid_body_handle).unwrap(), old_rigid_body);
+
+            masked_any = true;
+        }
+
+        let masked_rigid_body_set = match masked_any {
+            true => Some(masked_rigid_body_set),
+            false => None,
+        };
+
+        let transforms = match transforms.is_empty() {
+            true => None,
+            false => Some(QuantizedTransforms { quantization: other.diff_quantization.unwrap_or_default(), transforms }),
+        };
+
+        (masked_rigid_body_set, transforms)
+    }
+}
+
+impl DiffQuantization {
+
+    fn encode(&self, old: &BodyTransform, new: &BodyTransform) -> Option<TransformEncoding> {
+        let delta = (
+            quantize_delta(old.position.translation.x, new.position.translation.x, self.position_precision),
+            quantize_delta(old.position.translation.y, new.position.translation.y, self.position_precision),
+            quantize_delta(old.linvel.x, new.linvel.x, self.velocity_precision),
+            quantize_delta(old.linvel.y, new.linvel.y, self.velocity_precision),
+            quantize_delta(old.angvel, new.angvel, self.velocity_precision)
+        );
+
+        if let (Some(x), Some(y), Some(linvel_x), Some(linvel_y), Some(angvel)) = delta {
+            return Some(TransformEncoding::Delta { x, y, linvel_x, linvel_y, angvel })
+        }
+
+        let absolute = (
+            quantize(new.position.translation.x, self.position_precision),
+            quantize(new.position.translation.y, self.position_precision),
+            quantize(new.linvel.x, self.velocity_precision),
+            quantize(new.linvel.y, self.velocity_precision),
+            quantize(new.angvel, self.velocity_precision)
+        );
+
+        match absolute {
+            (Some(x), Some(y), Some(linvel_x), Some(linvel_y), Some(angvel)) => Some(TransformEncoding::Absolute { x, y, linvel_x, linvel_y, angvel }),
+            _ => None,
+        }
+    }
+}
+
+impl DiffThresholds {
+
+    fn exceeded(&self, old: &BodyTransform, new: &BodyTransform) -> bool {
+        (new.position.translation.vector - old.position.translation.vector).norm() >= self.position
+            || (new.position.rotation.angle() - old.position.rotation.angle()).abs() >= self.angle
+            || (new.linvel - old.linvel).norm() >= self.velocity
+            || (new.angvel - old.angvel).abs() >= self.velocity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diff::Diff;
+    use macroquad::math::Vec2;
+    use nalgebra::vector;
+    use rapier2d::dynamics::{RigidBodyBuilder, RigidBodyHandle};
+
+    use crate::space::Space;
+
+    use super::{resting_rigid_bodies, DiffQuantization, DiffThresholds, TransformEncoding};
+
+    fn space_with_body() -> (Space, RigidBodyHandle) {
+        let mut space = Space::new();
+
+        let rigid_body_handle = space.rigid_body_set.insert(RigidBodyBuilder::dynamic());
+
+        (space, rigid_body_handle)
+    }
+
+    #[test]
+    fn changes_under_the_thresholds_add_up_until_sent() {
+        let (mut sent, rigid_body_handle) = space_with_body();
+
+        let mut space = sent.clone();
+
+        space.diff_thresholds = Some(DiffThresholds { position: 1., velocity: 1., angle: 1. });
+
+        space.rigid_body_set.get_mut(rigid_body_handle).unwrap().set_translation(vector![0.6, 0.], true);
+
+        sent.apply(&sent.diff(&space));
+
+        assert_eq!(sent.rigid_body_set.get(rigid_body_handle).unwrap().translation().x, 0.);
+
+        // measured against what was sent, not the last frame
+        space.rigid_body_set.get_mut(rigid_body_handle).unwrap().set_translation(vector![1.2, 0.], true);
+
+        sent.apply(&sent.diff(&space));
+
+        assert_eq!(sent.rigid_body_set.get(rigid_body_handle).unwrap().translation().x, 1.2);
+    }
+
+    #[test]
+    fn bodies_resting_under_the_thresholds_are_sent_where_they_stopped() {
+        let (sent, rigid_body_handle) = space_with_body();
+
+        let mut space = sent.clone();
+
+        space.diff_thresholds = Some(DiffThresholds::default());
+        space.diff_quantization = Some(DiffQuantization::default());
+
+        space.rigid_body_set.get_mut(rigid_body_handle).unwrap().set_translation(vector![0.001, 0.], true);
+
+        // it didn't move during the last step
+        space.resting_rigid_bodies = resting_rigid_bodies(&space.rigid_body_set, &space.rigid_body_set);
+
+        let mut received = sent.clone();
+
+        received.apply(&sent.diff(&space));
+
+        assert_eq!(received.rigid_body_set.get(rigid_body_handle).unwrap().translation().x, 0.001);
+    }
+
+    #[test]
+    fn quantized_transforms_are_within_precision() {
+        let (sent, rigid_body_handle) = space_with_body();
+
+        let mut space = sent.clone();
+
+        space.diff_quantization = Some(DiffQuantization::default());
+
+        space.rigid_body_set.get_mut(rigid_body_handle).unwrap().set_translation(vector![12.345, -6.789], true);
+        space.rigid_body_set.get_mut(rigid_body_handle).unwrap().set_linvel(vector![3.21, 0.], true);
+
+        let diff = sent.diff(&space);
+
+        assert_eq!(diff.transforms.as_ref().unwrap().transforms.len(), 1);
+
+        let mut received = sent.clone();
+
+        received.apply(&diff);
+
+        let rigid_body = received.rigid_body_set.get(rigid_body_handle).unwrap();
+
+        assert!((rigid_body.translation().x - 12.345).abs() <= 0.005);
+        assert!((rigid_body.translation().y + 6.789).abs() <= 0.005);
+        assert!((rigid_body.linvel().x - 3.21).abs() <= 0.005);
+    }
+
+    #[test]
+    fn moving_platforms_are_left_out() {
+        let (sent, rigid_body_handle) = space_with_body();
+
+        let mut space = sent.clone();
+
+        space.add_moving_platform(rigid_body_handle, vec![Vec2::ZERO, Vec2::new(10., 0.)], 1.).unwrap();
+
+        space.rigid_body_set.get_mut(rigid_body_handle).unwrap().set_translation(vector![5., 0.], true);
+
+        let mut received = sent.clone();
+
+        received.apply(&sent.diff(&space));
+
+        assert_eq!(received.moving_platforms, space.moving_platforms);
+        assert_eq!(received.rigid_body_set.get(rigid_body_handle).unwrap().translation().x, 0.);
+    }
+
+    #[test]
+    fn small_changes_are_sent_as_deltas_and_big_ones_in_full() {
+        let (sent, rigid_body_handle) = space_with_body();
+
+        let mut space = sent.clone();
+
+        space.diff_quantization = Some(DiffQuantization::default());
+
+        space.rigid_body_set.get_mut(rigid_body_handle).unwrap().set_translation(vector![1.5, 0.], true);
+
+        let diff = sent.diff(&space);
+
+        assert!(matches!(diff.transforms.as_ref().unwrap().transforms[0].encoding, TransformEncoding::Delta { x: 150, .. }));
+
+        // 1000 units is 100000 steps, more than an i16 holds
+        space.rigid_body_set.get_mut(rigid_body_handle).unwrap().set_translation(vector![1000., 0.], true);
+
+        let diff = sent.diff(&space);
+
+        assert!(matches!(diff.transforms.as_ref().unwrap().transforms[0].encoding, TransformEncoding::Absolute { x: 100000, .. }));
+
+        let mut received = sent.clone();
+
+        received.apply(&diff);
+
+        assert!((received.rigid_body_set.get(rigid_body_handle).unwrap().translation().x - 1000.).abs() <= 0.005);
+    }
+}
@@ -0,0 +1,194 @@
+use macroquad::{color::{Color, WHITE}, math::{vec2, Rect, Vec2}, models::{draw_mesh, Mesh, Vertex}, texture::Texture2D};
+
+use crate::{coordinates::CoordinateSystem, texture_atlas::atlas_source, texture_loader::TextureLoader};
+
+/// u16 indices, so a mesh can't have more than this many quads
+const MAX_QUADS: usize = u16::MAX as usize / 4;
+
+/// One textured quad queued in a SpriteBatch
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sprite {
+    pub texture_path: String,
+    pub position: Vec2, // rapier coords of the center
+    pub size: Vec2,
+    pub rotation: f32, // radians, counter clockwise like rapier
+    pub source: Option<Rect>, // part of the texture to draw, in texture pixels
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub color: Color,
+    pub layer: i32 // higher layers are drawn on top
+}
+
+impl Sprite {
+
+    pub fn new(texture_path: &str, position: Vec2, size: Vec2) -> Self {
+        Self {
+            texture_path: texture_path.to_string(),
+            position,
+            size,
+            rotation: 0.,
+            source: None,
+            flip_x: false,
+            flip_y: false,
+            color: WHITE,
+            layer: 0,
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+
+        self
+    }
+
+    pub fn with_source(mut self, source: Rect) -> Self {
+        self.source = Some(source);
+
+        self
+    }
+
+    pub fn with_flip(mut self, flip_x: bool, flip_y: bool) -> Self {
+        self.flip_x = flip_x;
+        self.flip_y = flip_y;
+
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+
+        self
+    }
+
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+
+        self
+    }
+}
+
+/// Collects sprites over a frame and draws them in as few meshes as possible.
+/// Sprites are drawn by layer. Inside a layer they are grouped by texture, so sprites that overlap should be on different layers.
+/// Textures packed into the same atlas page share a mesh
+#[derive(Default)]
+pub struct SpriteBatch {
+    sprites: Vec<Sprite>
+}
+
+impl SpriteBatch {
+
+    pub fn new() -> Self {
+        Self {
+            sprites: vec![],
+        }
+    }
+
+    pub fn push(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    /// Queue a texture centered on `position` in rapier coords
+    pub fn draw_texture_rapier(&mut self, texture_path: &str, position: Vec2, size: Vec2, layer: i32) {
+        self.push(Sprite::new(texture_path, position, size).with_layer(layer));
+    }
+
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.sprites.clear();
+    }
+
+    /// Draw everything queued since the last flush and empty the batch. Returns how many meshes were drawn
+    pub fn flush(&mut self, textures: &mut TextureLoader, coordinates: &CoordinateSystem) -> usize {
+        let sprites = std::mem::take(&mut self.sprites);
+
+        // resolve every sprite to the texture it is actually drawn from, which is the atlas page for packed textures
+        let mut distinct: Vec<Texture2D> = vec![];
+
+        let mut resolved: Vec<(usize, Option<Rect>, Sprite)> = sprites.into_iter().map(|sprite| {
+            let (texture, region) = textures.get_region_blocking(&sprite.texture_path);
+
+            let texture_index = match distinct.iter().position(|other| other == texture) {
+                Some(texture_index) => texture_index,
+                None => {
+                    distinct.push(texture.clone());
+
+                    distinct.len() - 1
+                },
+            };
+
+            (texture_index, region, sprite)
+        }).collect();
+
+        // stable, so sprites keep the order they were queued in within a texture
+        resolved.sort_by_key(|(texture_index, _, sprite)| (sprite.layer, *texture_index));
+
+        let mut meshes = 0;
+
+        for run in resolved.chunk_by(|(a, _, a_sprite), (b, _, b_sprite)| a == b && a_sprite.layer == b_sprite.layer) {
+            let texture = &distinct[run[0].0];
+
+            for quads in run.chunks(MAX_QUADS) {
+                draw_mesh(&build_mesh(texture, quads, coordinates));
+
+                meshes += 1;
+            }
+        }
+
+        meshes
+    }
+}
+
+fn build_mesh(texture: &Texture2D, quads: &[(usize, Option<Rect>, Sprite)], coordinates: &CoordinateSystem) -> Mesh {
+    let texture_size = texture.size();
+
+    let mut vertices = Vec::with_capacity(quads.len() * 4);
+    let mut indices = Vec::with_capacity(quads.len() * 6);
+
+    for (_, region, sprite) in quads {
+        let source = atlas_source(*region, sprite.source).unwrap_or(Rect::new(0., 0., texture_size.x, texture_size.y));
+
+        let (mut u0, mut v0) = (source.x / texture_size.x, source.y / texture_size.y);
+        let (mut u1, mut v1) = (source.right() / texture_size.x, source.bottom() / texture_size.y);
+
+        if sprite.flip_x {
+            std::mem::swap(&mut u0, &mut u1);
+        }
+
+        if sprite.flip_y {
+            std::mem::swap(&mut v0, &mut v1);
+        }
+
+        let center = coordinates.rapier_to_macroquad(&sprite.position);
+
+        // macroquad y points down, so the rotation flips too
+        let rotation = Vec2::from_angle(-sprite.rotation);
+
+        let half_size = sprite.size / 2.;
+
+        let corners = [
+            (vec2(-half_size.x, -half_size.y), u0, v0),
+            (vec2(half_size.x, -half_size.y), u1, v0),
+            (vec2(half_size.x, half_size.y), u1, v1),
+            (vec2(-half_size.x, half_size.y), u0, v1),
+        ];
+
+        let first = vertices.len() as u16;
+
+        for (corner, u, v) in corners {
+            let corner = center + rotation.rotate(corner);
+
+            vertices.push(Vertex::new(corner.x, corner.y, 0., u, v, sprite.color));
+        }
+
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+
+    Mesh { vertices, indices, texture: Some(texture.clone()) }
+}
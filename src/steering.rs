@@ -0,0 +1,183 @@
+use diff::Diff;
+use macroquad::math::{vec2, Vec2};
+use nalgebra::{point, vector};
+use rapier2d::{geometry::Ray, pipeline::QueryFilter, prelude::RigidBodyHandle};
+use serde::{Deserialize, Serialize};
+
+use crate::{rng::SyncRng, space::Space, tags::TaggedBody};
+
+/// Steering behaviors for an agent with a dynamic rigid body. Each behavior returns a steering acceleration,
+/// which can be weighted and added together with `combine` and then applied to the body with `apply`,
+/// so agents move through the physics simulation instead of being teleported.
+/// Diffable, so wander state syncs along with the agent
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct Steering {
+    body: TaggedBody,
+    pub max_speed: f32,
+    pub max_force: f32, // the most acceleration a single behavior or a combination can ask for
+    pub arrive_radius: f32, // arrive starts slowing down this far from the target
+    pub wander_radius: f32,
+    pub wander_distance: f32, // how far ahead of the agent the wander circle sits
+    pub wander_jitter: f32, // radians the wander target can move per call
+    pub avoid_distance: f32, // how far ahead obstacles are looked for
+    wander_angle: f32
+}
+
+impl Steering {
+
+    pub fn new(rigid_body_handle: RigidBodyHandle, max_speed: f32, max_force: f32) -> Self {
+        Self {
+            body: rigid_body_handle.into(),
+            max_speed,
+            max_force,
+            arrive_radius: 100.,
+            wander_radius: 30.,
+            wander_distance: 60.,
+            wander_jitter: 0.5,
+            avoid_distance: 80.,
+            wander_angle: 0.,
+        }
+    }
+
+    pub fn rigid_body_handle(&self) -> RigidBodyHandle {
+        self.body.into()
+    }
+
+    pub fn with_arrive_radius(mut self, arrive_radius: f32) -> Self {
+        self.arrive_radius = arrive_radius;
+
+        self
+    }
+
+    pub fn with_avoid_distance(mut self, avoid_distance: f32) -> Self {
+        self.avoid_distance = avoid_distance;
+
+        self
+    }
+
+    /// Position and velocity of the agent's body. Zero if the body is gone
+    fn state(&self, space: &Space) -> (Vec2, Vec2) {
+        match space.rigid_body_set.get(self.rigid_body_handle()) {
+            Some(rigid_body) => (
+                vec2(rigid_body.translation().x, rigid_body.translation().y),
+                vec2(rigid_body.linvel().x, rigid_body.linvel().y)
+            ),
+            None => (Vec2::ZERO, Vec2::ZERO),
+        }
+    }
+
+    fn steer_towards(&self, desired_velocity: Vec2, velocity: Vec2) -> Vec2 {
+        (desired_velocity - velocity).clamp_length_max(self.max_force)
+    }
+
+    /// Head straight for `target` at full speed
+    pub fn seek(&self, space: &Space, target: Vec2) -> Vec2 {
+        let (position, velocity) = self.state(space);
+
+        self.steer_towards((target - position).normalize_or_zero() * self.max_speed, velocity)
+    }
+
+    /// Run directly away from `threat`. Ignores threats further away than `panic_distance`
+    pub fn flee(&self, space: &Space, threat: Vec2, panic_distance: f32) -> Vec2 {
+        let (position, velocity) = self.state(space);
+
+        if position.distance(threat) > panic_distance {
+            return Vec2::ZERO;
+        }
+
+        self.steer_towards((position - threat).normalize_or_zero() * self.max_speed, velocity)
+    }
+
+    /// Like seek, but slows down inside arrive_radius and stops on the target
+    pub fn arrive(&self, space: &Space, target: Vec2) -> Vec2 {
+        let (position, velocity) = self.state(space);
+
+        let offset = target - position;
+
+        let distance = offset.length();
+
+        let speed = match distance < self.arrive_radius {
+            true => self.max_speed * distance / self.arrive_radius,
+            false => self.max_speed,
+        };
+
+        self.steer_towards(offset.normalize_or_zero() * speed, velocity)
+    }
+
+    /// Meander around by steering towards a point that drifts around a circle in front of the agent.
+    /// Takes a SyncRng so every client wanders the same way
+    pub fn wander(&mut self, space: &Space, rng: &mut SyncRng) -> Vec2 {
+        let (position, velocity) = self.state(space);
+
+        self.wander_angle += rng.range_f32(-self.wander_jitter..self.wander_jitter);
+
+        let heading = match velocity.length_squared() > f32::EPSILON {
+            true => velocity.normalize(),
+            false => vec2(1., 0.),
+        };
+
+        let circle_center = position + heading * self.wander_distance;
+
+        let target = circle_center + Vec2::from_angle(self.wander_angle) * self.wander_radius;
+
+        self.steer_towards((target - position).normalize_or_zero() * self.max_speed, velocity)
+    }
+
+    /// Steer away from whatever is in front of the agent, harder the closer it is. Zero when the way is clear
+    pub fn avoid_obstacles(&self, space: &Space, filter: QueryFilter) -> Vec2 {
+        let (position, velocity) = self.state(space);
+
+        if velocity.length_squared() <= f32::EPSILON {
+            return Vec2::ZERO;
+        }
+
+        let heading = velocity.normalize();
+
+        let ray = Ray::new(point![position.x, position.y], vector![heading.x, heading.y]);
+
+        let filter = filter.exclude_rigid_body(self.rigid_body_handle()).exclude_sensors();
+
+        let hit = space.query_pipeline.cast_ray_and_get_normal(&space.rigid_body_set, &space.collider_set, &ray, self.avoid_distance, true, filter);
+
+        match hit {
+            Some((_, intersection)) => {
+                let urgency = 1. - intersection.time_of_impact / self.avoid_distance;
+
+                vec2(intersection.normal.x, intersection.normal.y) * self.max_force * urgency
+            },
+            None => Vec2::ZERO,
+        }
+    }
+
+    /// Add up weighted behaviors, capped at max_force
+    pub fn combine(&self, behaviors: &[(Vec2, f32)]) -> Vec2 {
+        behaviors.iter()
+            .map(|(force, weight)| *force * *weight)
+            .sum::<Vec2>()
+            .clamp_length_max(self.max_force)
+    }
+
+    /// Push the body by `steering` for `delta` seconds. Scaled by mass so the same numbers work for light and heavy agents
+    pub fn apply(&self, space: &mut Space, steering: Vec2, delta: f32) {
+        let rigid_body = match space.rigid_body_set.get_mut(self.rigid_body_handle()) {
+            Some(rigid_body) => rigid_body,
+            None => return,
+        };
+
+        let impulse = steering * rigid_body.mass() * delta;
+
+        rigid_body.apply_impulse(vector![impulse.x, impulse.y], true);
+
+        // keep the agent under max_speed without fighting other forces like gravity more than needed
+        let velocity = vec2(rigid_body.linvel().x, rigid_body.linvel().y);
+
+        if velocity.length() > self.max_speed {
+            let velocity = velocity.clamp_length_max(self.max_speed);
+
+            rigid_body.set_linvel(vector![velocity.x, velocity.y], true);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::{net::SocketAddr, ops::{Deref, DerefMut}, time::Duration};
+
+use diff::Diff;
+use futures_util::{SinkExt, StreamExt};
+use fxhash::FxHashMap;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{net::TcpListener, runtime::Handle, sync::mpsc::{self, error::TryRecvError, UnboundedReceiver, UnboundedSender}, task::AbortHandle};
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+
+use super::{codec::{BitcodeCodec, Codec}, frame::{decode_frame, FrameKind}, handshake::{HandshakeError, Hello}, server::{ClientId, DisconnectReason}, server_core::ServerCore, stream::AsyncServerStream, transport::ClientConnection};
+
+/// How long a new client gets to finish the websocket upgrade and send its hello
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Something a client's reader task received
+enum Incoming {
+    Frame(Vec<u8>),
+    Disconnected(DisconnectReason)
+}
+
+impl ClientConnection for UnboundedSender<Message> {
+    // the writer task owns the socket, so a closed channel just means the client is on its way out
+    fn send(&mut self, frame: Vec<u8>) -> bool {
+        UnboundedSender::send(self, Message::Binary(frame)).is_ok()
+    }
+}
+
+/// SyncServer backed by tokio. Sockets are serviced by background tasks, so the host loop only drains channels instead of polling every socket.
+/// Must be created from inside a tokio runtime. Everything that doesn't touch a socket lives in ServerCore, which this derefs to
+pub struct AsyncSyncServer<T: Serialize + DeserializeOwned + Diff + Clone + PartialEq, C: Codec = BitcodeCodec> {
+    core: ServerCore<T, C, UnboundedSender<Message>>,
+    runtime: Handle,
+    new_connections: UnboundedReceiver<(WebSocketStream<AsyncServerStream>, SocketAddr, Hello)>,
+    incoming_send: UnboundedSender<(ClientId, Incoming)>,
+    incoming_receive: UnboundedReceiver<(ClientId, Incoming)>,
+    readers: FxHashMap<ClientId, AbortHandle>, // each client's reader task, which holds its half of the socket
+    #[cfg(feature = "rustls")]
+    tls_acceptor: std::sync::Arc<std::sync::RwLock<Option<tokio_rustls::TlsAcceptor>>> // shared with the accept task
+}
+
+impl<T> AsyncSyncServer<T, BitcodeCodec>
+where 
+    T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
+    <T as Diff>::Repr: DeserializeOwned + Serialize {
+
+    pub fn new(address: SocketAddr, initial_state: T) -> Self {
+        Self::new_with_codec(address, initial_state)
+    }
+}
+
+impl<T, C> Deref for AsyncSyncServer<T, C>
+where 
+    T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
+    C: Codec {
+
+    type Target = ServerCore<T, C, UnboundedSender<Message>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.core
+    }
+}
+
+impl<T, C> DerefMut for AsyncSyncServer<T, C>
+where 
+    T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
+    C: Codec {
+
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.core
+    }
+}
+
+impl<T, C> AsyncSyncServer<T, C>
+where 
+    T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
+    <T as Diff>::Repr: DeserializeOwned + Serialize,
+    C: Codec {
+
+    /// Create a server using a serialization format other than the default. Clients must use the same codec
+    pub fn new_with_codec(address: SocketAddr, initial_state: T) -> Self {
+
+        let runtime = Handle::current();
+
+        // bind synchronously so new doesn't have to be async, then hand the socket to tokio
+        let listener = match std::net::TcpListener::bind(address) {
+            Ok(listener) => listener,
+            Err(error) => panic!("failed to bind listener: {}", error),
+        };
+
+        match listener.set_nonblocking(true) {
+            Ok(_) => {},
+            Err(error) => panic!("failed to set server as non blocking: {}", error),
+        };
+
+        let listener = match TcpListener::from_std(listener) {
+            Ok(listener) => listener,
+            Err(error) => panic!("failed to register listener with tokio: {}", error),
+        };
+
+        let (new_connections_send, new_connections) = mpsc::unbounded_channel();
+
+        #[cfg(feature = "rustls")]
+        let tls_acceptor: std::sync::Arc<std::sync::RwLock<Option<tokio_rustls::TlsAcceptor>>> = Default::default();
+
+        #[cfg(feature = "rustls")]
+        let accept_tls_acceptor = tls_acceptor.clone();
+
+        // accept and handshake in the background so slow clients can't stall the host
+        runtime.spawn(async move {
+            loop {
+                let (stream, address) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(error) => {
+                        log_error!("Something went wrong trying to accept a new client: {}", error);
+                        continue;
+                    },
+                };
+
+                let new_connections_send = new_connections_send.clone();
+
+                #[cfg(feature = "rustls")]
+                let tls_acceptor = accept_tls_acceptor.read().unwrap().clone();
+
+                tokio::spawn(async move {
+                    #[cfg(feature = "rustls")]
+                    let stream = wrap_stream(stream, tls_acceptor, address);
+
+                    #[cfg(not(feature = "rustls"))]
+                    let stream = async { Some(AsyncServerStream::Plain(stream)) };
+
+                    let connect = async { handshake::<C>(stream.await?, address).await };
+
+                    // a client that goes quiet mid handshake only holds up its own task, and only for a few seconds
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, connect).await {
+                        Ok(Some(connection)) => {
+                            let _ = new_connections_send.send(connection);
+                        },
+                        Ok(None) => {},
+                        Err(_) => log_warn!("client at {} took too long to handshake, dropping it", address),
+                    }
+                });
+            }
+        });
+
+        let (incoming_send, incoming_receive) = mpsc::unbounded_channel();
+
+        Self {
+            core: ServerCore::new(initial_state),
+            runtime,
+            new_connections,
+            incoming_send,
+            incoming_receive,
+            readers: FxHashMap::default(),
+            #[cfg(feature = "rustls")]
+            tls_acceptor
+        }
+    }
+
+    /// Terminate TLS on new connections so clients can connect with wss://. Browsers on https pages refuse plain ws
+    #[cfg(feature = "rustls")]
+    pub fn set_tls_config(&mut self, tls_config: Option<std::sync::Arc<rustls::ServerConfig>>) {
+        *self.tls_acceptor.write().unwrap() = tls_config.map(tokio_rustls::TlsAcceptor::from);
+    }
+
+    pub fn accept_new_client(&mut self) -> Option<ClientId> {
+
+        let (websocket, address, client_hello) = match self.new_connections.try_recv() {
+            Ok(connection) => connection,
+            Err(_) => return None, // no new clients
+        };
+
+        log_info!("received new connection from address: {}", address);
+
+        let (mut websocket_send, mut websocket_receive) = websocket.split();
+
+        let (outgoing, mut outgoing_receive) = mpsc::unbounded_channel::<Message>();
+
+        // writer task
+        self.runtime.spawn(async move {
+            while let Some(message) = outgoing_receive.recv().await {
+                if websocket_send.send(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let client_id = self.core.add_client(outgoing, address, &client_hello);
+
+        // reader task
+        let incoming_send = self.incoming_send.clone();
+
+        let reader = self.runtime.spawn(async move {
+            loop {
+                let incoming = match websocket_receive.next().await {
+                    Some(Ok(Message::Binary(frame_bytes))) => Incoming::Frame(frame_bytes),
+                    Some(Ok(Message::Close(_close_message))) => Incoming::Disconnected(DisconnectReason::Closed),
+                    None => Incoming::Disconnected(DisconnectReason::ConnectionReset), // the socket ended without a close frame
+                    Some(Ok(_)) => continue, // pings are answered by tungstenite
+                    Some(Err(tokio_tungstenite::tungstenite::Error::Io(_))) => Incoming::Disconnected(DisconnectReason::ConnectionReset),
+                    Some(Err(_)) => Incoming::Disconnected(DisconnectReason::ProtocolError),
+                };
+
+                let disconnected = matches!(incoming, Incoming::Disconnected(_));
+
+                if incoming_send.send((client_id, incoming)).is_err() || disconnected {
+                    break;
+                }
+            }
+        });
+
+        self.readers.insert(client_id, reader.abort_handle());
+
+        Some(client_id)
+    }
+
+    /// Clients the core dropped have their reader task stopped here. Their outgoing channel is already closed,
+    /// which ends the writer task, and with both halves gone the socket closes
+    pub fn receive_updates(&mut self) {
+
+        self.core.heartbeat();
+
+        loop {
+            let (client_id, incoming) = match self.incoming_receive.try_recv() {
+                Ok(incoming) => incoming,
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+            };
+
+            match incoming {
+                Incoming::Frame(frame_bytes) => self.core.receive_frame(client_id, frame_bytes),
+                Incoming::Disconnected(reason) => self.core.disconnect(client_id, reason),
+            }
+        }
+
+        // covers timeouts, failed sends and disconnect calls alike
+        let client_ids = self.core.client_ids();
+
+        self.readers.retain(|client_id, reader| {
+            if client_ids.contains(client_id) {
+                return true;
+            }
+
+            reader.abort();
+
+            false
+        });
+    }
+}
+
+/// Run the TLS handshake if TLS is on
+#[cfg(feature = "rustls")]
+async fn wrap_stream(stream: tokio::net::TcpStream, tls_acceptor: Option<tokio_rustls::TlsAcceptor>, address: SocketAddr) -> Option<AsyncServerStream> {
+    let tls_acceptor = match tls_acceptor {
+        Some(tls_acceptor) => tls_acceptor,
+        None => return Some(AsyncServerStream::Plain(stream)),
+    };
+
+    match tls_acceptor.accept(stream).await {
+        Ok(stream) => Some(AsyncServerStream::Tls(Box::new(stream))),
+        Err(error) => {
+            log_warn!("tls handshake failed with client at {}: {}", address, error);
+            None
+        },
+    }
+}
+
+/// Upgrade to a websocket and wait for the client's hello
+async fn handshake<C: Codec>(stream: AsyncServerStream, address: SocketAddr) -> Option<(WebSocketStream<AsyncServerStream>, SocketAddr, Hello)> {
+    let mut websocket = match tokio_tungstenite::accept_async(stream).await {
+        Ok(websocket) => websocket,
+        Err(error) => {
+            log_warn!("handshake failed with new client: {}", error);
+            return None;
+        },
+    };
+
+    // the client tells us what it supports before we send anything
+    let hello = match websocket.next().await {
+        Some(Ok(Message::Binary(frame_bytes))) => match decode_frame(&frame_bytes) {
+            Ok((FrameKind::Hello, payload)) => Hello::decode(&payload),
+            _ => Err(HandshakeError::BadMagic),
+        },
+        _ => {
+            log_warn!("client at {} never sent a hello, dropping it", address);
+            return None;
+        },
+    };
+
+    match hello.and_then(|hello| Hello::new(C::TAG, vec![]).check(&hello).map(|_| hello)) {
+        Ok(hello) => Some((websocket, address, hello)),
+        Err(error) => {
+            log_warn!("refusing client at {}: {}", address, error);
+
+            // still tell the client what we are running so it can report the mismatch
+            let _ = websocket.send(Message::Binary(Hello::new(C::TAG, vec![]).encode())).await;
+
+            None
+        },
+    }
+}
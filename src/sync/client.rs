@@ -1,55 +1,109 @@
+use std::marker::PhantomData;
 
 use diff::Diff;
-use ewebsock::{WsReceiver, WsSender};
-use lz4_flex::{compress_prepend_size, decompress_size_prepended};
-use macroquad::input::{is_key_down, KeyCode};
 use serde::{de::DeserializeOwned, Serialize};
 
-use crate::log;
+use crate::{current_unix_millis, time};
 
-pub struct SyncClient<T: Serialize + DeserializeOwned + Diff + Clone + PartialEq> {
-    previous_state: T,
-    server_send: WsSender,
-    server_receive: WsReceiver
+use super::{codec::{BitcodeCodec, Codec}, compression::{Compression, CompressionKind}, frame::{decode_frame, decode_ping_payload, encode_frame, encode_ping, DiffHeader, FrameKind}, handshake::Hello, inputs::{InputBatch, InputLog}, metrics::{MetricsTracker, SyncMetrics}, migration::MigrationPlan, server::ClientId, transport::{TransportEvent, WebSocketTransport}};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connected,
+    /// The connection dropped and we are trying to get it back
+    Reconnecting { attempt: u32 },
+    /// We ran out of reconnect attempts
+    Disconnected,
+    /// The server went away and we are its successor in the migration plan. Call promote to take over
+    Promoted
 }
 
-impl<T> SyncClient<T>
+/// A connection attempt in progress
+struct PendingConnection {
+    server: WebSocketTransport,
+    opened: bool // our hello has been sent
+}
+
+pub struct SyncClient<T: Serialize + DeserializeOwned + Diff + Clone + PartialEq, C: Codec = BitcodeCodec> {
+    previous_state: T, // what the server has of our state
+    empty_diff: Vec<u8>, // a serialized diff without any changes
+    server: WebSocketTransport,
+    messages: Vec<Vec<u8>>,
+    url: String,
+    status: ConnectionStatus,
+    pending_connection: Option<PendingConnection>,
+    next_reconnect_at: u64, // unix millis
+    pub max_reconnect_attempts: Option<u32>, // None retries forever
+    pub max_reconnect_delay: u64, // millis
+    pub heartbeat_timeout: u64, // millis without hearing from the server before we consider the connection lost
+    last_message_at: u64, // unix millis
+    send_rate: Option<f32>, // diffs per second, None sends every sync
+    last_send_at: Option<time::Instant>,
+    metrics: MetricsTracker,
+    last_ping_at: u64, // unix millis
+    compression: Compression, // negotiated with the server when connecting
+    client_id: ClientId, // assigned by the server when connecting
+    migration_plan: Option<MigrationPlan>,
+    input_log: InputLog,
+    unsent_inputs: Vec<(u64, Vec<u8>)>, // unix millis, serialized input
+    next_sequence: u64,
+    received_diffs: Option<Vec<(u64, <T as Diff>::Repr)>>, // only kept when recording
+    sent_diffs: Option<Vec<(u64, <T as Diff>::Repr)>>,
+    received_states: Option<Vec<(u64, T)>>, // full states the server resynced us with
+    codec: PhantomData<C>
+
+}
+
+impl<T> SyncClient<T, BitcodeCodec>
 where 
     T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
     <T as Diff>::Repr: DeserializeOwned + Serialize {
+
+    pub async fn connect(url: &str) -> Result<(Self, T), String> {
+        Self::connect_with_codec(url).await
+    }
+}
+
+impl<T, C> SyncClient<T, C>
+where 
+    T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
+    <T as Diff>::Repr: DeserializeOwned + Serialize,
+    C: Codec {
     
-    pub async fn connect(url: &str) -> (Self, T) {
+    /// Connect using a serialization format other than the default. The server must use the same codec.
+    /// Returns an Err if the server can't be reached or drops us before sending the initial state
+    pub async fn connect_with_codec(url: &str) -> Result<(Self, T), String> {
 
     
-        let (server_send, server_receive) = match ewebsock::connect(url, ewebsock::Options::default()) {
-            Ok(result) => result,
+        let mut server = match WebSocketTransport::connect(url) {
+            Ok(server) => server,
             Err(error) => {
-                panic!("failed to connect to server: {}", error)
+                return Err(format!("failed to connect to server: {}", error))
             },
         };
 
+        let hello = Hello::new(C::TAG, CompressionKind::supported());
+
         // wait for Opened event from server
         loop {
-            match server_receive.try_recv() {
+            match server.try_recv() {
                 Some(event) => {
                     match event {
-                        ewebsock::WsEvent::Opened => {
-                            println!("we got the opened message!");
+                        TransportEvent::Opened => {
+                            log_debug!("we got the opened message!");
+
+                            server.send(hello.encode());
+
                             break;
                         },
-                        ewebsock::WsEvent::Message(message) => {
-                            match message {
-                                _ => panic!("received a message from the server")
-                            }
-                        },
-                        ewebsock::WsEvent::Error(error) => panic!("received error when trying to connect to server: {}", error),
-                        ewebsock::WsEvent::Closed => panic!("server closed when trying to connect"),
+                        TransportEvent::Frame(_) => continue, // the server doesn't say anything until we send our hello
+                        TransportEvent::Error(error) => return Err(format!("received error when trying to connect to server: {}", error)),
+                        TransportEvent::Closed => return Err("server closed when trying to connect".to_string()),
                         
                     }
                 },
                 None => {
-                    log("Waiting for open message");
+                    log_debug!("Waiting for open message");
                     
                     macroquad::window::next_frame().await; // let js runtime main thread continue execution while we wait
 
@@ -58,21 +112,36 @@ where
             }
         };
 
-        // wait for initial state
-        let compressed_state_bytes = loop {
+        let mut compression = Compression::default();
+
+        let mut client_id = ClientId(0);
 
-            match server_receive.try_recv() {
+        // wait for the server's hello and then the initial state
+        let state_bytes = loop {
+
+            match server.try_recv() {
                 Some(event) => {
                     match event {
-                        ewebsock::WsEvent::Opened => todo!("unhandled opened event on connect"),
-                        ewebsock::WsEvent::Message(message) => {
-                            match message {
-                                ewebsock::WsMessage::Binary(bytes) => break bytes,
-                                _ => todo!("unhandled message type when receiving initial state")
+                        TransportEvent::Opened => continue,
+                        TransportEvent::Frame(bytes) => {
+                            match decode_frame(&bytes) {
+                                Ok((FrameKind::Hello, payload)) => {
+                                    let server_hello = match Hello::decode(&payload).and_then(|server_hello| hello.check(&server_hello).map(|_| server_hello)) {
+                                        Ok(server_hello) => server_hello,
+                                        Err(error) => return Err(format!("cannot sync with server: {}", error)),
+                                    };
+
+                                    compression.kind = server_hello.compressions.first().copied().unwrap_or(CompressionKind::None);
+
+                                    client_id = ClientId(server_hello.client_id);
+                                },
+                                Ok((FrameKind::State, state_bytes)) => break state_bytes,
+                                Ok((frame_kind, _)) => return Err(format!("expected initial state from server but received {:?}", frame_kind)),
+                                Err(error) => return Err(format!("failed to decode frame while connecting: {}", error)),
                             }
                         },
-                        ewebsock::WsEvent::Error(error) => todo!("unhandled error when receiving initial state: {}", error),
-                        ewebsock::WsEvent::Closed => todo!("unhandled closed event when receiving initial state"),
+                        TransportEvent::Error(error) => return Err(format!("received error when receiving initial state: {}", error)),
+                        TransportEvent::Closed => return Err("server closed when receiving initial state".to_string()),
                     }
                 },
                 None => {
@@ -81,38 +150,396 @@ where
                 }, // this means that the server would have blocked, so we try again
             };
         };
-        
-        let state_bytes = decompress_size_prepended(&compressed_state_bytes).expect("Failed to decompress initial state");
 
-        let state: T = match bitcode::deserialize(&state_bytes) {
+        let state: T = match C::deserialize(&state_bytes) {
             Ok(state) => state,
             Err(error) => {
-                panic!("failed to deserialize initial state: {}", error);
+                return Err(format!("failed to deserialize initial state: {}", error));
             },
         };
 
-        return (
+        Ok((
             Self {
                 previous_state: state.clone(),
-                server_receive,
-                server_send
+                empty_diff: C::serialize(&T::identity().diff(&T::identity())).expect("failed to serialize state diff"),
+                server,
+                messages: vec![],
+                url: url.to_string(),
+                status: ConnectionStatus::Connected,
+                pending_connection: None,
+                next_reconnect_at: 0,
+                max_reconnect_attempts: None,
+                max_reconnect_delay: 30000,
+                heartbeat_timeout: 10000,
+                last_message_at: current_unix_millis(),
+                send_rate: None,
+                last_send_at: None,
+                metrics: MetricsTracker::new(),
+                last_ping_at: 0,
+                compression,
+                client_id,
+                migration_plan: None,
+                input_log: InputLog::new(1000),
+                unsent_inputs: vec![],
+                next_sequence: 1,
+                received_diffs: None,
+                sent_diffs: None,
+                received_states: None,
+                codec: PhantomData
             },
 
             state
-        )
+        ))
+
+
+    }
+    pub fn status(&self) -> ConnectionStatus {
+        self.status
+    }
+
+    /// Limit how many diffs per second we send. Changes made between sends are batched into a single diff
+    pub fn set_send_rate(&mut self, send_rate: Option<f32>) {
+        self.send_rate = send_rate;
+    }
+
+    pub fn send_rate(&self) -> Option<f32> {
+        self.send_rate
+    }
+
+    pub fn time_since_last_send(&self) -> Option<std::time::Duration> {
+        self.last_send_at.map(|last_send_at| last_send_at.elapsed())
+    }
+
+    /// Our id on the server. Changes when we reconnect
+    pub fn client_id(&self) -> ClientId {
+        self.client_id
+    }
+
+    /// The server's latest plan for who takes over if it goes away
+    pub fn migration_plan(&self) -> Option<&MigrationPlan> {
+        self.migration_plan.as_ref()
+    }
+
+    /// The compression the server picked for this connection
+    pub fn compression(&self) -> CompressionKind {
+        self.compression.kind
+    }
+
+    /// Diffs and messages smaller than this many bytes are sent uncompressed
+    pub fn set_compression_threshold(&mut self, threshold: usize) {
+        self.compression.threshold = threshold;
+    }
+
+    /// Keep every diff sent to and received from the server along with the unix millis it happened at, for drain_received_diffs and drain_sent_diffs.
+    /// Full states the server resyncs us with are kept too, for drain_received_states
+    pub fn set_record_diffs(&mut self, record_diffs: bool) {
+        self.received_diffs = match record_diffs {
+            true => Some(self.received_diffs.take().unwrap_or_default()),
+            false => None,
+        };
+
+        self.sent_diffs = match record_diffs {
+            true => Some(self.sent_diffs.take().unwrap_or_default()),
+            false => None,
+        };
+
+        self.received_states = match record_diffs {
+            true => Some(self.received_states.take().unwrap_or_default()),
+            false => None,
+        };
+    }
+
+    /// Take the diffs received since the last call. Empty unless recording was turned on with set_record_diffs
+    pub fn drain_received_diffs(&mut self) -> Vec<(u64, <T as Diff>::Repr)> {
+        match &mut self.received_diffs {
+            Some(received_diffs) => std::mem::take(received_diffs),
+            None => vec![],
+        }
+    }
 
+    /// Take the diffs sent since the last call. Empty unless recording was turned on with set_record_diffs
+    pub fn drain_sent_diffs(&mut self) -> Vec<(u64, <T as Diff>::Repr)> {
+        match &mut self.sent_diffs {
+            Some(sent_diffs) => std::mem::take(sent_diffs),
+            None => vec![],
+        }
+    }
 
+    /// Take the states the server resynced us with since the last call, on reconnect or when it asks us to start over.
+    /// Empty unless recording was turned on with set_record_diffs
+    pub fn drain_received_states(&mut self) -> Vec<(u64, T)> {
+        match &mut self.received_states {
+            Some(received_states) => std::mem::take(received_states),
+            None => vec![],
+        }
     }
+
+    pub fn metrics(&mut self) -> SyncMetrics {
+        self.metrics.metrics()
+    }
+
+    fn send_frame(&mut self, frame: Vec<u8>) {
+        self.metrics.record_sent(frame.len());
+
+        self.server.send(frame);
+    }
+
     pub fn sync(&mut self, state: &mut T) {
+
+        if self.status != ConnectionStatus::Connected {
+            self.poll_reconnect(state);
+
+            return;
+        }
+
+        // ping once a second to measure latency
+        let now = current_unix_millis();
+
+        if now.saturating_sub(self.last_ping_at) >= 1000 {
+            self.last_ping_at = now;
+
+            self.send_frame(encode_ping(now));
+        }
         
         // send & receive state updates
-        self.send_update(state);
+        if self.send_due() {
+            self.send_inputs();
+            self.send_update(state);
+        }
         
         self.receive_updates(state);
-       
 
-        self.previous_state = state.clone();
+        // the server pings us every second, so a long silence means it is gone even if the socket hasn't noticed
+        if self.status == ConnectionStatus::Connected && current_unix_millis().saturating_sub(self.last_message_at) > self.heartbeat_timeout {
+            log_warn!("server stopped responding");
+
+            self.connection_lost();
+        }
+    }
+
+    /// Close the connection on purpose. The server sees a close frame instead of a dropped connection, and we won't try to reconnect
+    pub fn disconnect(&mut self) {
+        if self.status == ConnectionStatus::Connected {
+            self.server.close();
+        }
+
+        self.pending_connection = None;
+        self.status = ConnectionStatus::Disconnected;
+    }
+
+    fn send_due(&self) -> bool {
+        send_due(self.send_rate, self.time_since_last_send())
+    }
+
+    /// Log an input with the current time. It is sent to the server along with our next diff so it can check what we saw when we made it
+    pub fn record_input<I: Serialize>(&mut self, input: &I) {
+        let now = current_unix_millis();
+
+        let input_bytes = C::serialize(input).expect("failed to serialize input");
+
+        self.input_log.push(now, input_bytes.clone());
+        self.unsent_inputs.push((now, input_bytes));
+    }
+
+    /// Inputs we recorded between two unix millis times, inclusive. Only the last input_history millis are kept
+    pub fn inputs_between<I: DeserializeOwned>(&self, start: u64, end: u64) -> Vec<(u64, I)> {
+        self.input_log.between::<I, C>(start, end)
+    }
+
+    /// How many millis of inputs to keep around for inputs_between. Defaults to a second
+    pub fn set_input_history(&mut self, history: u64) {
+        self.input_log.history = history;
+    }
+
+    fn send_inputs(&mut self) {
+        if self.unsent_inputs.is_empty() {
+            return;
+        }
+
+        let input_batch = InputBatch {
+            sent_at: current_unix_millis(),
+            inputs: std::mem::take(&mut self.unsent_inputs),
+        };
+
+        let input_batch_bytes = C::serialize(&input_batch).expect("failed to serialize inputs");
+
+        self.send_frame(encode_frame(FrameKind::Inputs, &input_batch_bytes, self.compression));
+    }
+
+    /// Send a one shot message to the server
+    pub fn send_message<M: Serialize>(&mut self, message: &M) {
+        let message_bytes = C::serialize(message).expect("failed to serialize message");
+
+        self.send_frame(encode_frame(FrameKind::Message, &message_bytes, self.compression));
+    }
+
+    /// Take all messages the server sent since the last call.
+    /// Messages are stored untyped, so a game should use a single message enum for everything it sends
+    pub fn drain_messages<M: DeserializeOwned>(&mut self) -> Vec<M> {
+        std::mem::take(&mut self.messages).into_iter().filter_map(|message_bytes| {
+            match C::deserialize(&message_bytes) {
+                Ok(message) => Some(message),
+                Err(error) => {
+                    log_warn!("failed to deserialize message from server: {}", error);
+
+                    None
+                },
+            }
+        }).collect()
+    }
+
+    fn connection_lost(&mut self) {
+
+        if let Some(migration_plan) = &self.migration_plan {
+            if migration_plan.successor == self.client_id {
+                log_warn!("lost connection to server, promoting ourselves to host");
+
+                self.status = ConnectionStatus::Promoted;
+
+                return;
+            }
+
+            // the successor needs a moment to start listening, the reconnect backoff covers that
+            log_warn!("lost connection to server, migrating to {}", migration_plan.url);
+
+            self.url = migration_plan.url.clone();
+            self.migration_plan = None;
+        }
+
+        log_warn!("lost connection to server, reconnecting");
+
+        self.status = ConnectionStatus::Reconnecting { attempt: 0 };
+        self.next_reconnect_at = current_unix_millis();
+    }
+
+    fn schedule_reconnect(&mut self, attempt: u32) {
+
+        self.pending_connection = None;
+
+        if let Some(max_reconnect_attempts) = self.max_reconnect_attempts {
+            if attempt >= max_reconnect_attempts {
+                log_error!("giving up on reconnecting to server");
+
+                self.status = ConnectionStatus::Disconnected;
+
+                return;
+            }
+        }
+
+        // exponential backoff starting at half a second
+        let delay = 500u64.saturating_mul(1 << attempt.min(16)).min(self.max_reconnect_delay);
+
+        self.status = ConnectionStatus::Reconnecting { attempt: attempt + 1 };
+        self.next_reconnect_at = current_unix_millis() + delay;
+    }
+
+    /// Advance the reconnection attempt without blocking. Once the server sends us the full state again it replaces ours
+    fn poll_reconnect(&mut self, state: &mut T) {
+
+        let attempt = match self.status {
+            ConnectionStatus::Reconnecting { attempt } => attempt,
+            _ => return,
+        };
+
+        if self.pending_connection.is_none() {
+
+            if current_unix_millis() < self.next_reconnect_at {
+                return;
+            }
+
+            match WebSocketTransport::connect(&self.url) {
+                Ok(server) => {
+                    self.pending_connection = Some(PendingConnection { server, opened: false });
+                },
+                Err(error) => {
+                    log_warn!("failed to reconnect to server: {}", error);
+
+                    self.schedule_reconnect(attempt);
+
+                    return;
+                },
+            }
+        }
+
+        let pending_connection = self.pending_connection.as_mut().unwrap();
+
+        while let Some(event) = pending_connection.server.try_recv() {
+            match event {
+                TransportEvent::Opened => {
+                    pending_connection.opened = true;
+
+                    pending_connection.server.send(Hello::new(C::TAG, CompressionKind::supported()).encode());
+                },
+                // nothing is an answer to us until our hello is out
+                TransportEvent::Frame(_) if !pending_connection.opened => continue,
+                TransportEvent::Frame(bytes) => {
+
+                    let state_bytes = match decode_frame(&bytes) {
+                        Ok((FrameKind::State, state_bytes)) => state_bytes,
+                        Ok((FrameKind::Hello, payload)) => {
+                            let hello = Hello::new(C::TAG, CompressionKind::supported());
+
+                            match Hello::decode(&payload).and_then(|server_hello| hello.check(&server_hello).map(|_| server_hello)) {
+                                Ok(server_hello) => {
+                                    self.compression.kind = server_hello.compressions.first().copied().unwrap_or(CompressionKind::None);
+
+                                    self.client_id = ClientId(server_hello.client_id);
+                                },
+                                Err(error) => {
+                                    // the server was replaced with a different build, retrying won't help
+                                    log_error!("cannot sync with server: {}", error);
+
+                                    self.pending_connection = None;
+                                    self.status = ConnectionStatus::Disconnected;
+
+                                    return;
+                                },
+                            }
+
+                            continue;
+                        },
+                        _ => continue, // anything before the full state is stale
+                    };
+
+                    let new_state: T = match C::deserialize(&state_bytes) {
+                        Ok(new_state) => new_state,
+                        Err(error) => {
+                            log_error!("failed to deserialize resync state: {}", error);
+
+                            self.schedule_reconnect(attempt);
+
+                            return;
+                        },
+                    };
+
+                    *state = new_state;
+
+                    self.previous_state = state.clone();
+
+                    if let Some(received_states) = &mut self.received_states {
+                        received_states.push((current_unix_millis(), state.clone()));
+                    }
+
+                    let pending_connection = self.pending_connection.take().unwrap();
+
+                    self.server = pending_connection.server;
+
+                    self.status = ConnectionStatus::Connected;
+                    self.last_message_at = current_unix_millis();
+
+                    log_info!("reconnected to server");
+
+                    return;
+                },
+                TransportEvent::Error(_) | TransportEvent::Closed => {
+                    self.schedule_reconnect(attempt);
+
+                    return;
+                },
+            }
+        }
     }
+
     fn send_update(&mut self, state: &T) {
 
         if self.previous_state == *state {
@@ -121,53 +548,200 @@ where
 
         let state_diff = self.previous_state.diff(&state);
 
-        if is_key_down(KeyCode::M) {
-            println!();
+        let diff_bytes = C::serialize(&state_diff).expect("failed to serialize state diff");
+
+        // the diff held back everything that changed, like Space does with changes under its diff thresholds
+        if diff_bytes == self.empty_diff {
+            return;
         }
 
-        let diff_bytes = bitcode::serialize(&state_diff).expect("failed to serialize state diff");
-        
-        let compressed_diff_bytes = compress_prepend_size(&diff_bytes);
-        
-        self.server_send.send(
-            ewebsock::WsMessage::Binary(
-                compressed_diff_bytes.to_vec()
-            )
-        );
+        self.send_diff(&diff_bytes);
+
+        self.last_send_at = Some(time::now());
+
+        // the server has what we had plus this diff. anything the diff held back stays unsent and keeps adding up
+        self.previous_state.apply(&state_diff);
+
+        if let Some(sent_diffs) = &mut self.sent_diffs {
+            sent_diffs.push((current_unix_millis(), state_diff));
+        }
+    }
+
+    fn send_diff(&mut self, diff_bytes: &[u8]) {
+        let header = DiffHeader { origin: self.client_id.0, sequence: self.next_sequence };
+
+        self.next_sequence += 1;
         
+        let frame = encode_frame(FrameKind::StateDiff, &header.encode(diff_bytes), self.compression);
+
+        self.metrics.record_diff(diff_bytes.len(), frame.len());
+
+        self.send_frame(frame);
     }
 
     fn receive_updates(&mut self, state: &mut T) {
         // we loop until there are no new updates
         loop {
 
-            let compressed_state_diff_bytes = match self.server_receive.try_recv() {
+            let frame_bytes = match self.server.try_recv() {
                 Some(event) => {
                     match event {
-                        ewebsock::WsEvent::Opened => todo!("unhandled 'Opened' event"),
-                        ewebsock::WsEvent::Message(message) => {
-                            match message {
-                                ewebsock::WsMessage::Binary(bytes) => bytes,
-                                _ => todo!("unhandled message type when trying to receive updates from server")
-                            }
+                        TransportEvent::Opened => continue, // we are already connected
+                        TransportEvent::Frame(bytes) => bytes,
+                        TransportEvent::Error(error) => {
+                            log_error!("received error from server: {}", error);
+
+                            self.connection_lost();
+
+                            return;
+                        },
+                        TransportEvent::Closed => {
+                            self.connection_lost();
+
+                            return;
                         },
-                        ewebsock::WsEvent::Error(error) => todo!("unhandled 'Error' event when trying to receive update from server: {}", error),
-                        ewebsock::WsEvent::Closed => todo!("server closed"),
                     }
                 },
                 None => break, // this means there are no more updates
             };
             
-            let state_diff_bytes = decompress_size_prepended(&compressed_state_diff_bytes).expect("Failed to decompress incoming update");
+            self.metrics.record_received(frame_bytes.len());
+
+            self.last_message_at = current_unix_millis();
+
+            let (frame_kind, payload) = match decode_frame(&frame_bytes) {
+                Ok(frame) => frame,
+                Err(error) => {
+                    log_error!("server sent a frame we can't decode: {}", error);
+
+                    self.connection_lost();
+
+                    return;
+                },
+            };
+
+            let state_diff_bytes = match frame_kind {
+                FrameKind::StateDiff => match DiffHeader::decode(&payload) {
+                    // the server shouldn't send us our own diffs, but if it does they are already in our state
+                    Some((header, _)) if header.origin == self.client_id.0 => continue,
+                    Some((_, state_diff_bytes)) => state_diff_bytes.to_vec(),
+                    None => {
+                        log_error!("received malformed diff from server");
+
+                        self.connection_lost();
+
+                        return;
+                    },
+                },
+                FrameKind::Ping => {
+                    self.send_frame(encode_frame(FrameKind::Pong, &payload, self.compression));
+
+                    continue;
+                },
+                FrameKind::Pong => {
+                    if let Some(sent_at) = decode_ping_payload(&payload) {
+                        self.metrics.record_round_trip(std::time::Duration::from_millis(current_unix_millis().saturating_sub(sent_at)));
+                    }
+
+                    continue;
+                },
+                FrameKind::Message => {
+                    self.messages.push(payload);
+
+                    continue;
+                },
+                FrameKind::State => {
+                    // the server wants us to start over from its state
+                    *state = match C::deserialize(&payload) {
+                        Ok(new_state) => new_state,
+                        Err(error) => {
+                            log_error!("failed to deserialize resync state: {}", error);
+
+                            self.connection_lost();
+
+                            return;
+                        },
+                    };
+
+                    self.previous_state = state.clone();
 
-            let state_diff: <T as Diff>::Repr = match bitcode::deserialize(&state_diff_bytes) {
+                    if let Some(received_states) = &mut self.received_states {
+                        received_states.push((current_unix_millis(), state.clone()));
+                    }
+
+                    continue;
+                },
+                FrameKind::Hello => continue, // only expected while connecting
+                FrameKind::Inputs => continue, // only clients send these
+                FrameKind::Migration => {
+                    self.migration_plan = match C::deserialize(&payload) {
+                        Ok(migration_plan) => migration_plan,
+                        Err(error) => {
+                            log_error!("failed to deserialize migration plan: {}", error);
+
+                            self.connection_lost();
+
+                            return;
+                        },
+                    };
+
+                    continue;
+                },
+            };
+
+            let state_diff: <T as Diff>::Repr = match C::deserialize(&state_diff_bytes) {
                 Ok(state_diff) => state_diff,
                 Err(error) => {
-                    panic!("failed to deserialize game state diff: {}", error);
+                    // we are out of sync from here on, reconnecting gets us the full state again
+                    log_error!("failed to deserialize game state diff: {}", error);
+
+                    self.connection_lost();
+
+                    return;
                 },
             };
 
             state.apply(&state_diff); 
+
+            // apply it to the previous state too so we dont send the change back along with our own unsent changes
+            self.previous_state.apply(&state_diff);
+
+            if let Some(received_diffs) = &mut self.received_diffs {
+                received_diffs.push((current_unix_millis(), state_diff));
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Whether a client that sends `send_rate` times a second should send again, `since_last_send` after it last did. No rate sends every frame
+fn send_due(send_rate: Option<f32>, since_last_send: Option<std::time::Duration>) -> bool {
+    let (send_rate, since_last_send) = match (send_rate, since_last_send) {
+        (Some(send_rate), Some(since_last_send)) => (send_rate, since_last_send),
+        _ => return true,
+    };
+
+    since_last_send.as_secs_f32() >= 1. / send_rate
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::send_due;
+
+    #[test]
+    fn sends_every_frame_without_a_rate() {
+        assert!(send_due(None, Some(Duration::ZERO)));
+    }
+
+    #[test]
+    fn first_send_is_never_held_back() {
+        assert!(send_due(Some(10.), None));
+    }
+
+    #[test]
+    fn changes_wait_for_the_next_send() {
+        assert!(!send_due(Some(10.), Some(Duration::from_millis(50))));
+        assert!(send_due(Some(10.), Some(Duration::from_millis(100))));
+    }
+}
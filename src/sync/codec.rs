@@ -0,0 +1,111 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecError(pub String);
+
+impl std::fmt::Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Serialization format used for everything sent between SyncClient and SyncServer
+pub trait Codec: 'static {
+    /// Identifies the format on the wire so mismatched builds can be detected
+    const TAG: u8;
+    const NAME: &'static str;
+
+    fn serialize<V: Serialize>(value: &V) -> Result<Vec<u8>, CodecError>;
+    fn deserialize<V: DeserializeOwned>(bytes: &[u8]) -> Result<V, CodecError>;
+}
+
+/// Compact binary format, the default
+pub struct BitcodeCodec;
+
+impl Codec for BitcodeCodec {
+    const TAG: u8 = 0;
+    const NAME: &'static str = "bitcode";
+
+    fn serialize<V: Serialize>(value: &V) -> Result<Vec<u8>, CodecError> {
+        bitcode::serialize(value).map_err(|error| CodecError(error.to_string()))
+    }
+
+    fn deserialize<V: DeserializeOwned>(bytes: &[u8]) -> Result<V, CodecError> {
+        bitcode::deserialize(bytes).map_err(|error| CodecError(error.to_string()))
+    }
+}
+
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    const TAG: u8 = 1;
+    const NAME: &'static str = "bincode";
+
+    fn serialize<V: Serialize>(value: &V) -> Result<Vec<u8>, CodecError> {
+        bincode::serialize(value).map_err(|error| CodecError(error.to_string()))
+    }
+
+    fn deserialize<V: DeserializeOwned>(bytes: &[u8]) -> Result<V, CodecError> {
+        bincode::deserialize(bytes).map_err(|error| CodecError(error.to_string()))
+    }
+}
+
+/// Human readable, useful for inspecting traffic while debugging
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    const TAG: u8 = 2;
+    const NAME: &'static str = "json";
+
+    fn serialize<V: Serialize>(value: &V) -> Result<Vec<u8>, CodecError> {
+        serde_json::to_vec(value).map_err(|error| CodecError(error.to_string()))
+    }
+
+    fn deserialize<V: DeserializeOwned>(bytes: &[u8]) -> Result<V, CodecError> {
+        serde_json::from_slice(bytes).map_err(|error| CodecError(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::{BincodeCodec, BitcodeCodec, Codec, JsonCodec};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Message {
+        text: String,
+        values: Vec<i32>,
+        flag: Option<bool>
+    }
+
+    fn round_trip<C: Codec>() {
+        let message = Message { text: "hello".to_string(), values: vec![-1, 0, 1], flag: Some(true) };
+
+        let bytes = C::serialize(&message).unwrap();
+
+        assert_eq!(C::deserialize::<Message>(&bytes).unwrap(), message, "{} didn't round trip", C::NAME);
+    }
+
+    #[test]
+    fn every_codec_round_trips() {
+        round_trip::<BitcodeCodec>();
+        round_trip::<BincodeCodec>();
+        round_trip::<JsonCodec>();
+    }
+
+    #[test]
+    fn tags_are_unique() {
+        let tags = [BitcodeCodec::TAG, BincodeCodec::TAG, JsonCodec::TAG];
+
+        for (index, tag) in tags.iter().enumerate() {
+            assert!(!tags[index + 1..].contains(tag));
+        }
+    }
+
+    #[test]
+    fn garbage_is_an_error() {
+        assert!(JsonCodec::deserialize::<Message>(b"{").is_err());
+        assert!(BitcodeCodec::deserialize::<Message>(&[]).is_err());
+    }
+}
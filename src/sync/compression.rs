@@ -0,0 +1,226 @@
+use lz4_flex::{compress_prepend_size, decompress_size_prepended};
+
+/// Payloads that would decompress to more than this are refused, so a tiny frame can't make us allocate gigabytes.
+/// The same as tungstenite's default message size limit
+pub const MAX_DECOMPRESSED_SIZE: usize = 64 << 20;
+
+/// A compression algorithm that frame payloads can be run through
+pub trait Compressor {
+    fn compress(&self, payload: &[u8]) -> Vec<u8>;
+
+    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+pub struct NoCompression;
+
+impl Compressor for NoCompression {
+    fn compress(&self, payload: &[u8]) -> Vec<u8> {
+        payload.to_vec()
+    }
+
+    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        Ok(bytes.to_vec())
+    }
+}
+
+pub struct Lz4Compression;
+
+impl Compressor for Lz4Compression {
+    fn compress(&self, payload: &[u8]) -> Vec<u8> {
+        compress_prepend_size(payload)
+    }
+
+    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        // lz4_flex allocates whatever size the sender put in front
+        let size = match bytes {
+            [a, b, c, d, ..] => u32::from_le_bytes([*a, *b, *c, *d]) as usize,
+            _ => return Err("lz4 payload is missing its size".to_string()),
+        };
+
+        if size > MAX_DECOMPRESSED_SIZE {
+            return Err(format!("lz4 payload claims to be {} bytes", size));
+        }
+
+        decompress_size_prepended(bytes).map_err(|error| error.to_string())
+    }
+}
+
+#[cfg(feature = "zstd")]
+pub struct ZstdCompression {
+    pub level: i32
+}
+
+#[cfg(feature = "zstd")]
+impl Compressor for ZstdCompression {
+    fn compress(&self, payload: &[u8]) -> Vec<u8> {
+        zstd::encode_all(payload, self.level).expect("failed to compress payload with zstd")
+    }
+
+    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        use std::io::Read;
+
+        let decoder = zstd::stream::read::Decoder::new(bytes).map_err(|error| error.to_string())?;
+
+        let mut payload = vec![];
+
+        // one byte over the limit is enough to tell it is too big
+        decoder.take(MAX_DECOMPRESSED_SIZE as u64 + 1).read_to_end(&mut payload).map_err(|error| error.to_string())?;
+
+        if payload.len() > MAX_DECOMPRESSED_SIZE {
+            return Err(format!("zstd payload is bigger than {} bytes", MAX_DECOMPRESSED_SIZE));
+        }
+
+        Ok(payload)
+    }
+}
+
+/// Which compressor a frame was compressed with. Sent as the second byte of every frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompressionKind {
+    None = 0,
+    Lz4 = 1,
+    Zstd = 2 // only available with the zstd feature
+}
+
+impl CompressionKind {
+    /// Every kind this build can decode, best first
+    pub fn supported() -> Vec<CompressionKind> {
+        let mut supported = vec![];
+
+        if cfg!(feature = "zstd") {
+            supported.push(CompressionKind::Zstd);
+        }
+
+        supported.push(CompressionKind::Lz4);
+        supported.push(CompressionKind::None);
+
+        supported
+    }
+
+    /// Pick the first of our preferred kinds the peer also supports. Falls back to no compression, which every peer supports
+    pub fn negotiate(preferred: &[CompressionKind], peer_supported: &[CompressionKind]) -> CompressionKind {
+        preferred.iter()
+            .find(|kind| peer_supported.contains(kind) && kind.is_supported())
+            .copied()
+            .unwrap_or(CompressionKind::None)
+    }
+
+    pub fn is_supported(&self) -> bool {
+        match self {
+            CompressionKind::Zstd => cfg!(feature = "zstd"),
+            _ => true,
+        }
+    }
+
+    pub fn compressor(&self) -> Box<dyn Compressor> {
+        match self {
+            CompressionKind::None => Box::new(NoCompression),
+            CompressionKind::Lz4 => Box::new(Lz4Compression),
+
+            #[cfg(feature = "zstd")]
+            CompressionKind::Zstd => Box::new(ZstdCompression { level: 3 }),
+
+            #[cfg(not(feature = "zstd"))]
+            CompressionKind::Zstd => panic!("zstd compression requires the zstd feature"),
+        }
+    }
+}
+
+impl TryFrom<u8> for CompressionKind {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(CompressionKind::None),
+            1 => Ok(CompressionKind::Lz4),
+            2 => Ok(CompressionKind::Zstd),
+            _ => Err(value),
+        }
+    }
+}
+
+/// How a connection compresses the frames it sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    pub kind: CompressionKind,
+    pub threshold: usize // payloads smaller than this many bytes are sent uncompressed
+}
+
+impl Compression {
+    pub fn new(kind: CompressionKind, threshold: usize) -> Self {
+        Self {
+            kind,
+            threshold,
+        }
+    }
+
+    /// The kind a payload of this size should actually be sent with
+    pub fn kind_for(&self, payload_size: usize) -> CompressionKind {
+        if payload_size < self.threshold {
+            return CompressionKind::None
+        }
+
+        self.kind
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            kind: CompressionKind::Lz4,
+            threshold: 64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Compression, CompressionKind, MAX_DECOMPRESSED_SIZE};
+
+    #[test]
+    fn every_supported_kind_round_trips() {
+        let payload: Vec<u8> = (0..1000).map(|index| (index % 7) as u8).collect();
+
+        for kind in CompressionKind::supported() {
+            let compressor = kind.compressor();
+
+            assert_eq!(compressor.decompress(&compressor.compress(&payload)).unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn lz4_payloads_claiming_to_be_huge_are_refused() {
+        let mut bytes = (MAX_DECOMPRESSED_SIZE as u32 + 1).to_le_bytes().to_vec();
+
+        bytes.extend([0; 16]);
+
+        assert!(CompressionKind::Lz4.compressor().decompress(&bytes).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_payloads_over_the_limit_are_refused() {
+        let payload = vec![0; MAX_DECOMPRESSED_SIZE + 1];
+
+        let compressor = CompressionKind::Zstd.compressor();
+
+        assert!(compressor.decompress(&compressor.compress(&payload)).is_err());
+    }
+
+    #[test]
+    fn negotiation_picks_our_first_choice_the_peer_supports() {
+        let kind = CompressionKind::negotiate(&[CompressionKind::Zstd, CompressionKind::Lz4], &[CompressionKind::Lz4, CompressionKind::None]);
+
+        assert_eq!(kind, CompressionKind::Lz4);
+
+        assert_eq!(CompressionKind::negotiate(&[CompressionKind::Lz4], &[]), CompressionKind::None);
+    }
+
+    #[test]
+    fn small_payloads_go_uncompressed() {
+        let compression = Compression::new(CompressionKind::Lz4, 64);
+
+        assert_eq!(compression.kind_for(63), CompressionKind::None);
+        assert_eq!(compression.kind_for(64), CompressionKind::Lz4);
+    }
+}
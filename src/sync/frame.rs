@@ -0,0 +1,185 @@
+use super::compression::{Compression, CompressionKind};
+
+/// What a websocket message carries. Every binary message starts with one of these as its first byte, followed by the CompressionKind of the payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    State = 0, // the full state, sent once when a client connects
+    StateDiff = 1,
+    Message = 2, // one shot message that isn't part of the synced state
+    Ping = 3, // payload is the sender's unix millis, echoed back in a Pong
+    Pong = 4,
+    Hello = 5, // handshake exchanged before the initial state, never compressed
+    Migration = 6, // who takes over hosting if the server goes away
+    Inputs = 7 // timestamped inputs from a client, for lag compensation
+}
+
+impl TryFrom<u8> for FrameKind {
+    type Error = FrameError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(FrameKind::State),
+            1 => Ok(FrameKind::StateDiff),
+            2 => Ok(FrameKind::Message),
+            3 => Ok(FrameKind::Ping),
+            4 => Ok(FrameKind::Pong),
+            5 => Ok(FrameKind::Hello),
+            6 => Ok(FrameKind::Migration),
+            7 => Ok(FrameKind::Inputs),
+            _ => Err(FrameError::UnknownKind(value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    Empty,
+    UnknownKind(u8),
+    UnknownCompression(u8),
+    Decompression(String)
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Empty => write!(f, "received an empty frame"),
+            FrameError::UnknownKind(kind) => write!(f, "received a frame of unknown kind {}", kind),
+            FrameError::UnknownCompression(kind) => write!(f, "received a frame compressed with unsupported compression {}", kind),
+            FrameError::Decompression(error) => write!(f, "failed to decompress frame: {}", error),
+        }
+    }
+}
+
+/// Compress a payload and put the frame header in front of it
+pub fn encode_frame(kind: FrameKind, payload: &[u8], compression: Compression) -> Vec<u8> {
+    let compression_kind = compression.kind_for(payload.len());
+
+    let mut bytes = vec![kind as u8, compression_kind as u8];
+
+    bytes.extend(compression_kind.compressor().compress(payload));
+
+    bytes
+}
+
+/// Split off the frame header and decompress the payload
+pub fn decode_frame(bytes: &[u8]) -> Result<(FrameKind, Vec<u8>), FrameError> {
+    let (kind, compression_kind, compressed_payload) = match bytes {
+        [kind, compression_kind, compressed_payload @ ..] => (FrameKind::try_from(*kind)?, *compression_kind, compressed_payload),
+        _ => return Err(FrameError::Empty),
+    };
+
+    let compression_kind = match CompressionKind::try_from(compression_kind) {
+        Ok(compression_kind) if compression_kind.is_supported() => compression_kind,
+        _ => return Err(FrameError::UnknownCompression(compression_kind)),
+    };
+
+    let payload = match compression_kind.compressor().decompress(compressed_payload) {
+        Ok(payload) => payload,
+        Err(error) => return Err(FrameError::Decompression(error)),
+    };
+
+    Ok((kind, payload))
+}
+
+/// Origin of diffs the server made itself, in authoritative mode
+pub const SERVER_ORIGIN: u64 = u64::MAX;
+
+/// Goes in front of every state diff payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffHeader {
+    pub origin: u64, // id of the client that made the diff, or SERVER_ORIGIN
+    pub sequence: u64 // counts up per origin
+}
+
+impl DiffHeader {
+    pub fn encode(&self, state_diff_bytes: &[u8]) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(16 + state_diff_bytes.len());
+
+        payload.extend(self.origin.to_le_bytes());
+        payload.extend(self.sequence.to_le_bytes());
+        payload.extend(state_diff_bytes);
+
+        payload
+    }
+
+    /// Split a state diff payload into its header and the serialized diff
+    pub fn decode(payload: &[u8]) -> Option<(Self, &[u8])> {
+        if payload.len() < 16 {
+            return None
+        }
+
+        let (origin, rest) = payload.split_at(8);
+        let (sequence, state_diff_bytes) = rest.split_at(8);
+
+        Some((
+            Self {
+                origin: u64::from_le_bytes(origin.try_into().ok()?),
+                sequence: u64::from_le_bytes(sequence.try_into().ok()?),
+            },
+            state_diff_bytes
+        ))
+    }
+}
+
+pub fn encode_ping(now: u64) -> Vec<u8> {
+    // too small to be worth compressing
+    encode_frame(FrameKind::Ping, &now.to_le_bytes(), Compression::new(CompressionKind::None, 0))
+}
+
+/// Read the timestamp out of a ping or pong payload
+pub fn decode_ping_payload(payload: &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(payload.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sync::compression::{Compression, CompressionKind};
+
+    use super::{decode_frame, decode_ping_payload, encode_frame, encode_ping, DiffHeader, FrameError, FrameKind};
+
+    #[test]
+    fn frames_round_trip_with_every_compression() {
+        let payload: Vec<u8> = (0..500).map(|index| (index % 13) as u8).collect();
+
+        for kind in CompressionKind::supported() {
+            let frame = encode_frame(FrameKind::StateDiff, &payload, Compression::new(kind, 0));
+
+            assert_eq!(frame[1], kind as u8);
+
+            assert_eq!(decode_frame(&frame).unwrap(), (FrameKind::StateDiff, payload.clone()));
+        }
+    }
+
+    #[test]
+    fn small_payloads_are_sent_uncompressed() {
+        let frame = encode_frame(FrameKind::Message, &[1, 2, 3], Compression::new(CompressionKind::Lz4, 64));
+
+        assert_eq!(frame, vec![FrameKind::Message as u8, CompressionKind::None as u8, 1, 2, 3]);
+    }
+
+    #[test]
+    fn bad_headers_are_errors() {
+        assert_eq!(decode_frame(&[]), Err(FrameError::Empty));
+        assert_eq!(decode_frame(&[200, 0]), Err(FrameError::UnknownKind(200)));
+        assert_eq!(decode_frame(&[FrameKind::State as u8, 200]), Err(FrameError::UnknownCompression(200)));
+    }
+
+    #[test]
+    fn diff_headers_round_trip() {
+        let header = DiffHeader { origin: 3, sequence: 42 };
+
+        let payload = header.encode(&[9, 8, 7]);
+
+        assert_eq!(DiffHeader::decode(&payload), Some((header, &[9, 8, 7][..])));
+
+        assert_eq!(DiffHeader::decode(&payload[..15]), None);
+    }
+
+    #[test]
+    fn pings_round_trip() {
+        let (kind, payload) = decode_frame(&encode_ping(1234)).unwrap();
+
+        assert_eq!(kind, FrameKind::Ping);
+        assert_eq!(decode_ping_payload(&payload), Some(1234));
+    }
+}
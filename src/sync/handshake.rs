@@ -0,0 +1,206 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::{compression::{Compression, CompressionKind}, frame::{encode_frame, FrameKind}};
+
+/// First bytes of every hello, so we can tell a sync peer apart from anything else that connected
+pub const MAGIC: [u8; 4] = *b"GLSY";
+
+/// Bump whenever the framing or handshake changes in a way older builds can't read
+pub const PROTOCOL_VERSION: u16 = 6;
+
+static GAME_VERSION: AtomicU32 = AtomicU32::new(0);
+
+/// Set the version of the game build, sent in every hello. Clients and servers with different game versions refuse to sync.
+/// Bump it whenever the synced state changes shape. 0 (the default) skips the check
+pub fn set_game_version(game_version: u32) {
+    GAME_VERSION.store(game_version, Ordering::Relaxed);
+}
+
+pub fn game_version() -> u32 {
+    GAME_VERSION.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeError {
+    BadMagic,
+    ProtocolVersion { ours: u16, theirs: u16 },
+    Codec { ours: u8, theirs: u8 },
+    GameVersion { ours: u32, theirs: u32 }
+}
+
+impl std::fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandshakeError::BadMagic => write!(f, "peer is not speaking the sync protocol"),
+            HandshakeError::ProtocolVersion { ours, theirs } => write!(f, "peer uses sync protocol version {} but we use {}", theirs, ours),
+            HandshakeError::Codec { ours, theirs } => write!(f, "peer serializes with codec {} but we use codec {}", theirs, ours),
+            HandshakeError::GameVersion { ours, theirs } => write!(f, "peer is running game version {} but we are running {}", theirs, ours),
+        }
+    }
+}
+
+/// Exchanged when a client connects, before the initial state.
+/// The client sends every compression it can decode and the server answers with the single one it picked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hello {
+    pub protocol_version: u16,
+    pub codec: u8, // Codec::TAG
+    pub game_version: u32,
+    pub client_id: u64, // assigned by the server, 0 in the client's hello
+    pub compressions: Vec<CompressionKind>
+}
+
+impl Hello {
+    pub fn new(codec: u8, compressions: Vec<CompressionKind>) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            codec,
+            game_version: game_version(),
+            client_id: 0,
+            compressions,
+        }
+    }
+
+    pub fn with_client_id(mut self, client_id: u64) -> Self {
+        self.client_id = client_id;
+
+        self
+    }
+
+    /// Whether we can sync with a peer that sent this hello
+    pub fn check(&self, peer: &Hello) -> Result<(), HandshakeError> {
+        if self.protocol_version != peer.protocol_version {
+            return Err(HandshakeError::ProtocolVersion { ours: self.protocol_version, theirs: peer.protocol_version })
+        }
+
+        if self.codec != peer.codec {
+            return Err(HandshakeError::Codec { ours: self.codec, theirs: peer.codec })
+        }
+
+        // 0 means the game doesn't version itself
+        if self.game_version != 0 && peer.game_version != 0 && self.game_version != peer.game_version {
+            return Err(HandshakeError::GameVersion { ours: self.game_version, theirs: peer.game_version })
+        }
+
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+
+        bytes.extend(self.protocol_version.to_le_bytes());
+        bytes.push(self.codec);
+        bytes.extend(self.game_version.to_le_bytes());
+        bytes.extend(self.client_id.to_le_bytes());
+
+        bytes.push(self.compressions.len() as u8);
+        bytes.extend(self.compressions.iter().map(|kind| *kind as u8));
+
+        // the peer doesn't know what we can decompress yet
+        encode_frame(FrameKind::Hello, &bytes, Compression::new(CompressionKind::None, 0))
+    }
+
+    pub fn decode(payload: &[u8]) -> Result<Self, HandshakeError> {
+        let (magic, payload) = match payload.split_first_chunk::<4>() {
+            Some(split) => split,
+            None => return Err(HandshakeError::BadMagic),
+        };
+
+        if *magic != MAGIC {
+            return Err(HandshakeError::BadMagic)
+        }
+
+        // the protocol version comes first so a peer with a different layout still gets a useful error
+        let protocol_version = match payload.get(0..2) {
+            Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]),
+            None => return Err(HandshakeError::BadMagic),
+        };
+
+        if protocol_version != PROTOCOL_VERSION {
+            return Err(HandshakeError::ProtocolVersion { ours: PROTOCOL_VERSION, theirs: protocol_version })
+        }
+
+        let (codec, game_version, client_id, count, kinds) = match payload {
+            [_, _, codec, v0, v1, v2, v3, c0, c1, c2, c3, c4, c5, c6, c7, count, kinds @ ..] => (
+                *codec, 
+                u32::from_le_bytes([*v0, *v1, *v2, *v3]), 
+                u64::from_le_bytes([*c0, *c1, *c2, *c3, *c4, *c5, *c6, *c7]),
+                *count as usize, 
+                kinds
+            ),
+            _ => return Err(HandshakeError::BadMagic),
+        };
+
+        // skip kinds we don't know about, a newer peer may support more than we do
+        let compressions = kinds.iter()
+            .take(count)
+            .filter_map(|kind| CompressionKind::try_from(*kind).ok())
+            .collect();
+
+        Ok(Self {
+            protocol_version,
+            codec,
+            game_version,
+            client_id,
+            compressions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sync::{compression::CompressionKind, frame::{decode_frame, FrameKind}};
+
+    use super::{HandshakeError, Hello, PROTOCOL_VERSION};
+
+    fn hello_payload(hello: &Hello) -> Vec<u8> {
+        let (kind, payload) = decode_frame(&hello.encode()).unwrap();
+
+        assert_eq!(kind, FrameKind::Hello);
+
+        payload
+    }
+
+    #[test]
+    fn hellos_round_trip() {
+        let hello = Hello::new(1, vec![CompressionKind::Lz4, CompressionKind::None]).with_client_id(7);
+
+        assert_eq!(Hello::decode(&hello_payload(&hello)), Ok(hello));
+    }
+
+    #[test]
+    fn other_protocol_versions_are_refused() {
+        let mut payload = hello_payload(&Hello::new(0, vec![]));
+
+        payload[4..6].copy_from_slice(&(PROTOCOL_VERSION + 1).to_le_bytes());
+
+        assert_eq!(Hello::decode(&payload), Err(HandshakeError::ProtocolVersion { ours: PROTOCOL_VERSION, theirs: PROTOCOL_VERSION + 1 }));
+    }
+
+    #[test]
+    fn anything_else_is_bad_magic() {
+        assert_eq!(Hello::decode(b"GET / HTTP/1.1"), Err(HandshakeError::BadMagic));
+        assert_eq!(Hello::decode(&[]), Err(HandshakeError::BadMagic));
+    }
+
+    #[test]
+    fn unknown_compressions_are_skipped() {
+        let mut payload = hello_payload(&Hello::new(0, vec![CompressionKind::Lz4]));
+
+        // one more kind than we know about
+        *payload.last_mut().unwrap() = 200;
+
+        assert_eq!(Hello::decode(&payload).unwrap().compressions, vec![]);
+    }
+
+    #[test]
+    fn mismatched_peers_are_refused() {
+        let ours = Hello { game_version: 2, ..Hello::new(0, vec![]) };
+
+        assert_eq!(ours.check(&Hello { codec: 1, ..ours.clone() }), Err(HandshakeError::Codec { ours: 0, theirs: 1 }));
+        assert_eq!(ours.check(&Hello { game_version: 3, ..ours.clone() }), Err(HandshakeError::GameVersion { ours: 2, theirs: 3 }));
+
+        // unversioned games sync with anyone on the same protocol and codec
+        assert_eq!(ours.check(&Hello { game_version: 0, ..ours.clone() }), Ok(()));
+    }
+}
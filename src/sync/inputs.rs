@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::codec::Codec;
+
+/// Inputs a client recorded since its last send. Goes out right before the client's next diff
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InputBatch {
+    pub sent_at: u64, // client unix millis
+    pub inputs: Vec<(u64, Vec<u8>)> // client unix millis, serialized input
+}
+
+impl InputBatch {
+
+    /// Move the input times onto our clock. The inputs were made `sent_at - time` before the batch was sent,
+    /// and the batch took about half a round trip to get here
+    pub fn to_local_time(self, received_at: u64, round_trip_time: u64) -> Vec<(u64, Vec<u8>)> {
+        let sent_at_local = received_at.saturating_sub(round_trip_time / 2);
+
+        self.inputs.into_iter().map(|(time, input_bytes)| {
+            (sent_at_local.saturating_sub(self.sent_at.saturating_sub(time)), input_bytes)
+        }).collect()
+    }
+}
+
+/// Timestamped serialized inputs, oldest first. Only the last `history` millis are kept
+pub struct InputLog {
+    inputs: VecDeque<(u64, Vec<u8>)>,
+    pub history: u64 // millis
+}
+
+impl InputLog {
+    pub fn new(history: u64) -> Self {
+        Self {
+            inputs: VecDeque::new(),
+            history,
+        }
+    }
+
+    pub fn push(&mut self, time: u64, input_bytes: Vec<u8>) {
+        self.inputs.push_back((time, input_bytes));
+
+        while let Some((oldest_time, _)) = self.inputs.front() {
+            if time.saturating_sub(*oldest_time) <= self.history {
+                break;
+            }
+
+            self.inputs.pop_front();
+        }
+    }
+
+    /// Every input recorded from `start` up to and including `end`. Inputs that aren't an `I` are skipped
+    pub fn between<I: DeserializeOwned, C: Codec>(&self, start: u64, end: u64) -> Vec<(u64, I)> {
+        self.inputs.iter()
+            .filter(|(time, _)| *time >= start && *time <= end)
+            .filter_map(|(time, input_bytes)| {
+                match C::deserialize(input_bytes) {
+                    Ok(input) => Some((*time, input)),
+                    Err(error) => {
+                        log_warn!("failed to deserialize input: {}", error);
+
+                        None
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sync::codec::{BitcodeCodec, Codec};
+
+    use super::{InputBatch, InputLog};
+
+    fn input_bytes(input: u32) -> Vec<u8> {
+        BitcodeCodec::serialize(&input).unwrap()
+    }
+
+    #[test]
+    fn between_includes_both_ends() {
+        let mut input_log = InputLog::new(1000);
+
+        for time in [100, 200, 300, 400] {
+            input_log.push(time, input_bytes(time as u32));
+        }
+
+        let inputs = input_log.between::<u32, BitcodeCodec>(200, 300);
+
+        assert_eq!(inputs, vec![(200, 200), (300, 300)]);
+    }
+
+    #[test]
+    fn old_inputs_are_forgotten() {
+        let mut input_log = InputLog::new(100);
+
+        input_log.push(0, input_bytes(0));
+        input_log.push(150, input_bytes(1));
+
+        assert_eq!(input_log.between::<u32, BitcodeCodec>(0, 150), vec![(150, 1)]);
+    }
+
+    #[test]
+    fn inputs_of_another_type_are_skipped() {
+        let mut input_log = InputLog::new(1000);
+
+        input_log.push(0, vec![]);
+        input_log.push(1, input_bytes(7));
+
+        assert_eq!(input_log.between::<u32, BitcodeCodec>(0, 1), vec![(1, 7)]);
+    }
+
+    #[test]
+    fn batches_move_onto_the_local_clock() {
+        let batch = InputBatch { sent_at: 5000, inputs: vec![(4900, vec![])] };
+
+        // sent 50 millis before it arrived, made 100 millis before that
+        assert_eq!(batch.to_local_time(1000, 100), vec![(850, vec![])]);
+    }
+}
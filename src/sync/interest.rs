@@ -0,0 +1,45 @@
+use diff::Diff;
+use macroquad::math::Vec2;
+
+use crate::space::Space;
+
+/// The part of the world a client cares about, in rapier coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterestArea {
+    pub center: Vec2,
+    pub radius: f32
+}
+
+impl InterestArea {
+
+    pub fn new(center: Vec2, radius: f32) -> Self {
+        Self {
+            center,
+            radius,
+        }
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.center.distance_squared(point) <= self.radius * self.radius
+    }
+}
+
+/// State that can hold back the parts of itself that fall outside of a client's interest area
+pub trait InterestManaged: Diff + Clone {
+    /// A copy of self where everything outside of `area` is left how it was in `sent`, the state the client was last sent.
+    /// Diffing `sent` against it only contains what the client can see
+    fn mask_outside(&self, sent: &Self, area: InterestArea) -> Self;
+}
+
+impl InterestManaged for Space {
+
+    /// Holds back rigid bodies outside of the area along with their colliders.
+    /// Structural changes (new/removed bodies, joints, platforms) are always kept
+    fn mask_outside(&self, sent: &Self, area: InterestArea) -> Self {
+        self.masked(sent, |rigid_body| {
+            let translation = rigid_body.translation();
+
+            area.contains(Vec2::new(translation.x, translation.y))
+        })
+    }
+}
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use crate::time;
+
+/// Snapshot of network usage, refreshed once a second
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SyncMetrics {
+    pub bytes_sent_per_second: f32,
+    pub bytes_received_per_second: f32,
+    pub messages_sent_per_second: f32, // websocket messages of any kind, not just one shot messages
+    pub messages_received_per_second: f32,
+    pub total_bytes_sent: u64,
+    pub total_bytes_received: u64,
+    pub last_diff_raw_size: usize, // serialized diff before compression
+    pub last_diff_compressed_size: usize,
+    pub round_trip_time: Option<Duration>
+}
+
+/// Accumulates counters and publishes them as SyncMetrics every second
+pub(crate) struct MetricsTracker {
+    metrics: SyncMetrics,
+    window_start: time::Instant,
+    window_bytes_sent: u64,
+    window_bytes_received: u64,
+    window_messages_sent: u64,
+    window_messages_received: u64
+}
+
+impl MetricsTracker {
+
+    pub fn new() -> Self {
+        Self {
+            metrics: SyncMetrics::default(),
+            window_start: time::now(),
+            window_bytes_sent: 0,
+            window_bytes_received: 0,
+            window_messages_sent: 0,
+            window_messages_received: 0,
+        }
+    }
+
+    pub fn record_sent(&mut self, bytes: usize) {
+        self.window_bytes_sent += bytes as u64;
+        self.window_messages_sent += 1;
+        self.metrics.total_bytes_sent += bytes as u64;
+
+        self.roll_window();
+    }
+
+    pub fn record_received(&mut self, bytes: usize) {
+        self.window_bytes_received += bytes as u64;
+        self.window_messages_received += 1;
+        self.metrics.total_bytes_received += bytes as u64;
+
+        self.roll_window();
+    }
+
+    pub fn record_diff(&mut self, raw_size: usize, compressed_size: usize) {
+        self.metrics.last_diff_raw_size = raw_size;
+        self.metrics.last_diff_compressed_size = compressed_size;
+    }
+
+    pub fn record_round_trip(&mut self, round_trip_time: Duration) {
+        self.metrics.round_trip_time = Some(round_trip_time);
+    }
+
+    pub fn metrics(&mut self) -> SyncMetrics {
+        self.roll_window();
+
+        self.metrics
+    }
+
+    fn roll_window(&mut self) {
+        let elapsed = self.window_start.elapsed().as_secs_f32();
+
+        if elapsed < 1. {
+            return;
+        }
+
+        self.metrics.bytes_sent_per_second = self.window_bytes_sent as f32 / elapsed;
+        self.metrics.bytes_received_per_second = self.window_bytes_received as f32 / elapsed;
+        self.metrics.messages_sent_per_second = self.window_messages_sent as f32 / elapsed;
+        self.metrics.messages_received_per_second = self.window_messages_received as f32 / elapsed;
+
+        self.window_start = time::now();
+        self.window_bytes_sent = 0;
+        self.window_bytes_received = 0;
+        self.window_messages_sent = 0;
+        self.window_messages_received = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::time;
+
+    use super::MetricsTracker;
+
+    #[test]
+    fn totals_count_up_right_away() {
+        let mut metrics_tracker = MetricsTracker::new();
+
+        metrics_tracker.record_sent(100);
+        metrics_tracker.record_sent(50);
+        metrics_tracker.record_received(10);
+
+        let metrics = metrics_tracker.metrics();
+
+        assert_eq!(metrics.total_bytes_sent, 150);
+        assert_eq!(metrics.total_bytes_received, 10);
+
+        // rates only change once a full window has passed
+        assert_eq!(metrics.bytes_sent_per_second, 0.);
+    }
+
+    #[test]
+    fn rates_are_published_once_a_window_passes() {
+        let mut metrics_tracker = MetricsTracker::new();
+
+        metrics_tracker.record_sent(100);
+        metrics_tracker.record_sent(100);
+
+        metrics_tracker.window_start = time::now() - Duration::from_secs(2);
+
+        let metrics = metrics_tracker.metrics();
+
+        assert!((metrics.bytes_sent_per_second - 100.).abs() < 1.);
+        assert!((metrics.messages_sent_per_second - 1.).abs() < 0.01);
+
+        // the next window starts empty
+        assert_eq!(metrics_tracker.window_bytes_sent, 0);
+    }
+
+    #[test]
+    fn last_diff_sizes_are_kept() {
+        let mut metrics_tracker = MetricsTracker::new();
+
+        metrics_tracker.record_diff(1000, 300);
+        metrics_tracker.record_round_trip(Duration::from_millis(40));
+
+        let metrics = metrics_tracker.metrics();
+
+        assert_eq!((metrics.last_diff_raw_size, metrics.last_diff_compressed_size), (1000, 300));
+        assert_eq!(metrics.round_trip_time, Some(Duration::from_millis(40)));
+    }
+}
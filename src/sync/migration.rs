@@ -0,0 +1,35 @@
+use std::net::SocketAddr;
+
+use diff::Diff;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{client::SyncClient, codec::Codec, server::{ClientId, SyncServer}};
+
+/// Who takes over as host if the server goes away. The server sends it to every client with SyncServer::set_migration_plan
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MigrationPlan {
+    pub successor: ClientId,
+    /// Where the successor will listen once promoted
+    pub listen_address: SocketAddr,
+    /// Where every other client reconnects to, pointing at listen_address
+    pub url: String
+}
+
+impl<T, C> SyncClient<T, C>
+where 
+    T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
+    <T as Diff>::Repr: DeserializeOwned + Serialize,
+    C: Codec {
+
+    /// Once the status is Promoted, become the host. Starts a server from our last known state that the other clients will reconnect to
+    pub fn promote(self, state: &T) -> SyncServer<T, C> {
+        let migration_plan = match self.migration_plan() {
+            Some(migration_plan) => migration_plan.clone(),
+            None => panic!("tried to promote a client without a migration plan"),
+        };
+
+        log_info!("promoted to host, listening on {}", migration_plan.listen_address);
+
+        SyncServer::new_with_codec(migration_plan.listen_address, state.clone())
+    }
+}
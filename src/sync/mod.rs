@@ -1,2 +1,17 @@
 pub mod client;
-pub mod server;
\ No newline at end of file
+pub mod codec;
+pub mod compression;
+pub mod frame;
+pub mod handshake;
+pub mod inputs;
+pub mod interest;
+pub mod metrics;
+pub mod migration;
+pub mod prediction;
+pub mod server;
+pub mod server_core;
+pub mod snapshot;
+pub mod stream;
+pub mod transport;
+#[cfg(feature = "tokio")]
+pub mod async_server;
@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+
+use diff::Diff;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{client::SyncClient, codec::{BitcodeCodec, Codec}};
+
+/// An input tagged with the order it was made in. The server records the last sequence it applied for each client
+/// somewhere in the synced state so the client knows which inputs it no longer needs to replay
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SequencedInput<I> {
+    pub sequence: u64,
+    pub input: I
+}
+
+/// Client side prediction on top of a SyncClient in an authoritative server setup.
+/// Local inputs are applied immediately to a predicted copy of the state. When the server's state arrives,
+/// the predicted state is rebuilt from it and every input the server hasn't applied yet is replayed on top
+pub struct PredictedState<T: Serialize + DeserializeOwned + Diff + Clone + PartialEq, I, C: Codec = BitcodeCodec> {
+    client: SyncClient<T, C>,
+    authoritative: T,
+    predicted: T,
+    pending_inputs: VecDeque<SequencedInput<I>>,
+    next_sequence: u64,
+    apply_input: Box<dyn FnMut(&mut T, &I)>,
+    acknowledged: Box<dyn Fn(&T) -> u64>
+}
+
+impl<T, I, C> PredictedState<T, I, C>
+where
+    T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
+    <T as Diff>::Repr: DeserializeOwned + Serialize,
+    I: Serialize + Clone,
+    C: Codec {
+
+    /// `apply_input` must do exactly what the server does with an input.
+    /// `acknowledged` reads the last input sequence the server applied for this client out of the server's state
+    pub fn new(
+        client: SyncClient<T, C>,
+        state: T,
+        apply_input: impl FnMut(&mut T, &I) + 'static,
+        acknowledged: impl Fn(&T) -> u64 + 'static
+    ) -> Self {
+        Self {
+            client,
+            predicted: state.clone(),
+            authoritative: state,
+            pending_inputs: VecDeque::new(),
+            next_sequence: 1, // 0 means nothing has been acknowledged
+            apply_input: Box::new(apply_input),
+            acknowledged: Box::new(acknowledged),
+        }
+    }
+
+    /// The state to render, including inputs the server hasn't confirmed yet
+    pub fn state(&self) -> &T {
+        &self.predicted
+    }
+
+    /// The last state received from the server
+    pub fn authoritative(&self) -> &T {
+        &self.authoritative
+    }
+
+    pub fn client(&self) -> &SyncClient<T, C> {
+        &self.client
+    }
+
+    pub fn client_mut(&mut self) -> &mut SyncClient<T, C> {
+        &mut self.client
+    }
+
+    /// Inputs sent to the server that it hasn't applied yet
+    pub fn pending_inputs(&self) -> usize {
+        self.pending_inputs.len()
+    }
+
+    /// Apply an input locally and send it to the server. `message` wraps it in the game's message type
+    pub fn input<M: Serialize>(&mut self, input: I, message: impl FnOnce(SequencedInput<I>) -> M) -> u64 {
+        let sequence = self.next_sequence;
+
+        self.next_sequence += 1;
+
+        (self.apply_input)(&mut self.predicted, &input);
+
+        let sequenced_input = SequencedInput { sequence, input };
+
+        self.client.send_message(&message(sequenced_input.clone()));
+
+        self.pending_inputs.push_back(sequenced_input);
+
+        sequence
+    }
+
+    /// Receive the server's state and reconcile the predicted state with it
+    pub fn sync(&mut self) {
+        let received_before = self.client.metrics().total_bytes_received;
+
+        // we never modify the authoritative copy ourselves, so this only receives
+        self.client.sync(&mut self.authoritative);
+
+        // nothing arrived, the prediction is still valid
+        if self.client.metrics().total_bytes_received == received_before {
+            return;
+        }
+
+        let acknowledged = (self.acknowledged)(&self.authoritative);
+
+        self.predicted = reconcile(&self.authoritative, acknowledged, &mut self.pending_inputs, &mut self.apply_input);
+    }
+}
+
+/// Forget the inputs the server has applied, then rewind to what the server says and replay the rest on top
+fn reconcile<T: Clone, I>(authoritative: &T, acknowledged: u64, pending_inputs: &mut VecDeque<SequencedInput<I>>, apply_input: &mut dyn FnMut(&mut T, &I)) -> T {
+    while let Some(pending_input) = pending_inputs.front() {
+        if pending_input.sequence > acknowledged {
+            break;
+        }
+
+        pending_inputs.pop_front();
+    }
+
+    let mut predicted = authoritative.clone();
+
+    for pending_input in pending_inputs.iter() {
+        apply_input(&mut predicted, &pending_input.input);
+    }
+
+    predicted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::{reconcile, SequencedInput};
+
+    fn pending_inputs(moves: &[i32]) -> VecDeque<SequencedInput<i32>> {
+        moves.iter()
+            .enumerate()
+            .map(|(index, input)| SequencedInput { sequence: index as u64 + 1, input: *input })
+            .collect()
+    }
+
+    #[test]
+    fn unacknowledged_inputs_are_replayed_on_the_server_state() {
+        let mut pending_inputs = pending_inputs(&[1, 2, 4]);
+
+        // the server applied the first input and got 10 because of something we didn't predict
+        let predicted = reconcile(&10, 1, &mut pending_inputs, &mut |position: &mut i32, input: &i32| *position += input);
+
+        assert_eq!(predicted, 16);
+        assert_eq!(pending_inputs.len(), 2);
+    }
+
+    #[test]
+    fn everything_acknowledged_leaves_the_server_state() {
+        let mut pending_inputs = pending_inputs(&[1, 2]);
+
+        let predicted = reconcile(&3, 2, &mut pending_inputs, &mut |position: &mut i32, input: &i32| *position += input);
+
+        assert_eq!(predicted, 3);
+        assert!(pending_inputs.is_empty());
+    }
+}
@@ -1,23 +1,146 @@
-use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::{net::{SocketAddr, TcpListener}, ops::{Deref, DerefMut}, time::Duration};
 
 use diff::Diff;
-use lz4_flex::{compress_prepend_size, decompress_size_prepended};
-use serde::{de::DeserializeOwned, Serialize};
-use tungstenite::{Message, WebSocket};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tungstenite::{handshake::{server::{NoCallback, ServerHandshake}, MidHandshake}, Message, WebSocket};
 
-pub struct SyncServer<T: Serialize + DeserializeOwned + Diff + Clone + PartialEq> {
-    state: T,
-    clients: Vec<WebSocket<TcpStream>>,
-    listener: TcpListener
+use crate::current_unix_millis;
 
+use super::{codec::{BitcodeCodec, Codec}, frame::{decode_frame, FrameKind}, handshake::{HandshakeError, Hello}, interest::InterestArea, server_core::ServerCore, stream::ServerStream, transport::ClientConnection};
+
+/// Stable identifier assigned to a client when it connects
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientId(pub u64);
+
+impl std::fmt::Display for ClientId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientInfo {
+    pub id: ClientId,
+    pub address: SocketAddr,
+    pub connected_at: u64, // unix millis
+    pub last_message_at: u64, // unix millis
+    pub round_trip_time: Option<Duration>,
+    pub last_sequence: u64 // sequence of the last diff we applied from this client
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    Closed, // the client sent a close frame, so it quit on purpose
+    ConnectionReset,
+    ProtocolError,
+    TimedOut // we didn't hear from the client for longer than the heartbeat timeout
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent {
+    ClientConnected(ClientInfo),
+    ClientDisconnected(ClientInfo, DisconnectReason)
 }
 
-impl<T> SyncServer<T>
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ServerMode {
+    /// Clients own the simulation, the server just relays and applies their diffs
+    Relay,
+    /// The server owns the state and broadcasts its own diffs `tick_rate` times a second
+    Authoritative { tick_rate: f32 }
+}
+
+/// Decides whether a diff sent by a client may be applied
+pub type DiffValidator<T> = Box<dyn FnMut(ClientId, &T, &<T as Diff>::Repr) -> bool>;
+
+/// Picks the part of the world a client cares about. None sends the client everything
+pub type InterestAreaPicker<T> = Box<dyn FnMut(ClientId, &T) -> Option<InterestArea>>;
+
+impl ClientConnection for WebSocket<ServerStream> {
+    // websockets only have a reliable channel, so everything goes out the same way
+    fn send(&mut self, frame: Vec<u8>) -> bool {
+        match WebSocket::send(self, Message::Binary(frame)) {
+            Ok(_) => true,
+            Err(tungstenite::Error::Io(io_error)) if io_error.kind() == std::io::ErrorKind::WouldBlock => true, // queued, goes out on the next flush
+            Err(error) => {
+                log_warn!("failed to send to client: {}", error);
+
+                false
+            },
+        }
+    }
+}
+
+/// A connection that hasn't finished the websocket handshake or sent its hello yet
+enum Handshake {
+    Websocket(Box<MidHandshake<ServerHandshake<ServerStream, NoCallback>>>),
+    Hello(Box<WebSocket<ServerStream>>)
+}
+
+struct PendingClient {
+    address: SocketAddr,
+    handshake: Handshake,
+    deadline: u64 // unix millis
+}
+
+
+enum HandshakeProgress {
+    Waiting(PendingClient),
+    Failed,
+    Connected(ClientId)
+}
+
+/// Blocking websocket server. Call accept_new_client and receive_updates every frame.
+/// Everything that doesn't touch a socket lives in ServerCore, which this derefs to
+pub struct SyncServer<T: Serialize + DeserializeOwned + Diff + Clone + PartialEq, C: Codec = BitcodeCodec> {
+    core: ServerCore<T, C, WebSocket<ServerStream>>,
+    listener: TcpListener,
+    pending_clients: Vec<PendingClient>,
+    #[cfg(feature = "rustls")]
+    tls_config: Option<std::sync::Arc<rustls::ServerConfig>>
+
+}
+
+impl<T> SyncServer<T, BitcodeCodec>
 where 
     T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
     <T as Diff>::Repr: DeserializeOwned + Serialize {
-    
+
     pub fn new(address: SocketAddr, initial_state: T) -> Self {
+        Self::new_with_codec(address, initial_state)
+    }
+}
+
+impl<T, C> Deref for SyncServer<T, C>
+where 
+    T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
+    C: Codec {
+
+    type Target = ServerCore<T, C, WebSocket<ServerStream>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.core
+    }
+}
+
+impl<T, C> DerefMut for SyncServer<T, C>
+where 
+    T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
+    C: Codec {
+
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.core
+    }
+}
+
+impl<T, C> SyncServer<T, C>
+where 
+    T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
+    <T as Diff>::Repr: DeserializeOwned + Serialize,
+    C: Codec {
+    
+    /// Create a server using a serialization format other than the default. Clients must use the same codec
+    pub fn new_with_codec(address: SocketAddr, initial_state: T) -> Self {
 
         let listener = match TcpListener::bind(address) {
             Ok(listener) => listener,
@@ -30,172 +153,222 @@ where
         };
 
         Self {
-            state: initial_state, 
-            clients: vec![], 
-            listener
+            core: ServerCore::new(initial_state),
+            listener,
+            pending_clients: vec![],
+            #[cfg(feature = "rustls")]
+            tls_config: None
         }
     }
 
+    /// Terminate TLS on new connections so clients can connect with wss://. Browsers on https pages refuse plain ws
+    #[cfg(feature = "rustls")]
+    pub fn set_tls_config(&mut self, tls_config: Option<std::sync::Arc<rustls::ServerConfig>>) {
+        self.tls_config = tls_config;
+    }
+
     pub fn receive_updates(&mut self) {
 
-        let mut client_index = 0;
+        self.core.heartbeat();
 
-        'client_loop: while client_index < self.clients.len() {
+        for client_id in self.core.client_ids() {
 
-            // take the client out, receive all updates, then put it back in
-            let mut client = self.clients.remove(client_index);
-            
             // keep trying to receive updates until there are none
             loop {
 
-                let compressed_state_diff_bytes = match client.read() {
-                    Ok(message) => {
-                        match message {
-                            Message::Binary(compressed_state_diff_bytes) => {
-                                compressed_state_diff_bytes
-                            },
-                            Message::Close(_close_message) => {
-                                println!("client {} disconnected", client_index);
-                                continue 'client_loop;
-                            },
-                            _ => todo!("client tried to send non binary message")
-                        }
+                let websocket = match self.core.connection_mut(client_id) {
+                    Some(websocket) => websocket,
+                    None => break, // dropped while we were relaying to it
+                };
+
+                let frame_bytes = match websocket.read() {
+                    Ok(Message::Binary(frame_bytes)) => frame_bytes,
+                    Ok(Message::Close(_close_message)) => {
+                        self.core.disconnect(client_id, DisconnectReason::Closed);
+
+                        break;
                     },
-                    Err(error) => {
-                        match error {
-
-                            tungstenite::Error::Io(io_error) => {
-                                match io_error.kind() {
-                                    std::io::ErrorKind::WouldBlock => {
-                                        // this means that there was no update to read
-                                        self.clients.insert(client_index, client);
-                                        
-                                        client_index += 1;
-                                        
-                                        continue 'client_loop // move to the next client
-                                    },
-                                    std::io::ErrorKind::ConnectionReset => {
-                                        println!("client {} disconnected", client_index);
-
-                                        // do not increment client index because we arent putting this one back
-
-                                        continue 'client_loop;
-                                    }
-                                    _ => todo!("unhandled io error: {}", io_error),
-                                }
-                            },
-                            
-                            tungstenite::Error::Protocol(_error) => {
-                                println!("client {} disconnected due to protocol error", client_index);
-
-                                // do not increment client index because we arent putting this one back
-
-                                continue 'client_loop;
-                            },
-                            
-                            _ => todo!("unhandled websocket message read error: {}", error.to_string())
-                        }
+                    Ok(_) => continue, // pings are answered by tungstenite and we don't use text
+                    Err(tungstenite::Error::Io(io_error)) if io_error.kind() == std::io::ErrorKind::WouldBlock => break, // nothing left to read
+                    Err(tungstenite::Error::Protocol(error)) => {
+                        log_warn!("client {} disconnected due to protocol error: {}", client_id, error);
+
+                        self.core.disconnect(client_id, DisconnectReason::ProtocolError);
+
+                        break;
                     },
-                };
-                let state_diff_bytes = decompress_size_prepended(&compressed_state_diff_bytes).expect("Failed to decompress game state diff string bytes");
-    
-                let state_diff: <T as Diff>::Repr = match bitcode::deserialize(&state_diff_bytes) {
-                    Ok(state_diff) => state_diff,
                     Err(error) => {
-                        todo!("unhandled game state diff deserialization error: {}", error);
+                        log_warn!("client {} disconnected due to read error: {}", client_id, error);
+
+                        self.core.disconnect(client_id, DisconnectReason::ConnectionReset);
+
+                        break;
                     },
                 };
-    
-                // relay this update to other clients
-                'relay: for other_client_index in 0..self.clients.len() {
-    
-                    let mut other_client = self.clients.remove(other_client_index);
-    
-                    match other_client.send(Message::Binary(compressed_state_diff_bytes.clone())) {
-                        Ok(_) => {
-                            self.clients.insert(other_client_index, other_client);
 
-                            continue 'relay;
+                self.core.receive_frame(client_id, frame_bytes);
+            }
+        }
+    }
 
-                        },
-                        Err(error) => {
-                            todo!("unhandled error when relaying update data to client: {}", error);
-    
-                        },
-                    }
-    
-                }
+    /// Accept a waiting connection and move the handshakes of earlier ones along without blocking.
+    /// Returns a client that finished connecting. Others that are ready finish on the next calls
+    pub fn accept_new_client(&mut self) -> Option<ClientId> {
 
-                // apply it to our own game state
-                self.state.apply(&state_diff);
+        self.accept_connection();
+
+        let mut pending_clients = std::mem::take(&mut self.pending_clients).into_iter();
+
+        let mut connected = None;
+
+        for pending_client in pending_clients.by_ref() {
+            match self.poll_handshake(pending_client) {
+                HandshakeProgress::Waiting(pending_client) => self.pending_clients.push(pending_client),
+                HandshakeProgress::Failed => {},
+                HandshakeProgress::Connected(client_id) => {
+                    connected = Some(client_id);
+
+                    break;
+                },
             }
         }
+
+        self.pending_clients.extend(pending_clients);
+
+        connected
     }
 
-    pub fn accept_new_client(&mut self) -> Option<()> {
-        match self.listener.accept() {
-            Ok((stream, address)) => {
-                println!("received new connection from address: {}", address);
-
-                stream.set_nonblocking(true).expect("Failed to set new client as non blocking");
-
-                let mut websocket_stream = loop {
-                    match tungstenite::accept(stream.try_clone().expect("failed to clone stream")) {
-                        Ok(websocket_stream) => break websocket_stream,
-                        Err(error) => {
-                            match error {
-                                tungstenite::HandshakeError::Interrupted(_) => continue, // try again if the handshake isnt done yet
-                                tungstenite::HandshakeError::Failure(error) => panic!("handshake failed with new client: {}", error),
-                            }
-                        },
-                    };
-                };
-                
-
-                // send client current state
-                let state_bytes = bitcode::serialize(&self.state).expect("Failed to serialize current game state");
-
-                let compressed_state_bytes = compress_prepend_size(&state_bytes);
-
-                // keep attempting to send initial state to client
-                loop {
-                    match websocket_stream.send(
-                        Message::Binary(compressed_state_bytes.clone())
-                    ) {
-                        Ok(_) => break,
-                        Err(error) => {
-                            match error {
-                                tungstenite::Error::Io(io_error) => {
-                                    match io_error.kind() {
-                                        std::io::ErrorKind::WouldBlock => {
-                                            continue; // try again if the socket blocked
-                                        },
-                                        _ => panic!("Something went wrong trying to send initial state: {}", io_error)
-                                    }
-                                },
-                                _ => panic!("Something went wrong trying to send initial state: {}", error)
-                            }
-                        },
+    fn accept_connection(&mut self) {
+        let (stream, address) = match self.listener.accept() {
+            Ok(connection) => connection,
+            Err(error) => {
+                match error.kind() {
+                    std::io::ErrorKind::WouldBlock => return, // no new clients
+
+                    _ => {
+                        log_error!("Something went wrong trying to accept a new client");
+                        return
                     }
                 }
+            },
+        };
+
+        log_info!("received new connection from address: {}", address);
+
+        if let Err(error) = stream.set_nonblocking(true) {
+            log_warn!("failed to set new client as non blocking: {}", error);
+
+            return
+        }
+
+        let stream = match self.wrap_stream(stream) {
+            Some(stream) => stream,
+            None => return,
+        };
+
+        let handshake = match tungstenite::accept(stream) {
+            Ok(websocket_stream) => Handshake::Hello(Box::new(websocket_stream)),
+            Err(tungstenite::HandshakeError::Interrupted(mid_handshake)) => Handshake::Websocket(Box::new(mid_handshake)),
+            Err(tungstenite::HandshakeError::Failure(error)) => {
+                log_warn!("handshake failed with new client: {}", error);
+
+                return
+            },
+        };
+
+        self.pending_clients.push(
+            PendingClient { 
+                address, 
+                handshake, 
+                deadline: current_unix_millis() + 5000 
+            }
+        );
+    }
+
+    /// Continue a client's handshake as far as it can go without waiting
+    fn poll_handshake(&mut self, mut pending_client: PendingClient) -> HandshakeProgress {
+        let address = pending_client.address;
+
+        if current_unix_millis() > pending_client.deadline {
+            log_warn!("client at {} took too long to connect, dropping it", address);
+
+            return HandshakeProgress::Failed
+        }
+
+        let mut websocket_stream = match pending_client.handshake {
+            Handshake::Websocket(mid_handshake) => match (*mid_handshake).handshake() {
+                Ok(websocket_stream) => Box::new(websocket_stream),
+                Err(tungstenite::HandshakeError::Interrupted(mid_handshake)) => {
+                    pending_client.handshake = Handshake::Websocket(Box::new(mid_handshake));
+
+                    return HandshakeProgress::Waiting(pending_client)
+                },
+                Err(tungstenite::HandshakeError::Failure(error)) => {
+                    log_warn!("handshake failed with new client: {}", error);
 
-                println!("pushing new client");
+                    return HandshakeProgress::Failed
+                },
+            },
+            Handshake::Hello(websocket_stream) => websocket_stream,
+        };
 
-                self.clients.push(websocket_stream);
+        // the client tells us what it supports before we send anything
+        let client_hello = match websocket_stream.read() {
+            Ok(Message::Binary(frame_bytes)) => match decode_frame(&frame_bytes) {
+                Ok((FrameKind::Hello, payload)) => Hello::decode(&payload).and_then(|client_hello| {
+                    Hello::new(C::TAG, vec![]).check(&client_hello).map(|_| client_hello)
+                }),
+                _ => Err(HandshakeError::BadMagic),
+            },
+            Ok(_) => {
+                pending_client.handshake = Handshake::Hello(websocket_stream);
 
-                return Some(())
+                return HandshakeProgress::Waiting(pending_client)
+            },
+            Err(tungstenite::Error::Io(io_error)) if io_error.kind() == std::io::ErrorKind::WouldBlock => {
+                pending_client.handshake = Handshake::Hello(websocket_stream);
 
+                return HandshakeProgress::Waiting(pending_client) // the hello isnt here yet
             },
             Err(error) => {
-                match error.kind() {
-                    std::io::ErrorKind::WouldBlock => return None, // no new clients
+                log_warn!("client at {} disconnected before sending a hello: {}", address, error);
 
-                    _ => {
-                        println!("Something went wrong trying to accept a new client");
-                        return None
-                    }
-                }
+                return HandshakeProgress::Failed
+            },
+        };
+
+        match client_hello {
+            Ok(client_hello) => HandshakeProgress::Connected(self.core.add_client(*websocket_stream, address, &client_hello)),
+            Err(error) => {
+                log_warn!("refusing client at {}: {}", address, error);
+
+                // still tell the client what we are running so it can report the mismatch. we don't wait around for it to be written
+                let _ = websocket_stream.send(Message::Binary(Hello::new(C::TAG, vec![]).encode()));
+
+                HandshakeProgress::Failed
+            },
+        }
+    }
+
+    #[cfg(feature = "rustls")]
+    fn wrap_stream(&self, stream: std::net::TcpStream) -> Option<ServerStream> {
+        let tls_config = match &self.tls_config {
+            Some(tls_config) => tls_config.clone(),
+            None => return Some(ServerStream::Plain(stream)),
+        };
+
+        match rustls::ServerConnection::new(tls_config) {
+            // the tls handshake happens as part of the websocket handshake
+            Ok(connection) => Some(ServerStream::Tls(Box::new(rustls::StreamOwned::new(connection, stream)))),
+            Err(error) => {
+                log_warn!("failed to start tls session with new client: {}", error);
+                None
             },
         }
     }
-}
\ No newline at end of file
+
+    #[cfg(not(feature = "rustls"))]
+    fn wrap_stream(&self, stream: std::net::TcpStream) -> Option<ServerStream> {
+        Some(ServerStream::Plain(stream))
+    }
+}
@@ -0,0 +1,810 @@
+use std::{marker::PhantomData, net::SocketAddr, time::Duration};
+
+use diff::Diff;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{current_unix_millis, time};
+
+use super::{codec::Codec, compression::{Compression, CompressionKind}, frame::{decode_frame, decode_ping_payload, encode_frame, DiffHeader, FrameKind, SERVER_ORIGIN}, handshake::Hello, inputs::{InputBatch, InputLog}, interest::{InterestArea, InterestManaged}, metrics::{MetricsTracker, SyncMetrics}, migration::MigrationPlan, server::{ClientId, ClientInfo, DiffValidator, DisconnectReason, InterestAreaPicker, ServerEvent, ServerMode}, transport::ClientConnection};
+
+struct CoreClient<T, K: ClientConnection> {
+    info: ClientInfo,
+    connection: K,
+    compression: Compression, // negotiated in the hello
+    inputs: InputLog, // timestamped with our clock
+    sent_state: Option<T> // what this client has been sent, only kept while it has an interest area
+}
+
+/// Everything a sync server does apart from moving bytes around: client bookkeeping, relaying and ticking diffs, messages, inputs and metrics.
+/// SyncServer and AsyncSyncServer deref to this, so its methods are the public API of both.
+/// A server on another transport creates one with new and feeds it with add_client, receive_frame and disconnect
+pub struct ServerCore<T: Serialize + DeserializeOwned + Diff + Clone + PartialEq, C: Codec, K: ClientConnection> {
+    state: T,
+    clients: Vec<CoreClient<T, K>>,
+    next_client_id: u64,
+    events: Vec<ServerEvent>,
+    messages: Vec<(ClientId, Vec<u8>)>,
+    mode: ServerMode,
+    diff_validator: Option<DiffValidator<T>>,
+    interest_area: Option<InterestAreaPicker<T>>,
+    mask_outside: Option<fn(&T, &T, InterestArea) -> T>,
+    last_broadcast_state: T, // what clients have been told about in authoritative mode
+    empty_diff: Vec<u8>, // a serialized diff without any changes
+    last_tick: Option<time::Instant>,
+    metrics: MetricsTracker,
+    last_ping_at: u64, // unix millis
+    compressions: Vec<CompressionKind>, // what we offer clients, best first
+    compression_threshold: usize,
+    heartbeat_timeout: Duration,
+    migration_plan: Option<MigrationPlan>,
+    input_history: u64, // millis
+    next_sequence: u64, // stamped on every diff we send, so they count up per origin on each client
+    codec: PhantomData<C>
+}
+
+impl<T, C, K> ServerCore<T, C, K>
+where
+    T: Serialize + DeserializeOwned + Diff + Clone + PartialEq,
+    <T as Diff>::Repr: DeserializeOwned + Serialize,
+    C: Codec,
+    K: ClientConnection {
+
+    pub fn new(initial_state: T) -> Self {
+        Self {
+            last_broadcast_state: initial_state.clone(),
+            empty_diff: C::serialize(&T::identity().diff(&T::identity())).expect("failed to serialize state diff"),
+            state: initial_state,
+            clients: vec![],
+            next_client_id: 0,
+            events: vec![],
+            messages: vec![],
+            mode: ServerMode::Relay,
+            diff_validator: None,
+            interest_area: None,
+            mask_outside: None,
+            last_tick: None,
+            metrics: MetricsTracker::new(),
+            last_ping_at: 0,
+            compressions: CompressionKind::supported(),
+            compression_threshold: Compression::default().threshold,
+            heartbeat_timeout: Duration::from_secs(10),
+            migration_plan: None,
+            input_history: 1000,
+            next_sequence: 1,
+            codec: PhantomData
+        }
+    }
+
+    /// Network usage across all clients. Round trip times are per client in ClientInfo
+    pub fn metrics(&mut self) -> SyncMetrics {
+        self.metrics.metrics()
+    }
+
+    /// Which compressions to offer new clients, best first. Each client gets the first one it also supports
+    pub fn set_compression_preference(&mut self, compressions: Vec<CompressionKind>) {
+        self.compressions = compressions;
+    }
+
+    /// Payloads smaller than this many bytes are sent uncompressed. Applies to clients that connect afterwards
+    pub fn set_compression_threshold(&mut self, threshold: usize) {
+        self.compression_threshold = threshold;
+    }
+
+    /// Disconnect clients we haven't heard anything from for this long. Clients answer our pings every second, so anything past a few seconds means they are gone
+    pub fn set_heartbeat_timeout(&mut self, heartbeat_timeout: Duration) {
+        self.heartbeat_timeout = heartbeat_timeout;
+    }
+
+    /// Tell every client who takes over if this server goes away. The successor's status becomes Promoted and everyone else reconnects to `url`.
+    /// Pick a new successor if the current one disconnects
+    pub fn set_migration_plan(&mut self, migration_plan: Option<MigrationPlan>) {
+        let migration_bytes = C::serialize(&migration_plan).expect("failed to serialize migration plan");
+
+        self.migration_plan = migration_plan;
+
+        self.broadcast_frame(FrameKind::Migration, &migration_bytes);
+    }
+
+    /// How many millis of client inputs to keep for inputs_between. Applies to clients that connect afterwards
+    pub fn set_input_history(&mut self, input_history: u64) {
+        self.input_history = input_history;
+    }
+
+    /// Inputs a client recorded with SyncClient::record_input, for rewinding to what the client saw when it made them.
+    /// Times are unix millis on our clock, estimated from the client's round trip time
+    pub fn inputs_between<I: DeserializeOwned>(&self, client_id: ClientId, start: u64, end: u64) -> Vec<(u64, I)> {
+        match self.clients.iter().find(|client| client.info.id == client_id) {
+            Some(client) => client.inputs.between::<I, C>(start, end),
+            None => vec![],
+        }
+    }
+
+    pub fn migration_plan(&self) -> Option<&MigrationPlan> {
+        self.migration_plan.as_ref()
+    }
+
+    pub fn set_mode(&mut self, mode: ServerMode) {
+        self.mode = mode;
+    }
+
+    pub fn mode(&self) -> ServerMode {
+        self.mode
+    }
+
+    /// Only apply client diffs the validator returns true for. Rejected diffs are dropped and not relayed
+    pub fn set_diff_validator(&mut self, validator: impl FnMut(ClientId, &T, &<T as Diff>::Repr) -> bool + 'static) {
+        self.diff_validator = Some(Box::new(validator));
+    }
+
+    pub fn state(&self) -> &T {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut T {
+        &mut self.state
+    }
+
+    pub fn clients(&self) -> impl Iterator<Item = &ClientInfo> {
+        self.clients.iter().map(|client| &client.info)
+    }
+
+    pub fn client(&self, client_id: ClientId) -> Option<&ClientInfo> {
+        self.clients().find(|client| client.id == client_id)
+    }
+
+    /// Take all connect/disconnect events that happened since the last call
+    pub fn drain_events(&mut self) -> Vec<ServerEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Take all messages clients sent since the last call.
+    /// Messages are stored untyped, so a game should use a single message enum for everything it sends
+    pub fn drain_messages<M: DeserializeOwned>(&mut self) -> Vec<(ClientId, M)> {
+        std::mem::take(&mut self.messages).into_iter().filter_map(|(client_id, message_bytes)| {
+            match C::deserialize(&message_bytes) {
+                Ok(message) => Some((client_id, message)),
+                Err(error) => {
+                    log_warn!("failed to deserialize message from client {}: {}", client_id, error);
+
+                    None
+                },
+            }
+        }).collect()
+    }
+
+    /// Send a message to a single client
+    pub fn send_message<M: Serialize>(&mut self, client_id: ClientId, message: &M) {
+        let message_bytes = C::serialize(message).expect("failed to serialize message");
+
+        let client_index = match self.client_index(client_id) {
+            Some(client_index) => client_index,
+            None => {
+                log_warn!("tried to send message to client {} which is not connected", client_id);
+                return;
+            },
+        };
+
+        let frame = encode_frame(FrameKind::Message, &message_bytes, self.clients[client_index].compression);
+
+        self.send_frame(client_index, frame);
+    }
+
+    /// Send a message to every connected client
+    pub fn broadcast_message<M: Serialize>(&mut self, message: &M) {
+        let message_bytes = C::serialize(message).expect("failed to serialize message");
+
+        self.broadcast_frame(FrameKind::Message, &message_bytes);
+    }
+
+    /// In authoritative mode, run `update` on the state once the tick interval has passed and send the resulting diff to every client.
+    /// Returns true if a tick happened
+    pub fn tick(&mut self, update: impl FnOnce(&mut T, Duration)) -> bool {
+
+        let tick_rate = match self.mode {
+            ServerMode::Authoritative { tick_rate } => tick_rate,
+            ServerMode::Relay => return false,
+        };
+
+        let now = time::now();
+
+        let dt = match self.last_tick {
+            Some(last_tick) => now.duration_since(last_tick),
+            None => Duration::from_secs_f32(1. / tick_rate),
+        };
+
+        if dt.as_secs_f32() < 1. / tick_rate {
+            return false
+        }
+
+        self.last_tick = Some(now);
+
+        update(&mut self.state, dt);
+
+        if self.last_broadcast_state == self.state {
+            return true
+        }
+
+        let state_diff = self.last_broadcast_state.diff(&self.state);
+
+        // clients have what we had plus this diff. anything the diff held back stays unsent and keeps adding up
+        self.last_broadcast_state.apply(&state_diff);
+
+        self.broadcast_diff(SERVER_ORIGIN, state_diff, None);
+
+        true
+    }
+
+    /// Start tracking a client that finished the handshake. Queues our hello, the full state and the migration plan for it
+    pub fn add_client(&mut self, mut connection: K, address: SocketAddr, client_hello: &Hello) -> ClientId {
+        let compression = Compression::new(
+            CompressionKind::negotiate(&self.compressions, &client_hello.compressions),
+            self.compression_threshold
+        );
+
+        let client_id = ClientId(self.next_client_id);
+
+        self.next_client_id += 1;
+
+        let mut frames = vec![Hello::new(C::TAG, vec![compression.kind]).with_client_id(client_id.0).encode()];
+
+        // send client current state
+        let state_bytes = C::serialize(&self.state).expect("Failed to serialize current game state");
+
+        frames.push(encode_frame(FrameKind::State, &state_bytes, compression));
+
+        if let Some(migration_plan) = &self.migration_plan {
+            let migration_bytes = C::serialize(&Some(migration_plan)).expect("failed to serialize migration plan");
+
+            frames.push(encode_frame(FrameKind::Migration, &migration_bytes, compression));
+        }
+
+        for frame in frames {
+            self.metrics.record_sent(frame.len());
+
+            // a client that is gone already shows up as a connect followed by a disconnect, once the backend notices
+            connection.send(frame);
+        }
+
+        log_info!("pushing new client {}", client_id);
+
+        let now = current_unix_millis();
+
+        let client_info = ClientInfo {
+            id: client_id,
+            address,
+            connected_at: now,
+            last_message_at: now,
+            round_trip_time: None,
+            last_sequence: 0
+        };
+
+        self.events.push(ServerEvent::ClientConnected(client_info.clone()));
+
+        self.clients.push(
+            CoreClient {
+                info: client_info,
+                connection,
+                compression,
+                inputs: InputLog::new(self.input_history),
+                sent_state: None
+            }
+        );
+
+        client_id
+    }
+
+    pub fn client_ids(&self) -> Vec<ClientId> {
+        self.clients.iter().map(|client| client.info.id).collect()
+    }
+
+    /// None once the client has been dropped
+    pub fn connection_mut(&mut self, client_id: ClientId) -> Option<&mut K> {
+        self.clients.iter_mut()
+            .find(|client| client.info.id == client_id)
+            .map(|client| &mut client.connection)
+    }
+
+    /// Stop tracking a client, e.g. because its socket closed
+    pub fn disconnect(&mut self, client_id: ClientId, reason: DisconnectReason) {
+        let client_index = match self.client_index(client_id) {
+            Some(client_index) => client_index,
+            None => return, // already removed
+        };
+
+        let client = self.clients.remove(client_index);
+
+        log_info!("client {} disconnected: {:?}", client_id, reason);
+
+        self.events.push(ServerEvent::ClientDisconnected(client.info, reason));
+    }
+
+    /// Ping every client once a second to measure latency and drop the ones we haven't heard from in a while. Call before reading new frames
+    pub fn heartbeat(&mut self) {
+        let now = current_unix_millis();
+
+        if now.saturating_sub(self.last_ping_at) >= 1000 {
+            self.last_ping_at = now;
+
+            self.broadcast_frame(FrameKind::Ping, &now.to_le_bytes());
+        }
+
+        let heartbeat_timeout = self.heartbeat_timeout.as_millis() as u64;
+
+        let timed_out: Vec<ClientId> = self.clients.iter()
+            .filter(|client| now.saturating_sub(client.info.last_message_at) > heartbeat_timeout)
+            .map(|client| client.info.id)
+            .collect();
+
+        for client_id in timed_out {
+            self.disconnect(client_id, DisconnectReason::TimedOut);
+        }
+    }
+
+    /// Handle a frame a client sent us. Clients that send something we can't decode are disconnected
+    pub fn receive_frame(&mut self, client_id: ClientId, frame_bytes: Vec<u8>) {
+        let client_index = match self.client_index(client_id) {
+            Some(client_index) => client_index,
+            None => return, // already removed
+        };
+
+        self.clients[client_index].info.last_message_at = current_unix_millis();
+
+        self.metrics.record_received(frame_bytes.len());
+
+        let (frame_kind, payload) = match decode_frame(&frame_bytes) {
+            Ok(frame) => frame,
+            Err(error) => {
+                log_warn!("client {} sent a frame we can't decode, disconnecting it: {}", client_id, error);
+
+                self.disconnect(client_id, DisconnectReason::ProtocolError);
+
+                return;
+            },
+        };
+
+        let (header, state_diff_bytes) = match frame_kind {
+            FrameKind::StateDiff => match DiffHeader::decode(&payload) {
+                Some((header, state_diff_bytes)) => (header, state_diff_bytes.to_vec()),
+                None => {
+                    log_warn!("received malformed diff from client {}", client_id);
+
+                    return;
+                },
+            },
+            FrameKind::Message => {
+                self.messages.push((client_id, payload));
+
+                return;
+            },
+            FrameKind::Ping => {
+                let pong = encode_frame(FrameKind::Pong, &payload, self.clients[client_index].compression);
+
+                self.send_frame(client_index, pong);
+
+                return;
+            },
+            FrameKind::Pong => {
+                if let Some(sent_at) = decode_ping_payload(&payload) {
+                    let round_trip_time = Duration::from_millis(current_unix_millis().saturating_sub(sent_at));
+
+                    self.clients[client_index].info.round_trip_time = Some(round_trip_time);
+
+                    self.metrics.record_round_trip(round_trip_time);
+                }
+
+                return;
+            },
+            FrameKind::State => {
+                log_warn!("client {} tried to send a full state, ignoring it", client_id);
+
+                return;
+            },
+            FrameKind::Hello => return, // already handled when the client connected
+            FrameKind::Migration => return, // only the server sends these
+            FrameKind::Inputs => {
+                let input_batch: InputBatch = match C::deserialize(&payload) {
+                    Ok(input_batch) => input_batch,
+                    Err(error) => {
+                        log_warn!("failed to deserialize inputs from client {}: {}", client_id, error);
+
+                        return;
+                    },
+                };
+
+                let client = &mut self.clients[client_index];
+
+                let round_trip_time = client.info.round_trip_time.unwrap_or_default().as_millis() as u64;
+
+                for (time, input_bytes) in input_batch.to_local_time(current_unix_millis(), round_trip_time) {
+                    client.inputs.push(time, input_bytes);
+                }
+
+                return;
+            },
+        };
+
+        let client = &mut self.clients[client_index];
+
+        // already applied, e.g. sent again after a reconnect
+        if header.sequence <= client.info.last_sequence {
+            log_debug!("ignoring stale diff {} from client {}", header.sequence, client_id);
+
+            return;
+        }
+
+        client.info.last_sequence = header.sequence;
+
+        let state_diff: <T as Diff>::Repr = match C::deserialize(&state_diff_bytes) {
+            Ok(state_diff) => state_diff,
+            Err(error) => {
+                log_warn!("failed to deserialize diff from client {}: {}", client_id, error);
+
+                return;
+            },
+        };
+
+        if let Some(diff_validator) = &mut self.diff_validator {
+            if !diff_validator(client_id, &self.state, &state_diff) {
+                log_warn!("rejected diff from client {}", client_id);
+
+                return;
+            }
+        }
+
+        // apply it to our own game state
+        self.state.apply(&state_diff);
+
+        // in authoritative mode the diff reaches other clients through the next tick instead
+        if let ServerMode::Authoritative { .. } = self.mode {
+            return;
+        }
+
+        // relay this update to everyone but the sender
+        self.broadcast_diff(client_id.0, state_diff, Some(client_id));
+    }
+
+    fn client_index(&self, client_id: ClientId) -> Option<usize> {
+        self.clients.iter().position(|client| client.info.id == client_id)
+    }
+
+    /// Send a frame to one client, dropping it if it is gone
+    fn send_frame(&mut self, client_index: usize, frame: Vec<u8>) {
+        self.metrics.record_sent(frame.len());
+
+        if !self.clients[client_index].connection.send(frame) {
+            let client = self.clients.remove(client_index);
+
+            log_warn!("failed to send to client {}, dropping it", client.info.id);
+
+            self.events.push(ServerEvent::ClientDisconnected(client.info, DisconnectReason::ConnectionReset));
+        }
+    }
+
+    /// Send a payload to every client, compressed however each of them negotiated. Clients we can't send to are dropped
+    fn broadcast_frame(&mut self, kind: FrameKind, payload: &[u8]) {
+        let mut disconnected = vec![];
+
+        self.clients.retain_mut(|client| {
+            let frame = encode_frame(kind, payload, client.compression);
+
+            self.metrics.record_sent(frame.len());
+
+            match client.connection.send(frame) {
+                true => true,
+                false => {
+                    disconnected.push(client.info.clone());
+
+                    false
+                },
+            }
+        });
+
+        for client_info in disconnected {
+            log_warn!("failed to send to client {}, dropping it", client_info.id);
+
+            self.events.push(ServerEvent::ClientDisconnected(client_info, DisconnectReason::ConnectionReset));
+        }
+    }
+
+    /// Send a state diff to every client except one. Clients with an interest area get their own diff, made against the state they were sent last
+    fn broadcast_diff(&mut self, origin: u64, state_diff: <T as Diff>::Repr, except: Option<ClientId>) {
+
+        // the sender already has these changes
+        if let Some(sent_state) = self.clients.iter_mut()
+            .find(|client| Some(client.info.id) == except)
+            .and_then(|client| client.sent_state.as_mut()) {
+
+            sent_state.apply(&state_diff);
+        }
+
+        let state_diff_bytes = C::serialize(&state_diff).expect("failed to serialize state diff");
+
+        let mut disconnected = vec![];
+
+        self.clients.retain_mut(|client| {
+            if Some(client.info.id) == except {
+                return true;
+            }
+
+            let area = match (&mut self.interest_area, self.mask_outside) {
+                (Some(interest_area), Some(_)) => interest_area(client.info.id, &self.state),
+                _ => None,
+            };
+
+            let client_state_diff_bytes = match (area, self.mask_outside, &mut client.sent_state) {
+                (Some(area), Some(mask_outside), Some(sent_state)) => {
+                    let masked_state = mask_outside(&self.state, sent_state, area);
+
+                    // nothing this client can see changed
+                    if masked_state == *sent_state {
+                        return true;
+                    }
+
+                    let client_state_diff = sent_state.diff(&masked_state);
+
+                    let client_state_diff_bytes = C::serialize(&client_state_diff).expect("failed to serialize state diff");
+
+                    // what changed is held back by the diff itself, e.g. changes under Space's diff thresholds
+                    if client_state_diff_bytes == self.empty_diff {
+                        return true;
+                    }
+
+                    sent_state.apply(&client_state_diff);
+
+                    Some(client_state_diff_bytes)
+                },
+                // this diff brings the client up to date, filtering starts from here
+                (Some(_), Some(_), None) => {
+                    client.sent_state = Some(self.state.clone());
+
+                    None
+                },
+                // the area was dropped. catch the client up on what was held back, the shared diffs start from what everyone else has
+                (_, _, Some(sent_state)) => {
+                    let shared_state = match self.mode {
+                        ServerMode::Authoritative { .. } => &self.last_broadcast_state,
+                        ServerMode::Relay => &self.state,
+                    };
+
+                    let client_state_diff_bytes = C::serialize(&sent_state.diff(shared_state)).expect("failed to serialize state diff");
+
+                    client.sent_state = None;
+
+                    Some(client_state_diff_bytes)
+                },
+                _ => None,
+            };
+
+            let state_diff_bytes = client_state_diff_bytes.as_ref().unwrap_or(&state_diff_bytes);
+
+            if *state_diff_bytes == self.empty_diff {
+                return true;
+            }
+
+            let header = DiffHeader { origin, sequence: self.next_sequence };
+
+            self.next_sequence += 1;
+
+            let client_frame = encode_frame(FrameKind::StateDiff, &header.encode(state_diff_bytes), client.compression);
+
+            self.metrics.record_diff(state_diff_bytes.len(), client_frame.len());
+
+            self.metrics.record_sent(client_frame.len());
+
+            if !client.connection.send(client_frame) {
+                disconnected.push(client.info.clone());
+
+                return false;
+            }
+
+            true
+        });
+
+        for client_info in disconnected {
+            log_warn!("failed to send diff to client {}, dropping it", client_info.id);
+
+            self.events.push(ServerEvent::ClientDisconnected(client_info, DisconnectReason::ConnectionReset));
+        }
+    }
+}
+
+impl<T, C, K> ServerCore<T, C, K>
+where
+    T: Serialize + DeserializeOwned + Diff + Clone + PartialEq + InterestManaged + 'static,
+    <T as Diff>::Repr: DeserializeOwned + Serialize,
+    C: Codec,
+    K: ClientConnection {
+
+    /// Only send each client what changes inside the area returned for it. Returning None sends that client everything.
+    /// Keeps a copy of the state per client with an area, to diff against what it was sent
+    pub fn set_interest_area(&mut self, interest_area: impl FnMut(ClientId, &T) -> Option<InterestArea> + 'static) {
+        self.interest_area = Some(Box::new(interest_area));
+
+        self.mask_outside = Some(T::mask_outside);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, thread, time::Duration};
+
+    use diff::Diff;
+    use macroquad::math::Vec2;
+    use nalgebra::vector;
+    use rapier2d::dynamics::{RigidBodyBuilder, RigidBodyHandle};
+
+    use crate::{space::Space, sync::{codec::{BitcodeCodec, Codec}, compression::{Compression, CompressionKind}, frame::{decode_frame, encode_frame, DiffHeader, FrameKind}, handshake::Hello, interest::InterestArea, migration::MigrationPlan, server::{ClientId, DisconnectReason, ServerEvent}, transport::ClientConnection}};
+
+    use super::ServerCore;
+
+    impl ClientConnection for Vec<Vec<u8>> {
+        fn send(&mut self, frame: Vec<u8>) -> bool {
+            self.push(frame);
+
+            true
+        }
+    }
+
+    type TestCore = ServerCore<Space, BitcodeCodec, Vec<Vec<u8>>>;
+
+    fn add_client(core: &mut TestCore) -> ClientId {
+        let client_id = core.add_client(vec![], "127.0.0.1:0".parse().unwrap(), &Hello::new(BitcodeCodec::TAG, CompressionKind::supported()));
+
+        // the hello isn't a regular frame, drop it so the rest can be decoded
+        core.connection_mut(client_id).unwrap().remove(0);
+
+        client_id
+    }
+
+    /// What a client ends up with after applying every frame it was sent
+    fn client_state(core: &mut TestCore, client_id: ClientId) -> Space {
+        let mut state = None;
+
+        for frame in core.connection_mut(client_id).unwrap().drain(..) {
+            match decode_frame(&frame).unwrap() {
+                (FrameKind::State, payload) => state = Some(BitcodeCodec::deserialize::<Space>(&payload).unwrap()),
+                (FrameKind::StateDiff, payload) => {
+                    let (_, state_diff_bytes) = DiffHeader::decode(&payload).unwrap();
+
+                    let state_diff = BitcodeCodec::deserialize(state_diff_bytes).unwrap();
+
+                    state.as_mut().unwrap().apply(&state_diff);
+                },
+                _ => {},
+            }
+        }
+
+        state.unwrap()
+    }
+
+    fn sent_kinds(core: &mut TestCore, client_id: ClientId) -> Vec<FrameKind> {
+        core.connection_mut(client_id).unwrap().drain(..)
+            .map(|frame| decode_frame(&frame).unwrap().0)
+            .collect()
+    }
+
+    fn send_update(core: &mut TestCore, client_id: ClientId, sequence: u64, update: impl FnOnce(&mut Space)) {
+        let mut new_state = core.state().clone();
+
+        update(&mut new_state);
+
+        let state_diff_bytes = BitcodeCodec::serialize(&core.state().diff(&new_state)).unwrap();
+
+        let header = DiffHeader { origin: client_id.0, sequence };
+
+        core.receive_frame(client_id, encode_frame(FrameKind::StateDiff, &header.encode(&state_diff_bytes), Compression::default()));
+    }
+
+    #[test]
+    fn client_catches_up_when_its_interest_area_is_dropped() {
+        let mut space = Space::new();
+
+        let rigid_body_handle: RigidBodyHandle = space.rigid_body_set.insert(RigidBodyBuilder::dynamic());
+
+        let mut core = TestCore::new(space);
+
+        let watching = Rc::new(Cell::new(true));
+
+        core.set_interest_area({
+            let watching = watching.clone();
+
+            // far away from the body
+            move |_, _| watching.get().then_some(InterestArea::new(Vec2::new(1000., 1000.), 1.))
+        });
+
+        let observer = add_client(&mut core);
+        let mover = add_client(&mut core);
+
+        send_update(&mut core, mover, 1, |space| space.rigid_body_set.get_mut(rigid_body_handle).unwrap().set_translation(vector![5., 0.], true));
+        send_update(&mut core, mover, 2, |space| space.rigid_body_set.get_mut(rigid_body_handle).unwrap().set_translation(vector![10., 0.], true));
+
+        watching.set(false);
+
+        // the shared diff only has the gravity change, the observer also needs the body it didn't see move
+        send_update(&mut core, mover, 3, |space| space.gravity = vector![0., -1.]);
+
+        let observer_state = client_state(&mut core, observer);
+
+        assert!(observer_state == *core.state());
+    }
+
+    #[test]
+    fn relayed_diffs_skip_their_sender() {
+        let mut core = TestCore::new(Space::new());
+
+        let observer = add_client(&mut core);
+        let mover = add_client(&mut core);
+
+        sent_kinds(&mut core, observer);
+        sent_kinds(&mut core, mover);
+
+        send_update(&mut core, mover, 1, |space| space.gravity = vector![0., -1.]);
+
+        assert_eq!(sent_kinds(&mut core, observer), vec![FrameKind::StateDiff]);
+        assert_eq!(sent_kinds(&mut core, mover), vec![]);
+    }
+
+    #[test]
+    fn heartbeat_pings_clients() {
+        let mut core = TestCore::new(Space::new());
+
+        let client_id = add_client(&mut core);
+
+        sent_kinds(&mut core, client_id);
+
+        core.heartbeat();
+
+        assert_eq!(sent_kinds(&mut core, client_id), vec![FrameKind::Ping]);
+        assert_eq!(core.client_ids(), vec![client_id]);
+    }
+
+    #[test]
+    fn quiet_clients_time_out() {
+        let mut core = TestCore::new(Space::new());
+
+        core.set_heartbeat_timeout(Duration::ZERO);
+
+        let client_id = add_client(&mut core);
+
+        thread::sleep(Duration::from_millis(5));
+
+        core.heartbeat();
+
+        assert!(core.client_ids().is_empty());
+
+        let timed_out = core.drain_events().into_iter()
+            .any(|event| matches!(event, ServerEvent::ClientDisconnected(info, DisconnectReason::TimedOut) if info.id == client_id));
+
+        assert!(timed_out);
+    }
+
+    #[test]
+    fn migration_plans_reach_current_and_new_clients() {
+        let mut core = TestCore::new(Space::new());
+
+        let successor = add_client(&mut core);
+
+        sent_kinds(&mut core, successor);
+
+        let migration_plan = MigrationPlan {
+            successor,
+            listen_address: "127.0.0.1:5557".parse().unwrap(),
+            url: "ws://127.0.0.1:5557".to_string()
+        };
+
+        core.set_migration_plan(Some(migration_plan.clone()));
+
+        assert_eq!(sent_kinds(&mut core, successor), vec![FrameKind::Migration]);
+
+        let late_client = add_client(&mut core);
+
+        let migration_frame = core.connection_mut(late_client).unwrap().drain(..)
+            .map(|frame| decode_frame(&frame).unwrap())
+            .find(|(kind, _)| *kind == FrameKind::Migration)
+            .unwrap();
+
+        let received_plan: Option<MigrationPlan> = BitcodeCodec::deserialize(&migration_frame.1).unwrap();
+
+        assert_eq!(received_plan, Some(migration_plan));
+    }
+}
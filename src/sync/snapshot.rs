@@ -0,0 +1,177 @@
+use std::collections::VecDeque;
+
+use diff::Diff;
+
+use crate::space::Space;
+
+/// State that can be blended between two snapshots
+pub trait Interpolate {
+    /// `t` is 0 at self and 1 at other
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for Space {
+
+    /// Blends the positions of bodies that exist in both. Everything else is taken from self
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        let mut interpolated = self.clone();
+
+        for (handle, rigid_body) in interpolated.rigid_body_set.iter_mut() {
+            let other_rigid_body = match other.rigid_body_set.get(handle) {
+                Some(other_rigid_body) => other_rigid_body,
+                None => continue,
+            };
+
+            let position = rigid_body.position().lerp_slerp(other_rigid_body.position(), t);
+
+            rigid_body.set_position(position, false);
+        }
+
+        interpolated
+    }
+}
+
+/// Holds remote diffs back by an interpolation delay so they can be played out smoothly instead of as they arrive.
+/// Feed it with SyncClient::set_record_diffs and drain_received_diffs
+pub struct SnapshotBuffer<T: Diff> {
+    base: T, // the state with every diff up to base_time applied
+    base_time: u64, // unix millis
+    diffs: VecDeque<(u64, <T as Diff>::Repr)>,
+    pub interpolation_delay: u64 // millis
+}
+
+impl<T: Diff + Clone> SnapshotBuffer<T> {
+    pub fn new(state: T, interpolation_delay: u64) -> Self {
+        Self {
+            base: state,
+            base_time: 0,
+            diffs: VecDeque::new(),
+            interpolation_delay,
+        }
+    }
+
+    /// Diffs must be pushed in the order they were received
+    pub fn push(&mut self, timestamp: u64, diff: <T as Diff>::Repr) {
+        self.diffs.push_back((timestamp, diff));
+    }
+
+    /// Throw away buffered diffs and start over from a new state, for example after the server resynced us
+    pub fn reset(&mut self, state: T) {
+        self.base = state;
+        self.diffs.clear();
+    }
+
+    /// How many diffs are waiting for their time to come
+    pub fn buffered(&self) -> usize {
+        self.diffs.len()
+    }
+
+    fn advance(&mut self, render_time: u64) -> u64 {
+        let target_time = render_time.saturating_sub(self.interpolation_delay);
+
+        // render time only moves forward, so anything before the target can be folded into the base
+        while let Some((timestamp, _)) = self.diffs.front() {
+            if *timestamp > target_time {
+                break;
+            }
+
+            let (timestamp, diff) = self.diffs.pop_front().unwrap();
+
+            self.base.apply(&diff);
+            self.base_time = timestamp;
+        }
+
+        target_time
+    }
+
+    /// The remote state as it was `interpolation_delay` millis before `render_time`
+    pub fn state_at(&mut self, render_time: u64) -> &T {
+        self.advance(render_time);
+
+        &self.base
+    }
+}
+
+impl<T: Diff + Clone + Interpolate> SnapshotBuffer<T> {
+
+    /// Like state_at, but blended towards the next buffered diff instead of snapping from one diff to the next
+    pub fn interpolated_state_at(&mut self, render_time: u64) -> T {
+        let target_time = self.advance(render_time);
+
+        let (next_time, next_diff) = match self.diffs.front() {
+            Some(next) => next,
+            None => return self.base.clone(), // ran out of buffer, hold the latest state
+        };
+
+        let mut next = self.base.clone();
+
+        next.apply(next_diff);
+
+        let t = (target_time.saturating_sub(self.base_time)) as f32 / (next_time.saturating_sub(self.base_time)).max(1) as f32;
+
+        self.base.interpolate(&next, t.clamp(0., 1.))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diff::Diff;
+    use nalgebra::vector;
+    use rapier2d::dynamics::{RigidBodyBuilder, RigidBodyHandle};
+
+    use crate::space::Space;
+
+    use super::SnapshotBuffer;
+
+    /// A buffer whose body moves 10 units to the right at 100 and again at 200
+    fn moving_body() -> (SnapshotBuffer<Space>, RigidBodyHandle) {
+        let mut space = Space::new();
+
+        let rigid_body_handle = space.rigid_body_set.insert(RigidBodyBuilder::dynamic());
+
+        let mut snapshot_buffer = SnapshotBuffer::new(space.clone(), 50);
+
+        for timestamp in [100, 200] {
+            let mut moved = space.clone();
+
+            let x = moved.rigid_body_set.get(rigid_body_handle).unwrap().translation().x;
+
+            moved.rigid_body_set.get_mut(rigid_body_handle).unwrap().set_translation(vector![x + 10., 0.], false);
+
+            snapshot_buffer.push(timestamp, space.diff(&moved));
+
+            space = moved;
+        }
+
+        (snapshot_buffer, rigid_body_handle)
+    }
+
+    #[test]
+    fn diffs_are_held_back_by_the_delay() {
+        let (mut snapshot_buffer, rigid_body_handle) = moving_body();
+
+        assert_eq!(snapshot_buffer.state_at(120).rigid_body_set.get(rigid_body_handle).unwrap().translation().x, 0.);
+
+        assert_eq!(snapshot_buffer.state_at(170).rigid_body_set.get(rigid_body_handle).unwrap().translation().x, 10.);
+        assert_eq!(snapshot_buffer.buffered(), 1);
+    }
+
+    #[test]
+    fn bodies_are_blended_between_diffs() {
+        let (mut snapshot_buffer, rigid_body_handle) = moving_body();
+
+        // halfway between the diffs at 100 and 200
+        let interpolated = snapshot_buffer.interpolated_state_at(200);
+
+        assert!((interpolated.rigid_body_set.get(rigid_body_handle).unwrap().translation().x - 15.).abs() < 0.001);
+    }
+
+    #[test]
+    fn running_out_of_diffs_holds_the_latest_state() {
+        let (mut snapshot_buffer, rigid_body_handle) = moving_body();
+
+        let interpolated = snapshot_buffer.interpolated_state_at(1000);
+
+        assert_eq!(interpolated.rigid_body_set.get(rigid_body_handle).unwrap().translation().x, 20.);
+    }
+}
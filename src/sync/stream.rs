@@ -0,0 +1,114 @@
+use std::{io::{Read, Write}, net::TcpStream};
+
+#[cfg(feature = "rustls")]
+use std::sync::Arc;
+
+/// The socket under a client's websocket, with or without TLS
+pub enum ServerStream {
+    Plain(TcpStream),
+    #[cfg(feature = "rustls")]
+    Tls(Box<rustls::StreamOwned<rustls::ServerConnection, TcpStream>>)
+}
+
+impl Read for ServerStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            ServerStream::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "rustls")]
+            ServerStream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for ServerStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            ServerStream::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "rustls")]
+            ServerStream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            ServerStream::Plain(stream) => stream.flush(),
+            #[cfg(feature = "rustls")]
+            ServerStream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// The socket under a client's websocket on AsyncSyncServer, with or without TLS
+#[cfg(feature = "tokio")]
+pub enum AsyncServerStream {
+    Plain(tokio::net::TcpStream),
+    #[cfg(feature = "rustls")]
+    Tls(Box<tokio_rustls::server::TlsStream<tokio::net::TcpStream>>)
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for AsyncServerStream {
+    fn poll_read(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>, buf: &mut tokio::io::ReadBuf<'_>) -> std::task::Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            AsyncServerStream::Plain(stream) => std::pin::Pin::new(stream).poll_read(cx, buf),
+            #[cfg(feature = "rustls")]
+            AsyncServerStream::Tls(stream) => std::pin::Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for AsyncServerStream {
+    fn poll_write(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>, buf: &[u8]) -> std::task::Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            AsyncServerStream::Plain(stream) => std::pin::Pin::new(stream).poll_write(cx, buf),
+            #[cfg(feature = "rustls")]
+            AsyncServerStream::Tls(stream) => std::pin::Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            AsyncServerStream::Plain(stream) => std::pin::Pin::new(stream).poll_flush(cx),
+            #[cfg(feature = "rustls")]
+            AsyncServerStream::Tls(stream) => std::pin::Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            AsyncServerStream::Plain(stream) => std::pin::Pin::new(stream).poll_shutdown(cx),
+            #[cfg(feature = "rustls")]
+            AsyncServerStream::Tls(stream) => std::pin::Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Load a certificate chain and private key from PEM files, for SyncServer::set_tls_config and AsyncSyncServer::set_tls_config
+#[cfg(feature = "rustls")]
+pub fn load_tls_config(certificate_path: &str, private_key_path: &str) -> Result<Arc<rustls::ServerConfig>, String> {
+    let mut certificate_reader = std::io::BufReader::new(
+        std::fs::File::open(certificate_path).map_err(|error| format!("failed to open certificate {}: {}", certificate_path, error))?
+    );
+
+    let certificates = rustls_pemfile::certs(&mut certificate_reader)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| format!("failed to read certificate {}: {}", certificate_path, error))?;
+
+    let mut private_key_reader = std::io::BufReader::new(
+        std::fs::File::open(private_key_path).map_err(|error| format!("failed to open private key {}: {}", private_key_path, error))?
+    );
+
+    let private_key = match rustls_pemfile::private_key(&mut private_key_reader) {
+        Ok(Some(private_key)) => private_key,
+        Ok(None) => return Err(format!("no private key found in {}", private_key_path)),
+        Err(error) => return Err(format!("failed to read private key {}: {}", private_key_path, error)),
+    };
+
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certificates, private_key)
+        .map_err(|error| format!("invalid certificate or private key: {}", error))?;
+
+    Ok(Arc::new(config))
+}
@@ -0,0 +1,54 @@
+use ewebsock::{WsReceiver, WsSender};
+
+/// Something the websocket to the server received
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TransportEvent {
+    Opened,
+    Frame(Vec<u8>),
+    Error(String),
+    Closed
+}
+
+/// A client's end of the server connection. Each server backend provides its own
+pub trait ClientConnection {
+    /// Queue a frame for the client without blocking. Returns false if the client is gone
+    fn send(&mut self, frame: Vec<u8>) -> bool;
+}
+
+/// SyncClient's connection to the server. ewebsock works natively and on wasm
+pub(crate) struct WebSocketTransport {
+    sender: WsSender,
+    receiver: WsReceiver
+}
+
+impl WebSocketTransport {
+    /// Start connecting without blocking. Opened is reported once it is ready to send
+    pub fn connect(url: &str) -> Result<Self, String> {
+        let (sender, receiver) = ewebsock::connect(url, ewebsock::Options::default())?;
+
+        Ok(Self { sender, receiver })
+    }
+
+    pub fn send(&mut self, frame: Vec<u8>) {
+        self.sender.send(ewebsock::WsMessage::Binary(frame));
+    }
+
+    /// The next thing that happened on the connection, or None if nothing has
+    pub fn try_recv(&mut self) -> Option<TransportEvent> {
+        loop {
+            let event = match self.receiver.try_recv()? {
+                ewebsock::WsEvent::Opened => TransportEvent::Opened,
+                ewebsock::WsEvent::Message(ewebsock::WsMessage::Binary(bytes)) => TransportEvent::Frame(bytes),
+                ewebsock::WsEvent::Message(_) => continue, // the server only sends binary frames
+                ewebsock::WsEvent::Error(error) => TransportEvent::Error(error),
+                ewebsock::WsEvent::Closed => TransportEvent::Closed,
+            };
+
+            return Some(event)
+        }
+    }
+
+    pub fn close(&mut self) {
+        self.sender.close();
+    }
+}
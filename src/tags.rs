@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use diff::Diff;
+use rapier2d::prelude::RigidBodyHandle;
+use serde::{Deserialize, Serialize};
+
+use crate::{level::Level, space::Space};
+
+/// A rigid body handle stored as its raw parts, since handles themselves can't be diffed
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Copy, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct TaggedBody {
+    index: u32,
+    generation: u32
+}
+
+impl From<RigidBodyHandle> for TaggedBody {
+    fn from(rigid_body_handle: RigidBodyHandle) -> Self {
+        let (index, generation) = rigid_body_handle.into_raw_parts();
+
+        Self {
+            index,
+            generation,
+        }
+    }
+}
+
+impl From<TaggedBody> for RigidBodyHandle {
+    fn from(tagged_body: TaggedBody) -> Self {
+        RigidBodyHandle::from_raw_parts(tagged_body.index, tagged_body.generation)
+    }
+}
+
+/// Names and tags for rigid bodies, so game code can ask for "player_spawn" or everything tagged "spawn_point"
+/// instead of hard coding handles. Diffable, so it syncs with the rest of the game state.
+/// A name belongs to one body, a tag can be on any number of them
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Default)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct TagRegistry {
+    names: HashMap<String, TaggedBody>,
+    tags: HashMap<String, Vec<TaggedBody>>
+}
+
+impl TagRegistry {
+
+    pub fn new() -> Self {
+        Self {
+            names: HashMap::new(),
+            tags: HashMap::new(),
+        }
+    }
+
+    /// Names and tags every entity in a level
+    pub fn from_level(level: &Level) -> Self {
+        let mut registry = Self::new();
+
+        for entity in &level.entities {
+            registry.name(&entity.name, entity.rigid_body_handle);
+
+            for tag in &entity.tags {
+                registry.tag(entity.rigid_body_handle, tag);
+            }
+        }
+
+        registry
+    }
+
+    /// Give a body a unique name. Replaces whatever body had the name before
+    pub fn name(&mut self, name: &str, rigid_body_handle: RigidBodyHandle) {
+        self.names.insert(name.to_string(), rigid_body_handle.into());
+    }
+
+    pub fn tag(&mut self, rigid_body_handle: RigidBodyHandle, tag: &str) {
+        let tagged = self.tags.entry(tag.to_string()).or_default();
+
+        let tagged_body = TaggedBody::from(rigid_body_handle);
+
+        if !tagged.contains(&tagged_body) {
+            tagged.push(tagged_body);
+        }
+    }
+
+    pub fn untag(&mut self, rigid_body_handle: RigidBodyHandle, tag: &str) {
+        let tagged_body = TaggedBody::from(rigid_body_handle);
+
+        if let Some(tagged) = self.tags.get_mut(tag) {
+            tagged.retain(|other| *other != tagged_body);
+
+            if tagged.is_empty() {
+                self.tags.remove(tag);
+            }
+        }
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<RigidBodyHandle> {
+        self.names.get(name).map(|tagged_body| (*tagged_body).into())
+    }
+
+    /// Every body with the tag, in the order they were tagged
+    pub fn find_by_tag(&self, tag: &str) -> Vec<RigidBodyHandle> {
+        match self.tags.get(tag) {
+            Some(tagged) => tagged.iter().map(|tagged_body| (*tagged_body).into()).collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn first_by_tag(&self, tag: &str) -> Option<RigidBodyHandle> {
+        self.tags.get(tag)?.first().map(|tagged_body| (*tagged_body).into())
+    }
+
+    pub fn has_tag(&self, rigid_body_handle: RigidBodyHandle, tag: &str) -> bool {
+        self.tags.get(tag).is_some_and(|tagged| tagged.contains(&rigid_body_handle.into()))
+    }
+
+    pub fn tags_of(&self, rigid_body_handle: RigidBodyHandle) -> Vec<&String> {
+        let tagged_body = TaggedBody::from(rigid_body_handle);
+
+        self.tags.iter()
+            .filter(|(_, tagged)| tagged.contains(&tagged_body))
+            .map(|(tag, _)| tag)
+            .collect()
+    }
+
+    pub fn name_of(&self, rigid_body_handle: RigidBodyHandle) -> Option<&String> {
+        let tagged_body = TaggedBody::from(rigid_body_handle);
+
+        self.names.iter()
+            .find(|(_, other)| **other == tagged_body)
+            .map(|(name, _)| name)
+    }
+
+    /// Forget a body's name and tags, for when it is removed
+    pub fn remove(&mut self, rigid_body_handle: RigidBodyHandle) {
+        let tagged_body = TaggedBody::from(rigid_body_handle);
+
+        self.names.retain(|_, other| *other != tagged_body);
+
+        for tagged in self.tags.values_mut() {
+            tagged.retain(|other| *other != tagged_body);
+        }
+
+        self.tags.retain(|_, tagged| !tagged.is_empty());
+    }
+
+    /// Drop names and tags of bodies that are no longer in the space
+    pub fn retain_existing(&mut self, space: &Space) {
+        self.names.retain(|_, tagged_body| space.rigid_body_set.contains((*tagged_body).into()));
+
+        for tagged in self.tags.values_mut() {
+            tagged.retain(|tagged_body| space.rigid_body_set.contains((*tagged_body).into()));
+        }
+
+        self.tags.retain(|_, tagged| !tagged.is_empty());
+    }
+}
@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use gamelibrary::{animation_loader::AnimationLoader, menu::Menu, texture_loader::TextureLoader};
+use gamelibrary::{animation_loader::AnimationLoader, font_loader::FontLoader, menu::Menu, texture_loader::TextureLoader};
 
 use macroquad::prelude::*;
 
@@ -19,9 +19,11 @@ async fn main() {
 
     let mut textures = TextureLoader::new();
 
+    let mut fonts = FontLoader::new();
+
     let mut animation_loader = AnimationLoader::new();
 
-    let animation = animation_loader.get(&"example_animation".to_string());
+    let mut animation = animation_loader.instance(&"example_animation".to_string());
 
     let mut draw_params = DrawTextureParams::default();
 
@@ -32,11 +34,11 @@ async fn main() {
 
     loop {
 
-        menu.draw().await;
+        menu.draw(&mut fonts).await;
 
         menu.update(None);
 
-        for item in menu.clone().get_menu_items() {
+        for item in menu.clone().buttons() {
 
             // i still cannot figure out why this is required but otherwise it gets stuck in an infinite loop in the for loop
             if !item.clicked {
@@ -67,7 +69,7 @@ async fn main() {
             }
         }
 
-        animation.draw(100., 100., &mut textures, draw_params.clone()).await;
+        animation.draw(animation_loader.get(&animation.animation_path), 100., 100., &mut textures, draw_params.clone()).await;
 
         next_frame().await
     }
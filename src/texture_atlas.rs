@@ -0,0 +1,156 @@
+use fxhash::FxHashMap;
+use macroquad::{color::BLANK, math::Rect, texture::{FilterMode, Image, Texture2D}};
+
+/// Empty pixels left around every packed texture so neighbours don't bleed into each other
+const PADDING: u16 = 1;
+
+struct AtlasPage {
+    image: Image,
+    texture: Texture2D,
+    // textures are packed in rows (shelves), left to right
+    shelf_x: u16,
+    shelf_y: u16,
+    shelf_height: u16
+}
+
+impl AtlasPage {
+    fn new(size: u16) -> Self {
+        let image = Image::gen_image_color(size, size, BLANK);
+
+        let texture = Texture2D::from_image(&image);
+
+        texture.set_filter(FilterMode::Nearest);
+
+        Self {
+            image,
+            texture,
+            shelf_x: 0,
+            shelf_y: 0,
+            shelf_height: 0,
+        }
+    }
+
+    /// Where an image of this size would go, None if the page is full
+    fn allocate(&mut self, width: u16, height: u16) -> Option<(u16, u16)> {
+        let size = self.image.width;
+
+        let padded_width = width + PADDING * 2;
+        let padded_height = height + PADDING * 2;
+
+        // start a new shelf when this one runs out of room
+        if self.shelf_x + padded_width > size {
+            self.shelf_y += self.shelf_height;
+            self.shelf_x = 0;
+            self.shelf_height = 0;
+        }
+
+        if self.shelf_x + padded_width > size || self.shelf_y + padded_height > size {
+            return None;
+        }
+
+        let position = (self.shelf_x + PADDING, self.shelf_y + PADDING);
+
+        self.shelf_x += padded_width;
+        self.shelf_height = self.shelf_height.max(padded_height);
+
+        Some(position)
+    }
+
+    fn blit(&mut self, image: &Image, x: u16, y: u16) {
+        let page_width = self.image.width as usize;
+        let row_length = image.width as usize * 4;
+
+        for row in 0..image.height as usize {
+            let source_start = row * row_length;
+            let destination_start = ((y as usize + row) * page_width + x as usize) * 4;
+
+            self.image.bytes[destination_start..destination_start + row_length]
+                .copy_from_slice(&image.bytes[source_start..source_start + row_length]);
+        }
+
+        self.texture.update(&self.image);
+    }
+}
+
+/// Packs small textures into a few large ones so sprites from different files can be drawn without switching textures
+pub struct TextureAtlas {
+    pages: Vec<AtlasPage>,
+    regions: FxHashMap<String, (usize, Rect)>, // page index, where the texture is in the page
+    pub page_size: u16,
+    pub max_texture_size: u16 // textures bigger than this on either side keep their own texture
+}
+
+impl TextureAtlas {
+
+    pub fn new(page_size: u16, max_texture_size: u16) -> Self {
+        Self {
+            pages: vec![],
+            regions: FxHashMap::default(),
+            page_size,
+            max_texture_size: max_texture_size.min(page_size.saturating_sub(PADDING * 2)),
+        }
+    }
+
+    pub fn fits(&self, image: &Image) -> bool {
+        image.width <= self.max_texture_size && image.height <= self.max_texture_size
+    }
+
+    /// Pack an image under `path`. Returns None if the image is too big for the atlas
+    pub fn insert(&mut self, path: &String, image: &Image) -> Option<(&Texture2D, Rect)> {
+
+        if !self.fits(image) {
+            return None;
+        }
+
+        if !self.regions.contains_key(path) {
+            let allocation = self.pages.iter_mut()
+                .enumerate()
+                .find_map(|(index, page)| page.allocate(image.width, image.height).map(|position| (index, position)));
+
+            let (page_index, (x, y)) = match allocation {
+                Some(allocation) => allocation,
+                None => {
+                    let mut page = AtlasPage::new(self.page_size);
+
+                    let position = page.allocate(image.width, image.height).unwrap();
+
+                    self.pages.push(page);
+
+                    (self.pages.len() - 1, position)
+                },
+            };
+
+            self.pages[page_index].blit(image, x, y);
+
+            self.regions.insert(path.clone(), (page_index, Rect::new(x as f32, y as f32, image.width as f32, image.height as f32)));
+        }
+
+        self.get(path)
+    }
+
+    /// The page a packed texture is on and where it is in it
+    pub fn get(&self, path: &String) -> Option<(&Texture2D, Rect)> {
+        let (page_index, region) = self.regions.get(path)?;
+
+        Some((&self.pages[*page_index].texture, *region))
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Drop every page. Packed textures have to be inserted again
+    pub fn clear(&mut self) {
+        self.pages.clear();
+        self.regions.clear();
+    }
+}
+
+/// Turn a source rect relative to the original texture into one relative to the atlas page it was packed into
+pub fn atlas_source(region: Option<Rect>, source: Option<Rect>) -> Option<Rect> {
+    match (region, source) {
+        (Some(region), Some(source)) => Some(Rect::new(region.x + source.x, region.y + source.y, source.w, source.h)),
+        (Some(region), None) => Some(region),
+        (None, source) => source,
+    }
+}
@@ -1,8 +1,35 @@
-use fxhash::FxHashMap;
-use macroquad::texture::{self, load_texture, Texture2D};
+use fxhash::{FxHashMap, FxHashSet};
+use macroquad::{math::Rect, texture::{load_image, load_texture, FilterMode, Image, Texture2D}};
+
+use crate::texture_atlas::TextureAtlas;
+
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+use crate::hot_reload::FileWatcher;
+
+/// Size of the checkerboard drawn in place of textures that failed to load
+const FALLBACK_SIZE: u16 = 16;
+const FALLBACK_CHECKER_SIZE: u16 = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextureStats {
+    pub textures: usize, // cached on their own, not counting atlas pages
+    pub atlas_pages: usize,
+    pub estimated_bytes: usize // rgba8 size of everything on the gpu. Doesn't include mipmaps or driver overhead
+}
 
 pub struct TextureLoader {
-    pub cache: FxHashMap<String, Texture2D>
+    pub cache: FxHashMap<String, Texture2D>,
+    failed_paths: FxHashSet<String>,
+    fallback: Option<Texture2D>,
+    pub atlas: Option<TextureAtlas>,
+    pub default_filter: FilterMode,
+    filters: Vec<(String, FilterMode)>, // glob pattern, filter
+    pub strict: bool, // get_blocking panics on anything that wasn't loaded beforehand
+    pub memory_budget: Option<usize>, // bytes of textures cached on their own. Least recently used ones are unloaded to stay under it. Atlas pages can't be unloaded one by one, so they don't count
+    last_used: FxHashMap<String, u64>,
+    use_counter: u64,
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    pub watcher: FileWatcher
 }
 
 impl Default for TextureLoader {
@@ -14,20 +41,433 @@ impl Default for TextureLoader {
 impl TextureLoader {
 
     pub fn new() -> Self {
-        TextureLoader { cache: FxHashMap::default() }
+        TextureLoader { 
+            cache: FxHashMap::default(),
+            failed_paths: FxHashSet::default(),
+            fallback: None,
+            atlas: None,
+            default_filter: FilterMode::Nearest,
+            filters: vec![],
+            strict: false,
+            memory_budget: None,
+            last_used: FxHashMap::default(),
+            use_counter: 0,
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            watcher: FileWatcher::new(),
+        }
     }
     pub async fn get(&mut self, texture_path: &String) -> &Texture2D {
         // this can probably be optimized with a match statement but i cant figure it out the borrowing stuff
         if !self.cache.contains_key(texture_path) {
 
-            let texture = load_texture(&texture_path).await.unwrap();
-            
-            texture.set_filter(texture::FilterMode::Nearest);
+            // a missing texture shouldn't take the whole game down, so it gets a checkerboard instead
+            let texture = match load_texture(&texture_path).await {
+                Ok(texture) => {
+                    texture.set_filter(self.filter_for(texture_path));
+
+                    self.failed_paths.remove(texture_path);
+
+                    texture
+                },
+                Err(error) => {
+                    log_warn!("failed to load texture {}: {}", texture_path, error);
+
+                    self.failed_paths.insert(texture_path.clone());
+
+                    self.fallback()
+                },
+            };
 
             self.cache.insert(texture_path.clone(), texture);
 
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            self.watcher.watch(texture_path);
+
+            self.touch(texture_path);
+
+            self.enforce_budget(texture_path);
+
+        }
+
+        else {
+            self.touch(texture_path);
         }
 
         self.cache.get(texture_path).unwrap()
     }
-}
\ No newline at end of file
+
+    /// Use `filter` for textures whose path matches `pattern`, like "assets/ui/*" or "assets/fonts/*.png".
+    /// `*` matches anything, including slashes. When several patterns match, the longest one wins.
+    /// Only applies to textures loaded after this is called
+    pub fn set_filter(&mut self, pattern: &str, filter: FilterMode) {
+        self.filters.retain(|(existing, _)| existing != pattern);
+
+        self.filters.push((pattern.to_string(), filter));
+    }
+
+    pub fn with_filter(mut self, pattern: &str, filter: FilterMode) -> Self {
+        self.set_filter(pattern, filter);
+
+        self
+    }
+
+    pub fn filter_for(&self, texture_path: &str) -> FilterMode {
+        self.filters.iter()
+            .filter(|(pattern, _)| glob_match(pattern, texture_path))
+            .max_by_key(|(pattern, _)| pattern.len())
+            .map(|(_, filter)| *filter)
+            .unwrap_or(self.default_filter)
+    }
+
+    pub fn with_memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = Some(memory_budget);
+
+        self
+    }
+
+    fn touch(&mut self, texture_path: &String) {
+        self.use_counter += 1;
+
+        self.last_used.insert(texture_path.clone(), self.use_counter);
+    }
+
+    /// Unload least recently used textures until they fit in the memory budget. Never unloads `keep`
+    fn enforce_budget(&mut self, keep: &String) {
+        let memory_budget = match self.memory_budget {
+            Some(memory_budget) => memory_budget,
+            None => return,
+        };
+
+        while self.texture_bytes() > memory_budget {
+            let least_recently_used = self.cache.keys()
+                .filter(|texture_path| *texture_path != keep)
+                .min_by_key(|texture_path| self.last_used.get(*texture_path).copied().unwrap_or(0))
+                .cloned();
+
+            match least_recently_used {
+                Some(texture_path) => {
+                    log_debug!("unloading {} to stay under the texture memory budget", texture_path);
+
+                    self.unload(&texture_path);
+                },
+                None => break, // only the texture we are keeping is left
+            }
+        }
+    }
+
+    /// Drop a cached texture. The next get loads it again. Returns whether it was loaded
+    pub fn unload(&mut self, texture_path: &String) -> bool {
+        self.last_used.remove(texture_path);
+        self.failed_paths.remove(texture_path);
+
+        #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+        self.watcher.unwatch(texture_path);
+
+        self.cache.remove(texture_path).is_some()
+    }
+
+    /// Drop every cached texture, including the atlas
+    pub fn clear(&mut self) {
+        let texture_paths: Vec<String> = self.cache.keys().cloned().collect();
+
+        for texture_path in &texture_paths {
+            self.unload(texture_path);
+        }
+
+        if let Some(atlas) = &mut self.atlas {
+            atlas.clear();
+        }
+    }
+
+    /// Estimated size of the textures cached on their own, without the atlas
+    fn texture_bytes(&self) -> usize {
+        self.cache.iter()
+            .filter(|(texture_path, _)| !self.failed_paths.contains(*texture_path)) // fallbacks all share one texture
+            .map(|(_, texture)| texture.width() as usize * texture.height() as usize * 4)
+            .sum()
+    }
+
+    pub fn stats(&self) -> TextureStats {
+        let texture_bytes = self.texture_bytes();
+
+        let (atlas_pages, atlas_bytes) = match &self.atlas {
+            Some(atlas) => (atlas.page_count(), atlas.page_count() * atlas.page_size as usize * atlas.page_size as usize * 4),
+            None => (0, 0),
+        };
+
+        TextureStats {
+            textures: self.cache.len(),
+            atlas_pages,
+            estimated_bytes: texture_bytes + atlas_bytes,
+        }
+    }
+
+    /// Pack small textures loaded through get_region into shared atlas pages
+    pub fn with_atlas(mut self, page_size: u16, max_texture_size: u16) -> Self {
+        self.atlas = Some(TextureAtlas::new(page_size, max_texture_size));
+
+        self
+    }
+
+    /// Like get, but small textures come out of the atlas if there is one, along with where they are in it.
+    /// Combine the rect with any source rect of your own using texture_atlas::atlas_source
+    pub async fn get_region(&mut self, texture_path: &String) -> (&Texture2D, Option<Rect>) {
+
+        if self.needs_packing(texture_path) {
+            let image = load_image(texture_path).await.map_err(|error| error.to_string());
+
+            self.pack_image(texture_path, image);
+        }
+
+        match self.packed_region(texture_path) {
+            Some(region) => (self.atlas.as_ref().unwrap().get(texture_path).unwrap().0, Some(region)),
+            None => (self.get(texture_path).await, None),
+        }
+    }
+
+    /// Like get_region, but without awaiting. See get_blocking
+    pub fn get_region_blocking(&mut self, texture_path: &String) -> (&Texture2D, Option<Rect>) {
+
+        if self.needs_packing(texture_path) {
+            let image = self.read_image_blocking(texture_path);
+
+            self.pack_image(texture_path, image);
+        }
+
+        match self.packed_region(texture_path) {
+            Some(region) => (self.atlas.as_ref().unwrap().get(texture_path).unwrap().0, Some(region)),
+            None => (self.get_blocking(texture_path), None),
+        }
+    }
+
+    /// Whether a texture should go into the atlas and isn't loaded yet.
+    /// Textures that are already cached on their own stay that way
+    fn needs_packing(&self, texture_path: &String) -> bool {
+        match &self.atlas {
+            Some(atlas) => atlas.get(texture_path).is_none() && !self.cache.contains_key(texture_path),
+            None => false,
+        }
+    }
+
+    fn packed_region(&self, texture_path: &String) -> Option<Rect> {
+        self.atlas.as_ref()
+            .and_then(|atlas| atlas.get(texture_path))
+            .map(|(_, region)| region)
+    }
+
+    fn pack_image(&mut self, texture_path: &String, image: Result<Image, String>) {
+        let filter = self.filter_for(texture_path);
+
+        // on errors get logs it and serves the fallback
+        if let Ok(image) = image {
+            let atlas = self.atlas.as_mut().unwrap();
+
+            // atlas pages are always nearest filtered
+            if filter == FilterMode::Nearest && atlas.fits(&image) {
+                atlas.insert(texture_path, &image);
+            } 
+            
+            else {
+                let texture = Texture2D::from_image(&image);
+
+                texture.set_filter(filter);
+
+                self.cache.insert(texture_path.clone(), texture);
+            }
+        }
+    }
+
+    /// Make every get_blocking of a texture that hasn't been loaded yet panic instead of reading it from disk.
+    /// Catches missed preloads on native before they turn into missing textures on wasm, where get_blocking can't load anything
+    pub fn with_strict(mut self) -> Self {
+        self.strict = true;
+
+        self
+    }
+
+    /// Like get, but usable outside async code. On native the texture is read from disk right away.
+    /// On wasm it has to be loaded already, for example with Assets::preload, or the fallback checkerboard is drawn until it is
+    pub fn get_blocking(&mut self, texture_path: &String) -> &Texture2D {
+
+        if self.cache.contains_key(texture_path) {
+            self.touch(texture_path);
+
+            return self.cache.get(texture_path).unwrap();
+        }
+
+        match self.read_image_blocking(texture_path) {
+            Ok(image) => {
+                let texture = Texture2D::from_image(&image);
+
+                texture.set_filter(self.filter_for(texture_path));
+
+                self.failed_paths.remove(texture_path);
+
+                self.cache.insert(texture_path.clone(), texture);
+
+                #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+                self.watcher.watch(texture_path);
+
+                self.touch(texture_path);
+
+                self.enforce_budget(texture_path);
+
+                self.cache.get(texture_path).unwrap()
+            },
+            Err(error) => {
+                // not cached, so a later get or preload can still load the real one
+                if self.failed_paths.insert(texture_path.clone()) {
+                    log_warn!("failed to load texture {}: {}", texture_path, error);
+                }
+
+                self.fallback();
+
+                self.fallback.as_ref().unwrap()
+            },
+        }
+    }
+
+    fn read_image_blocking(&self, texture_path: &String) -> Result<Image, String> {
+
+        if self.strict {
+            panic!("texture {} was not preloaded", texture_path)
+        }
+
+        read_image_from_disk(texture_path)
+    }
+
+    /// Paths that couldn't be loaded and are being drawn as the fallback checkerboard
+    pub fn failed_paths(&self) -> &FxHashSet<String> {
+        &self.failed_paths
+    }
+
+    /// Magenta and black checkerboard, made the first time a texture fails to load
+    fn fallback(&mut self) -> Texture2D {
+        if let Some(fallback) = &self.fallback {
+            return fallback.clone();
+        }
+
+        let mut bytes = Vec::with_capacity(FALLBACK_SIZE as usize * FALLBACK_SIZE as usize * 4);
+
+        for y in 0..FALLBACK_SIZE {
+            for x in 0..FALLBACK_SIZE {
+                let magenta = (x / FALLBACK_CHECKER_SIZE + y / FALLBACK_CHECKER_SIZE) % 2 == 0;
+
+                match magenta {
+                    true => bytes.extend([255, 0, 255, 255]),
+                    false => bytes.extend([0, 0, 0, 255]),
+                }
+            }
+        }
+
+        let fallback = Texture2D::from_rgba8(FALLBACK_SIZE, FALLBACK_SIZE, &bytes);
+
+        fallback.set_filter(FilterMode::Nearest);
+
+        self.fallback = Some(fallback.clone());
+
+        fallback
+    }
+
+    /// Reload every cached texture that changed on disk. Call once per frame
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    pub async fn hot_reload(&mut self) {
+        for texture_path in self.watcher.changed() {
+
+            // keep the old texture if the new one is only half written
+            match load_texture(&texture_path).await {
+                Ok(texture) => {
+                    texture.set_filter(self.filter_for(&texture_path));
+
+                    log_info!("reloaded {}", texture_path);
+
+                    self.failed_paths.remove(&texture_path);
+
+                    self.cache.insert(texture_path, texture);
+                },
+                Err(error) => log_warn!("failed to reload {}: {}", texture_path, error),
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_image_from_disk(texture_path: &String) -> Result<Image, String> {
+    let bytes = std::fs::read(texture_path).map_err(|error| error.to_string())?;
+
+    Image::from_file_with_format(&bytes, None).map_err(|error| error.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_image_from_disk(_texture_path: &String) -> Result<Image, String> {
+    Err("textures can't be loaded without awaiting in the browser, preload it first".to_string())
+}
+
+/// Whether `path` matches `pattern`, where `*` matches any number of characters
+fn glob_match(pattern: &str, path: &str) -> bool {
+    let mut parts = pattern.split('*');
+
+    // split always gives at least one part
+    let first = parts.next().unwrap();
+
+    let mut remaining = match path.strip_prefix(first) {
+        Some(remaining) => remaining,
+        None => return false,
+    };
+
+    let parts: Vec<&str> = parts.collect();
+
+    // no stars, so the whole path has to match
+    if parts.is_empty() {
+        return remaining.is_empty();
+    }
+
+    for (index, part) in parts.iter().enumerate() {
+        // the last part has to be at the very end
+        if index == parts.len() - 1 {
+            return remaining.ends_with(part);
+        }
+
+        match remaining.find(part) {
+            Some(position) => remaining = &remaining[position + part.len()..],
+            None => return false,
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    #[test]
+    fn without_stars_the_whole_path_matches() {
+        assert!(glob_match("assets/player.png", "assets/player.png"));
+        assert!(!glob_match("assets/player.png", "assets/player.png.bak"));
+        assert!(!glob_match("assets/player.png", "assets/player"));
+    }
+
+    #[test]
+    fn stars_match_anything_including_slashes() {
+        assert!(glob_match("assets/ui/*", "assets/ui/button.png"));
+        assert!(glob_match("assets/ui/*", "assets/ui/icons/close.png"));
+        assert!(glob_match("assets/ui/*", "assets/ui/"));
+        assert!(!glob_match("assets/ui/*", "assets/fonts/ui.png"));
+
+        assert!(glob_match("*.png", "assets/player.png"));
+        assert!(!glob_match("*.png", "assets/player.jpg"));
+
+        assert!(glob_match("*", ""));
+        assert!(glob_match("assets/*/tiles/*.png", "assets/level1/tiles/grass.png"));
+        assert!(!glob_match("assets/*/tiles/*.png", "assets/level1/props/grass.png"));
+    }
+
+    #[test]
+    fn parts_dont_overlap() {
+        assert!(!glob_match("a*a", "a"));
+        assert!(glob_match("a*a", "aa"));
+        assert!(!glob_match("*ab*ab", "ab"));
+        assert!(glob_match("*ab*ab", "abab"));
+    }
+}
@@ -0,0 +1,418 @@
+use diff::Diff;
+use macroquad::{color::WHITE, file::load_string, math::{vec2, Rect, Vec2}, models::{draw_mesh, Mesh, Vertex}};
+use nalgebra::vector;
+use rapier2d::geometry::{ColliderBuilder, ColliderHandle};
+use serde::{Deserialize, Serialize};
+
+use crate::{coordinates::CoordinateSystem, space::Space, texture_atlas::atlas_source, texture_loader::TextureLoader};
+
+pub mod tmx;
+
+// tiled stores flips in the top bits of each gid
+const FLIPPED_HORIZONTALLY: u32 = 0x80000000;
+const FLIPPED_VERTICALLY: u32 = 0x40000000;
+const FLIPPED_DIAGONALLY: u32 = 0x20000000;
+
+/// Tiles per side of a chunk. 16 * 16 tiles is 1024 vertices, well inside a u16 index
+const CHUNK_SIZE: u32 = 16;
+
+/// The parts of a Tiled map this module uses, laid out like the JSON (.tmj) format. .tmx maps are read into the same structure
+#[derive(Deserialize, Clone, Debug)]
+pub struct TiledMap {
+    pub width: u32,
+    pub height: u32,
+    pub tilewidth: u32,
+    pub tileheight: u32,
+    pub layers: Vec<TiledLayer>,
+    pub tilesets: Vec<TiledTileset>
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct TiledLayer {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String, // only "tilelayer" is drawn
+    #[serde(default)]
+    pub data: Option<Vec<u32>>, // None for object layers and infinite maps
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+    #[serde(default)]
+    pub properties: Vec<TiledProperty>
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+impl TiledLayer {
+    pub fn property(&self, name: &str) -> Option<&serde_json::Value> {
+        self.properties.iter()
+            .find(|property| property.name == name)
+            .map(|property| &property.value)
+    }
+
+    /// Layers with a `collision` bool property set in Tiled
+    pub fn is_collision(&self) -> bool {
+        self.property("collision").and_then(|value| value.as_bool()).unwrap_or(false)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct TiledProperty {
+    pub name: String,
+    pub value: serde_json::Value
+}
+
+/// Only embedded tilesets with a single image are supported
+#[derive(Deserialize, Clone, Debug)]
+pub struct TiledTileset {
+    pub firstgid: u32,
+    #[serde(default)]
+    pub image: Option<String>, // relative to the map
+    #[serde(default)]
+    pub source: Option<String>, // set for external tilesets
+    #[serde(default)]
+    pub columns: u32,
+    #[serde(default)]
+    pub tilecount: u32,
+    #[serde(default)]
+    pub tilewidth: u32,
+    #[serde(default)]
+    pub tileheight: u32,
+    #[serde(default)]
+    pub margin: u32,
+    #[serde(default)]
+    pub spacing: u32
+}
+
+/// What gets synced for a tilemap. Every client loads the map from the same path instead of receiving the tiles
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct TilemapRef {
+    pub path: String,
+    pub x: f32, // rapier coords of the top left corner of the map
+    pub y: f32
+}
+
+impl TilemapRef {
+    pub fn new(path: &str, position: Vec2) -> Self {
+        Self {
+            path: path.to_string(),
+            x: position.x,
+            y: position.y,
+        }
+    }
+
+    pub async fn load(&self) -> Result<Tilemap, String> {
+        Tilemap::load(&self.path, vec2(self.x, self.y)).await
+    }
+}
+
+struct Chunk {
+    mesh: Mesh,
+    bounds: Rect // macroquad coords, for culling
+}
+
+/// A Tiled map drawn through the TextureLoader in chunked meshes, with colliders generated from its collision layers
+pub struct Tilemap {
+    pub path: String,
+    pub map: TiledMap,
+    pub position: Vec2, // rapier coords of the top left corner of the map
+    chunks: Vec<Chunk>,
+    built_for: Option<CoordinateSystem>, // meshes are in macroquad coords, so they are rebuilt when the coordinate system changes
+    colliders: Vec<ColliderHandle>
+}
+
+impl Tilemap {
+
+    pub fn new(path: &str, map: TiledMap, position: Vec2) -> Result<Self, String> {
+
+        if let Some(tileset) = map.tilesets.iter().find(|tileset| tileset.source.is_some()) {
+            return Err(format!("{} uses external tileset {:?}, embed it in the map instead", path, tileset.source));
+        }
+
+        Ok(
+            Self {
+                path: path.to_string(),
+                map,
+                position,
+                chunks: vec![],
+                built_for: None,
+                colliders: vec![],
+            }
+        )
+    }
+
+    /// Load a Tiled map. Paths ending in .tmx are read as XML, anything else as JSON
+    pub async fn load(path: &str, position: Vec2) -> Result<Self, String> {
+        let map_source = load_string(path).await.map_err(|error| format!("failed to load {}: {}", path, error))?;
+
+        let map = match path.ends_with(".tmx") {
+            true => tmx::parse(&map_source).map_err(|error| format!("invalid tilemap {}: {}", path, error))?,
+            false => serde_json::from_str(&map_source).map_err(|error| format!("invalid tilemap {}: {}", path, error))?,
+        };
+
+        Self::new(path, map, position)
+    }
+
+    pub fn reference(&self) -> TilemapRef {
+        TilemapRef::new(&self.path, self.position)
+    }
+
+    /// Tileset image paths, resolved relative to the map
+    pub fn texture_paths(&self) -> Vec<String> {
+        self.map.tilesets.iter()
+            .filter_map(|tileset| tileset.image.as_ref())
+            .map(|image| self.resolve(image))
+            .collect()
+    }
+
+    /// Await every tileset texture, so draw doesn't have to load them on wasm
+    pub async fn load_textures(&self, textures: &mut TextureLoader) {
+        for texture_path in self.texture_paths() {
+            textures.get_region(&texture_path).await;
+        }
+    }
+
+    fn resolve(&self, image: &str) -> String {
+        match self.path.rfind('/') {
+            Some(index) => format!("{}/{}", &self.path[..index], image),
+            None => image.to_string(),
+        }
+    }
+
+    pub fn tile_size(&self) -> Vec2 {
+        vec2(self.map.tilewidth as f32, self.map.tileheight as f32)
+    }
+
+    /// The tileset a gid belongs to, which is the one with the highest firstgid not above it
+    fn tileset_for(&self, gid: u32) -> Option<&TiledTileset> {
+        self.map.tilesets.iter()
+            .filter(|tileset| tileset.firstgid <= gid)
+            .max_by_key(|tileset| tileset.firstgid)
+    }
+
+    /// The gid at a tile of a layer without its flip bits. 0 is empty
+    pub fn tile(&self, layer: &TiledLayer, column: u32, row: u32) -> u32 {
+        if column >= layer.width || row >= layer.height {
+            return 0;
+        }
+
+        match &layer.data {
+            Some(data) => data.get((row * layer.width + column) as usize).copied().unwrap_or(0) & !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY),
+            None => 0,
+        }
+    }
+
+    fn build_chunks(&mut self, textures: &mut TextureLoader, coordinates: &CoordinateSystem) {
+        self.chunks.clear();
+
+        let tile_size = self.tile_size();
+
+        for layer in self.map.layers.iter().filter(|layer| layer.kind == "tilelayer" && layer.visible) {
+            let data = match &layer.data {
+                Some(data) => data,
+                None => continue,
+            };
+
+            for tileset in &self.map.tilesets {
+                let image = match &tileset.image {
+                    Some(image) => image,
+                    None => continue,
+                };
+
+                let (texture, region) = textures.get_region_blocking(&self.resolve(image));
+
+                let texture_size = texture.size();
+
+                let columns = tileset.columns.max(1);
+
+                for chunk_row in (0..layer.height).step_by(CHUNK_SIZE as usize) {
+                    for chunk_column in (0..layer.width).step_by(CHUNK_SIZE as usize) {
+                        let mut vertices = vec![];
+                        let mut indices = vec![];
+
+                        for row in chunk_row..(chunk_row + CHUNK_SIZE).min(layer.height) {
+                            for column in chunk_column..(chunk_column + CHUNK_SIZE).min(layer.width) {
+                                // a layer with less data than its size says is treated as empty past the end
+                                let raw_gid = data.get((row * layer.width + column) as usize).copied().unwrap_or(0);
+
+                                let gid = raw_gid & !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY);
+
+                                // only tiles from this tileset go in this mesh
+                                if gid == 0 || self.tileset_for(gid).map(|other| other.firstgid) != Some(tileset.firstgid) {
+                                    continue;
+                                }
+
+                                let local_id = gid - tileset.firstgid;
+
+                                let source = Rect::new(
+                                    (tileset.margin + (local_id % columns) * (tileset.tilewidth + tileset.spacing)) as f32,
+                                    (tileset.margin + (local_id / columns) * (tileset.tileheight + tileset.spacing)) as f32,
+                                    tileset.tilewidth as f32,
+                                    tileset.tileheight as f32,
+                                );
+
+                                let source = atlas_source(region, Some(source)).unwrap();
+
+                                let (mut u0, mut v0) = (source.x / texture_size.x, source.y / texture_size.y);
+                                let (mut u1, mut v1) = (source.right() / texture_size.x, source.bottom() / texture_size.y);
+
+                                // diagonal flips aren't supported
+                                if raw_gid & FLIPPED_HORIZONTALLY != 0 {
+                                    std::mem::swap(&mut u0, &mut u1);
+                                }
+
+                                if raw_gid & FLIPPED_VERTICALLY != 0 {
+                                    std::mem::swap(&mut v0, &mut v1);
+                                }
+
+                                // tiles bigger than the map grid stick up from the bottom of their cell, like in tiled
+                                let top_left = coordinates.rapier_to_macroquad(&vec2(
+                                    self.position.x + column as f32 * tile_size.x,
+                                    self.position.y - (row + 1) as f32 * tile_size.y + tileset.tileheight as f32
+                                ));
+
+                                let (w, h) = (tileset.tilewidth as f32, tileset.tileheight as f32);
+
+                                let first = vertices.len() as u16;
+
+                                vertices.push(Vertex::new(top_left.x, top_left.y, 0., u0, v0, WHITE));
+                                vertices.push(Vertex::new(top_left.x + w, top_left.y, 0., u1, v0, WHITE));
+                                vertices.push(Vertex::new(top_left.x + w, top_left.y + h, 0., u1, v1, WHITE));
+                                vertices.push(Vertex::new(top_left.x, top_left.y + h, 0., u0, v1, WHITE));
+
+                                indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+                            }
+                        }
+
+                        if vertices.is_empty() {
+                            continue;
+                        }
+
+                        let min = vertices.iter().fold(Vec2::MAX, |min, vertex| min.min(vertex.position.truncate()));
+                        let max = vertices.iter().fold(Vec2::MIN, |max, vertex| max.max(vertex.position.truncate()));
+
+                        self.chunks.push(Chunk {
+                            mesh: Mesh { vertices, indices, texture: Some(texture.clone()) },
+                            bounds: Rect::new(min.x, min.y, max.x - min.x, max.y - min.y),
+                        });
+                    }
+                }
+            }
+        }
+
+        self.built_for = Some(*coordinates);
+    }
+
+    /// Throw away the meshes so they are rebuilt on the next draw, after changing tiles or position
+    pub fn invalidate(&mut self) {
+        self.built_for = None;
+    }
+
+    /// Draw every visible tile layer in order. Only chunks that overlap `camera_rect` are drawn
+    pub fn draw(&mut self, textures: &mut TextureLoader, camera_rect: &Rect, coordinates: &CoordinateSystem) {
+        if self.built_for != Some(*coordinates) {
+            self.build_chunks(textures, coordinates);
+        }
+
+        for chunk in self.chunks.iter().filter(|chunk| chunk.bounds.overlaps(camera_rect)) {
+            draw_mesh(&chunk.mesh);
+        }
+    }
+
+    /// Static colliders for every solid tile in the layer, merged into as few boxes as possible.
+    /// Rows are merged first, then the strip is grown downwards while every tile under it is solid and unclaimed
+    pub fn generate_colliders(&mut self, space: &mut Space, layer_name: &str) -> Result<Vec<ColliderHandle>, String> {
+        let layer = match self.map.layers.iter().find(|layer| layer.name == layer_name) {
+            Some(layer) => layer.clone(),
+            None => return Err(format!("{} has no layer named {}", self.path, layer_name)),
+        };
+
+        let tile_size = self.tile_size();
+
+        let (width, height) = (layer.width, layer.height);
+
+        let mut claimed = vec![false; (width * height) as usize];
+
+        let solid = |column: u32, row: u32, claimed: &Vec<bool>| {
+            self.tile(&layer, column, row) != 0 && !claimed[(row * width + column) as usize]
+        };
+
+        let mut boxes = vec![];
+
+        for row in 0..height {
+            for column in 0..width {
+                if !solid(column, row, &claimed) {
+                    continue;
+                }
+
+                let mut box_width = 1;
+
+                while column + box_width < width && solid(column + box_width, row, &claimed) {
+                    box_width += 1;
+                }
+
+                let mut box_height = 1;
+
+                while row + box_height < height && (column..column + box_width).all(|other| solid(other, row + box_height, &claimed)) {
+                    box_height += 1;
+                }
+
+                for claimed_row in row..row + box_height {
+                    for claimed_column in column..column + box_width {
+                        claimed[(claimed_row * width + claimed_column) as usize] = true;
+                    }
+                }
+
+                boxes.push((column, row, box_width, box_height));
+            }
+        }
+
+        let mut handles = vec![];
+
+        for (column, row, box_width, box_height) in boxes {
+            let half_extents = vec2(box_width as f32 * tile_size.x, box_height as f32 * tile_size.y) / 2.;
+
+            let center = vec2(
+                self.position.x + column as f32 * tile_size.x + half_extents.x,
+                self.position.y - row as f32 * tile_size.y - half_extents.y
+            );
+
+            let collider = ColliderBuilder::cuboid(half_extents.x, half_extents.y)
+                .translation(vector![center.x, center.y])
+                .build();
+
+            handles.push(space.collider_set.insert(collider));
+        }
+
+        self.colliders.extend(handles.iter().copied());
+
+        Ok(handles)
+    }
+
+    /// Colliders for every layer with the `collision` property
+    pub fn generate_all_colliders(&mut self, space: &mut Space) -> Vec<ColliderHandle> {
+        let layer_names: Vec<String> = self.map.layers.iter()
+            .filter(|layer| layer.is_collision())
+            .map(|layer| layer.name.clone())
+            .collect();
+
+        layer_names.iter()
+            .flat_map(|layer_name| self.generate_colliders(space, layer_name).unwrap_or_default())
+            .collect()
+    }
+
+    /// Remove every collider this map generated
+    pub fn remove_colliders(&mut self, space: &mut Space) {
+        for collider_handle in self.colliders.drain(..) {
+            space.collider_set.remove(collider_handle, &mut space.island_manager, &mut space.rigid_body_set, false);
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use std::io::Read;
+
+use super::{TiledLayer, TiledMap, TiledProperty, TiledTileset};
+
+/// An element of a .tmx file. Only what Tiled writes is handled: no doctype, cdata or namespaces
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn number(&self, name: &str) -> Result<u32, String> {
+        match self.attribute(name) {
+            Some(value) => value.parse().map_err(|_| format!("<{}> has an invalid {}: {}", self.name, name, value)),
+            None => Ok(0),
+        }
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+/// Read a Tiled XML (.tmx) map into the same structure as the JSON format
+pub fn parse(source: &str) -> Result<TiledMap, String> {
+    let root = parse_document(source)?;
+
+    if root.name != "map" {
+        return Err(format!("expected <map>, found <{}>", root.name));
+    }
+
+    let mut layers = vec![];
+    let mut tilesets = vec![];
+
+    for child in &root.children {
+        match child.name.as_str() {
+            "tileset" => tilesets.push(parse_tileset(child)?),
+            "layer" => layers.push(parse_layer(child, "tilelayer")?),
+            "objectgroup" => layers.push(parse_layer(child, "objectgroup")?),
+            "imagelayer" => layers.push(parse_layer(child, "imagelayer")?),
+            "group" => layers.push(parse_layer(child, "group")?), // nested layers are skipped, same as in json maps
+            _ => {},
+        }
+    }
+
+    Ok(
+        TiledMap {
+            width: root.number("width")?,
+            height: root.number("height")?,
+            tilewidth: root.number("tilewidth")?,
+            tileheight: root.number("tileheight")?,
+            layers,
+            tilesets,
+        }
+    )
+}
+
+fn parse_tileset(element: &Element) -> Result<TiledTileset, String> {
+    Ok(
+        TiledTileset {
+            firstgid: element.number("firstgid")?,
+            image: element.child("image").and_then(|image| image.attribute("source")).map(str::to_string),
+            source: element.attribute("source").map(str::to_string),
+            columns: element.number("columns")?,
+            tilecount: element.number("tilecount")?,
+            tilewidth: element.number("tilewidth")?,
+            tileheight: element.number("tileheight")?,
+            margin: element.number("margin")?,
+            spacing: element.number("spacing")?,
+        }
+    )
+}
+
+fn parse_layer(element: &Element, kind: &str) -> Result<TiledLayer, String> {
+    let name = element.attribute("name").unwrap_or_default().to_string();
+
+    let data = match element.child("data") {
+        Some(data) => parse_data(data).map_err(|error| format!("layer {}: {}", name, error))?,
+        None => None,
+    };
+
+    let properties = match element.child("properties") {
+        Some(properties) => properties.children.iter()
+            .filter(|property| property.name == "property")
+            .map(parse_property)
+            .collect(),
+        None => vec![],
+    };
+
+    Ok(
+        TiledLayer {
+            name,
+            kind: kind.to_string(),
+            data,
+            width: element.number("width")?,
+            height: element.number("height")?,
+            visible: element.attribute("visible") != Some("0"),
+            properties,
+        }
+    )
+}
+
+fn parse_property(element: &Element) -> TiledProperty {
+    // multi line strings are stored as text instead of the value attribute
+    let value = element.attribute("value").unwrap_or(&element.text);
+
+    let value = match element.attribute("type").unwrap_or("string") {
+        "bool" => serde_json::Value::Bool(value == "true"),
+        "int" | "float" | "object" => serde_json::from_str(value).unwrap_or(serde_json::Value::String(value.to_string())),
+        _ => serde_json::Value::String(value.to_string()),
+    };
+
+    TiledProperty {
+        name: element.attribute("name").unwrap_or_default().to_string(),
+        value,
+    }
+}
+
+/// The gids of a layer. None for infinite maps, which store their tiles in chunks
+fn parse_data(element: &Element) -> Result<Option<Vec<u32>>, String> {
+    if element.child("chunk").is_some() {
+        return Ok(None);
+    }
+
+    let gids = match element.attribute("encoding") {
+        Some("csv") => element.text.split(',')
+            .map(str::trim)
+            .filter(|gid| !gid.is_empty())
+            .map(|gid| gid.parse().map_err(|_| format!("invalid gid {}", gid)))
+            .collect::<Result<Vec<u32>, String>>()?,
+        Some("base64") => {
+            let bytes = decode_base64(&element.text)?;
+
+            let bytes = match element.attribute("compression") {
+                None => bytes,
+                Some("zlib") => decompress(flate2::read::ZlibDecoder::new(bytes.as_slice()))?,
+                Some("gzip") => decompress(flate2::read::GzDecoder::new(bytes.as_slice()))?,
+                Some(compression) => return Err(format!("{} compressed layers aren't supported, use csv, zlib or gzip", compression)),
+            };
+
+            bytes.chunks_exact(4)
+                .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+                .collect()
+        },
+        // the deprecated format with one element per tile
+        None => element.children.iter()
+            .filter(|tile| tile.name == "tile")
+            .map(|tile| tile.number("gid"))
+            .collect::<Result<Vec<u32>, String>>()?,
+        Some(encoding) => return Err(format!("unknown layer encoding {}", encoding)),
+    };
+
+    Ok(Some(gids))
+}
+
+fn decompress(mut decoder: impl Read) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+
+    decoder.read_to_end(&mut bytes).map_err(|error| format!("failed to decompress layer: {}", error))?;
+
+    Ok(bytes)
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for character in text.bytes().filter(|character| !character.is_ascii_whitespace() && *character != b'=') {
+        let value = match character {
+            b'A'..=b'Z' => character - b'A',
+            b'a'..=b'z' => character - b'a' + 26,
+            b'0'..=b'9' => character - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(format!("invalid base64 character {}", character as char)),
+        };
+
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Build the element tree of a document and return its root
+fn parse_document(source: &str) -> Result<Element, String> {
+    // elements that are still open, innermost last
+    let mut open: Vec<Element> = vec![];
+
+    let mut rest = source;
+
+    loop {
+        let tag_start = match rest.find('<') {
+            Some(tag_start) => tag_start,
+            None => break,
+        };
+
+        if let Some(element) = open.last_mut() {
+            element.text.push_str(&unescape(&rest[..tag_start]));
+        }
+
+        rest = &rest[tag_start..];
+
+        // declarations and comments carry nothing we need
+        let skip_to = if rest.starts_with("<?") {
+            Some("?>")
+        } else if rest.starts_with("<!--") {
+            Some("-->")
+        } else if rest.starts_with("<!") {
+            Some(">")
+        } else {
+            None
+        };
+
+        if let Some(end) = skip_to {
+            let tag_end = rest.find(end).ok_or("unterminated declaration")?;
+
+            rest = &rest[tag_end + end.len()..];
+
+            continue;
+        }
+
+        let tag_end = rest.find('>').ok_or("unterminated tag")?;
+
+        let tag = &rest[1..tag_end];
+
+        rest = &rest[tag_end + 1..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            let element = open.pop().ok_or_else(|| format!("unexpected </{}>", name.trim()))?;
+
+            if element.name != name.trim() {
+                return Err(format!("<{}> closed by </{}>", element.name, name.trim()));
+            }
+
+            match open.last_mut() {
+                Some(parent) => parent.children.push(element),
+                None => return Ok(element),
+            }
+
+            continue;
+        }
+
+        let self_closing = tag.ends_with('/');
+
+        let element = parse_tag(tag.trim_end_matches('/'))?;
+
+        if !self_closing {
+            open.push(element);
+
+            continue;
+        }
+
+        match open.last_mut() {
+            Some(parent) => parent.children.push(element),
+            None => return Ok(element),
+        }
+    }
+
+    Err(match open.last() {
+        Some(element) => format!("<{}> is never closed", element.name),
+        None => "no root element".to_string(),
+    })
+}
+
+/// The name and attributes between the angle brackets of an opening tag
+fn parse_tag(tag: &str) -> Result<Element, String> {
+    let tag = tag.trim();
+
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+
+    let mut element = Element {
+        name: tag[..name_end].to_string(),
+        attributes: vec![],
+        children: vec![],
+        text: String::new(),
+    };
+
+    let mut rest = tag[name_end..].trim_start();
+
+    while !rest.is_empty() {
+        let equals = rest.find('=').ok_or_else(|| format!("attribute without a value in <{}>", element.name))?;
+
+        let name = rest[..equals].trim().to_string();
+
+        let value_start = rest[equals + 1..].trim_start();
+
+        let quote = value_start.chars().next().filter(|quote| *quote == '"' || *quote == '\'')
+            .ok_or_else(|| format!("unquoted attribute {} in <{}>", name, element.name))?;
+
+        let value_end = value_start[1..].find(quote).ok_or_else(|| format!("unterminated attribute {} in <{}>", name, element.name))?;
+
+        element.attributes.push((name, unescape(&value_start[1..value_end + 1])));
+
+        rest = value_start[value_end + 2..].trim_start();
+    }
+
+    Ok(element)
+}
+
+fn unescape(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#10;", "\n")
+        .replace("&amp;", "&")
+}
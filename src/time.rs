@@ -1,8 +1,19 @@
 
+use std::{sync::atomic::{AtomicI64, AtomicU64, Ordering}, time::Duration};
+
 use chrono::Utc;
 use diff::Diff;
 use serde::{Deserialize, Serialize};
 
+use crate::current_unix_millis;
+
+/// std::time::Instant panics on wasm32-unknown-unknown, so everything in the crate measures time with this instead
+pub type Instant = web_time::Instant;
+
+pub fn now() -> Instant {
+    web_time::Instant::now()
+}
+
 #[derive(Serialize, Deserialize, Diff, PartialEq, Clone)]
 #[diff(attr(
     #[derive(Serialize, Deserialize)]
@@ -58,3 +69,226 @@ impl Time {
         
     }
 }
+
+static GAME_TIME_OFFSET: AtomicI64 = AtomicI64::new(0); // millis
+static NEXT_CLOCK_ID: AtomicU64 = AtomicU64::new(1);
+static PUBLISHING_CLOCK: AtomicU64 = AtomicU64::new(0); // id of the clock that sets GAME_TIME_OFFSET, 0 for none
+
+/// current_unix_millis, shifted by however far the GameClock has fallen behind or run ahead of the real time. Animations play on this,
+/// so pausing the clock pauses them too. Without a GameClock it is just current_unix_millis
+pub fn game_unix_millis() -> u64 {
+    (current_unix_millis() as i64 + GAME_TIME_OFFSET.load(Ordering::Relaxed)).max(0) as u64
+}
+
+/// The game's clock. Update it once per frame and read the frame delta and fixed ticks from it instead of the real time,
+/// so pausing and slow motion apply to physics and animations alike.
+/// Only the first clock made drives game_unix_millis, until it is dropped. Any others just keep their own time
+pub struct GameClock {
+    pub paused: bool,
+    pub time_scale: f32, // 0.5 is half speed
+    pub fixed_delta: Duration, // length of one fixed tick in game time
+    pub smoothing: f32, // 0 uses every frame's delta as is, closer to 1 evens out jittery frame times more
+    pub max_delta: Duration, // longer frames are clamped so a hitch doesn't turn into a huge step
+    last_update: Option<Instant>,
+    smoothed_delta: f32, // seconds of real time
+    delta: f32, // seconds of game time this frame
+    elapsed: f64, // seconds of game time since the clock was made
+    offset: f64, // seconds the game time is ahead of the real time, negative after pausing or slowing down
+    accumulator: f32, // game time not yet used up by fixed ticks
+    tick: u64,
+    ticks_this_frame: u32,
+    id: u64
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GameClock {
+
+    pub fn new() -> Self {
+        let id = NEXT_CLOCK_ID.fetch_add(1, Ordering::Relaxed);
+
+        if PUBLISHING_CLOCK.compare_exchange(0, id, Ordering::Relaxed, Ordering::Relaxed).is_err() {
+            log_warn!("another GameClock already drives game_unix_millis, this one won't");
+        }
+
+        Self {
+            paused: false,
+            time_scale: 1.,
+            fixed_delta: Duration::from_secs_f32(1. / 60.),
+            smoothing: 0.,
+            max_delta: Duration::from_millis(250),
+            last_update: None,
+            smoothed_delta: 0.,
+            delta: 0.,
+            elapsed: 0.,
+            offset: 0.,
+            accumulator: 0.,
+            tick: 0,
+            ticks_this_frame: 0,
+            id,
+        }
+    }
+
+    pub fn with_fixed_delta(mut self, fixed_delta: Duration) -> Self {
+        self.fixed_delta = fixed_delta;
+
+        self
+    }
+
+    pub fn with_smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing.clamp(0., 0.99);
+
+        self
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Advance the clock by the real time since the last update. Call once at the start of every frame
+    pub fn update(&mut self) {
+        let now = now();
+
+        let real_delta = match self.last_update {
+            Some(last_update) => (now - last_update).min(self.max_delta).as_secs_f32(),
+            None => 0.,
+        };
+
+        self.last_update = Some(now);
+
+        self.advance(real_delta);
+    }
+
+    fn advance(&mut self, real_delta: f32) {
+        // the first real frame has nothing to smooth against
+        self.smoothed_delta = match self.smoothed_delta > 0. {
+            true => self.smoothed_delta * self.smoothing + real_delta * (1. - self.smoothing),
+            false => real_delta,
+        };
+
+        let scale = match self.paused {
+            true => 0.,
+            false => self.time_scale.max(0.),
+        };
+
+        self.delta = self.smoothed_delta * scale;
+        self.elapsed += self.delta as f64;
+
+        // uses the unsmoothed delta so game_unix_millis doesn't drift away from the real time while playing normally
+        self.offset += (real_delta * (scale - 1.)) as f64;
+
+        if PUBLISHING_CLOCK.load(Ordering::Relaxed) == self.id {
+            GAME_TIME_OFFSET.store((self.offset * 1000.) as i64, Ordering::Relaxed);
+        }
+
+        self.accumulator += self.delta;
+
+        let fixed_delta = self.fixed_delta.as_secs_f32().max(f32::EPSILON);
+
+        self.ticks_this_frame = (self.accumulator / fixed_delta) as u32;
+        self.accumulator -= self.ticks_this_frame as f32 * fixed_delta;
+        self.tick += self.ticks_this_frame as u64;
+    }
+
+    /// Game time that passed this frame. 0 while paused
+    pub fn delta(&self) -> Duration {
+        Duration::from_secs_f32(self.delta)
+    }
+
+    /// Real time that passed this frame, ignoring pause and time_scale. For effects like hitstop that run on the wall clock
+    pub fn real_delta(&self) -> Duration {
+        Duration::from_secs_f32(self.smoothed_delta)
+    }
+
+    /// current_unix_millis shifted by how far this clock is ahead of or behind the real time. The same as game_unix_millis for the first clock
+    pub fn unix_millis(&self) -> u64 {
+        (current_unix_millis() as i64 + (self.offset * 1000.) as i64).max(0) as u64
+    }
+
+    /// Game time since the clock was made
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.elapsed)
+    }
+
+    /// How many fixed ticks fit in the game time so far
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// How many fixed ticks should run this frame
+    pub fn ticks_this_frame(&self) -> u32 {
+        self.ticks_this_frame
+    }
+
+    /// How far between the last fixed tick and the next one we are, for interpolating what gets drawn
+    pub fn tick_alpha(&self) -> f32 {
+        self.accumulator / self.fixed_delta.as_secs_f32().max(f32::EPSILON)
+    }
+}
+
+impl Drop for GameClock {
+    fn drop(&mut self) {
+        // the next clock starts from the real time again
+        if PUBLISHING_CLOCK.compare_exchange(self.id, 0, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+            GAME_TIME_OFFSET.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::GameClock;
+
+    #[test]
+    fn fast_forwarding_runs_ahead_of_the_real_time() {
+        let mut clock = GameClock::new();
+
+        clock.time_scale = 2.;
+
+        clock.advance(0.1);
+
+        assert_eq!(clock.delta(), Duration::from_secs_f32(0.2));
+        assert!((clock.offset - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn pausing_falls_behind_the_real_time() {
+        let mut clock = GameClock::new();
+
+        clock.pause();
+
+        clock.advance(0.1);
+
+        assert_eq!(clock.delta(), Duration::ZERO);
+        assert!((clock.offset + 0.1).abs() < 1e-6);
+
+        clock.resume();
+
+        clock.advance(0.1);
+
+        // normal speed neither catches up nor falls further behind
+        assert!((clock.offset + 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fixed_ticks_follow_game_time() {
+        let mut clock = GameClock::new().with_fixed_delta(Duration::from_millis(125));
+
+        clock.time_scale = 2.;
+
+        clock.advance(0.25);
+
+        assert_eq!(clock.ticks_this_frame(), 4);
+        assert_eq!(clock.tick(), 4);
+    }
+}
@@ -1,18 +1,203 @@
-use std::{collections::HashMap, time::{Duration, Instant}};
+use std::collections::VecDeque;
 
-// entity -> hashmap -> json -> diffed -> network -> json -> hashmap -> loaded
-struct Timeline {
-    frames: HashMap<Duration, String>,
-    start: Instant
+use diff::Diff;
+
+/// Records a state every tick so it can be looked at again later, for replays or rewinding time.
+/// A full copy is kept every keyframe_interval ticks and only diffs in between, so seeking replays at most keyframe_interval diffs
+pub struct Timeline<T: Diff> {
+    keyframes: VecDeque<(u64, T)>, // tick, state
+    diffs: VecDeque<(u64, <T as Diff>::Repr)>, // tick, diff from the previous recorded tick
+    last_state: Option<(u64, T)>, // the latest recorded tick, so the next one can be diffed against it
+    pub keyframe_interval: u64, // ticks
+    pub max_length: u64 // ticks. Older ticks are dropped, a whole keyframe at a time
+}
+
+impl<T: Diff + Clone> Timeline<T> {
+
+    pub fn new(keyframe_interval: u64, max_length: u64) -> Self {
+        Self {
+            keyframes: VecDeque::new(),
+            diffs: VecDeque::new(),
+            last_state: None,
+            keyframe_interval: keyframe_interval.max(1),
+            max_length,
+        }
+    }
+
+    /// The earliest tick that can still be seeked to
+    pub fn first_tick(&self) -> Option<u64> {
+        self.keyframes.front().map(|(tick, _)| *tick)
+    }
+
+    pub fn latest_tick(&self) -> Option<u64> {
+        self.last_state.as_ref().map(|(tick, _)| *tick)
+    }
+
+    /// Record the state at `tick`. Recording a tick at or before the latest one throws away everything after it first,
+    /// so recording after a rewind starts a new future
+    pub fn record(&mut self, tick: u64, state: &T) {
+
+        if self.latest_tick().is_some_and(|latest_tick| tick <= latest_tick) {
+            self.truncate_after(tick.saturating_sub(1));
+
+            // nothing before `tick` left to diff against
+            if self.latest_tick().is_some_and(|latest_tick| latest_tick >= tick) {
+                self.clear();
+            }
+        }
+
+        let keyframe_due = match self.keyframes.back() {
+            Some((keyframe_tick, _)) => tick >= keyframe_tick + self.keyframe_interval,
+            None => true,
+        };
+
+        match (&self.last_state, keyframe_due) {
+            (Some((_, last_state)), false) => self.diffs.push_back((tick, last_state.diff(state))),
+            _ => self.keyframes.push_back((tick, state.clone())),
+        }
+
+        self.last_state = Some((tick, state.clone()));
+
+        self.prune();
+    }
+
+    /// The state as it was at `tick`, or at the closest recorded tick before it. None if `tick` is older than the timeline
+    pub fn state_at(&self, tick: u64) -> Option<T> {
+
+        if let Some((latest_tick, last_state)) = &self.last_state {
+            if tick >= *latest_tick {
+                return Some(last_state.clone());
+            }
+        }
+
+        let (keyframe_tick, keyframe) = self.keyframes.iter()
+            .rev()
+            .find(|(keyframe_tick, _)| *keyframe_tick <= tick)?;
+
+        let mut state = keyframe.clone();
+
+        for (_, diff) in self.diffs.iter().filter(|(diff_tick, _)| *diff_tick > *keyframe_tick && *diff_tick <= tick) {
+            state.apply(diff);
+        }
+
+        Some(state)
+    }
+
+    /// Forget everything recorded after `tick`, for rewinding time and carrying on from there
+    pub fn truncate_after(&mut self, tick: u64) {
+        let state = self.state_at(tick);
+
+        self.keyframes.retain(|(keyframe_tick, _)| *keyframe_tick <= tick);
+        self.diffs.retain(|(diff_tick, _)| *diff_tick <= tick);
+
+        // the latest tick is now the last one recorded at or before `tick`
+        let latest_tick = self.keyframes.back().map(|(keyframe_tick, _)| *keyframe_tick)
+            .max(self.diffs.back().map(|(diff_tick, _)| *diff_tick));
+
+        self.last_state = match (latest_tick, state) {
+            (Some(latest_tick), Some(state)) => Some((latest_tick, state)),
+            _ => None,
+        };
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.diffs.clear();
+        self.last_state = None;
+    }
+
+    /// Drop whole keyframes that fell out of max_length. A keyframe is only dropped once the next one is old enough,
+    /// so every tick inside max_length can still be rebuilt
+    fn prune(&mut self) {
+        let latest_tick = match self.latest_tick() {
+            Some(latest_tick) => latest_tick,
+            None => return,
+        };
+
+        let oldest_kept = latest_tick.saturating_sub(self.max_length);
+
+        while self.keyframes.len() > 1 && self.keyframes[1].0 <= oldest_kept {
+            self.keyframes.pop_front();
+        }
+
+        let first_tick = match self.first_tick() {
+            Some(first_tick) => first_tick,
+            None => return,
+        };
+
+        while self.diffs.front().is_some_and(|(diff_tick, _)| *diff_tick <= first_tick) {
+            self.diffs.pop_front();
+        }
+    }
 }
 
-impl Timeline {
-    
-    fn reset(&mut self) {
-        self.start = Instant::now();
+#[cfg(test)]
+mod tests {
+    use super::Timeline;
+
+    fn recorded(ticks: u64, keyframe_interval: u64, max_length: u64) -> Timeline<i32> {
+        let mut timeline = Timeline::new(keyframe_interval, max_length);
+
+        for tick in 0..ticks {
+            timeline.record(tick, &(tick as i32 * 10));
+        }
+
+        timeline
     }
 
-    fn get_current_frame(&self) {
+    #[test]
+    fn every_tick_can_be_rebuilt() {
+        let timeline = recorded(20, 4, 100);
+
+        for tick in 0..20 {
+            assert_eq!(timeline.state_at(tick), Some(tick as i32 * 10));
+        }
 
+        // past the end is the latest state
+        assert_eq!(timeline.state_at(50), Some(190));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn recording_after_a_rewind_replaces_the_future() {
+        let mut timeline = recorded(10, 4, 100);
+
+        timeline.record(5, &500);
+
+        assert_eq!(timeline.latest_tick(), Some(5));
+        assert_eq!(timeline.state_at(4), Some(40));
+        assert_eq!(timeline.state_at(5), Some(500));
+        assert_eq!(timeline.state_at(9), Some(500));
+    }
+
+    #[test]
+    fn truncate_after_rewinds_the_latest_state() {
+        let mut timeline = recorded(10, 4, 100);
+
+        timeline.truncate_after(6);
+
+        assert_eq!(timeline.latest_tick(), Some(6));
+        assert_eq!(timeline.state_at(9), Some(60));
+
+        timeline.record(7, &700);
+
+        assert_eq!(timeline.state_at(6), Some(60));
+        assert_eq!(timeline.state_at(7), Some(700));
+    }
+
+    #[test]
+    fn old_ticks_are_dropped_a_keyframe_at_a_time() {
+        let timeline = recorded(50, 4, 10);
+
+        let first_tick = timeline.first_tick().unwrap();
+
+        assert!(first_tick <= 39);
+        assert!(first_tick > 0);
+        assert_eq!(first_tick % 4, 0);
+
+        assert_eq!(timeline.state_at(0), None);
+
+        for tick in 39..50 {
+            assert_eq!(timeline.state_at(tick), Some(tick as i32 * 10));
+        }
+    }
+}
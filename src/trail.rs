@@ -0,0 +1,206 @@
+use std::{collections::VecDeque, time::Duration};
+
+use macroquad::{color::Color, math::{vec2, Vec2}, models::{draw_mesh, Mesh, Vertex}, shapes::draw_line};
+use nalgebra::{point, vector};
+use rapier2d::{geometry::{ColliderHandle, Ray}, pipeline::QueryFilter, prelude::RigidBodyHandle};
+
+use crate::{coordinates::CoordinateSystem, space::Space, time::{self, Instant}};
+
+/// Fading ribbon behind a rigid body, for projectiles, dashes and sword swings.
+/// Purely visual, so it isn't synced. Each client records its own from the synced body
+pub struct Trail {
+    pub rigid_body_handle: RigidBodyHandle,
+    points: VecDeque<(Vec2, Instant)>, // rapier coords, newest at the back
+    pub lifetime: Duration, // how long a point lasts before it has faded out completely
+    pub min_distance: f32, // the body has to move this far before another point is recorded
+    pub max_points: usize,
+    pub width: f32, // at the newest point, tapering to 0 at the oldest
+    pub color: Color
+}
+
+impl Trail {
+
+    pub fn new(rigid_body_handle: RigidBodyHandle, color: Color) -> Self {
+        Self {
+            rigid_body_handle,
+            points: VecDeque::new(),
+            lifetime: Duration::from_millis(300),
+            min_distance: 2.,
+            max_points: 64,
+            width: 6.,
+            color,
+        }
+    }
+
+    pub fn with_lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+
+        self
+    }
+
+    pub fn with_width(mut self, width: f32) -> Self {
+        self.width = width;
+
+        self
+    }
+
+    pub fn with_max_points(mut self, max_points: usize) -> Self {
+        self.max_points = max_points;
+
+        self
+    }
+
+    /// Record where the body is now and drop points that have faded out. Keeps fading after the body is gone
+    pub fn update(&mut self, space: &Space) {
+        let now = time::now();
+
+        while self.points.front().is_some_and(|(_, recorded_at)| now.duration_since(*recorded_at) >= self.lifetime) {
+            self.points.pop_front();
+        }
+
+        let position = match space.rigid_body_set.get(self.rigid_body_handle) {
+            Some(rigid_body) => vec2(rigid_body.translation().x, rigid_body.translation().y),
+            None => return,
+        };
+
+        let moved = match self.points.back() {
+            Some((last, _)) => last.distance(position) >= self.min_distance,
+            None => true,
+        };
+
+        if moved {
+            self.points.push_back((position, now));
+        }
+
+        while self.points.len() > self.max_points {
+            self.points.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// The body is gone and the trail has faded out, so it can be dropped
+    pub fn finished(&self, space: &Space) -> bool {
+        self.points.is_empty() && !space.rigid_body_set.contains(self.rigid_body_handle)
+    }
+
+    pub fn draw(&self, coordinates: &CoordinateSystem) {
+        if self.points.len() < 2 {
+            return;
+        }
+
+        let now = time::now();
+
+        let mut vertices = Vec::with_capacity(self.points.len() * 2);
+        let mut indices = Vec::with_capacity((self.points.len() - 1) * 6);
+
+        for (index, (point, recorded_at)) in self.points.iter().enumerate() {
+            let fade = 1. - (now.duration_since(*recorded_at).as_secs_f32() / self.lifetime.as_secs_f32()).min(1.);
+
+            let point = coordinates.rapier_to_macroquad(point);
+
+            // the ribbon's edges sit either side of the line, perpendicular to the direction between its neighbours
+            let previous = coordinates.rapier_to_macroquad(&self.points[index.saturating_sub(1)].0);
+            let next = coordinates.rapier_to_macroquad(&self.points[(index + 1).min(self.points.len() - 1)].0);
+
+            let normal = (next - previous).normalize_or_zero().perp() * self.width * fade / 2.;
+
+            let color = Color::new(self.color.r, self.color.g, self.color.b, self.color.a * fade);
+
+            vertices.push(Vertex::new(point.x + normal.x, point.y + normal.y, 0., 0., 0., color));
+            vertices.push(Vertex::new(point.x - normal.x, point.y - normal.y, 0., 0., 0., color));
+
+            if index > 0 {
+                let first = (index * 2 - 2) as u16;
+
+                indices.extend_from_slice(&[first, first + 1, first + 2, first + 1, first + 3, first + 2]);
+            }
+        }
+
+        draw_mesh(&Mesh { vertices, indices, texture: None });
+    }
+}
+
+/// A hitscan shot's line, shown for a moment and fading out
+#[derive(Clone, Debug)]
+pub struct Tracer {
+    pub start: Vec2, // rapier coords
+    pub end: Vec2,
+    pub spawned_at: Instant,
+    pub duration: Duration,
+    pub width: f32,
+    pub color: Color
+}
+
+impl Tracer {
+
+    pub fn new(start: Vec2, end: Vec2, color: Color) -> Self {
+        Self {
+            start,
+            end,
+            spawned_at: time::now(),
+            duration: Duration::from_millis(80),
+            width: 2.,
+            color,
+        }
+    }
+
+    /// Cast a ray from `origin` along `direction` and end the tracer at whatever it hits, or after `max_distance`.
+    /// Returns the collider that was hit too, so the same ray can be used for damage
+    pub fn hitscan(space: &mut Space, origin: Vec2, direction: Vec2, max_distance: f32, filter: QueryFilter, color: Color) -> (Self, Option<ColliderHandle>) {
+        let direction = direction.normalize_or_zero();
+
+        space.query_pipeline.update(&space.collider_set);
+
+        let ray = Ray::new(point![origin.x, origin.y], vector![direction.x, direction.y]);
+
+        let (distance, hit) = match space.query_pipeline.cast_ray(&space.rigid_body_set, &space.collider_set, &ray, max_distance, true, filter) {
+            Some((collider_handle, distance)) => (distance, Some(collider_handle)),
+            None => (max_distance, None),
+        };
+
+        (Self::new(origin, origin + direction * distance, color), hit)
+    }
+
+    pub fn finished(&self) -> bool {
+        self.spawned_at.elapsed() >= self.duration
+    }
+
+    pub fn draw(&self, coordinates: &CoordinateSystem) {
+        let fade = 1. - (self.spawned_at.elapsed().as_secs_f32() / self.duration.as_secs_f32()).min(1.);
+
+        let start = coordinates.rapier_to_macroquad(&self.start);
+        let end = coordinates.rapier_to_macroquad(&self.end);
+
+        draw_line(start.x, start.y, end.x, end.y, self.width, Color::new(self.color.r, self.color.g, self.color.b, self.color.a * fade));
+    }
+}
+
+/// Every live tracer, dropped once they have faded
+#[derive(Default)]
+pub struct Tracers {
+    tracers: Vec<Tracer>
+}
+
+impl Tracers {
+
+    pub fn new() -> Self {
+        Self {
+            tracers: vec![],
+        }
+    }
+
+    pub fn spawn(&mut self, tracer: Tracer) {
+        self.tracers.push(tracer);
+    }
+
+    pub fn draw(&mut self, coordinates: &CoordinateSystem) {
+        self.tracers.retain(|tracer| !tracer.finished());
+
+        for tracer in &self.tracers {
+            tracer.draw(coordinates);
+        }
+    }
+}
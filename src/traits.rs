@@ -4,15 +4,14 @@ use macroquad::input::{self, is_key_down, is_mouse_button_down, is_mouse_button_
 use macroquad::math::{vec2, Rect, Vec2};
 use macroquad::shapes::DrawRectangleParams;
 use macroquad::texture::{draw_texture_ex, DrawTextureParams};
-use macroquad::window::screen_height;
 use nalgebra::{point, vector};
 use rapier2d::geometry::ColliderHandle;
-use rapier2d::math::Rotation;
 use rapier2d::pipeline::QueryFilter;
 use rapier2d::prelude::RigidBodyHandle;
 
+use crate::coordinates::CoordinateSystem;
+use crate::editor::Snapping;
 use crate::space::Space;
-use crate::{rapier_mouse_world_pos, rapier_to_macroquad};
 use crate::texture_loader::TextureLoader;
 
 pub trait HasPhysics {
@@ -23,6 +22,24 @@ pub trait HasPhysics {
     fn dragging(&mut self) -> &mut bool; // structure is currently being dragged
     fn drag_offset(&mut self) -> &mut Option<Vec2>; // when dragging the body, we teleport the body to the mouse plus this offset
 
+    /// Add this entity's handles to the owned lists passed to Space::step, so the local simulation moves it
+    fn register_owned(&self, owned_rigid_bodies: &mut Vec<RigidBodyHandle>, owned_colliders: &mut Vec<ColliderHandle>) {
+        if !owned_rigid_bodies.contains(self.rigid_body_handle()) {
+            owned_rigid_bodies.push(*self.rigid_body_handle());
+        }
+
+        if !owned_colliders.contains(self.collider_handle()) {
+            owned_colliders.push(*self.collider_handle());
+        }
+    }
+
+    /// Remove this entity's handles from the owned lists, e.g. when another client takes it over
+    fn unregister_owned(&self, owned_rigid_bodies: &mut Vec<RigidBodyHandle>, owned_colliders: &mut Vec<ColliderHandle>) {
+        owned_rigid_bodies.retain(|rigid_body_handle| rigid_body_handle != self.rigid_body_handle());
+
+        owned_colliders.retain(|collider_handle| collider_handle != self.collider_handle());
+    }
+
     fn remove_body_and_collider(&mut self, space: &mut Space) {
 
         space.rigid_body_set.remove(*self.rigid_body_handle(), &mut space.island_manager, &mut space.collider_set, &mut space.impulse_joint_set, &mut space.multibody_joint_set, true);
@@ -47,60 +64,7 @@ pub trait HasPhysics {
         contains_point
     } 
 
-    fn editor_rotate(&mut self, space: &mut Space) {
-        if !*self.selected() {return}
-
-        if !is_key_down(input::KeyCode::R) {return}
-
-        let rigid_body = space.rigid_body_set.get_mut(*self.rigid_body_handle()).unwrap();
-        
-        rigid_body.set_rotation(Rotation::from_angle(rigid_body.rotation().angle() - 0.05), true);
-    }
-
-    fn editor_resize(&mut self, space: &mut Space) {
-
-        if !*self.selected() {
-            return;
-        }
-        let collider = space.collider_set.get_mut(*self.collider_handle()).unwrap();
-        let rigid_body = space.rigid_body_set.get_mut(*self.rigid_body_handle()).unwrap();
-
-        let shape = collider.shape_mut().as_cuboid_mut().unwrap();
-
-        let increase_unit = 10.;
-
-        if is_key_down(input::KeyCode::Right) {
-            
-            shape.half_extents.x += increase_unit;
-            rigid_body.set_position(vector![rigid_body.position().translation.x + increase_unit, rigid_body.position().translation.y].into(), true)
-        }
-
-        if is_key_down(input::KeyCode::Up) {
-            shape.half_extents.y += increase_unit;
-            rigid_body.set_position(vector![rigid_body.position().translation.x, rigid_body.position().translation.y + increase_unit].into(), true)
-        }
-
-        if is_key_down(input::KeyCode::Down) {
-            shape.half_extents.y -= increase_unit;
-            rigid_body.set_position(vector![rigid_body.position().translation.x, rigid_body.position().translation.y - increase_unit].into(), true)
-        }
-
-        if is_key_down(input::KeyCode::Left) {
-            shape.half_extents.x -= increase_unit;
-            rigid_body.set_position(vector![rigid_body.position().translation.x - increase_unit, rigid_body.position().translation.y].into(), true)
-        }
-
-        if shape.half_extents.x <= 0. {
-            shape.half_extents.x = 1.
-        }
-
-        if shape.half_extents.y <= 0. {
-            shape.half_extents.y = 1.
-        }
-        
-    }
-
-    async fn draw_outline(&self, space: &Space, outline_thickness: f32) {
+    fn draw_outline(&self, space: &Space, outline_thickness: f32, coordinates: &CoordinateSystem) {
         let rigid_body = space.rigid_body_set.get(*self.rigid_body_handle()).unwrap();
         let collider = space.collider_set.get(*self.collider_handle()).unwrap();
 
@@ -111,7 +75,7 @@ pub trait HasPhysics {
         let position = rigid_body.position().translation;
         let rotation = rigid_body.rotation().angle();
 
-        let draw_pos = rapier_to_macroquad(&vec2(position.x, position.y));
+        let draw_pos = coordinates.rapier_to_macroquad(&vec2(position.x, position.y));
 
         // draw the outline
         if *self.selected() {
@@ -124,7 +88,7 @@ pub trait HasPhysics {
             );
         } 
     }
-    async fn draw_texture(&self, space: &Space, texture_path: &String, textures: &mut TextureLoader, flip_x: bool, flip_y: bool) {
+    fn draw_texture(&self, space: &Space, texture_path: &String, textures: &mut TextureLoader, flip_x: bool, flip_y: bool, coordinates: &CoordinateSystem) {
         let rigid_body = space.rigid_body_set.get(*self.rigid_body_handle()).unwrap();
         let collider = space.collider_set.get(*self.collider_handle()).unwrap();
 
@@ -135,16 +99,18 @@ pub trait HasPhysics {
         let position = rigid_body.position().translation;
         let rotation = rigid_body.rotation().angle();
 
-        let draw_pos = rapier_to_macroquad(&vec2(position.x, position.y));
+        let draw_pos = coordinates.rapier_to_macroquad(&vec2(position.x, position.y));
+
+        let (texture, region) = textures.get_region_blocking(texture_path);
 
         draw_texture_ex(
-            textures.get(texture_path).await, 
+            texture, 
             draw_pos.x - shape.half_extents.x, 
             draw_pos.y - shape.half_extents.y, 
             WHITE, 
             DrawTextureParams {
                 dest_size: Some(vec2(shape.half_extents.x * 2., shape.half_extents.y * 2.)),
-                source: None,
+                source: region,
                 rotation: rotation * -1.,
                 flip_x,
                 flip_y,
@@ -155,25 +121,32 @@ pub trait HasPhysics {
         
     }
     
-    fn update_selected(&mut self, space: &mut Space, camera_rect: &Rect) {
+    fn update_selected(&mut self, space: &mut Space, camera_rect: &Rect, coordinates: &CoordinateSystem) {
 
         if !is_mouse_button_pressed(input::MouseButton::Left) {
             return;
         }
 
-        let mouse_rapier_coords = rapier_mouse_world_pos(camera_rect);
+        let mouse_rapier_coords = coordinates.rapier_mouse_world_pos(camera_rect);
 
-        if self.contains_point(space, mouse_rapier_coords){
-            *self.selected_mut() = true;
-        }
+        let shift = is_key_down(input::KeyCode::LeftShift) || is_key_down(input::KeyCode::RightShift);
+
+        let clicked = self.contains_point(space, mouse_rapier_coords);
+
+        // shift click adds to or removes from the selection instead of replacing it
+        if shift {
+            if clicked {
+                *self.selected_mut() = !*self.selected();
+            }
 
-        else {
-            *self.selected_mut() = false;
+            return;
         }
+
+        *self.selected_mut() = clicked;
         
     }
 
-    fn update_drag(&mut self, space: &mut Space, camera_rect: &Rect) {
+    fn update_drag(&mut self, space: &mut Space, camera_rect: &Rect, coordinates: &CoordinateSystem, snapping: &Snapping) {
         // Drag the collider / rigid body with the mouse
 
         if !*self.dragging() {
@@ -184,9 +157,25 @@ pub trait HasPhysics {
         
         let collider = space.collider_set.get_mut(*self.collider_handle()).unwrap();
 
-        let mouse_pos = rapier_mouse_world_pos(camera_rect);
+        let mouse_pos = coordinates.rapier_mouse_world_pos(camera_rect);
 
-        let offset_mouse_pos = mouse_pos - drag_offset;
+        let mut offset_mouse_pos = snapping.snap_vec(mouse_pos - drag_offset);
+
+        // holding X keeps the body on its current y and holding Y keeps it on its current x
+        if snapping.axis_lock {
+            let current_position = match collider.parent() {
+                Some(rigid_body_handle) => space.rigid_body_set.get(rigid_body_handle).unwrap().position().translation,
+                None => collider.position().translation,
+            };
+
+            if is_key_down(input::KeyCode::X) {
+                offset_mouse_pos.y = current_position.y;
+            }
+
+            else if is_key_down(input::KeyCode::Y) {
+                offset_mouse_pos.x = current_position.x;
+            }
+        }
 
         // if the collider has a parent rigid body, we move that instead of the collider
         match &mut collider.parent() {
@@ -213,7 +202,7 @@ pub trait HasPhysics {
         
     }
 
-    fn update_is_dragging(&mut self, space: &mut Space, camera_rect: &Rect) {
+    fn update_is_dragging(&mut self, space: &mut Space, camera_rect: &Rect, coordinates: &CoordinateSystem) {
         // Determine if the collider is being dragged
 
         if !*self.selected() {
@@ -228,7 +217,7 @@ pub trait HasPhysics {
             return
         }
 
-        let mouse_pos = rapier_mouse_world_pos(camera_rect);
+        let mouse_pos = coordinates.rapier_mouse_world_pos(camera_rect);
 
         // if the body does not contain the mouse, but the button is down, we just dont do anything, because this is still a valid dragging state IF we are already dragging
 
@@ -283,7 +272,7 @@ pub trait HasPhysics {
 
     }
 
-    async fn draw_collider(&mut self, space: &Space) {
+    fn draw_collider(&mut self, space: &Space, coordinates: &CoordinateSystem) {
         let collider_handle = self.collider_handle();
         let collider = space.collider_set.get(*collider_handle).expect("Invalid collider handle");
 
@@ -310,11 +299,13 @@ pub trait HasPhysics {
             _ => panic!("cannot draw non cuboid shape")
         };
 
+        let draw_pos = coordinates.rapier_to_macroquad(&vec2(position.translation.x, position.translation.y));
+
         // draw the outline
         if *self.selected() {
             macroquad::shapes::draw_rectangle_ex(
-                position.translation.x, 
-                ((position.translation.y) * -1.) + screen_height(), 
+                draw_pos.x, 
+                draw_pos.y, 
                 (hx * 2.) + 10., 
                 (hy * 2.)+ 10., 
                 DrawRectangleParams { offset: macroquad::math::Vec2::new(0.5, 0.5), rotation: rotation.angle() * -1., color: WHITE }
@@ -322,25 +313,150 @@ pub trait HasPhysics {
         } 
 
         macroquad::shapes::draw_rectangle_ex(
-            position.translation.x, 
-            ((position.translation.y) * -1.) + screen_height(), 
+            draw_pos.x, 
+            draw_pos.y, 
             hx * 2., 
             hy * 2., 
             DrawRectangleParams { offset: macroquad::math::Vec2::new(0.5, 0.5), rotation: rotation.angle() * -1., color: WHITE }
         );
 
-        // for resize_handle in self.get_resize_handles() {
-        //     // draw the resize handles
-        //     macroquad::shapes::draw_rectangle_ex(
-        //         position.x, 
-        //         position.y, 
-        //         resize_handle.w, 
-        //         resize_handle.h, 
-        //         DrawRectangleParams { offset: macroquad::math::Vec2::new(0.5, 0.5), rotation: rotation * -1., color: ORANGE }
-        //     )
-        // }
-        
-
     }
 }
 
+
+/// Implements HasPhysics for a struct that keeps its physics state in fields named
+/// `collider_handle`, `rigid_body_handle`, `selected`, `dragging` and `drag_offset`.
+/// Pass `field = other_name` pairs after the type to use different field names
+#[macro_export]
+macro_rules! impl_has_physics {
+    ($entity:ty) => {
+        $crate::impl_has_physics!($entity, 
+            collider_handle = collider_handle, 
+            rigid_body_handle = rigid_body_handle, 
+            selected = selected, 
+            dragging = dragging, 
+            drag_offset = drag_offset
+        );
+    };
+
+    (
+        $entity:ty, 
+        collider_handle = $collider_handle:ident, 
+        rigid_body_handle = $rigid_body_handle:ident, 
+        selected = $selected:ident, 
+        dragging = $dragging:ident, 
+        drag_offset = $drag_offset:ident
+    ) => {
+        impl $crate::traits::HasPhysics for $entity {
+            fn collider_handle(&self) -> &$crate::rapier2d::geometry::ColliderHandle {
+                &self.$collider_handle
+            }
+
+            fn rigid_body_handle(&self) -> &$crate::rapier2d::prelude::RigidBodyHandle {
+                &self.$rigid_body_handle
+            }
+
+            fn selected(&self) -> &bool {
+                &self.$selected
+            }
+
+            fn selected_mut(&mut self) -> &mut bool {
+                &mut self.$selected
+            }
+
+            fn dragging(&mut self) -> &mut bool {
+                &mut self.$dragging
+            }
+
+            fn drag_offset(&mut self) -> &mut Option<$crate::macroquad::math::Vec2> {
+                &mut self.$drag_offset
+            }
+        }
+    };
+}
+
+/// Implements Diff for an entity using only the listed fields, so handles and editor state like `selected` don't get synced.
+/// The physics side of the entity is synced through the Space, so only gameplay fields need listing.
+/// Creates a `$diff` struct holding the changed fields, serialized as a tuple in field order. The entity must implement Default, which is used as the identity
+///
+/// ```ignore
+/// impl_entity_diff!(Player, PlayerDiff { health: u32, name: String });
+/// ```
+#[macro_export]
+macro_rules! impl_entity_diff {
+    ($entity:ty, $diff:ident { $($field:ident: $field_type:ty),* $(,)? }) => {
+        pub struct $diff {
+            $(pub $field: Option<<$field_type as $crate::diff::Diff>::Repr>),*
+        }
+
+        // written out by hand because serde's derive needs serde as a direct dependency of the game
+        impl $crate::serde::Serialize for $diff {
+            fn serialize<S: $crate::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use $crate::serde::ser::SerializeTuple;
+
+                let mut tuple = serializer.serialize_tuple(<[&str]>::len(&[$(stringify!($field)),*]))?;
+
+                $(tuple.serialize_element(&self.$field)?;)*
+
+                tuple.end()
+            }
+        }
+
+        impl<'de> $crate::serde::Deserialize<'de> for $diff {
+            fn deserialize<D: $crate::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct DiffVisitor;
+
+                impl<'de> $crate::serde::de::Visitor<'de> for DiffVisitor {
+                    type Value = $diff;
+
+                    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        formatter.write_str(stringify!($diff))
+                    }
+
+                    #[allow(unused_mut, unused_variables, unused_assignments)]
+                    fn visit_seq<A: $crate::serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                        let mut index = 0;
+
+                        $(
+                            let $field = match seq.next_element()? {
+                                Some(value) => value,
+                                None => return Err($crate::serde::de::Error::invalid_length(index, &self)),
+                            };
+
+                            index += 1;
+                        )*
+
+                        Ok($diff { $($field),* })
+                    }
+                }
+
+                deserializer.deserialize_tuple(<[&str]>::len(&[$(stringify!($field)),*]), DiffVisitor)
+            }
+        }
+
+        impl $crate::diff::Diff for $entity {
+            type Repr = $diff;
+
+            fn diff(&self, other: &Self) -> Self::Repr {
+                $diff {
+                    $($field: match self.$field == other.$field {
+                        true => None,
+                        false => Some($crate::diff::Diff::diff(&self.$field, &other.$field)),
+                    }),*
+                }
+            }
+
+            fn apply(&mut self, diff: &Self::Repr) {
+                $(
+                    if let Some(field_diff) = &diff.$field {
+                        $crate::diff::Diff::apply(&mut self.$field, field_diff);
+                    }
+                )*
+            }
+
+            fn identity() -> Self {
+                Default::default()
+            }
+        }
+    };
+}
@@ -0,0 +1,152 @@
+use diff::Diff;
+use macroquad::{camera::{set_camera, set_default_camera, Camera2D}, color::{Color, BLACK, WHITE}, input::mouse_position, math::{vec2, Rect, Vec2}, texture::{draw_texture_ex, render_target, DrawTextureParams, FilterMode, RenderTarget}, window::{clear_background, screen_height, screen_width}};
+use serde::{Deserialize, Serialize};
+
+use crate::coordinates::CoordinateSystem;
+
+/// Where a virtual screen ends up on the window. Cheap to copy around to anything that needs to map the mouse, like Menu
+#[derive(Serialize, Deserialize, Diff, PartialEq, Clone, Copy, Debug)]
+#[diff(attr(
+    #[derive(Serialize, Deserialize)]
+))]
+pub struct Viewport {
+    pub rect: Rect, // window pixels
+    pub size: Vec2 // virtual pixels
+}
+
+impl Viewport {
+
+    pub fn scale(&self) -> f32 {
+        self.rect.w / self.size.x
+    }
+
+    /// A window position in virtual pixels. Positions on the black bars end up outside of 0..size
+    pub fn to_virtual(&self, window_position: Vec2) -> Vec2 {
+        (window_position - self.rect.point()) / self.scale()
+    }
+
+    pub fn to_window(&self, virtual_position: Vec2) -> Vec2 {
+        self.rect.point() + virtual_position * self.scale()
+    }
+
+    pub fn mouse_position(&self) -> Vec2 {
+        self.to_virtual(Vec2::from_array(mouse_position().into()))
+    }
+
+    /// Same mapping as mouse_world_pos, but through the virtual screen
+    pub fn mouse_world_pos(&self, camera_rect: &Rect) -> Vec2 {
+        camera_rect.point() + self.mouse_position() / self.size * camera_rect.size()
+    }
+
+    /// Draws straight to the window in virtual pixels, lined up with the virtual screen.
+    /// For things that should stay sharp at the window's resolution, like text
+    pub fn window_camera(&self) -> Camera2D {
+        let mut camera = Camera2D::from_display_rect(Rect::new(0., 0., self.size.x, self.size.y));
+        camera.zoom.y = -camera.zoom.y;
+
+        // gl viewports start from the bottom of the window
+        camera.viewport = Some((
+            self.rect.x as i32, 
+            (screen_height() - self.rect.y - self.rect.h) as i32, 
+            self.rect.w as i32, 
+            self.rect.h as i32
+        ));
+
+        camera
+    }
+}
+
+/// Renders the game at a fixed resolution and scales it up to fit the window, with black bars filling the rest.
+/// Draw everything between begin and end
+pub struct VirtualScreen {
+    pub width: u32,
+    pub height: u32,
+    pub integer_scaling: bool, // only scale by whole numbers so pixel art stays square
+    pub bar_color: Color,
+    render_target: RenderTarget
+}
+
+impl VirtualScreen {
+
+    pub fn new(width: u32, height: u32) -> Self {
+        let render_target = render_target(width, height);
+
+        render_target.texture.set_filter(FilterMode::Nearest);
+
+        Self {
+            width,
+            height,
+            integer_scaling: true,
+            bar_color: BLACK,
+            render_target,
+        }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        vec2(self.width as f32, self.height as f32)
+    }
+
+    /// For converting between rapier and macroquad coordinates while drawing to the virtual screen
+    pub fn coordinates(&self) -> CoordinateSystem {
+        CoordinateSystem::render_target(&self.render_target)
+    }
+
+    /// Where the virtual screen lands on the window right now
+    pub fn viewport(&self) -> Viewport {
+        let size = self.size();
+
+        let mut scale = (screen_width() / size.x).min(screen_height() / size.y);
+
+        // a window smaller than the virtual screen has to shrink it, whole numbers or not
+        if self.integer_scaling && scale >= 1. {
+            scale = scale.floor();
+        }
+
+        let scaled_size = size * scale;
+
+        Viewport {
+            rect: Rect::new(
+                ((screen_width() - scaled_size.x) / 2.).floor(), 
+                ((screen_height() - scaled_size.y) / 2.).floor(), 
+                scaled_size.x, 
+                scaled_size.y
+            ),
+            size,
+        }
+    }
+
+    /// A camera that shows `camera_rect` of the world on the virtual screen.
+    /// Render targets are stored upside down, so unlike the window camera the y zoom isn't flipped
+    pub fn camera(&self, camera_rect: Rect) -> Camera2D {
+        let mut camera = Camera2D::from_display_rect(camera_rect);
+
+        camera.render_target = Some(self.render_target.clone());
+
+        camera
+    }
+
+    /// Start drawing to the virtual screen in virtual pixels. Switch to `camera` for world drawing
+    pub fn begin(&self) {
+        set_camera(&self.camera(Rect::new(0., 0., self.size().x, self.size().y)));
+    }
+
+    /// Stop drawing to the virtual screen and put it on the window
+    pub fn end(&self) {
+        set_default_camera();
+
+        clear_background(self.bar_color);
+
+        let viewport = self.viewport();
+
+        draw_texture_ex(
+            &self.render_target.texture,
+            viewport.rect.x,
+            viewport.rect.y,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(viewport.rect.size()),
+                ..Default::default()
+            }
+        );
+    }
+}